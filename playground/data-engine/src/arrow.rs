@@ -0,0 +1,226 @@
+//! Reading Arrow IPC streams produced by Arrow JS
+//!
+//! Only the message framing and record batch lengths are read, enough to
+//! check a stream and count its rows without an Arrow implementation.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use playground_types::data::{ArrowResult, ColumnSchema};
+use playground_types::messages::{ErrorCode, ErrorInfo};
+
+/// Marks the start of a message in the current IPC format
+const CONTINUATION: u32 = 0xFFFF_FFFF;
+
+/// `MessageHeader` union tag for record batches
+const HEADER_RECORD_BATCH: u8 = 3;
+
+/// Wrap an IPC stream for transfer to JS, counting its rows
+pub fn arrow_result(bytes: &[u8], schema: Vec<ColumnSchema>) -> Result<ArrowResult, ErrorInfo> {
+    Ok(ArrowResult {
+        row_count: ipc_row_count(bytes)?,
+        ipc_bytes_base64: STANDARD.encode(bytes),
+        schema,
+    })
+}
+
+/// Total rows in the record batches of an Arrow IPC stream
+pub fn ipc_row_count(bytes: &[u8]) -> Result<u64, ErrorInfo> {
+    let mut rows = 0;
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let mut len = read_u32(bytes, pos)?;
+        pos = advance(pos, 4)?;
+        if len == CONTINUATION {
+            len = read_u32(bytes, pos)?;
+            pos = advance(pos, 4)?;
+        }
+        // A zero length marks the end of the stream
+        if len == 0 {
+            break;
+        }
+        let end = advance(pos, len as usize)?;
+        let metadata = bytes
+            .get(pos..end)
+            .ok_or_else(|| invalid_ipc("message runs past the end of the stream"))?;
+        pos = end;
+
+        let message = Table::root(metadata)?;
+        if message.u8_field(1)? == Some(HEADER_RECORD_BATCH) {
+            let batch = message
+                .table_field(2)?
+                .ok_or_else(|| invalid_ipc("record batch message has no header"))?;
+            rows += u64::try_from(batch.i64_field(0)?.unwrap_or(0))
+                .map_err(|_| invalid_ipc("record batch length is negative"))?;
+        }
+        let body_len = usize::try_from(message.i64_field(3)?.unwrap_or(0))
+            .map_err(|_| invalid_ipc("message body length out of range"))?;
+        pos = advance(pos, body_len)?;
+        if pos > bytes.len() {
+            return Err(invalid_ipc("message body runs past the end of the stream"));
+        }
+    }
+    Ok(rows)
+}
+
+/// A FlatBuffers table
+struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    vtable: usize,
+}
+
+impl<'a> Table<'a> {
+    fn root(buf: &'a [u8]) -> Result<Self, ErrorInfo> {
+        Self::at(buf, read_u32(buf, 0)? as usize)
+    }
+
+    fn at(buf: &'a [u8], pos: usize) -> Result<Self, ErrorInfo> {
+        let offset = read_u32(buf, pos)? as i32 as i64;
+        let vtable = usize::try_from(pos as i64 - offset)
+            .map_err(|_| invalid_ipc("vtable offset out of range"))?;
+        Ok(Self { buf, pos, vtable })
+    }
+
+    /// Position of field `index`, or `None` if it's absent
+    fn field(&self, index: usize) -> Result<Option<usize>, ErrorInfo> {
+        let vtable_len = read_u16(self.buf, self.vtable)? as usize;
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_len {
+            return Ok(None);
+        }
+        match read_u16(self.buf, advance(self.vtable, entry)?)? {
+            0 => Ok(None),
+            offset => advance(self.pos, offset as usize).map(Some),
+        }
+    }
+
+    fn u8_field(&self, index: usize) -> Result<Option<u8>, ErrorInfo> {
+        self.field(index)?
+            .map(|pos| read(self.buf, pos).map(|[b]: [u8; 1]| b))
+            .transpose()
+    }
+
+    fn i64_field(&self, index: usize) -> Result<Option<i64>, ErrorInfo> {
+        self.field(index)?
+            .map(|pos| read(self.buf, pos).map(i64::from_le_bytes))
+            .transpose()
+    }
+
+    fn table_field(&self, index: usize) -> Result<Option<Table<'a>>, ErrorInfo> {
+        self.field(index)?
+            .map(|pos| Table::at(self.buf, advance(pos, read_u32(self.buf, pos)? as usize)?))
+            .transpose()
+    }
+}
+
+/// `pos + len`, failing on overflow rather than wrapping (`usize` is 32
+/// bits on wasm32, so a malformed length field can reach it)
+fn advance(pos: usize, len: usize) -> Result<usize, ErrorInfo> {
+    pos.checked_add(len)
+        .ok_or_else(|| invalid_ipc("offset out of range"))
+}
+
+fn read<const N: usize>(buf: &[u8], pos: usize) -> Result<[u8; N], ErrorInfo> {
+    buf.get(pos..advance(pos, N)?)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid_ipc("read past the end of a message"))
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Result<u16, ErrorInfo> {
+    read(buf, pos).map(u16::from_le_bytes)
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32, ErrorInfo> {
+    read(buf, pos).map(u32::from_le_bytes)
+}
+
+fn invalid_ipc(message: &str) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::ParseError,
+        message: "Invalid Arrow IPC stream".to_string(),
+        details: Some(message.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An encapsulated IPC message whose header is a table with `length`
+    /// as its first field, followed by a zeroed body
+    fn message(header_type: u8, length: i64, body: usize) -> Vec<u8> {
+        let mut fb = Vec::new();
+        fb.extend(16u32.to_le_bytes()); // root table at 16
+                                        // Message vtable at 4: size, table size, version, header_type, header, bodyLength
+        for v in [12u16, 20, 16, 18, 12, 4] {
+            fb.extend(v.to_le_bytes());
+        }
+        fb.extend(12i32.to_le_bytes()); // Message table at 16, vtable 12 back
+        fb.extend((body as i64).to_le_bytes());
+        fb.extend(16u32.to_le_bytes()); // header table at 28 + 16
+        fb.extend(5i16.to_le_bytes()); // MetadataVersion V5
+        fb.extend([header_type, 0]);
+        // RecordBatch vtable at 36: size, table size, length
+        for v in [6u16, 12, 4, 0] {
+            fb.extend(v.to_le_bytes());
+        }
+        fb.extend(8i32.to_le_bytes()); // RecordBatch table at 44, vtable 8 back
+        fb.extend(length.to_le_bytes());
+
+        let mut out = Vec::new();
+        out.extend(CONTINUATION.to_le_bytes());
+        out.extend((fb.len() as u32).to_le_bytes());
+        out.extend(fb);
+        out.extend(vec![0; body]);
+        out
+    }
+
+    #[test]
+    fn counts_rows_across_batches() {
+        let mut stream = message(1, 0, 0); // schema
+        stream.extend(message(HEADER_RECORD_BATCH, 1024, 64));
+        stream.extend(message(HEADER_RECORD_BATCH, 317, 24));
+        stream.extend(CONTINUATION.to_le_bytes());
+        stream.extend(0u32.to_le_bytes());
+
+        assert_eq!(ipc_row_count(&stream).unwrap(), 1341);
+        let result = arrow_result(&stream, Vec::new()).unwrap();
+        assert_eq!(result.row_count, 1341);
+        let decoded = STANDARD.decode(&result.ipc_bytes_base64).unwrap();
+        assert_eq!(ipc_row_count(&decoded).unwrap(), 1341);
+    }
+
+    #[test]
+    fn rejects_truncated_stream() {
+        let stream = message(HEADER_RECORD_BATCH, 10, 0);
+        let err = ipc_row_count(&stream[..20]).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ParseError);
+    }
+
+    #[test]
+    fn rejects_oversized_lengths() {
+        // bodyLength sits 20 bytes into the metadata, after the 8-byte prefix
+        for body_len in [-1i64, i64::MAX] {
+            let mut stream = message(HEADER_RECORD_BATCH, 10, 0);
+            stream[28..36].copy_from_slice(&body_len.to_le_bytes());
+            let err = ipc_row_count(&stream).unwrap_err();
+            assert_eq!(err.code, ErrorCode::ParseError);
+        }
+
+        let mut stream = CONTINUATION.to_le_bytes().to_vec();
+        stream.extend(u32::MAX.to_le_bytes());
+        assert_eq!(
+            ipc_row_count(&stream).unwrap_err().code,
+            ErrorCode::ParseError
+        );
+        assert!(advance(usize::MAX - 2, 4).is_err());
+
+        let stream = message(HEADER_RECORD_BATCH, -1, 0);
+        let err = ipc_row_count(&stream).unwrap_err();
+        assert_eq!(
+            err.details.as_deref(),
+            Some("record batch length is negative")
+        );
+        assert!(read::<4>(&stream, usize::MAX - 2).is_err());
+    }
+}
@@ -0,0 +1,383 @@
+//! SQL autocomplete suggestions from the loaded catalog
+
+use crate::schema::quote_ident;
+use playground_types::editor::{AutocompleteSuggestion, SuggestionKind};
+
+/// Maximum suggestions returned per request
+const MAX_SUGGESTIONS: usize = 50;
+
+/// SQL keywords offered as completions
+const KEYWORDS: &[&str] = &[
+    "SELECT",
+    "FROM",
+    "WHERE",
+    "GROUP BY",
+    "ORDER BY",
+    "HAVING",
+    "LIMIT",
+    "OFFSET",
+    "JOIN",
+    "LEFT JOIN",
+    "INNER JOIN",
+    "ON",
+    "AS",
+    "AND",
+    "OR",
+    "NOT",
+    "IN",
+    "IS NULL",
+    "IS NOT NULL",
+    "BETWEEN",
+    "LIKE",
+    "ILIKE",
+    "DISTINCT",
+    "CASE",
+    "WHEN",
+    "THEN",
+    "ELSE",
+    "END",
+    "WITH",
+    "UNION",
+    "UNION ALL",
+    "ASC",
+    "DESC",
+    "CREATE",
+    "VIEW",
+    "TABLE",
+    "INSERT INTO",
+    "VALUES",
+    "UPDATE",
+    "SET",
+    "DELETE",
+    "DROP",
+    "DESCRIBE",
+    "SUMMARIZE",
+    "QUALIFY",
+    "PIVOT",
+];
+
+/// Common DuckDB functions and their documentation
+const FUNCTIONS: &[(&str, &str)] = &[
+    ("abs", "abs(x) - absolute value"),
+    (
+        "approx_count_distinct",
+        "approx_count_distinct(x) - approximate number of distinct values",
+    ),
+    ("avg", "avg(x) - average of non-NULL values"),
+    ("cast", "cast(x AS type) - convert a value to another type"),
+    ("ceil", "ceil(x) - round up to the nearest integer"),
+    ("coalesce", "coalesce(a, b, ...) - first non-NULL argument"),
+    (
+        "concat",
+        "concat(a, b, ...) - concatenate strings, skipping NULLs",
+    ),
+    (
+        "count",
+        "count(x) - number of non-NULL values; count(*) counts rows",
+    ),
+    ("corr", "corr(y, x) - correlation coefficient"),
+    ("current_date", "current_date - today's date"),
+    (
+        "date_diff",
+        "date_diff(part, start, end) - difference between dates in `part` units",
+    ),
+    (
+        "date_part",
+        "date_part(part, date) - extract a field such as 'year' from a date",
+    ),
+    (
+        "date_trunc",
+        "date_trunc(part, date) - truncate a date to `part` precision",
+    ),
+    ("first", "first(x) - first value in the group"),
+    ("floor", "floor(x) - round down to the nearest integer"),
+    ("greatest", "greatest(a, b, ...) - largest argument"),
+    ("least", "least(a, b, ...) - smallest argument"),
+    ("length", "length(s) - number of characters in a string"),
+    ("list", "list(x) - collect values into a list"),
+    ("lower", "lower(s) - convert to lower case"),
+    ("max", "max(x) - largest value"),
+    ("median", "median(x) - middle value"),
+    ("min", "min(x) - smallest value"),
+    ("nullif", "nullif(a, b) - NULL if a = b, otherwise a"),
+    (
+        "quantile_cont",
+        "quantile_cont(x, q) - interpolated quantile q in [0, 1]",
+    ),
+    (
+        "regexp_extract",
+        "regexp_extract(s, pattern, group) - extract a regex match",
+    ),
+    (
+        "regexp_matches",
+        "regexp_matches(s, pattern) - whether s matches a regex",
+    ),
+    (
+        "replace",
+        "replace(s, from, to) - replace all occurrences of a substring",
+    ),
+    (
+        "round",
+        "round(x, digits) - round to `digits` decimal places",
+    ),
+    (
+        "row_number",
+        "row_number() OVER (...) - row position within a window",
+    ),
+    ("stddev", "stddev(x) - sample standard deviation"),
+    (
+        "strftime",
+        "strftime(ts, format) - format a timestamp as text",
+    ),
+    (
+        "string_agg",
+        "string_agg(s, sep) - concatenate strings in a group",
+    ),
+    (
+        "strptime",
+        "strptime(s, format) - parse text into a timestamp",
+    ),
+    (
+        "substring",
+        "substring(s, start, length) - part of a string (1-based)",
+    ),
+    ("sum", "sum(x) - total of non-NULL values"),
+    ("trim", "trim(s) - remove leading and trailing whitespace"),
+    (
+        "try_cast",
+        "try_cast(x AS type) - convert a value, or NULL on failure",
+    ),
+    ("upper", "upper(s) - convert to upper case"),
+    ("year", "year(date) - year component of a date"),
+];
+
+/// Keywords after which a table name is expected
+const TABLE_CONTEXT: &[&str] = &[
+    "FROM",
+    "JOIN",
+    "INTO",
+    "UPDATE",
+    "TABLE",
+    "DESCRIBE",
+    "SUMMARIZE",
+];
+
+/// Keywords after which a column or expression is expected
+const COLUMN_CONTEXT: &[&str] = &[
+    "SELECT", "WHERE", "BY", "ON", "HAVING", "AND", "OR", "SET", "WHEN", "THEN", "ELSE",
+    "DISTINCT", "NOT",
+];
+
+/// A loaded table and its columns
+pub struct CatalogTable {
+    pub name: String,
+    pub columns: Vec<String>,
+}
+
+/// Suggest completions for the word ending at `cursor` (a character offset)
+pub fn suggest(text: &str, cursor: u32, catalog: &[CatalogTable]) -> Vec<AutocompleteSuggestion> {
+    let end = text
+        .char_indices()
+        .nth(cursor as usize)
+        .map_or(text.len(), |(i, _)| i);
+    let before = &text[..end];
+    let word_start = before
+        .rfind(|c: char| !is_word_char(c))
+        .map_or(0, |i| i + 1);
+    let prefix = &before[word_start..];
+
+    // `table.` restricts suggestions to that table's columns
+    if let Some(qualifier) = before[..word_start].strip_suffix('.') {
+        let start = qualifier
+            .rfind(|c: char| !is_word_char(c))
+            .map_or(0, |i| i + 1);
+        let qualifier = &qualifier[start..];
+        return catalog
+            .iter()
+            .filter(|t| t.name.eq_ignore_ascii_case(qualifier))
+            .flat_map(|t| column_suggestions(t, prefix))
+            .take(MAX_SUGGESTIONS)
+            .collect();
+    }
+
+    let context = previous_word(&before[..word_start]).to_ascii_uppercase();
+    let tables = catalog
+        .iter()
+        .filter(|t| matches_prefix(&t.name, prefix))
+        .map(|t| suggestion(&t.name, ident_text(&t.name), SuggestionKind::Table, None));
+    let columns = catalog.iter().flat_map(|t| column_suggestions(t, prefix));
+    let functions = FUNCTIONS
+        .iter()
+        .filter(|(name, _)| matches_prefix(name, prefix))
+        .map(|(name, doc)| {
+            suggestion(
+                name,
+                format!("{}(", name),
+                SuggestionKind::Function,
+                Some(doc.to_string()),
+            )
+        });
+    let keywords = KEYWORDS
+        .iter()
+        .filter(|k| matches_prefix(k, prefix))
+        .map(|k| suggestion(k, k.to_string(), SuggestionKind::Keyword, None));
+
+    let suggestions: Vec<AutocompleteSuggestion> = if TABLE_CONTEXT.contains(&context.as_str()) {
+        tables.chain(keywords).collect()
+    } else if COLUMN_CONTEXT.contains(&context.as_str()) {
+        columns
+            .chain(functions)
+            .chain(keywords)
+            .chain(tables)
+            .collect()
+    } else if prefix.is_empty() {
+        keywords.collect()
+    } else {
+        keywords
+            .chain(tables)
+            .chain(columns)
+            .chain(functions)
+            .collect()
+    };
+    dedup(suggestions)
+}
+
+fn column_suggestions<'a>(
+    table: &'a CatalogTable,
+    prefix: &'a str,
+) -> impl Iterator<Item = AutocompleteSuggestion> + 'a {
+    table
+        .columns
+        .iter()
+        .filter(move |c| matches_prefix(c, prefix))
+        .map(move |c| {
+            suggestion(
+                c,
+                ident_text(c),
+                SuggestionKind::Column,
+                Some(format!("Column of {}", table.name)),
+            )
+        })
+}
+
+fn suggestion(
+    label: &str,
+    insert_text: String,
+    kind: SuggestionKind,
+    documentation: Option<String>,
+) -> AutocompleteSuggestion {
+    AutocompleteSuggestion {
+        label: label.to_string(),
+        insert_text,
+        kind,
+        documentation,
+    }
+}
+
+/// Drop repeated labels (columns shared by several tables) and cap the list
+fn dedup(suggestions: Vec<AutocompleteSuggestion>) -> Vec<AutocompleteSuggestion> {
+    let mut seen = std::collections::HashSet::new();
+    suggestions
+        .into_iter()
+        .filter(|s| seen.insert((s.label.clone(), s.kind.clone() as u8)))
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn matches_prefix(candidate: &str, prefix: &str) -> bool {
+    candidate.len() >= prefix.len()
+        && candidate.is_char_boundary(prefix.len())
+        && candidate[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// The last whole word before the one being typed
+fn previous_word(text: &str) -> &str {
+    text.trim_end()
+        .rsplit(|c: char| !is_word_char(c))
+        .find(|w| !w.is_empty())
+        .unwrap_or("")
+}
+
+/// Insert identifiers bare when possible, quoted otherwise
+fn ident_text(name: &str) -> String {
+    let simple = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(is_word_char);
+    if simple {
+        name.to_string()
+    } else {
+        quote_ident(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Vec<CatalogTable> {
+        vec![
+            CatalogTable {
+                name: "aadt_2022".to_string(),
+                columns: vec!["route".to_string(), "aadt".to_string()],
+            },
+            CatalogTable {
+                name: "aadt_2023".to_string(),
+                columns: vec!["route".to_string(), "aadt".to_string()],
+            },
+            CatalogTable {
+                name: "schools".to_string(),
+                columns: vec!["name".to_string(), "enrollment".to_string()],
+            },
+        ]
+    }
+
+    fn labels(text: &str) -> Vec<(String, SuggestionKind)> {
+        suggest(text, text.chars().count() as u32, &catalog())
+            .into_iter()
+            .map(|s| (s.label, s.kind))
+            .collect()
+    }
+
+    #[test]
+    fn completes_from_keyword() {
+        let suggestions = labels("SELECT * FRO");
+        assert_eq!(
+            suggestions.first(),
+            Some(&("FROM".to_string(), SuggestionKind::Keyword))
+        );
+    }
+
+    #[test]
+    fn suggests_tables_after_from() {
+        assert_eq!(
+            labels("SELECT * FROM aa"),
+            vec![
+                ("aadt_2022".to_string(), SuggestionKind::Table),
+                ("aadt_2023".to_string(), SuggestionKind::Table),
+            ]
+        );
+    }
+
+    #[test]
+    fn suggests_columns_after_where() {
+        let suggestions = labels("SELECT * FROM schools WHERE en");
+        assert_eq!(
+            suggestions.first(),
+            Some(&("enrollment".to_string(), SuggestionKind::Column))
+        );
+    }
+
+    #[test]
+    fn suggests_qualified_columns() {
+        assert_eq!(
+            labels("SELECT schools.n"),
+            vec![("name".to_string(), SuggestionKind::Column)]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Running `DataRequest::Batch` entries in order
+
+use playground_types::data::{DataRequest, DataResponse};
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use std::future::Future;
+
+/// Run each request with `run`, collecting responses in request order
+///
+/// A failed request yields a `DataResponse::Error` in its slot and the rest
+/// still run, except after a cancellation, which fails the remainder too.
+/// Nested batches are rejected.
+pub async fn run_batch<'a, F, Fut>(requests: &'a [DataRequest], mut run: F) -> Vec<DataResponse>
+where
+    F: FnMut(&'a DataRequest) -> Fut,
+    Fut: Future<Output = Result<DataResponse, ErrorInfo>>,
+{
+    let mut responses = Vec::with_capacity(requests.len());
+    let mut cancelled = None;
+    for req in requests {
+        let result = match (req, &cancelled) {
+            (_, Some(error)) => Err(ErrorInfo::clone(error)),
+            (DataRequest::Batch { .. }, None) => Err(ErrorInfo {
+                code: ErrorCode::InvalidQuery,
+                message: "Batches cannot be nested".to_string(),
+                details: None,
+            }),
+            (_, None) => run(req).await,
+        };
+        responses.push(match result {
+            Ok(response) => response,
+            Err(error) => {
+                if error.code == ErrorCode::Cancelled {
+                    cancelled = Some(error.clone());
+                }
+                DataResponse::Error(error)
+            }
+        });
+    }
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use playground_types::data::{
+        DataFormat, LoadRequest, LoadResult, QueryRequest, QueryResult, TableKind, TableSchema,
+    };
+
+    #[test]
+    fn failed_entry_keeps_its_slot() {
+        let requests = vec![
+            DataRequest::Load(LoadRequest {
+                name: "aadt".to_string(),
+                url: "/data/aadt.parquet".to_string(),
+                format: DataFormat::Parquet,
+            }),
+            DataRequest::Query(QueryRequest::new("SELECT * FROM aadt")),
+            DataRequest::Query(QueryRequest::new("SELECT * FROM missing")),
+        ];
+        let responses = block_on(run_batch(&requests, |req| async move {
+            match req {
+                DataRequest::Load(load) => Ok(DataResponse::Load(LoadResult {
+                    name: load.name.clone(),
+                    row_count: 3,
+                    schema: TableSchema {
+                        name: load.name.clone(),
+                        columns: Vec::new(),
+                        row_count: 3,
+                        kind: TableKind::Loaded,
+                    },
+                })),
+                DataRequest::Query(query) if query.sql.contains("missing") => Err(ErrorInfo {
+                    code: ErrorCode::NotFound,
+                    message: "Table with name missing does not exist".to_string(),
+                    details: None,
+                }),
+                DataRequest::Query(_) => Ok(DataResponse::Query(QueryResult::page(
+                    Vec::new(),
+                    Vec::new(),
+                    0,
+                    0,
+                ))),
+                _ => unreachable!(),
+            }
+        }));
+
+        assert_eq!(responses.len(), 3);
+        assert!(matches!(responses[0], DataResponse::Load(_)));
+        assert!(matches!(responses[1], DataResponse::Query(_)));
+        assert!(matches!(
+            &responses[2],
+            DataResponse::Error(error) if error.code == ErrorCode::NotFound
+        ));
+    }
+
+    #[test]
+    fn cancellation_fails_the_rest() {
+        let requests = vec![
+            DataRequest::Query(QueryRequest::new("SELECT 1")),
+            DataRequest::Query(QueryRequest::new("SELECT 2")),
+            DataRequest::Batch {
+                requests: Vec::new(),
+            },
+        ];
+        let mut calls = 0;
+        let responses = block_on(run_batch(&requests, |_| {
+            calls += 1;
+            async {
+                Err(ErrorInfo {
+                    code: ErrorCode::Cancelled,
+                    message: "Query was cancelled".to_string(),
+                    details: None,
+                })
+            }
+        }));
+        assert_eq!(calls, 1);
+        assert!(responses
+            .iter()
+            .all(|r| matches!(r, DataResponse::Error(e) if e.code == ErrorCode::Cancelled)));
+    }
+}
@@ -0,0 +1,125 @@
+//! LRU cache of recent query results
+
+use playground_types::data::{QueryRequest, QueryResult};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+
+/// Default number of results kept
+pub const DEFAULT_CAPACITY: usize = 32;
+
+/// Hash of everything that determines a query's result
+pub fn query_key(req: &QueryRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    req.sql.trim().hash(&mut hasher);
+    req.limit.hash(&mut hasher);
+    req.offset.hash(&mut hasher);
+    serde_json::to_string(&req.params)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    req.timezone.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Least-recently-used results, most recent at the front
+pub struct QueryCache {
+    capacity: usize,
+    entries: VecDeque<(u64, QueryResult)>,
+    /// Bumped on every `clear`, so results of queries that were running
+    /// during an invalidation are not stored
+    generation: u64,
+}
+
+impl QueryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+            generation: 0,
+        }
+    }
+
+    /// Current generation, to pass back to `insert`
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Look up a result and mark it most recently used
+    pub fn get(&mut self, key: u64) -> Option<QueryResult> {
+        let index = self.entries.iter().position(|(k, _)| *k == key)?;
+        let entry = self.entries.remove(index)?;
+        let result = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(result)
+    }
+
+    /// Store a result computed during `generation`, evicting the least
+    /// recently used beyond capacity
+    pub fn insert(&mut self, key: u64, result: QueryResult, generation: u64) {
+        if generation != self.generation {
+            return;
+        }
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push_front((key, result));
+        self.entries.truncate(self.capacity);
+    }
+
+    /// Change the capacity; 0 disables caching
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.entries.truncate(capacity);
+    }
+
+    /// Drop every cached result
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(total_rows: u64) -> QueryResult {
+        QueryResult {
+            query_id: String::new(),
+            columns: Vec::new(),
+            rows: Vec::new(),
+            total_rows,
+            truncated: false,
+            cached: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = QueryCache::new(2);
+        cache.insert(1, result(1), 0);
+        cache.insert(2, result(2), 0);
+        // Touch 1 so 2 becomes the eviction candidate
+        assert!(cache.get(1).is_some());
+        cache.insert(3, result(3), 0);
+
+        assert!(cache.get(2).is_none());
+        assert_eq!(cache.get(1).unwrap().total_rows, 1);
+        assert_eq!(cache.get(3).unwrap().total_rows, 3);
+    }
+
+    #[test]
+    fn zero_capacity_disables_cache() {
+        let mut cache = QueryCache::new(0);
+        cache.insert(1, result(1), 0);
+        assert!(cache.get(1).is_none());
+    }
+
+    #[test]
+    fn skips_results_from_before_clear() {
+        let mut cache = QueryCache::new(2);
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert(1, result(1), generation);
+        assert!(cache.get(1).is_none());
+    }
+}
@@ -0,0 +1,185 @@
+//! A DuckDB connection with interrupt support
+//!
+//! Queries go through `send` so a pending query can be interrupted with
+//! `cancelSent`; cancellation is also checked between record batches and
+//! before prepared statements run.
+
+use crate::convert::to_json_value;
+use crate::duckdb::{
+    table_to_ipc, ArrowRow, ArrowTable, AsyncDuckDB, AsyncDuckDBConnection, AsyncPreparedStatement,
+    RecordBatch, RecordBatchReader,
+};
+use crate::error::{cancelled_error, query_error};
+use crate::params::Param;
+use crate::schema::column_schema;
+use playground_types::data::ColumnSchema;
+use playground_types::messages::ErrorInfo;
+use serde_json::Value;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// A connection to the database
+pub struct Connection {
+    conn: AsyncDuckDBConnection,
+    cancelled: Rc<Cell<bool>>,
+}
+
+/// Handle for interrupting whatever a connection is running
+#[derive(Clone)]
+pub struct InterruptHandle {
+    conn: AsyncDuckDBConnection,
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl InterruptHandle {
+    /// Cancel the pending statement and fail any further reads
+    pub async fn interrupt(&self) {
+        self.cancelled.set(true);
+        self.conn.cancel_sent().await.ok();
+    }
+}
+
+impl Connection {
+    /// Open a new connection
+    pub async fn open(db: &AsyncDuckDB) -> Result<Self, ErrorInfo> {
+        let conn = db.connect().await.map_err(query_error)?;
+        Ok(Self {
+            conn: conn.unchecked_into(),
+            cancelled: Rc::new(Cell::new(false)),
+        })
+    }
+
+    /// Handle that can interrupt this connection from another task
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            conn: self.conn.clone(),
+            cancelled: self.cancelled.clone(),
+        }
+    }
+
+    /// Start a statement and return a reader over its result
+    pub async fn send(&self, sql: &str) -> Result<RecordBatchReader, ErrorInfo> {
+        log::debug!("query: {}", sql);
+        if self.cancelled.get() {
+            return Err(cancelled_error());
+        }
+        let reader = self.conn.send(sql).await.map_err(|e| self.error(e))?;
+        Ok(reader.unchecked_into())
+    }
+
+    /// Run a statement with bound parameters, discarding any result
+    pub async fn execute(&self, sql: &str, params: &[Param]) -> Result<(), ErrorInfo> {
+        log::debug!("prepared: {}", sql);
+        if self.cancelled.get() {
+            return Err(cancelled_error());
+        }
+        let stmt: AsyncPreparedStatement = self
+            .conn
+            .prepare(sql)
+            .await
+            .map_err(|e| self.error(e))?
+            .unchecked_into();
+        let values: js_sys::Array = params.iter().map(Param::to_js).collect();
+        let result = stmt.query(&values).await.map_err(|e| self.error(e));
+        stmt.close().await.ok();
+        result.map(|_| ())
+    }
+
+    /// Read the next record batch, or `None` when the result is exhausted
+    pub async fn next_batch(
+        &self,
+        reader: &RecordBatchReader,
+    ) -> Result<Option<RecordBatch>, ErrorInfo> {
+        if self.cancelled.get() {
+            reader.cancel().await.ok();
+            return Err(cancelled_error());
+        }
+        let next: js_sys::IteratorNext = reader
+            .next()
+            .await
+            .map_err(|e| self.error(e))?
+            .unchecked_into();
+        if next.done() {
+            Ok(None)
+        } else {
+            Ok(Some(next.value().unchecked_into()))
+        }
+    }
+
+    /// Run a statement and collect all result rows (`StructRowProxy` objects)
+    pub async fn query(&self, sql: &str) -> Result<js_sys::Array, ErrorInfo> {
+        self.query_with_progress(sql, |_| {}).await
+    }
+
+    /// Like `query`, calling `on_rows` with the running row count after each batch
+    pub async fn query_with_progress(
+        &self,
+        sql: &str,
+        mut on_rows: impl FnMut(u64),
+    ) -> Result<js_sys::Array, ErrorInfo> {
+        let reader = self.send(sql).await?;
+        let rows = js_sys::Array::new();
+        while let Some(batch) = self.next_batch(&reader).await? {
+            for row in batch.to_array().iter() {
+                rows.push(&row);
+            }
+            on_rows(rows.length() as u64);
+        }
+        Ok(rows)
+    }
+
+    /// Run a statement and serialize its result as an Arrow IPC stream
+    pub async fn query_ipc(&self, sql: &str) -> Result<Vec<u8>, ErrorInfo> {
+        let reader = self.send(sql).await?;
+        let batches = js_sys::Array::new();
+        while let Some(batch) = self.next_batch(&reader).await? {
+            batches.push(&batch);
+        }
+        Ok(table_to_ipc(&ArrowTable::new(&batches), "stream").to_vec())
+    }
+
+    /// Run a query returning a single value
+    pub async fn scalar(&self, sql: &str) -> Result<Value, ErrorInfo> {
+        let row = self.query(sql).await?.get(0);
+        if row.is_undefined() {
+            return Ok(Value::Null);
+        }
+        let row = row.unchecked_into::<ArrowRow>().to_json();
+        Ok(to_json_value(&js_sys::Object::values(&row).get(0)))
+    }
+
+    /// Infer column schemas for a query or table via `DESCRIBE`
+    pub async fn describe(&self, target: &str) -> Result<Vec<ColumnSchema>, ErrorInfo> {
+        let rows = self.query(&format!("DESCRIBE {}", target)).await?;
+        let columns = rows
+            .iter()
+            .map(|row| {
+                let row = row.unchecked_into::<ArrowRow>().to_json();
+                let field = |key: &str| {
+                    js_sys::Reflect::get(&row, &JsValue::from_str(key))
+                        .ok()
+                        .and_then(|v| v.as_string())
+                        .unwrap_or_default()
+                };
+                column_schema(field("column_name"), &field("column_type"), &field("null"))
+            })
+            .collect();
+        Ok(columns)
+    }
+
+    /// Close the connection
+    pub async fn close(self) {
+        self.conn.close().await.ok();
+    }
+
+    /// Classify an error, reporting interrupted statements as cancelled
+    fn error(&self, err: JsValue) -> ErrorInfo {
+        if self.cancelled.get() {
+            cancelled_error()
+        } else {
+            query_error(err)
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Conversions between JS values and Rust/JSON values
+
+use crate::duckdb::ArrowRow;
+use playground_types::data::ColumnSchema;
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Deserialize a request object passed in from JS
+pub fn from_js<T: DeserializeOwned>(value: &JsValue) -> Result<T, ErrorInfo> {
+    let json = js_sys::JSON::stringify(value).map_err(|_| ErrorInfo {
+        code: ErrorCode::ParseError,
+        message: "Request is not JSON-serializable".to_string(),
+        details: None,
+    })?;
+    serde_json::from_str(&String::from(json)).map_err(|e| ErrorInfo {
+        code: ErrorCode::ParseError,
+        message: "Invalid request".to_string(),
+        details: Some(e.to_string()),
+    })
+}
+
+/// Serialize a response into a plain JS object
+pub fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    js_sys::JSON::parse(&json)
+}
+
+/// Convert a single Arrow cell value into JSON
+///
+/// Int64 columns arrive as `BigInt`, which `JSON.stringify` rejects, so they
+/// are converted explicitly. Nested values fall back to their JSON form.
+pub fn to_json_value(value: &JsValue) -> Value {
+    if value.is_null() || value.is_undefined() {
+        return Value::Null;
+    }
+    if let Some(b) = value.as_bool() {
+        return Value::Bool(b);
+    }
+    if let Some(n) = value.as_f64() {
+        return serde_json::Number::from_f64(n)
+            .map(Value::Number)
+            .unwrap_or(Value::Null);
+    }
+    if let Some(s) = value.as_string() {
+        return Value::String(s);
+    }
+    if let Some(big) = value.dyn_ref::<js_sys::BigInt>() {
+        let text = big.to_string(10).map(String::from).unwrap_or_default();
+        return text
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| text.parse::<u64>().map(Value::from))
+            .unwrap_or(Value::String(text));
+    }
+    js_sys::JSON::stringify(value)
+        .ok()
+        .and_then(|s| serde_json::from_str(&String::from(s)).ok())
+        .unwrap_or(Value::Null)
+}
+
+/// Read one column of an Arrow row proxy as JSON
+pub fn row_value(row: &JsValue, key: &str) -> Value {
+    let row = row.unchecked_ref::<ArrowRow>().to_json();
+    js_sys::Reflect::get(&row, &JsValue::from_str(key))
+        .map(|v| to_json_value(&v))
+        .unwrap_or(Value::Null)
+}
+
+/// Convert Arrow row proxies into JSON rows ordered by `columns`
+pub fn table_rows(rows: &js_sys::Array, columns: &[ColumnSchema]) -> Vec<Vec<Value>> {
+    let keys: Vec<JsValue> = columns.iter().map(|c| JsValue::from_str(&c.name)).collect();
+    rows.iter()
+        .map(|row| {
+            let row = row.unchecked_into::<ArrowRow>().to_json();
+            keys.iter()
+                .map(|key| {
+                    js_sys::Reflect::get(&row, key)
+                        .map(|v| to_json_value(&v))
+                        .unwrap_or(Value::Null)
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,65 @@
+//! Query cursors for pulling large results in batches
+
+use crate::connection::Connection;
+use crate::convert::table_rows;
+use crate::duckdb::RecordBatchReader;
+use playground_types::data::{ColumnSchema, QueryResultBatch};
+use playground_types::messages::ErrorInfo;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+/// An open streamed result
+///
+/// Each cursor owns its connection, since DuckDB only keeps one streaming
+/// result alive per connection.
+pub struct QueryCursor {
+    conn: Connection,
+    reader: RecordBatchReader,
+    columns: Vec<ColumnSchema>,
+    /// Rows read from Arrow but not yet returned
+    buffered: VecDeque<Vec<Value>>,
+    batch_index: u32,
+    exhausted: bool,
+}
+
+impl QueryCursor {
+    pub fn new(conn: Connection, reader: RecordBatchReader, columns: Vec<ColumnSchema>) -> Self {
+        Self {
+            conn,
+            reader,
+            columns,
+            buffered: VecDeque::new(),
+            batch_index: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Pull up to `batch_size` rows, reading record batches as needed
+    pub async fn next_batch(&mut self, batch_size: u32) -> Result<QueryResultBatch, ErrorInfo> {
+        let batch_size = batch_size.max(1) as usize;
+        while self.buffered.len() < batch_size && !self.exhausted {
+            match self.conn.next_batch(&self.reader).await? {
+                Some(batch) => self
+                    .buffered
+                    .extend(table_rows(&batch.to_array(), &self.columns)),
+                None => self.exhausted = true,
+            }
+        }
+
+        let take = batch_size.min(self.buffered.len());
+        let batch = QueryResultBatch {
+            columns: self.columns.clone(),
+            rows: self.buffered.drain(..take).collect(),
+            batch_index: self.batch_index,
+            is_last: self.exhausted && self.buffered.is_empty(),
+        };
+        self.batch_index += 1;
+        Ok(batch)
+    }
+
+    /// Release the underlying Arrow stream and connection
+    pub async fn close(self) {
+        self.reader.cancel().await.ok();
+        self.conn.close().await;
+    }
+}
@@ -0,0 +1,273 @@
+//! Date-range filters applied around a query
+
+use crate::schema::quote_ident;
+use crate::sql::subquery;
+use playground_types::data::DateRangeParam;
+use playground_types::messages::{ErrorCode, ErrorInfo};
+
+/// Filter the rows of `sql` to `range`
+///
+/// The query is wrapped as a subquery so the filter works regardless of
+/// its own `WHERE`, `GROUP BY` or `LIMIT`. A date-only `end` covers the
+/// whole day when the column holds timestamps.
+pub fn apply_date_range(sql: &str, range: &DateRangeParam) -> Result<String, ErrorInfo> {
+    let start = range.start.as_deref().map(date_literal).transpose()?;
+    let end = range.end.as_deref().map(date_literal).transpose()?;
+    if let (Some(start), Some(end)) = (&start, &end) {
+        if start.is_after(end) {
+            return Err(invalid_range(format!(
+                "Date range starts after it ends: {} > {}",
+                start.value, end.value
+            )));
+        }
+    }
+
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    let column = quote_ident(&range.column);
+    let mut conditions = Vec::new();
+    if let Some(start) = start {
+        conditions.push(format!("{} >= {}", column, start.sql()));
+    }
+    if let Some(end) = end {
+        conditions.push(match end.kind {
+            LiteralKind::Date => format!("{} < {} + INTERVAL 1 DAY", column, end.sql()),
+            LiteralKind::Timestamp => format!("{} <= {}", column, end.sql()),
+        });
+    }
+    if conditions.is_empty() {
+        return Ok(sql.to_string());
+    }
+    Ok(format!(
+        "SELECT * FROM {} AS _date_range WHERE {}",
+        subquery(sql),
+        conditions.join(" AND ")
+    ))
+}
+
+#[derive(Debug, PartialEq)]
+enum LiteralKind {
+    Date,
+    Timestamp,
+}
+
+/// A validated bound, normalized to `YYYY-MM-DD[ HH:MM:SS[.fff]]`
+#[derive(Debug)]
+struct DateLiteral {
+    kind: LiteralKind,
+    /// Year, month and day
+    date: [u32; 3],
+    value: String,
+}
+
+impl DateLiteral {
+    /// Whether this start bound comes after the `end` bound
+    ///
+    /// A date-only bound covers its whole day, so it overlaps any time on
+    /// the same day.
+    fn is_after(&self, end: &DateLiteral) -> bool {
+        match self.date.cmp(&end.date) {
+            std::cmp::Ordering::Equal => {
+                self.kind == LiteralKind::Timestamp
+                    && end.kind == LiteralKind::Timestamp
+                    && self.value > end.value
+            }
+            ordering => ordering.is_gt(),
+        }
+    }
+
+    fn sql(&self) -> String {
+        match self.kind {
+            LiteralKind::Date => format!("DATE '{}'", self.value),
+            LiteralKind::Timestamp => format!("TIMESTAMP '{}'", self.value),
+        }
+    }
+}
+
+/// Parse an ISO-8601 date or date-time without a UTC offset
+fn date_literal(text: &str) -> Result<DateLiteral, ErrorInfo> {
+    let invalid = || {
+        invalid_range(format!(
+            "Invalid date '{}': expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS",
+            text
+        ))
+    };
+    let (date, time) = match text.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (text, None),
+    };
+
+    let [year, month, day] = fields(date, '-', [4, 2, 2]).ok_or_else(invalid)?;
+    let ymd = [year, month, day];
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    let Some(time) = time else {
+        return Ok(DateLiteral {
+            kind: LiteralKind::Date,
+            date: ymd,
+            value: date.to_string(),
+        });
+    };
+
+    let (clock, fraction) = match time.split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (time, None),
+    };
+    let (hour, minute, second) = match fields(clock, ':', [2, 2, 2]) {
+        Some([hour, minute, second]) => (hour, minute, second),
+        None => {
+            let [hour, minute] = fields(clock, ':', [2, 2]).ok_or_else(invalid)?;
+            if fraction.is_some() {
+                return Err(invalid());
+            }
+            (hour, minute, 0)
+        }
+    };
+    if hour > 23 || minute > 59 || second > 59 {
+        return Err(invalid());
+    }
+    let mut value = format!("{} {:02}:{:02}:{:02}", date, hour, minute, second);
+    if let Some(fraction) = fraction {
+        if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        value.push('.');
+        value.push_str(fraction);
+    }
+    Ok(DateLiteral {
+        kind: LiteralKind::Timestamp,
+        date: ymd,
+        value,
+    })
+}
+
+/// Split `text` on `sep` into all-digit fields of exactly the given widths
+fn fields<const N: usize>(text: &str, sep: char, widths: [usize; N]) -> Option<[u32; N]> {
+    let mut parts = text.split(sep);
+    let mut values = [0; N];
+    for (value, width) in values.iter_mut().zip(widths) {
+        let part = parts.next()?;
+        if part.len() != width || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        *value = part.parse().ok()?;
+    }
+    parts.next().is_none().then_some(values)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn invalid_range(message: String) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::InvalidQuery,
+        message,
+        details: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: Option<&str>, end: Option<&str>) -> DateRangeParam {
+        DateRangeParam {
+            column: "vote_date".to_string(),
+            start: start.map(str::to_string),
+            end: end.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn start_only_range_is_open_ended() {
+        let sql =
+            apply_date_range("SELECT * FROM ballots;", &range(Some("2024-11-05"), None)).unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM (\nSELECT * FROM ballots\n) AS _date_range \
+             WHERE \"vote_date\" >= DATE '2024-11-05'"
+        );
+    }
+
+    #[test]
+    fn date_end_covers_whole_day() {
+        let sql = apply_date_range(
+            "SELECT * FROM crashes",
+            &range(Some("2024-01-01T06:30"), Some("2024-01-31")),
+        )
+        .unwrap();
+        assert!(sql.ends_with(
+            "WHERE \"vote_date\" >= TIMESTAMP '2024-01-01 06:30:00' \
+             AND \"vote_date\" < DATE '2024-01-31' + INTERVAL 1 DAY"
+        ));
+    }
+
+    #[test]
+    fn empty_range_leaves_query_unchanged() {
+        let sql = apply_date_range("SELECT 1", &range(None, None)).unwrap();
+        assert_eq!(sql, "SELECT 1");
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for bad in [
+            "2024-02-30",
+            "2023-02-29",
+            "2024-13-01",
+            "11/05/2024",
+            "2024-11-05' OR 1=1 --",
+            "2024-11-05T25:00:00",
+            "2024-11-05T07:00:00Z",
+        ] {
+            let err = apply_date_range("SELECT 1", &range(Some(bad), None)).unwrap_err();
+            assert_eq!(err.code, ErrorCode::InvalidQuery, "{}", bad);
+        }
+        assert!(apply_date_range("SELECT 1", &range(Some("2024-02-29"), None)).is_ok());
+    }
+
+    #[test]
+    fn rejects_reversed_range() {
+        let err = apply_date_range("SELECT 1", &range(Some("2024-12-01"), Some("2024-01-01")))
+            .unwrap_err();
+        assert!(err.message.contains("starts after it ends"));
+
+        let err = apply_date_range(
+            "SELECT 1",
+            &range(Some("2024-01-01T06:30"), Some("2024-01-01T06:00")),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("starts after it ends"));
+    }
+
+    #[test]
+    fn same_day_bounds_are_not_reversed() {
+        for (start, end) in [
+            ("2024-01-01T06:30", "2024-01-01"),
+            ("2024-01-01", "2024-01-01T06:30"),
+            ("2024-01-01T06:30", "2024-01-01T06:30:00.5"),
+        ] {
+            assert!(
+                apply_date_range("SELECT 1", &range(Some(start), Some(end))).is_ok(),
+                "{start}..{end}"
+            );
+        }
+    }
+
+    #[test]
+    fn wrapping_survives_trailing_comment() {
+        let sql = apply_date_range(
+            "SELECT * FROM ballots -- all precincts",
+            &range(Some("2024-11-05"), None),
+        )
+        .unwrap();
+        assert!(sql.contains("-- all precincts\n) AS _date_range"), "{sql}");
+    }
+}
@@ -0,0 +1,120 @@
+//! Bindings to the DuckDB-WASM JavaScript API
+//!
+//! Only the parts of `@duckdb/duckdb-wasm` used by the engine are bound here.
+//! The database itself is instantiated on the JS side and handed to
+//! [`crate::DataEngine`].
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    /// `duckdb.AsyncDuckDB` database instance
+    #[derive(Clone, Debug)]
+    pub type AsyncDuckDB;
+
+    /// Open a new connection to the database
+    #[wasm_bindgen(method, catch)]
+    pub async fn connect(this: &AsyncDuckDB) -> Result<JsValue, JsValue>;
+
+    /// Register an in-memory buffer as a virtual file
+    #[wasm_bindgen(method, catch, js_name = registerFileBuffer)]
+    pub async fn register_file_buffer(
+        this: &AsyncDuckDB,
+        name: &str,
+        buffer: js_sys::Uint8Array,
+    ) -> Result<JsValue, JsValue>;
+
+    /// Create an empty in-memory file for `COPY ... TO` to write into
+    #[wasm_bindgen(method, catch, js_name = registerEmptyFileBuffer)]
+    pub async fn register_empty_file_buffer(
+        this: &AsyncDuckDB,
+        name: &str,
+    ) -> Result<JsValue, JsValue>;
+
+    /// Read a virtual file's contents (a `Uint8Array`)
+    #[wasm_bindgen(method, catch, js_name = copyFileToBuffer)]
+    pub async fn copy_file_to_buffer(this: &AsyncDuckDB, name: &str) -> Result<JsValue, JsValue>;
+
+    /// Remove a registered virtual file
+    #[wasm_bindgen(method, catch, js_name = dropFile)]
+    pub async fn drop_file(this: &AsyncDuckDB, name: &str) -> Result<JsValue, JsValue>;
+
+    /// `duckdb.AsyncDuckDBConnection`
+    #[derive(Clone, Debug)]
+    pub type AsyncDuckDBConnection;
+
+    /// Run a query and stream the result as Arrow record batches
+    #[wasm_bindgen(method, catch)]
+    pub async fn send(this: &AsyncDuckDBConnection, text: &str) -> Result<JsValue, JsValue>;
+
+    /// Prepare a statement with `?` placeholders
+    #[wasm_bindgen(method, catch)]
+    pub async fn prepare(this: &AsyncDuckDBConnection, text: &str) -> Result<JsValue, JsValue>;
+
+    /// Cancel the query started with `send`, if it is still pending
+    #[wasm_bindgen(method, catch, js_name = cancelSent)]
+    pub async fn cancel_sent(this: &AsyncDuckDBConnection) -> Result<JsValue, JsValue>;
+
+    /// Close the connection
+    #[wasm_bindgen(method, catch)]
+    pub async fn close(this: &AsyncDuckDBConnection) -> Result<JsValue, JsValue>;
+
+    /// `duckdb.AsyncPreparedStatement`
+    #[derive(Clone, Debug)]
+    pub type AsyncPreparedStatement;
+
+    /// Run the statement with one value per placeholder
+    #[wasm_bindgen(method, catch, variadic)]
+    pub async fn query(
+        this: &AsyncPreparedStatement,
+        params: &js_sys::Array,
+    ) -> Result<JsValue, JsValue>;
+
+    /// Release the prepared statement
+    #[wasm_bindgen(method, catch)]
+    pub async fn close(this: &AsyncPreparedStatement) -> Result<JsValue, JsValue>;
+
+    /// `AsyncRecordBatchStreamReader` over a streamed result
+    #[derive(Clone, Debug)]
+    pub type RecordBatchReader;
+
+    /// Read the next record batch (an `IteratorResult`)
+    #[wasm_bindgen(method, catch)]
+    pub async fn next(this: &RecordBatchReader) -> Result<JsValue, JsValue>;
+
+    /// Stop reading and release the stream
+    #[wasm_bindgen(method, catch)]
+    pub async fn cancel(this: &RecordBatchReader) -> Result<JsValue, JsValue>;
+
+    /// Apache Arrow `RecordBatch`
+    #[derive(Clone, Debug)]
+    pub type RecordBatch;
+
+    /// Rows as `StructRowProxy` objects
+    #[wasm_bindgen(method, js_name = toArray)]
+    pub fn to_array(this: &RecordBatch) -> js_sys::Array;
+
+    /// A single Arrow row (`StructRowProxy`)
+    #[derive(Clone, Debug)]
+    pub type ArrowRow;
+
+    /// Convert the row proxy into a plain object keyed by column name
+    #[wasm_bindgen(method, js_name = toJSON)]
+    pub fn to_json(this: &ArrowRow) -> js_sys::Object;
+}
+
+// Apache Arrow JS, which DuckDB-WASM results are built on
+#[wasm_bindgen(module = "apache-arrow")]
+extern "C" {
+    /// Apache Arrow `Table`
+    #[wasm_bindgen(js_name = Table)]
+    pub type ArrowTable;
+
+    /// Table over record batches that share a schema
+    #[wasm_bindgen(constructor, js_class = "Table")]
+    pub fn new(batches: &js_sys::Array) -> ArrowTable;
+
+    /// Serialize a table in the IPC `"stream"` or `"file"` format
+    #[wasm_bindgen(js_name = tableToIPC)]
+    pub fn table_to_ipc(table: &ArrowTable, format: &str) -> js_sys::Uint8Array;
+}
@@ -0,0 +1,1636 @@
+//! DataEngine - query execution against a DuckDB-WASM connection
+
+use crate::arrow::arrow_result;
+use crate::autocomplete::{suggest, CatalogTable};
+use crate::batch::run_batch;
+use crate::cache::{query_key, QueryCache, DEFAULT_CAPACITY};
+use crate::connection::{Connection, InterruptHandle};
+use crate::convert::{from_js, row_value, table_rows, to_js};
+use crate::cursor::QueryCursor;
+use crate::duckdb::{ArrowRow, AsyncDuckDB};
+use crate::error::{error_message, into_js_error, query_error};
+use crate::explain::{error_position, parse_plan};
+use crate::export::{copy_statement, export_file, exported_bytes};
+use crate::fetch::fetch_bytes;
+use crate::guard::check_plan;
+use crate::histogram::Bins;
+use crate::normalize::normalize_result;
+use crate::params::bind_params;
+use crate::policy::{check_sql, check_table_name, ENGINE_FILE_DIR, LOCK_EXTERNAL_ACCESS};
+use crate::progress::ProgressReporter;
+use crate::relationships::{apply_overlap, infer_relationships, overlap_ratio, overlap_sql};
+use crate::retry::with_retry;
+use crate::sample::sample_plan;
+use crate::schema::{column_type, quote_ident, quote_literal, schema_mismatch, select_list};
+use crate::settings::{
+    check_memory_limit, clamp_settings, settings_statements, MEMORY_LIMIT_QUERY,
+};
+use crate::sql::{leading_keyword, split_statements, subquery};
+use crate::staged::schema_first;
+use crate::timeout::{sleep, timeout_ms, with_timeout};
+use crate::timezone::{invalid_timezone, set_timezone_sql};
+use crate::view::{create_view_sql, table_kind};
+use playground_types::chart::{ChartData, DataValues, Dataset};
+use playground_types::data::{
+    ArrowResult, ColumnStats, ColumnType, ConfigureResult, DataFormat, DataRequest, DataResponse,
+    DropRequest, DropResult, EngineSettings, ExplainResult, ExportDataFormat, ExportedBytes,
+    HistogramRequest, ListTablesResult, LoadRequest, LoadResult, QueryCursorHandle, QueryRequest,
+    QueryResult, RelationshipHint, SampleMethod, ScriptResult, SqlPolicy, StatementError,
+    StatementOutcome, TableKind, TableSchema, TableSummary, ValueCount,
+};
+use playground_types::editor::{AutocompleteRequest, AutocompleteResult};
+use playground_types::messages::{
+    generate_id, CancelRequest, ErrorCode, ErrorInfo, MessageId, MessageResult, Request, Response,
+};
+use playground_types::storage::{QueryGuard, QueryHistoryEntry, QueryPreferences, RetryPolicy};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::future::Future;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// SQL engine wrapping a DuckDB-WASM database
+///
+/// Loads share one connection. Each query runs on its own connection so it
+/// can be interrupted without affecting anything else.
+#[wasm_bindgen]
+pub struct DataEngine {
+    db: AsyncDuckDB,
+    conn: Connection,
+    /// Tables registered by `load`, keyed by name
+    tables: RefCell<HashMap<String, TableSummary>>,
+    /// Open streaming cursors
+    cursors: RefCell<HashMap<QueryCursorHandle, QueryCursor>>,
+    next_cursor: Cell<QueryCursorHandle>,
+    /// Suffix for the next export file name
+    next_export: Cell<u32>,
+    /// Queries started through `handle`, keyed by request ID
+    in_flight: RefCell<HashMap<MessageId, InterruptHandle>>,
+    /// Recent results, invalidated whenever a table is loaded or dropped
+    query_cache: RefCell<QueryCache>,
+    /// Receives `QueryProgress` events for queries started through `handle`
+    progress_callback: RefCell<Option<js_sys::Function>>,
+    /// Receives early responses, such as `SchemaReady`, for requests
+    /// started through `handle`
+    response_callback: RefCell<Option<js_sys::Function>>,
+    /// Default query timeout in seconds, 0 for none
+    timeout_seconds: Cell<u32>,
+    /// Receives a `QueryHistoryEntry` after each query
+    history_callback: RefCell<Option<js_sys::Function>>,
+    /// Limits checked before each query runs, if enabled
+    query_guard: RefCell<Option<QueryGuard>>,
+    /// Backoff for retrying data file fetches
+    retry_policy: RefCell<RetryPolicy>,
+    /// What user SQL may do, if restricted
+    sql_policy: RefCell<Option<SqlPolicy>>,
+    /// Set once DuckDB's external access is locked
+    external_access_locked: Cell<bool>,
+    /// Distinguishes this engine's database from earlier ones
+    session_id: MessageId,
+}
+
+#[wasm_bindgen]
+impl DataEngine {
+    /// Create an engine with a new connection to an instantiated database
+    pub async fn connect(db: AsyncDuckDB) -> Result<DataEngine, JsValue> {
+        let conn = Connection::open(&db).await.map_err(into_js_error)?;
+        Ok(DataEngine {
+            db,
+            conn,
+            tables: RefCell::new(HashMap::new()),
+            cursors: RefCell::new(HashMap::new()),
+            next_cursor: Cell::new(1),
+            next_export: Cell::new(1),
+            in_flight: RefCell::new(HashMap::new()),
+            query_cache: RefCell::new(QueryCache::new(DEFAULT_CAPACITY)),
+            progress_callback: RefCell::new(None),
+            response_callback: RefCell::new(None),
+            timeout_seconds: Cell::new(QueryPreferences::default().timeout_seconds),
+            history_callback: RefCell::new(None),
+            query_guard: RefCell::new(None),
+            retry_policy: RefCell::new(RetryPolicy::default()),
+            sql_policy: RefCell::new(Some(SqlPolicy::default())),
+            external_access_locked: Cell::new(false),
+            session_id: generate_id(),
+        })
+    }
+
+    /// ID of this engine's in-memory database, for
+    /// `Notebook::stale_cells`; a new engine, e.g. after a page reload,
+    /// starts with an empty database and a new ID
+    #[wasm_bindgen(js_name = sessionId)]
+    pub fn session_id(&self) -> String {
+        self.session_id.clone()
+    }
+
+    /// Register a function to receive a `QueryHistoryEntry` after each
+    /// query, e.g. to forward as `StorageCommand::RecordQuery`, or clear it
+    #[wasm_bindgen(js_name = setHistoryCallback)]
+    pub fn set_history_callback(&self, callback: Option<js_sys::Function>) {
+        *self.history_callback.borrow_mut() = callback;
+    }
+
+    /// Set the default query timeout from `QueryPreferences.timeout_seconds`
+    ///
+    /// Queries running longer are interrupted and fail with
+    /// `ErrorCode::Timeout`; 0 disables the timeout. A request's own
+    /// `timeout_seconds` takes precedence.
+    #[wasm_bindgen(js_name = setQueryTimeout)]
+    pub fn set_query_timeout(&self, seconds: u32) {
+        self.timeout_seconds.set(seconds);
+    }
+
+    /// Apply `QueryPreferences.guard`, or turn the guard off if
+    /// `guard_enabled` is unset
+    ///
+    /// While enabled, each query is explained first and rejected with
+    /// `ErrorCode::LimitExceeded` if its plan breaks one of the limits.
+    #[wasm_bindgen(js_name = setQueryGuard)]
+    pub fn set_query_guard(&self, preferences: JsValue) -> Result<(), JsValue> {
+        let preferences: QueryPreferences = from_js(&preferences).map_err(into_js_error)?;
+        *self.query_guard.borrow_mut() = preferences.guard_enabled.then_some(preferences.guard);
+        Ok(())
+    }
+
+    /// Set how data file loads retry, from `QueryPreferences.retry`
+    ///
+    /// Network failures and 5xx responses are retried with exponential
+    /// backoff; once the attempts run out the load fails with
+    /// `ErrorCode::NetworkError`.
+    #[wasm_bindgen(js_name = setRetryPolicy)]
+    pub fn set_retry_policy(&self, policy: JsValue) -> Result<(), JsValue> {
+        *self.retry_policy.borrow_mut() = from_js(&policy).map_err(into_js_error)?;
+        Ok(())
+    }
+
+    /// Restrict what user SQL may do to `SqlPolicy`, or lift the
+    /// restriction with `null`
+    ///
+    /// Queries, scripts, exports, cursors, views and the table names given
+    /// to `sample`, `profileColumn` and `histogram` are checked before they
+    /// run and fail with `ErrorCode::AuthError` if they break the policy.
+    /// Loads are not affected. The default policy is in force until this
+    /// is called.
+    ///
+    /// While the policy forbids file functions, DuckDB's external access
+    /// is locked before the first checked SQL runs, so file access can
+    /// only be granted by lifting the policy before then.
+    #[wasm_bindgen(js_name = setSqlPolicy)]
+    pub fn set_sql_policy(&self, policy: JsValue) -> Result<(), JsValue> {
+        let policy = if policy.is_null() || policy.is_undefined() {
+            None
+        } else {
+            Some(from_js(&policy).map_err(into_js_error)?)
+        };
+        *self.sql_policy.borrow_mut() = policy;
+        Ok(())
+    }
+
+    /// Turn off DuckDB's access to files and the network for the rest of
+    /// the session now, rather than before the first checked query
+    ///
+    /// Unlike the SQL policy this is enforced by DuckDB itself. It can't
+    /// be undone. Loads and exports keep working, since the engine's own
+    /// files stay accessible.
+    #[wasm_bindgen(js_name = lockExternalAccess)]
+    pub async fn lock_external_access(&self) -> Result<(), JsValue> {
+        self.lock().await.map_err(into_js_error)
+    }
+
+    /// Register a function to receive `QueryProgress` events, or clear it
+    ///
+    /// Events are throttled to roughly one every 100ms per query.
+    #[wasm_bindgen(js_name = setProgressCallback)]
+    pub fn set_progress_callback(&self, callback: Option<js_sys::Function>) {
+        *self.progress_callback.borrow_mut() = callback;
+    }
+
+    /// Register a function to receive early `Response<DataResponse>`s, or
+    /// clear it
+    ///
+    /// A `Load` sent through `handle` first reports
+    /// `DataResponse::SchemaReady` here, under the request's ID, once the
+    /// file's columns are known. It always arrives before `handle`
+    /// resolves with the `Load` response.
+    #[wasm_bindgen(js_name = setResponseCallback)]
+    pub fn set_response_callback(&self, callback: Option<js_sys::Function>) {
+        *self.response_callback.borrow_mut() = callback;
+    }
+
+    /// Handle a `Request<DataRequest>` envelope and return a `Response<DataResponse>`
+    ///
+    /// Failures are reported in the response rather than by rejecting, so
+    /// the caller can always match it to its request ID.
+    pub async fn handle(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let started = js_sys::Date::now();
+        let req: Request<DataRequest> = from_js(&req).map_err(into_js_error)?;
+        let result = match self.dispatch(&req.id, &req.payload).await {
+            Ok(data) => MessageResult::ok(data),
+            Err(error) => MessageResult::Error { error },
+        };
+        let finished = js_sys::Date::now();
+        to_js(&Response {
+            id: req.id,
+            timestamp: finished as u64,
+            execution_time_ms: (finished - started) as u32,
+            result,
+        })
+    }
+
+    /// Interrupt the query started by the request in a `CancelRequest`
+    ///
+    /// The interrupted request resolves with an `ErrorCode::Cancelled`
+    /// error. Returns `false` if the request is not running.
+    pub async fn cancel(&self, req: JsValue) -> Result<bool, JsValue> {
+        let req: CancelRequest = from_js(&req).map_err(into_js_error)?;
+        let handle = self.in_flight.borrow().get(&req.target_id).cloned();
+        match handle {
+            Some(handle) => {
+                log::debug!("cancelling request {}", req.target_id);
+                handle.interrupt().await;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Fetch a data file and register it as a table (`LoadRequest` -> `LoadResult`)
+    ///
+    /// Loading a name that is already registered replaces the earlier table.
+    pub async fn load(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let req: LoadRequest = from_js(&req).map_err(into_js_error)?;
+        let result = self.load_table(None, &req).await.map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Append the rows of another data file to a loaded table (`LoadResult`)
+    ///
+    /// The format is taken from the URL's extension. The file's columns
+    /// must match the table's, otherwise this fails with
+    /// `ErrorCode::ParseError` and the table is left as it was. The
+    /// result carries the table's new total `row_count`.
+    pub async fn append(&self, name: String, url: String) -> Result<JsValue, JsValue> {
+        let result = self
+            .append_table(&name, &url)
+            .await
+            .map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Drop a table or view to free its memory (`DropRequest` -> `DropResult`)
+    ///
+    /// Dropping a name that does not exist reports `freed: false`.
+    #[wasm_bindgen(js_name = dropTable)]
+    pub async fn drop_table(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let req: DropRequest = from_js(&req).map_err(into_js_error)?;
+        let result = self.drop_relation(&req.name).await.map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Define a view named `name` over `sql` (`TableSchema`)
+    ///
+    /// An existing view of that name is replaced. Fails with
+    /// `ErrorCode::InvalidQuery` if a loaded table has the name, or if
+    /// `sql` is not a single query.
+    #[wasm_bindgen(js_name = createView)]
+    pub async fn create_view(&self, name: String, sql: String) -> Result<JsValue, JsValue> {
+        self.check_policy(&sql).await.map_err(into_js_error)?;
+        let loaded = self.tables.borrow().contains_key(&name);
+        let statement = create_view_sql(&name, &sql, loaded).map_err(into_js_error)?;
+        self.conn.query(&statement).await.map_err(into_js_error)?;
+        self.query_cache.borrow_mut().clear();
+        let schema = self.table_schema(&name).await.map_err(into_js_error)?;
+        to_js(&schema)
+    }
+
+    /// Drop the view named `name` (`DropResult`)
+    ///
+    /// Tables are left alone: a name that isn't a view reports `freed: false`.
+    #[wasm_bindgen(js_name = dropView)]
+    pub async fn drop_view(&self, name: String) -> Result<JsValue, JsValue> {
+        let views = self.view_names().await.map_err(into_js_error)?;
+        let result = if views.contains(&name) {
+            self.drop_relation(&name).await.map_err(into_js_error)?
+        } else {
+            DropResult { name, freed: false }
+        };
+        to_js(&result)
+    }
+
+    /// Names of the views in the database, sorted (`string[]`)
+    #[wasm_bindgen(js_name = listViews)]
+    pub async fn list_views(&self) -> Result<JsValue, JsValue> {
+        let views = self.view_names().await.map_err(into_js_error)?;
+        to_js(&views)
+    }
+
+    /// Likely joins between the database's tables (`RelationshipHint[]`)
+    ///
+    /// Candidates come from column names and types; each is then scored by
+    /// how many sampled values the two columns share. Most likely first.
+    #[wasm_bindgen(js_name = suggestJoins)]
+    pub async fn suggest_joins(&self) -> Result<JsValue, JsValue> {
+        let tables = self.list_tables().await.map_err(into_js_error)?;
+        let mut hints = infer_relationships(&tables.tables);
+        let mut overlaps = Vec::with_capacity(hints.len());
+        for hint in &hints {
+            overlaps.push(self.value_overlap(hint).await);
+        }
+        apply_overlap(&mut hints, &overlaps);
+        to_js(&hints)
+    }
+
+    /// Summaries of loaded tables, sorted by name (`TableSummary[]`)
+    ///
+    /// Reads only what was recorded at load time; use `handle` with a
+    /// `schema` request for a table's columns.
+    #[wasm_bindgen(js_name = listTablesSummary)]
+    pub fn list_tables_summary(&self) -> Result<JsValue, JsValue> {
+        let mut tables: Vec<TableSummary> = self.tables.borrow().values().cloned().collect();
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        to_js(&tables)
+    }
+
+    /// Suggest completions for an `AutocompleteRequest` (`AutocompleteResult`)
+    pub async fn autocomplete(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let req: AutocompleteRequest = from_js(&req).map_err(into_js_error)?;
+        let catalog = self.catalog().await.map_err(into_js_error)?;
+        to_js(&AutocompleteResult {
+            suggestions: suggest(&req.text, req.cursor_position, &catalog),
+        })
+    }
+
+    /// Apply `EngineSettings` and return a `ConfigureResult`
+    ///
+    /// Out-of-range values are clamped, with a warning for each. The
+    /// settings apply to the whole database, including running queries.
+    /// The memory limit is read back to check DuckDB applied it.
+    pub async fn configure(&self, settings: JsValue) -> Result<JsValue, JsValue> {
+        let settings: EngineSettings = from_js(&settings).map_err(into_js_error)?;
+        let (applied, warnings) = clamp_settings(&settings);
+        for statement in settings_statements(&applied) {
+            self.conn.query(&statement).await.map_err(into_js_error)?;
+        }
+        if let Some(mb) = applied.memory_limit_mb {
+            let reported = self
+                .conn
+                .scalar(MEMORY_LIMIT_QUERY)
+                .await
+                .map_err(into_js_error)?;
+            check_memory_limit(mb, reported.as_str().unwrap_or_default()).map_err(into_js_error)?;
+        }
+        to_js(&ConfigureResult { applied, warnings })
+    }
+
+    /// Set how many recent query results are cached; 0 disables caching
+    #[wasm_bindgen(js_name = setQueryCacheCapacity)]
+    pub fn set_query_cache_capacity(&self, capacity: usize) {
+        self.query_cache.borrow_mut().set_capacity(capacity);
+    }
+
+    /// Drop all cached query results
+    #[wasm_bindgen(js_name = clearQueryCache)]
+    pub fn clear_query_cache(&self) {
+        self.query_cache.borrow_mut().clear();
+    }
+
+    /// Execute a `QueryRequest` and return a `QueryResult`
+    #[wasm_bindgen(js_name = executeQuery)]
+    pub async fn execute_query(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let req: QueryRequest = from_js(&req).map_err(into_js_error)?;
+        let result = self.run_query(None, &req).await.map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Execute a `QueryRequest` and return an `ArrowResult`
+    ///
+    /// Rows come back as an Arrow IPC stream for Arrow JS rather than
+    /// JSON. Offset and limit apply as for `executeQuery`; results are not
+    /// cached.
+    #[wasm_bindgen(js_name = executeArrow)]
+    pub async fn execute_arrow(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let req: QueryRequest = from_js(&req).map_err(into_js_error)?;
+        let result = self.arrow_query(None, &req).await.map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Execute a `QueryRequest`, binding `params` to its `?` placeholders
+    ///
+    /// Fails with `ErrorCode::InvalidQuery` if the number of params does not
+    /// match the placeholders. `executeQuery` also binds params when any are
+    /// given.
+    #[wasm_bindgen(js_name = executePrepared)]
+    pub async fn execute_prepared(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let req: QueryRequest = from_js(&req).map_err(into_js_error)?;
+        bind_params(strip_statement(&req.sql), &req.params).map_err(into_js_error)?;
+        let result = self.run_query(None, &req).await.map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Run a multi-statement script and return a `ScriptResult`
+    ///
+    /// Statements run in order on one connection; the first failure stops
+    /// the script. Row-returning statements use the default row limit.
+    #[wasm_bindgen(js_name = executeScript)]
+    pub async fn execute_script(&self, sql: String) -> Result<JsValue, JsValue> {
+        let result = self.run_script(&sql).await.map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Explain a `QueryRequest` and return an `ExplainResult`
+    ///
+    /// With `analyze` the query is executed and each plan node carries its
+    /// actual row count and timing.
+    pub async fn explain(&self, req: JsValue, analyze: bool) -> Result<JsValue, JsValue> {
+        let req: QueryRequest = from_js(&req).map_err(into_js_error)?;
+        let result = self
+            .explain_query(&req, analyze)
+            .await
+            .map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Preview up to `n` rows of a table (`SampleMethod` -> `QueryResult`)
+    ///
+    /// Cheaper than writing sampling SQL: `first` reads only the leading
+    /// rows, `reservoir` makes one pass, and `random` can be seeded for a
+    /// reproducible draw.
+    pub async fn sample(&self, table: String, n: u32, method: JsValue) -> Result<JsValue, JsValue> {
+        let method: SampleMethod = from_js(&method).map_err(into_js_error)?;
+        let result = self
+            .sample_table(&table, n, &method)
+            .await
+            .map_err(into_js_error)?;
+        to_js(&result)
+    }
+
+    /// Compute `ColumnStats` for one column of a loaded table
+    #[wasm_bindgen(js_name = profileColumn)]
+    pub async fn profile_column(&self, table: String, column: String) -> Result<JsValue, JsValue> {
+        let stats = self
+            .column_stats(&table, &column)
+            .await
+            .map_err(into_js_error)?;
+        to_js(&stats)
+    }
+
+    /// Export the full result of a `QueryRequest` as `ExportedBytes`
+    ///
+    /// The request's offset and row limit are not applied.
+    #[wasm_bindgen(js_name = exportResult)]
+    pub async fn export_result(&self, req: JsValue, format: JsValue) -> Result<JsValue, JsValue> {
+        let req: QueryRequest = from_js(&req).map_err(into_js_error)?;
+        let format: ExportDataFormat = from_js(&format).map_err(into_js_error)?;
+        let exported = self
+            .export_query(&req, &format)
+            .await
+            .map_err(into_js_error)?;
+        to_js(&exported)
+    }
+
+    /// Bin a numeric column for a histogram (`HistogramRequest` -> `ChartData`)
+    pub async fn histogram(&self, req: JsValue) -> Result<JsValue, JsValue> {
+        let req: HistogramRequest = from_js(&req).map_err(into_js_error)?;
+        let data = self.histogram_data(&req).await.map_err(into_js_error)?;
+        to_js(&data)
+    }
+
+    /// Open a cursor over a query result for reading in batches
+    ///
+    /// The request's offset and row limit are not applied; rows are pulled
+    /// on demand with `cursorNext` until `is_last`.
+    #[wasm_bindgen(js_name = executeStreaming)]
+    pub async fn execute_streaming(&self, req: JsValue) -> Result<QueryCursorHandle, JsValue> {
+        let req: QueryRequest = from_js(&req).map_err(into_js_error)?;
+        let cursor = self.open_cursor(&req).await.map_err(into_js_error)?;
+
+        let handle = self.next_cursor.get();
+        self.next_cursor.set(handle.wrapping_add(1));
+        self.cursors.borrow_mut().insert(handle, cursor);
+        Ok(handle)
+    }
+
+    /// Read the next `QueryResultBatch` from a cursor
+    ///
+    /// The cursor is closed automatically after its last batch.
+    #[wasm_bindgen(js_name = cursorNext)]
+    pub async fn cursor_next(
+        &self,
+        handle: QueryCursorHandle,
+        batch_size: u32,
+    ) -> Result<JsValue, JsValue> {
+        let mut cursor = self.take_cursor(handle).map_err(into_js_error)?;
+        let batch = cursor.next_batch(batch_size).await;
+        match &batch {
+            Ok(b) if !b.is_last => {
+                self.cursors.borrow_mut().insert(handle, cursor);
+            }
+            _ => cursor.close().await,
+        }
+        to_js(&batch.map_err(into_js_error)?)
+    }
+
+    /// Close a cursor and release its Arrow result
+    #[wasm_bindgen(js_name = cursorClose)]
+    pub async fn cursor_close(&self, handle: QueryCursorHandle) -> Result<(), JsValue> {
+        let cursor = self.take_cursor(handle).map_err(into_js_error)?;
+        cursor.close().await;
+        Ok(())
+    }
+}
+
+impl DataEngine {
+    /// Run the request from the envelope
+    ///
+    /// Batch entries run one at a time; a cancel request for the envelope's
+    /// ID interrupts the current entry and fails the rest.
+    async fn dispatch(&self, id: &MessageId, req: &DataRequest) -> Result<DataResponse, ErrorInfo> {
+        match req {
+            DataRequest::Batch { requests } => Ok(DataResponse::Batch {
+                responses: run_batch(requests, |req| self.dispatch_single(id, req)).await,
+            }),
+            req => self.dispatch_single(id, req).await,
+        }
+    }
+
+    /// Run a single, non-batch request
+    async fn dispatch_single(
+        &self,
+        id: &MessageId,
+        req: &DataRequest,
+    ) -> Result<DataResponse, ErrorInfo> {
+        Ok(match req {
+            DataRequest::Load(req) => DataResponse::Load(self.load_table(Some(id), req).await?),
+            DataRequest::Drop(req) => DataResponse::Drop(self.drop_relation(&req.name).await?),
+            DataRequest::Query(req) => DataResponse::Query(self.run_query(Some(id), req).await?),
+            DataRequest::Schema(req) => DataResponse::Schema(self.table_schema(&req.table).await?),
+            DataRequest::ListTables(_) => DataResponse::ListTables(self.list_tables().await?),
+            DataRequest::Batch { .. } => {
+                return Err(ErrorInfo {
+                    code: ErrorCode::InvalidQuery,
+                    message: "Batches cannot be nested".to_string(),
+                    details: None,
+                })
+            }
+        })
+    }
+
+    /// Run a query and report it to the history callback
+    async fn run_query(
+        &self,
+        id: Option<&MessageId>,
+        req: &QueryRequest,
+    ) -> Result<QueryResult, ErrorInfo> {
+        let started = js_sys::Date::now();
+        let mut result = self.cached_query(id, req).await;
+        if let (true, Err(err)) = (req.explain_on_error, &mut result) {
+            self.diagnose(req, err).await;
+        }
+        self.record_history(id, req, started, result.as_ref());
+        result
+    }
+
+    /// Send a `QueryHistoryEntry` for a finished query to the history callback
+    fn record_history(
+        &self,
+        id: Option<&MessageId>,
+        req: &QueryRequest,
+        started: f64,
+        result: Result<&QueryResult, &ErrorInfo>,
+    ) {
+        let Some(callback) = self.history_callback.borrow().clone() else {
+            return;
+        };
+        let entry = QueryHistoryEntry::new(
+            id.cloned().unwrap_or_else(generate_id),
+            req.sql.clone(),
+            started as u64,
+            (js_sys::Date::now() - started) as u32,
+            result,
+        );
+        match to_js(&entry) {
+            Ok(entry) => {
+                // A throwing callback must not fail the query
+                if let Err(err) = callback.call1(&JsValue::NULL, &entry) {
+                    log::warn!("history callback failed: {:?}", err);
+                }
+            }
+            Err(err) => log::warn!("failed to serialize history entry: {:?}", err),
+        }
+    }
+
+    /// Run a query on a fresh connection, tracking it under `id` for cancellation
+    ///
+    /// Served from the query cache when possible. The query is interrupted
+    /// if it outlives its timeout.
+    async fn cached_query(
+        &self,
+        id: Option<&MessageId>,
+        req: &QueryRequest,
+    ) -> Result<QueryResult, ErrorInfo> {
+        self.check_policy(&req.sql).await?;
+        let key = query_key(req);
+        if let Some(mut cached) = self.query_cache.borrow_mut().get(key) {
+            cached.cached = true;
+            return Ok(cached);
+        }
+        let generation = self.query_cache.borrow().generation();
+        self.check_guard(req).await?;
+
+        let conn = Connection::open(&self.db).await?;
+        let mut progress =
+            id.map(|id| ProgressReporter::new(id.clone(), self.progress_callback.borrow().clone()));
+        let query = query_result(&conn, req, progress.as_mut());
+        let result = self.tracked(id, &conn, req, query).await;
+        conn.close().await;
+
+        let mut result = result?;
+        result.query_id = format!("{:016x}", key);
+        self.query_cache
+            .borrow_mut()
+            .insert(key, result.clone(), generation);
+        Ok(result)
+    }
+
+    /// Await `query` running on `conn`, interrupting it if it outlives
+    /// `req`'s timeout
+    ///
+    /// While it runs, the query is registered under `id` for cancellation.
+    async fn tracked<T>(
+        &self,
+        id: Option<&MessageId>,
+        conn: &Connection,
+        req: &QueryRequest,
+        query: impl Future<Output = Result<T, ErrorInfo>>,
+    ) -> Result<T, ErrorInfo> {
+        if let Some(id) = id {
+            self.in_flight
+                .borrow_mut()
+                .insert(id.clone(), conn.interrupt_handle());
+        }
+        let result = match timeout_ms(req.timeout_seconds, self.timeout_seconds.get()) {
+            Some(limit_ms) => with_timeout(query, &conn.interrupt_handle(), limit_ms).await,
+            None => query.await,
+        };
+        if let Some(id) = id {
+            self.in_flight.borrow_mut().remove(id);
+        }
+        result
+    }
+
+    /// Run each statement of a script on a fresh connection
+    async fn run_script(&self, sql: &str) -> Result<ScriptResult, ErrorInfo> {
+        self.check_policy(sql).await?;
+        let conn = Connection::open(&self.db).await?;
+        let mut outcomes = Vec::new();
+        let mut error = None;
+        let mut modified = false;
+        for (index, statement) in split_statements(sql).into_iter().enumerate() {
+            let outcome = if returns_rows(statement) {
+                query_result(&conn, &QueryRequest::new(statement), None)
+                    .await
+                    .map(StatementOutcome::Rows)
+            } else {
+                modified = true;
+                execute_statement(&conn, statement).await
+            };
+            match outcome {
+                Ok(outcome) => outcomes.push(outcome),
+                Err(e) => {
+                    error = Some(StatementError {
+                        index: index as u32,
+                        sql: statement.to_string(),
+                        error: e,
+                    });
+                    break;
+                }
+            }
+        }
+        conn.close().await;
+
+        // DDL and DML may change what any cached query would return
+        if modified {
+            self.query_cache.borrow_mut().clear();
+        }
+        Ok(ScriptResult { outcomes, error })
+    }
+
+    /// Run `EXPLAIN` on a fresh connection and parse the plan
+    async fn explain_query(
+        &self,
+        req: &QueryRequest,
+        analyze: bool,
+    ) -> Result<ExplainResult, ErrorInfo> {
+        self.check_policy(&req.sql).await?;
+        if !req.params.is_empty() {
+            return Err(ErrorInfo {
+                code: ErrorCode::InvalidQuery,
+                message: "Queries with bound parameters cannot be explained".to_string(),
+                details: None,
+            });
+        }
+        let options = if analyze {
+            "ANALYZE, FORMAT JSON"
+        } else {
+            "FORMAT JSON"
+        };
+        let conn = Connection::open(&self.db).await?;
+        let rows = conn
+            .query(&format!(
+                "EXPLAIN ({}) {}",
+                options,
+                strip_statement(&req.sql)
+            ))
+            .await;
+        conn.close().await;
+
+        let raw = explain_text(&rows?);
+        Ok(ExplainResult {
+            root: parse_plan(&raw),
+            raw,
+            analyzed: analyze,
+        })
+    }
+
+    /// Reject `sql` if it breaks the SQL policy, locking external access
+    /// first if the policy forbids file functions
+    async fn check_policy(&self, sql: &str) -> Result<(), ErrorInfo> {
+        let Some(policy) = self.sql_policy.borrow().clone() else {
+            return Ok(());
+        };
+        check_sql(&policy, sql)?;
+        self.lock_for(&policy).await
+    }
+
+    /// Reject a table name the SQL policy would treat as a file path,
+    /// locking external access as `check_policy` does
+    async fn check_table_policy(&self, table: &str) -> Result<(), ErrorInfo> {
+        let Some(policy) = self.sql_policy.borrow().clone() else {
+            return Ok(());
+        };
+        check_table_name(&policy, table)?;
+        self.lock_for(&policy).await
+    }
+
+    /// Lock external access if `policy` forbids file functions
+    async fn lock_for(&self, policy: &SqlPolicy) -> Result<(), ErrorInfo> {
+        if policy.allow_file_functions {
+            return Ok(());
+        }
+        self.lock().await
+    }
+
+    /// Run `LOCK_EXTERNAL_ACCESS` unless it already has
+    async fn lock(&self) -> Result<(), ErrorInfo> {
+        if self.external_access_locked.get() {
+            return Ok(());
+        }
+        for statement in LOCK_EXTERNAL_ACCESS {
+            self.conn.query(statement).await?;
+        }
+        self.external_access_locked.set(true);
+        Ok(())
+    }
+
+    /// Reject a query whose plan breaks the query guard's limits
+    ///
+    /// Queries that can't be explained, such as ones with bound parameters
+    /// or syntax errors, are let through to succeed or fail on their own.
+    async fn check_guard(&self, req: &QueryRequest) -> Result<(), ErrorInfo> {
+        let Some(guard) = self.query_guard.borrow().clone() else {
+            return Ok(());
+        };
+        match self.explain_query(req, false).await {
+            Ok(ExplainResult {
+                root: Some(plan), ..
+            }) => check_plan(&guard, &plan),
+            _ => Ok(()),
+        }
+    }
+
+    /// Add context to a failed query's `ErrorInfo.details`
+    ///
+    /// If the statement still plans, the failure happened while running
+    /// and the plan is attached; otherwise the error is located in the SQL.
+    async fn diagnose(&self, req: &QueryRequest, err: &mut ErrorInfo) {
+        if matches!(err.code, ErrorCode::Cancelled | ErrorCode::Timeout) {
+            return;
+        }
+        let sql = strip_statement(&req.sql);
+        let mut plan = None;
+        if req.params.is_empty() {
+            if let Ok(conn) = Connection::open(&self.db).await {
+                plan = conn
+                    .query(&format!("EXPLAIN {}", sql))
+                    .await
+                    .ok()
+                    .map(|rows| format!("Query plan:\n{}", explain_text(&rows)));
+                conn.close().await;
+            }
+        }
+        let Some(context) = plan.or_else(|| error_position(&err.message, sql)) else {
+            return;
+        };
+        err.details = Some(match err.details.take() {
+            Some(details) => format!("{}\n\n{}", details, context),
+            None => context,
+        });
+    }
+
+    /// Draw a sample on a fresh connection, which drops its temp table on close
+    async fn sample_table(
+        &self,
+        table: &str,
+        n: u32,
+        method: &SampleMethod,
+    ) -> Result<QueryResult, ErrorInfo> {
+        self.check_table_policy(table).await?;
+        let plan = sample_plan(table, n, method);
+        let conn = Connection::open(&self.db).await?;
+        let result = async {
+            for statement in &plan.setup {
+                conn.query(statement).await?;
+            }
+            let mut req = QueryRequest::new(plan.sql);
+            req.limit = n;
+            query_result(&conn, &req, None).await
+        }
+        .await;
+        conn.close().await;
+        result
+    }
+
+    /// Profile a column on a fresh connection
+    async fn column_stats(&self, table: &str, column: &str) -> Result<ColumnStats, ErrorInfo> {
+        self.check_table_policy(table).await?;
+        let conn = Connection::open(&self.db).await?;
+        let stats = profile(&conn, table, column).await;
+        conn.close().await;
+        stats
+    }
+
+    /// Write a query result to an in-memory file with `COPY` and read it back
+    async fn export_query(
+        &self,
+        req: &QueryRequest,
+        format: &ExportDataFormat,
+    ) -> Result<ExportedBytes, ErrorInfo> {
+        self.check_policy(&req.sql).await?;
+        let export = self.next_export.get();
+        self.next_export.set(export.wrapping_add(1));
+        let file = export_file(export, format);
+
+        self.db
+            .register_empty_file_buffer(&file)
+            .await
+            .map_err(query_error)?;
+        let conn = Connection::open(&self.db).await?;
+        let written = async {
+            let source = query_source(&conn, req).await?;
+            conn.query(&copy_statement(&source, &file, format)).await?;
+            self.db
+                .copy_file_to_buffer(&file)
+                .await
+                .map_err(query_error)
+        }
+        .await;
+        conn.close().await;
+        self.db.drop_file(&file).await.ok();
+
+        let bytes = js_sys::Uint8Array::new(&written?).to_vec();
+        Ok(exported_bytes(&bytes, format))
+    }
+
+    /// Run a query on its own connection and serialize the page as Arrow IPC
+    ///
+    /// Guarded, timed out and cancellable like `cached_query`.
+    async fn arrow_query(
+        &self,
+        id: Option<&MessageId>,
+        req: &QueryRequest,
+    ) -> Result<ArrowResult, ErrorInfo> {
+        self.check_policy(&req.sql).await?;
+        self.check_guard(req).await?;
+        let conn = Connection::open(&self.db).await?;
+        let query = async {
+            let source = query_source(&conn, req).await?;
+            let columns = conn.describe(&format!("SELECT * FROM {}", source)).await?;
+            let bytes = conn
+                .query_ipc(&format!(
+                    "SELECT * FROM {} LIMIT {} OFFSET {}",
+                    source, req.limit, req.offset
+                ))
+                .await?;
+            arrow_result(&bytes, columns)
+        };
+        let result = self.tracked(id, &conn, req, query).await;
+        conn.close().await;
+        result
+    }
+
+    /// Find the column's range and count rows per bin
+    async fn histogram_data(&self, req: &HistogramRequest) -> Result<ChartData, ErrorInfo> {
+        self.check_table_policy(&req.table).await?;
+        let table = quote_ident(&req.table);
+        let column = quote_ident(&req.column);
+        let (lo, hi) = match req.range {
+            Some((lo, hi)) if lo <= hi => (lo, hi),
+            Some((lo, hi)) => {
+                return Err(ErrorInfo {
+                    code: ErrorCode::InvalidQuery,
+                    message: format!("Histogram range {}..{} is empty", lo, hi),
+                    details: None,
+                })
+            }
+            None => {
+                let rows = self
+                    .conn
+                    .query(&format!(
+                        "SELECT MIN({c})::DOUBLE AS lo, MAX({c})::DOUBLE AS hi FROM {t}",
+                        c = column,
+                        t = table
+                    ))
+                    .await?;
+                let row = rows.get(0);
+                match (
+                    row_value(&row, "lo").as_f64(),
+                    row_value(&row, "hi").as_f64(),
+                ) {
+                    (Some(lo), Some(hi)) => (lo, hi),
+                    // All NULL: one bin holding every row
+                    _ => {
+                        let rows = self
+                            .conn
+                            .scalar(&format!("SELECT COUNT(*) FROM {}", table))
+                            .await?
+                            .as_u64()
+                            .unwrap_or(0);
+                        return Ok(ChartData {
+                            labels: vec!["NULL".to_string()],
+                            datasets: vec![Dataset {
+                                label: req.column.clone(),
+                                data: DataValues::Numbers(vec![Some(rows as f64)]),
+                                style: None,
+                                y_axis_id: None,
+                            }],
+                        });
+                    }
+                }
+            }
+        };
+
+        let bins = Bins::new(lo, hi, req.bins);
+        let rows = self
+            .conn
+            .query(&format!(
+                "SELECT {} AS bin, COUNT(*)::BIGINT AS count FROM {} \
+                 WHERE {} IS NOT NULL GROUP BY bin",
+                bins.index_sql(&column),
+                table,
+                column
+            ))
+            .await?;
+        let counts: Vec<(usize, u64)> = rows
+            .iter()
+            .filter_map(|row| {
+                // INTEGER arrives as a JS number, BIGINT as an integer
+                let bin = row_value(&row, "bin").as_f64()?;
+                let count = row_value(&row, "count").as_u64()?;
+                Some((bin as usize, count))
+            })
+            .collect();
+        Ok(bins.chart_data(&req.column, &counts))
+    }
+
+    /// Start streaming a query on its own connection
+    async fn open_cursor(&self, req: &QueryRequest) -> Result<QueryCursor, ErrorInfo> {
+        self.check_policy(&req.sql).await?;
+        let conn = Connection::open(&self.db).await?;
+        let opened = async {
+            let source = query_source(&conn, req).await?;
+            let columns = conn.describe(&format!("SELECT * FROM {}", source)).await?;
+            let reader = conn
+                .send(&format!(
+                    "SELECT {} FROM {}",
+                    select_list(&columns, false),
+                    source
+                ))
+                .await?;
+            Ok((reader, columns))
+        }
+        .await;
+        match opened {
+            Ok((reader, columns)) => Ok(QueryCursor::new(conn, reader, columns)),
+            Err(e) => {
+                conn.close().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Remove a cursor from the open set
+    fn take_cursor(&self, handle: QueryCursorHandle) -> Result<QueryCursor, ErrorInfo> {
+        self.cursors
+            .borrow_mut()
+            .remove(&handle)
+            .ok_or_else(|| ErrorInfo {
+                code: ErrorCode::NotFound,
+                message: format!("Cursor {} is closed or does not exist", handle),
+                details: None,
+            })
+    }
+
+    /// Fetch, register and materialize a data file as a table
+    ///
+    /// With an `id`, the file's schema is sent to the response callback
+    /// before the table is materialized.
+    async fn load_table(
+        &self,
+        id: Option<&MessageId>,
+        req: &LoadRequest,
+    ) -> Result<LoadResult, ErrorInfo> {
+        let started = js_sys::Date::now();
+        let bytes = self.fetch(&req.url).await?;
+        let file = format!("{}{}.{}", ENGINE_FILE_DIR, req.name, req.format.extension());
+
+        // A previous load of the same name may still hold the file slot
+        self.db.drop_file(&file).await.ok();
+        self.db
+            .register_file_buffer(&file, bytes)
+            .await
+            .map_err(|e| load_error(&req.url, error_message(&e)))?;
+
+        let created = match id {
+            Some(id) => {
+                schema_first(
+                    self.file_schema(&req.name, &req.format, &file),
+                    |schema| self.send_early(id, started, DataResponse::SchemaReady(schema)),
+                    self.create_table(&req.name, &req.format, &file),
+                )
+                .await
+            }
+            None => self.create_table(&req.name, &req.format, &file).await,
+        };
+        // The table is materialized, so the buffer is no longer needed
+        self.db.drop_file(&file).await.ok();
+        created.map_err(|e| load_error(&req.url, e.message))?;
+        self.loaded(&req.name).await
+    }
+
+    /// Columns of a registered file as they will be loaded, read without
+    /// materializing it (for Parquet, from the footer alone)
+    async fn file_schema(
+        &self,
+        name: &str,
+        format: &DataFormat,
+        file: &str,
+    ) -> Result<TableSchema, ErrorInfo> {
+        let source = read_source(format, file);
+        let columns = self.source_columns(format, &source).await?;
+        Ok(TableSchema {
+            name: name.to_string(),
+            columns: self
+                .conn
+                .describe(&format!("SELECT {} FROM {}", columns, source))
+                .await?,
+            row_count: 0,
+            kind: TableKind::Loaded,
+        })
+    }
+
+    /// Send a response for request `id` to the response callback ahead of
+    /// its final one
+    fn send_early(&self, id: &MessageId, started: f64, data: DataResponse) {
+        let Some(callback) = self.response_callback.borrow().clone() else {
+            return;
+        };
+        let now = js_sys::Date::now();
+        let response = Response {
+            id: id.clone(),
+            timestamp: now as u64,
+            execution_time_ms: (now - started) as u32,
+            result: MessageResult::ok(data),
+        };
+        match to_js(&response) {
+            // A throwing callback must not fail the request
+            Ok(response) => {
+                if let Err(err) = callback.call1(&JsValue::NULL, &response) {
+                    log::warn!("response callback failed: {:?}", err);
+                }
+            }
+            Err(err) => log::warn!("failed to serialize early response: {:?}", err),
+        }
+    }
+
+    /// Fetch a data file, retrying transient failures per the retry policy
+    async fn fetch(&self, url: &str) -> Result<js_sys::Uint8Array, ErrorInfo> {
+        let policy = self.retry_policy.borrow().clone();
+        with_retry(&policy, || fetch_bytes(url), sleep).await
+    }
+
+    /// Fetch a data file and insert its rows into a loaded table
+    async fn append_table(&self, name: &str, url: &str) -> Result<LoadResult, ErrorInfo> {
+        if !self.tables.borrow().contains_key(name) {
+            return Err(ErrorInfo {
+                code: ErrorCode::NotFound,
+                message: format!("Table {} is not loaded", name),
+                details: None,
+            });
+        }
+        let format = DataFormat::from_path(url);
+        let bytes = self.fetch(url).await?;
+        let file = format!("{}{}.append.{}", ENGINE_FILE_DIR, name, format.extension());
+
+        self.db.drop_file(&file).await.ok();
+        self.db
+            .register_file_buffer(&file, bytes)
+            .await
+            .map_err(|e| load_error(url, error_message(&e)))?;
+
+        let inserted = self.insert_rows(name, &format, &file).await;
+        self.db.drop_file(&file).await.ok();
+        inserted.map_err(|e| load_error(url, e.message))?;
+        self.loaded(name).await
+    }
+
+    /// Record a table's new schema and row count after it changed
+    async fn loaded(&self, name: &str) -> Result<LoadResult, ErrorInfo> {
+        self.query_cache.borrow_mut().clear();
+        let mut schema = self.table_schema(name).await?;
+        schema.kind = TableKind::Loaded;
+        self.tables.borrow_mut().insert(
+            name.to_string(),
+            TableSummary {
+                name: name.to_string(),
+                column_count: schema.columns.len() as u32,
+                row_count: schema.row_count,
+            },
+        );
+        Ok(LoadResult {
+            name: name.to_string(),
+            row_count: schema.row_count,
+            schema,
+        })
+    }
+
+    /// Create (or replace) a table from a registered file
+    async fn create_table(
+        &self,
+        name: &str,
+        format: &DataFormat,
+        file: &str,
+    ) -> Result<(), ErrorInfo> {
+        let source = read_source(format, file);
+        let columns = self.source_columns(format, &source).await?;
+        self.conn
+            .query(&format!(
+                "CREATE OR REPLACE TABLE {} AS SELECT {} FROM {}",
+                quote_ident(name),
+                columns,
+                source
+            ))
+            .await?;
+        Ok(())
+    }
+
+    /// Insert the rows of a registered file into an existing table
+    ///
+    /// The schema is checked first; the insert is a single statement, so a
+    /// failure part-way leaves the table unchanged.
+    async fn insert_rows(
+        &self,
+        name: &str,
+        format: &DataFormat,
+        file: &str,
+    ) -> Result<(), ErrorInfo> {
+        let source = read_source(format, file);
+        let columns = self.source_columns(format, &source).await?;
+        let select = format!("SELECT {} FROM {}", columns, source);
+        let existing = self.conn.describe(&quote_ident(name)).await?;
+        let incoming = self.conn.describe(&select).await?;
+        if let Some(problem) = schema_mismatch(&existing, &incoming) {
+            return Err(ErrorInfo {
+                code: ErrorCode::ParseError,
+                message: format!("Schema does not match table {}: {}", name, problem),
+                details: None,
+            });
+        }
+        self.conn
+            .query(&format!("INSERT INTO {} {}", quote_ident(name), select))
+            .await?;
+        Ok(())
+    }
+
+    /// Select list for reading a registered file as table columns
+    async fn source_columns(&self, format: &DataFormat, source: &str) -> Result<String, ErrorInfo> {
+        let columns = match format {
+            // Name headerless columns positionally: column0..columnN
+            DataFormat::Csv { header: false, .. } => positional_columns(
+                self.conn
+                    .describe(&format!("SELECT * FROM {}", source))
+                    .await?
+                    .iter()
+                    .map(|c| c.name.as_str()),
+            ),
+            _ => "*".to_string(),
+        };
+        Ok(columns)
+    }
+
+    /// Drop a table or view by name and invalidate cached results
+    async fn drop_relation(&self, name: &str) -> Result<DropResult, ErrorInfo> {
+        let kind = self
+            .conn
+            .scalar(&format!(
+                "SELECT table_type FROM information_schema.tables WHERE table_name = {}",
+                quote_literal(name)
+            ))
+            .await?;
+        self.tables.borrow_mut().remove(name);
+        let kind = match kind.as_str() {
+            Some("VIEW") => "VIEW",
+            Some(_) => "TABLE",
+            None => {
+                return Ok(DropResult {
+                    name: name.to_string(),
+                    freed: false,
+                })
+            }
+        };
+        self.conn
+            .query(&format!("DROP {} IF EXISTS {}", kind, quote_ident(name)))
+            .await?;
+        self.query_cache.borrow_mut().clear();
+        Ok(DropResult {
+            name: name.to_string(),
+            freed: true,
+        })
+    }
+
+    /// Names and columns of every table and view
+    async fn catalog(&self) -> Result<Vec<CatalogTable>, ErrorInfo> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT table_name, column_name FROM information_schema.columns \
+                 ORDER BY table_name, ordinal_position",
+            )
+            .await?;
+        let mut catalog: Vec<CatalogTable> = Vec::new();
+        for row in rows.iter() {
+            let table = row_value(&row, "table_name");
+            let column = row_value(&row, "column_name");
+            let (Some(table), Some(column)) = (table.as_str(), column.as_str()) else {
+                continue;
+            };
+            match catalog.last_mut() {
+                Some(last) if last.name == table => last.columns.push(column.to_string()),
+                _ => catalog.push(CatalogTable {
+                    name: table.to_string(),
+                    columns: vec![column.to_string()],
+                }),
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// Describe a registered table or view and count its rows
+    async fn table_schema(&self, name: &str) -> Result<TableSchema, ErrorInfo> {
+        let table_type = self
+            .conn
+            .scalar(&format!(
+                "SELECT table_type FROM information_schema.tables WHERE table_name = {}",
+                quote_literal(name)
+            ))
+            .await?;
+        let loaded = self.tables.borrow().contains_key(name);
+        let kind = table_kind(table_type.as_str().unwrap_or_default(), loaded);
+        let table = quote_ident(name);
+        let columns = self.conn.describe(&table).await?;
+        let row_count = self
+            .conn
+            .scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .await?
+            .as_u64()
+            .unwrap_or(0);
+        Ok(TableSchema {
+            name: name.to_string(),
+            columns,
+            row_count,
+            kind,
+        })
+    }
+
+    /// Share of sampled values a hint's columns have in common, or `None`
+    /// if it can't be measured
+    async fn value_overlap(&self, hint: &RelationshipHint) -> Option<f32> {
+        let rows = self.conn.query(&overlap_sql(hint)).await.ok()?;
+        let row = rows.get(0);
+        let count = |key| row_value(&row, key).as_u64();
+        overlap_ratio(
+            count("left_sampled")?,
+            count("left_matched")?,
+            count("right_sampled")?,
+            count("right_matched")?,
+        )
+    }
+
+    /// Names of every view, sorted
+    async fn view_names(&self) -> Result<Vec<String>, ErrorInfo> {
+        let rows = self
+            .conn
+            .query(
+                "SELECT table_name FROM information_schema.tables \
+                 WHERE table_type = 'VIEW' ORDER BY table_name",
+            )
+            .await?;
+        Ok(rows
+            .iter()
+            .filter_map(|row| row_value(&row, "table_name").as_str().map(str::to_string))
+            .collect())
+    }
+
+    /// Describe every table and view in the database
+    async fn list_tables(&self) -> Result<ListTablesResult, ErrorInfo> {
+        let rows = self
+            .conn
+            .query("SELECT table_name FROM information_schema.tables ORDER BY table_name")
+            .await?;
+        let mut tables = Vec::new();
+        for row in rows.iter() {
+            let row = row.unchecked_into::<ArrowRow>().to_json();
+            if let Some(name) = js_sys::Object::values(&row).get(0).as_string() {
+                tables.push(self.table_schema(&name).await?);
+            }
+        }
+        Ok(ListTablesResult { tables })
+    }
+}
+
+/// Run a query on `conn` with the request's offset, row cap and time zone
+/// applied
+async fn query_result(
+    conn: &Connection,
+    req: &QueryRequest,
+    mut progress: Option<&mut ProgressReporter>,
+) -> Result<QueryResult, ErrorInfo> {
+    if let Some(zone) = &req.timezone {
+        conn.query(&set_timezone_sql(zone)?)
+            .await
+            .map_err(|e| invalid_timezone(zone, Some(e.message)))?;
+    }
+    let source = query_source(conn, req).await?;
+    let columns = conn.describe(&format!("SELECT * FROM {}", source)).await?;
+    let total_rows = conn
+        .scalar(&format!("SELECT COUNT(*) FROM {}", source))
+        .await?
+        .as_u64()
+        .unwrap_or(0);
+    if let Some(progress) = progress.as_deref_mut() {
+        progress.set_total_rows(total_rows.saturating_sub(req.offset).min(req.limit as u64));
+        progress.update(0);
+    }
+
+    let rows = conn
+        .query_with_progress(
+            &format!(
+                "SELECT {} FROM {} LIMIT {} OFFSET {}",
+                select_list(&columns, req.timezone.is_some()),
+                source,
+                req.limit,
+                req.offset
+            ),
+            |rows| {
+                if let Some(progress) = progress.as_deref_mut() {
+                    progress.update(rows);
+                }
+            },
+        )
+        .await?;
+    let rows = table_rows(&rows, &columns);
+
+    let mut result = QueryResult::page(columns, rows, total_rows, req.offset);
+    normalize_result(&mut result);
+    Ok(result)
+}
+
+/// Statements whose result can be nested in a `SELECT`
+const ROW_KEYWORDS: &[&str] = &[
+    "SELECT",
+    "WITH",
+    "FROM",
+    "VALUES",
+    "TABLE",
+    "DESCRIBE",
+    "SHOW",
+    "SUMMARIZE",
+];
+
+/// Whether a script statement should be run as a query
+fn returns_rows(statement: &str) -> bool {
+    ROW_KEYWORDS.contains(&leading_keyword(statement).as_str())
+}
+
+/// Run a statement for its side effects, reading DuckDB's `Count` of changed rows
+async fn execute_statement(conn: &Connection, sql: &str) -> Result<StatementOutcome, ErrorInfo> {
+    let rows = conn.query(sql).await?;
+    let affected_rows = rows
+        .iter()
+        .next()
+        .and_then(|row| row_value(&row, "Count").as_u64())
+        .unwrap_or(0);
+    Ok(StatementOutcome::Ack { affected_rows })
+}
+
+/// Number of most frequent values reported for string columns
+const TOP_VALUES: u32 = 10;
+
+/// Summarize a column with `SUMMARIZE`, adding top values for strings
+async fn profile(conn: &Connection, table: &str, column: &str) -> Result<ColumnStats, ErrorInfo> {
+    let table_ident = quote_ident(table);
+    let column_ident = quote_ident(column);
+    // Decimal and HUGEINT summary columns are cast so they arrive as numbers
+    let rows = conn
+        .query(&format!(
+            "SELECT column_type, min, max, approx_unique::BIGINT AS approx_unique, \
+             TRY_CAST(avg AS DOUBLE) AS avg, TRY_CAST(std AS DOUBLE) AS std, \
+             count::BIGINT AS count FROM (SUMMARIZE SELECT {} FROM {})",
+            column_ident, table_ident
+        ))
+        .await?;
+    let row = rows.get(0);
+    if row.is_undefined() {
+        return Err(ErrorInfo {
+            code: ErrorCode::NotFound,
+            message: format!("Column {} not found in {}", column, table),
+            details: None,
+        });
+    }
+
+    let data_type = column_type(row_value(&row, "column_type").as_str().unwrap_or_default());
+    let text = |key: &str| row_value(&row, key).as_str().map(str::to_string);
+    let number = |key: &str| row_value(&row, key).as_f64();
+    let row_count = row_value(&row, "count").as_u64().unwrap_or(0);
+    let numeric = data_type.is_numeric();
+
+    // SUMMARIZE only reports a rounded null percentage, so count exactly
+    let null_count = conn
+        .query(&format!(
+            "SELECT (COUNT(*) - COUNT({}))::BIGINT AS null_count FROM {}",
+            column_ident, table_ident
+        ))
+        .await?
+        .iter()
+        .next()
+        .and_then(|row| row_value(&row, "null_count").as_u64())
+        .unwrap_or(0);
+
+    let top_values = if data_type == ColumnType::String {
+        conn.query(&format!(
+            "SELECT {c} AS value, COUNT(*)::BIGINT AS count FROM {t} \
+             WHERE {c} IS NOT NULL GROUP BY {c} ORDER BY count DESC, value LIMIT {k}",
+            c = column_ident,
+            t = table_ident,
+            k = TOP_VALUES
+        ))
+        .await?
+        .iter()
+        .map(|row| ValueCount {
+            value: row_value(&row, "value")
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            count: row_value(&row, "count").as_u64().unwrap_or(0),
+        })
+        .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(ColumnStats {
+        table: table.to_string(),
+        column: column.to_string(),
+        min: text("min"),
+        max: text("max"),
+        distinct_count: row_value(&row, "approx_unique").as_u64().unwrap_or(0),
+        null_count,
+        mean: number("avg").filter(|_| numeric),
+        stddev: number("std").filter(|_| numeric),
+        top_values,
+        data_type,
+        row_count,
+    })
+}
+
+/// The `FROM` clause for selecting a request's result, aliased as `_q`
+///
+/// Parameterized statements can't be nested, so their result is
+/// materialized into a temp table on `conn` and read back from there.
+async fn query_source(conn: &Connection, req: &QueryRequest) -> Result<String, ErrorInfo> {
+    let sql = strip_statement(&req.sql);
+    if req.params.is_empty() {
+        return Ok(format!("{} AS _q", subquery(sql)));
+    }
+    let params = bind_params(sql, &req.params)?;
+    conn.execute(&format!("CREATE TEMP TABLE _bound AS {}", sql), &params)
+        .await?;
+    Ok("_bound AS _q".to_string())
+}
+
+/// The `explain_value` column of `EXPLAIN` output, one row per line
+fn explain_text(rows: &js_sys::Array) -> String {
+    rows.iter()
+        .map(|row| {
+            let row = row.unchecked_into::<ArrowRow>().to_json();
+            js_sys::Reflect::get(&row, &JsValue::from_str("explain_value"))
+                .ok()
+                .and_then(|v| v.as_string())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// DuckDB table function reading a registered file
+fn read_source(format: &DataFormat, file: &str) -> String {
+    let file = quote_literal(file);
+    match format {
+        DataFormat::Parquet => format!("read_parquet({})", file),
+        DataFormat::Csv { delimiter, header } => {
+            let mut options = format!("header = {}", header);
+            if let Some(delimiter) = delimiter {
+                options.push_str(&format!(
+                    ", delim = {}",
+                    quote_literal(&delimiter.to_string())
+                ));
+            }
+            format!("read_csv_auto({}, {})", file, options)
+        }
+        DataFormat::NdJson => format!("read_json_auto({}, format = 'newline_delimited')", file),
+    }
+}
+
+/// Select list renaming DuckDB's guessed names for headerless CSV columns
+/// to `column0..columnN`
+fn positional_columns<'a>(names: impl IntoIterator<Item = &'a str>) -> String {
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| format!("{} AS column{}", quote_ident(name), i))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Error for a data file that could not be read
+fn load_error(url: &str, details: String) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::ParseError,
+        message: format!("Failed to load {}", url),
+        details: Some(details),
+    }
+}
+
+/// Trim whitespace and trailing semicolons so a statement can be nested
+fn strip_statement(sql: &str) -> &str {
+    sql.trim().trim_end_matches(';').trim_end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_headerless_semicolon_csv() {
+        let format = DataFormat::Csv {
+            delimiter: Some(';'),
+            header: false,
+        };
+        assert_eq!(
+            read_source(&format, "enrollment.csv"),
+            "read_csv_auto('enrollment.csv', header = false, delim = ';')"
+        );
+
+        // DuckDB names headerless columns column0, column1, ... or
+        // column00, column01, ... depending on the count
+        assert_eq!(
+            positional_columns(["column00", "column01", "column02"]),
+            "\"column00\" AS column0, \"column01\" AS column1, \"column02\" AS column2"
+        );
+    }
+
+    #[test]
+    fn reads_csv_with_defaults() {
+        let format = DataFormat::Csv {
+            delimiter: None,
+            header: true,
+        };
+        assert_eq!(
+            read_source(&format, "it's.csv"),
+            "read_csv_auto('it''s.csv', header = true)"
+        );
+        assert_eq!(
+            read_source(&DataFormat::Parquet, "parcels.parquet"),
+            "read_parquet('parcels.parquet')"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Mapping DuckDB errors into `ErrorInfo`
+
+use playground_types::messages::{ErrorCode, ErrorInfo, IntoErrorInfo};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Extract a human-readable message from a thrown JS value
+pub fn error_message(err: &JsValue) -> String {
+    if let Some(e) = err.dyn_ref::<js_sys::Error>() {
+        return String::from(e.message());
+    }
+    err.as_string().unwrap_or_else(|| format!("{:?}", err))
+}
+
+/// An error thrown by DuckDB-WASM
+pub struct DuckDbError(pub JsValue);
+
+impl IntoErrorInfo for DuckDbError {
+    fn into_error_info(self) -> ErrorInfo {
+        let message = error_message(&self.0);
+        ErrorInfo {
+            code: query_error_code(&message),
+            message,
+            details: None,
+        }
+    }
+}
+
+playground_types::error_info_from!(DuckDbError);
+
+/// Classify a DuckDB error message
+///
+/// DuckDB prefixes messages with the failing stage, e.g.
+/// `Parser Error: syntax error at or near "FORM"`.
+fn query_error_code(message: &str) -> ErrorCode {
+    if message.starts_with("Parser Error") || message.starts_with("Binder Error") {
+        ErrorCode::InvalidQuery
+    } else if message.starts_with("Catalog Error") {
+        ErrorCode::NotFound
+    } else if message.starts_with("Out of Memory Error") {
+        ErrorCode::LimitExceeded
+    } else if message.starts_with("INTERRUPT Error") {
+        ErrorCode::Cancelled
+    } else if message.starts_with("Conversion Error") || message.starts_with("Invalid Input Error")
+    {
+        ErrorCode::ParseError
+    } else if message.starts_with("HTTP Error") || message.starts_with("IO Error") {
+        ErrorCode::NetworkError
+    } else {
+        ErrorCode::Unknown
+    }
+}
+
+/// Classify a DuckDB error raised while running a query
+pub fn query_error(err: JsValue) -> ErrorInfo {
+    DuckDbError(err).into_error_info()
+}
+
+/// Error for a query interrupted by a cancel request
+pub fn cancelled_error() -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::Cancelled,
+        message: "Query was cancelled".to_string(),
+        details: None,
+    }
+}
+
+/// Convert an `ErrorInfo` into a JS value for rejecting a promise
+pub fn into_js_error(error: ErrorInfo) -> JsValue {
+    crate::convert::to_js(&error).unwrap_or_else(|_| JsValue::from_str(&error.message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duckdb_stages_map_to_error_codes() {
+        let cases = [
+            (
+                "Parser Error: syntax error at or near \"FORM\"",
+                ErrorCode::InvalidQuery,
+            ),
+            (
+                "Binder Error: Referenced column \"yr\" not found",
+                ErrorCode::InvalidQuery,
+            ),
+            (
+                "Catalog Error: Table with name aadt does not exist!",
+                ErrorCode::NotFound,
+            ),
+            (
+                "Out of Memory Error: failed to allocate data",
+                ErrorCode::LimitExceeded,
+            ),
+            ("INTERRUPT Error: Interrupted!", ErrorCode::Cancelled),
+            (
+                "Conversion Error: Could not convert string 'x' to INT32",
+                ErrorCode::ParseError,
+            ),
+            ("HTTP Error: Unable to connect", ErrorCode::NetworkError),
+            ("Something else", ErrorCode::Unknown),
+        ];
+        for (message, code) in cases {
+            assert_eq!(query_error_code(message), code, "{message}");
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Parsing DuckDB's JSON query plans and locating query errors
+
+use crate::sql::{is_ident_char, scan_code};
+use playground_types::data::PlanNode;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Operators DuckDB wraps around the real plan in `EXPLAIN ANALYZE` output
+const WRAPPER_OPERATORS: &[&str] = &["EXPLAIN_ANALYZE", "RESULT_COLLECTOR"];
+
+/// Parse the output of `EXPLAIN (FORMAT JSON)` or `EXPLAIN (ANALYZE, FORMAT JSON)`
+///
+/// Plain plans are an array of operators keyed by `name`. Analyzed plans are
+/// a profiling tree whose root is the query itself, with operators keyed by
+/// `operator_name` and carrying timing and cardinality.
+pub fn parse_plan(raw: &str) -> Option<PlanNode> {
+    let value: Value = serde_json::from_str(raw).ok()?;
+    let root = match &value {
+        Value::Array(nodes) => nodes.first()?,
+        Value::Object(_) => &value,
+        _ => return None,
+    };
+    plan_root(root).map(plan_node)
+}
+
+/// Skip past the query node and wrapper operators to the first real operator
+fn plan_root(node: &Value) -> Option<&Value> {
+    match operator_name(node) {
+        Some(name) if !WRAPPER_OPERATORS.contains(&name) => Some(node),
+        _ => children(node).find_map(plan_root),
+    }
+}
+
+fn plan_node(node: &Value) -> PlanNode {
+    PlanNode {
+        name: operator_name(node).unwrap_or_default().to_string(),
+        estimated_cardinality: node
+            .get("extra_info")
+            .and_then(|info| info.get("Estimated Cardinality"))
+            .and_then(count),
+        actual_cardinality: node.get("operator_cardinality").and_then(count),
+        // DuckDB reports seconds
+        timing_ms: node
+            .get("operator_timing")
+            .and_then(Value::as_f64)
+            .map(|secs| secs * 1000.0),
+        children: children(node).map(plan_node).collect(),
+    }
+}
+
+fn operator_name(node: &Value) -> Option<&str> {
+    node.get("name")
+        .or_else(|| node.get("operator_name"))
+        .and_then(Value::as_str)
+        .map(str::trim)
+}
+
+fn children(node: &Value) -> impl Iterator<Item = &Value> {
+    node.get("children")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+/// Where in `sql` a DuckDB error points, as a character offset and a
+/// caret snippet of the offending line
+///
+/// The position comes from `at end of input`, or else the first quoted
+/// token in the message (`"FORM"`, `Referenced column "x"`) found in the
+/// statement. Returns `None` when the message names nothing in `sql`.
+pub fn error_position(message: &str, sql: &str) -> Option<String> {
+    let byte = if message.contains("at end of input") {
+        sql.trim_end().len()
+    } else {
+        let token = message.split('"').nth(1).filter(|t| !t.is_empty())?;
+        find_token(sql, token)?
+    };
+    let offset = sql[..byte].chars().count();
+
+    let line_start = sql[..byte].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = sql[byte..].find('\n').map_or(sql.len(), |i| byte + i);
+    let line_number = sql[..byte].matches('\n').count() + 1;
+    let prefix = format!("LINE {}: ", line_number);
+    let column = sql[line_start..byte].chars().count();
+    Some(format!(
+        "at character {}\n{}{}\n{}^",
+        offset + 1,
+        prefix,
+        &sql[line_start..line_end],
+        " ".repeat(prefix.len() + column)
+    ))
+}
+
+/// Byte offset of the first whole-word, case-insensitive match of `token`
+/// in `sql`, outside string literals and comments
+///
+/// A match may sit inside a quoted identifier that holds exactly `token`.
+fn find_token(sql: &str, token: &str) -> Option<usize> {
+    let mut code = HashSet::new();
+    scan_code(sql, |i, _| {
+        code.insert(i);
+    });
+    // ASCII lowercasing keeps byte offsets, so they index `sql` directly
+    let haystack = sql.to_ascii_lowercase();
+    let token = token.to_ascii_lowercase();
+    let boundary = |c: Option<char>, edge: Option<char>| {
+        !(c.is_some_and(is_ident_char) && edge.is_some_and(is_ident_char))
+    };
+    haystack.match_indices(&token).map(|(i, _)| i).find(|&i| {
+        let end = i + token.len();
+        let before = sql[..i].chars().next_back();
+        let after = sql[end..].chars().next();
+        let quoted = before == Some('"') && after == Some('"');
+        (code.contains(&i) || quoted)
+            && boundary(before, token.chars().next())
+            && boundary(after, token.chars().next_back())
+    })
+}
+
+/// Read a row count given as a number or a string such as `"~1,024"`
+fn count(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect::<String>()
+            .parse()
+            .ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_group_by_plan() {
+        // EXPLAIN (FORMAT JSON) SELECT district, COUNT(*) FROM schools GROUP BY district
+        let raw = r##"[{
+            "name": "HASH_GROUP_BY",
+            "children": [{
+                "name": "SEQ_SCAN ",
+                "children": [],
+                "extra_info": {"Table": "schools", "Projections": "district", "Estimated Cardinality": "1024"}
+            }],
+            "extra_info": {"Groups": "#0", "Aggregates": "count_star()", "Estimated Cardinality": "~12"}
+        }]"##;
+        let root = parse_plan(raw).unwrap();
+        assert_eq!(root.name, "HASH_GROUP_BY");
+        assert_eq!(root.estimated_cardinality, Some(12));
+        assert_eq!(root.children[0].name, "SEQ_SCAN");
+        assert_eq!(root.children[0].estimated_cardinality, Some(1024));
+        assert_eq!(root.timing_ms, None);
+    }
+
+    #[test]
+    fn parses_analyzed_group_by_plan() {
+        let raw = r#"{
+            "query_name": "SELECT district, COUNT(*) FROM schools GROUP BY district",
+            "latency": 0.004,
+            "children": [{
+                "operator_name": "EXPLAIN_ANALYZE",
+                "operator_timing": 0.0,
+                "operator_cardinality": 0,
+                "children": [{
+                    "operator_name": "HASH_GROUP_BY",
+                    "operator_timing": 0.0025,
+                    "operator_cardinality": 12,
+                    "extra_info": {"Estimated Cardinality": "12"},
+                    "children": [{
+                        "operator_name": "TABLE_SCAN",
+                        "operator_timing": 0.001,
+                        "operator_cardinality": 1024,
+                        "extra_info": {},
+                        "children": []
+                    }]
+                }]
+            }]
+        }"#;
+        let root = parse_plan(raw).unwrap();
+        assert_eq!(root.name, "HASH_GROUP_BY");
+        assert_eq!(root.actual_cardinality, Some(12));
+        assert_eq!(root.timing_ms, Some(2.5));
+        assert_eq!(root.children[0].actual_cardinality, Some(1024));
+    }
+
+    #[test]
+    fn locates_missing_column() {
+        let message = "Binder Error: Referenced column \"precint\" not found in FROM clause!\n\
+                       Candidate bindings: \"results.precinct\"";
+        let sql = "SELECT district,\n       precint, votes\nFROM results";
+        assert_eq!(
+            error_position(message, sql).unwrap(),
+            "at character 25\nLINE 2:        precint, votes\n               ^"
+        );
+    }
+
+    #[test]
+    fn locates_syntax_errors() {
+        let sql = "SELECT * FORM results";
+        let near = error_position("Parser Error: syntax error at or near \"FORM\"", sql);
+        assert_eq!(
+            near.unwrap(),
+            "at character 10\nLINE 1: SELECT * FORM results\n                 ^"
+        );
+        let end = error_position(
+            "Parser Error: syntax error at end of input",
+            "SELECT * FROM ",
+        );
+        assert!(end.unwrap().starts_with("at character 14\n"));
+        assert_eq!(error_position("Out of Memory Error: failed", sql), None);
+    }
+
+    #[test]
+    fn matches_whole_identifiers_outside_strings() {
+        let message = "Binder Error: Referenced column \"id\" not found in FROM clause!";
+        let sql = "SELECT pid, 'id' AS label, -- id\n       id FROM parcels";
+        assert_eq!(
+            error_position(message, sql).unwrap(),
+            "at character 41\nLINE 2:        id FROM parcels\n               ^"
+        );
+
+        let message = "Binder Error: Referenced column \"Parcel ID\" not found";
+        let sql = "SELECT \"Parcel ID\" FROM parcels";
+        assert!(error_position(message, sql)
+            .unwrap()
+            .starts_with("at character 9\n"));
+
+        let sql = "SELECT pid, idx FROM parcels";
+        assert_eq!(
+            error_position("Binder Error: Referenced column \"id\"", sql),
+            None
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Exporting query results as downloadable files
+
+use crate::policy::ENGINE_FILE_DIR;
+use crate::schema::quote_literal;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use playground_types::data::{ExportDataFormat, ExportedBytes};
+
+/// Options for DuckDB's `COPY ... TO` in the given format
+fn copy_options(format: &ExportDataFormat) -> &'static str {
+    match format {
+        ExportDataFormat::Csv => "FORMAT CSV, HEADER",
+        ExportDataFormat::Parquet => "FORMAT PARQUET",
+        ExportDataFormat::Json => "FORMAT JSON, ARRAY true",
+    }
+}
+
+/// Name of the `export`th in-memory file an export writes to
+pub fn export_file(export: u32, format: &ExportDataFormat) -> String {
+    format!(
+        "{}export_{}.{}",
+        ENGINE_FILE_DIR,
+        export,
+        format.extension()
+    )
+}
+
+/// `COPY` writing every row of `source` (a relation or subquery) to `file`
+pub fn copy_statement(source: &str, file: &str, format: &ExportDataFormat) -> String {
+    format!(
+        "COPY (SELECT * FROM {}) TO {} ({})",
+        source,
+        quote_literal(file),
+        copy_options(format)
+    )
+}
+
+/// Wrap file contents written by `COPY` for transfer to JS
+pub fn exported_bytes(bytes: &[u8], format: &ExportDataFormat) -> ExportedBytes {
+    ExportedBytes {
+        data_base64: STANDARD.encode(bytes),
+        mime_type: format.mime_type().to_string(),
+        filename: format!("query-results.{}", format.extension()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_copy_for_each_format() {
+        let source = "(SELECT district, enrollment FROM schools)";
+        for (format, file, options) in [
+            (
+                ExportDataFormat::Csv,
+                "/engine/export_1.csv",
+                "FORMAT CSV, HEADER",
+            ),
+            (
+                ExportDataFormat::Parquet,
+                "/engine/export_1.parquet",
+                "FORMAT PARQUET",
+            ),
+            (
+                ExportDataFormat::Json,
+                "/engine/export_1.json",
+                "FORMAT JSON, ARRAY true",
+            ),
+        ] {
+            assert_eq!(export_file(1, &format), file);
+            assert_eq!(
+                copy_statement(source, file, &format),
+                format!(
+                    "COPY (SELECT * FROM (SELECT district, enrollment FROM schools)) \
+                     TO '{}' ({})",
+                    file, options
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn names_downloads_by_format() {
+        for (format, filename, mime_type) in [
+            (ExportDataFormat::Csv, "query-results.csv", "text/csv"),
+            (
+                ExportDataFormat::Parquet,
+                "query-results.parquet",
+                "application/vnd.apache.parquet",
+            ),
+            (
+                ExportDataFormat::Json,
+                "query-results.json",
+                "application/json",
+            ),
+        ] {
+            let exported = exported_bytes(b"", &format);
+            assert_eq!(exported.filename, filename);
+            assert_eq!(exported.mime_type, mime_type);
+        }
+    }
+}
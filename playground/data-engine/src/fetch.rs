@@ -0,0 +1,59 @@
+//! Network fetches for data files
+
+use crate::error::error_message;
+use playground_types::data::DataManifest;
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    /// Global `fetch`, available in both windows and workers
+    #[wasm_bindgen(js_name = fetch, catch)]
+    async fn fetch_url(url: &str) -> Result<JsValue, JsValue>;
+}
+
+/// Fetch a URL and return the response body as bytes
+pub async fn fetch_bytes(url: &str) -> Result<js_sys::Uint8Array, ErrorInfo> {
+    let network_error = |err: JsValue| ErrorInfo {
+        code: ErrorCode::NetworkError,
+        message: format!("Failed to fetch {}", url),
+        details: Some(error_message(&err)),
+    };
+
+    let response: web_sys::Response = fetch_url(url)
+        .await
+        .map_err(network_error)?
+        .unchecked_into();
+    if !response.ok() {
+        return Err(ErrorInfo {
+            code: if response.status() == 404 {
+                ErrorCode::NotFound
+            } else {
+                ErrorCode::NetworkError
+            },
+            message: format!("Failed to fetch {}", url),
+            details: Some(format!(
+                "HTTP {} {}",
+                response.status(),
+                response.status_text()
+            )),
+        });
+    }
+
+    let buffer = response.array_buffer().map_err(network_error)?;
+    let buffer = JsFuture::from(buffer).await.map_err(network_error)?;
+    Ok(js_sys::Uint8Array::new(&buffer))
+}
+
+/// Fetch and parse a JSON `DataManifest`
+pub async fn fetch_manifest(url: &str) -> Result<DataManifest, ErrorInfo> {
+    let bytes = fetch_bytes(url).await?.to_vec();
+    let json = String::from_utf8(bytes).map_err(|e| ErrorInfo {
+        code: ErrorCode::ParseError,
+        message: format!("Manifest at {} is not UTF-8", url),
+        details: Some(e.to_string()),
+    })?;
+    DataManifest::from_json(&json)
+}
@@ -0,0 +1,138 @@
+//! Rejecting dangerous queries from their plan before they run
+
+use playground_types::data::PlanNode;
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use playground_types::storage::QueryGuard;
+
+/// Operators that stop reading their input after a fixed number of rows
+const LIMIT_OPERATORS: &[&str] = &["LIMIT", "STREAMING_LIMIT"];
+
+/// Operators that pull rows from their input one at a time, so a `LIMIT`
+/// above them still bounds how much of the input is produced
+///
+/// Anything else (sorts, `TOP_N`, aggregates, joins) consumes its whole
+/// input before emitting a row.
+const STREAMING_OPERATORS: &[&str] = &["PROJECTION", "FILTER"];
+
+/// Check a query's `EXPLAIN` plan against `guard`
+///
+/// Fails with `ErrorCode::LimitExceeded`, explaining which limit the
+/// query broke, if an operator's row estimate is above
+/// `max_estimated_cardinality` or, when forbidden, a cross join has no
+/// `LIMIT` above it with only projections and filters in between.
+pub fn check_plan(guard: &QueryGuard, plan: &PlanNode) -> Result<(), ErrorInfo> {
+    check_node(guard, plan, false)
+}
+
+fn check_node(guard: &QueryGuard, node: &PlanNode, limited: bool) -> Result<(), ErrorInfo> {
+    if let Some(rows) = node
+        .estimated_cardinality
+        .filter(|&rows| rows > guard.max_estimated_cardinality)
+    {
+        return Err(rejected(format!(
+            "{} is estimated to produce {} rows, more than the limit of {}",
+            node.name, rows, guard.max_estimated_cardinality
+        )));
+    }
+    let name = node.name.trim();
+    if name == "CROSS_PRODUCT" && !limited && guard.forbid_cross_join_without_limit {
+        return Err(rejected(
+            "it cross joins tables without a LIMIT; add a join condition or a LIMIT".to_string(),
+        ));
+    }
+    let limited =
+        LIMIT_OPERATORS.contains(&name) || (limited && STREAMING_OPERATORS.contains(&name));
+    node.children
+        .iter()
+        .try_for_each(|child| check_node(guard, child, limited))
+}
+
+fn rejected(reason: String) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::LimitExceeded,
+        message: format!("Query rejected by the query guard: {}", reason),
+        details: Some("Narrow the query, or disable the guard in query preferences".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::explain::parse_plan;
+
+    // EXPLAIN (FORMAT JSON) SELECT * FROM parcels, assessments
+    const CROSS_JOIN: &str = r#"[{
+        "name": "CROSS_PRODUCT",
+        "children": [
+            {"name": "SEQ_SCAN ", "children": [], "extra_info": {"Table": "parcels", "Estimated Cardinality": "40000"}},
+            {"name": "SEQ_SCAN ", "children": [], "extra_info": {"Table": "assessments", "Estimated Cardinality": "2000"}}
+        ],
+        "extra_info": {"Estimated Cardinality": "80000000"}
+    }]"#;
+
+    #[test]
+    fn rejects_unbounded_cross_join() {
+        let plan = parse_plan(CROSS_JOIN).unwrap();
+        let err = check_plan(&QueryGuard::default(), &plan).unwrap_err();
+        assert_eq!(err.code, ErrorCode::LimitExceeded);
+        assert!(err.message.contains("cross joins"), "{}", err.message);
+
+        let allowed = QueryGuard {
+            forbid_cross_join_without_limit: false,
+            ..QueryGuard::default()
+        };
+        assert!(check_plan(&allowed, &plan).is_ok());
+    }
+
+    #[test]
+    fn allows_limited_cross_join() {
+        let raw = format!(
+            r#"[{{"name": "STREAMING_LIMIT", "children": {}, "extra_info": {{}}}}]"#,
+            CROSS_JOIN
+        );
+        let plan = parse_plan(&raw).unwrap();
+        assert!(check_plan(&QueryGuard::default(), &plan).is_ok());
+
+        let raw = format!(
+            r#"[{{"name": "LIMIT", "children": [{{"name": "PROJECTION", "children": [
+                {{"name": "FILTER", "children": {}, "extra_info": {{}}}}
+            ], "extra_info": {{}}}}], "extra_info": {{}}}}]"#,
+            CROSS_JOIN
+        );
+        let plan = parse_plan(&raw).unwrap();
+        assert!(check_plan(&QueryGuard::default(), &plan).is_ok());
+    }
+
+    #[test]
+    fn limit_above_blocking_operator_does_not_bound_cross_join() {
+        // ORDER BY ... LIMIT plans as TOP_N, which sorts its whole input
+        let top_n = format!(
+            r#"[{{"name": "TOP_N", "children": {}, "extra_info": {{}}}}]"#,
+            CROSS_JOIN
+        );
+        // A LIMIT over an aggregate only limits the groups
+        let aggregate = format!(
+            r#"[{{"name": "LIMIT", "children": [
+                {{"name": "HASH_GROUP_BY", "children": {}, "extra_info": {{}}}}
+            ], "extra_info": {{}}}}]"#,
+            CROSS_JOIN
+        );
+        for raw in [top_n, aggregate] {
+            let plan = parse_plan(&raw).unwrap();
+            let err = check_plan(&QueryGuard::default(), &plan).unwrap_err();
+            assert!(err.message.contains("cross joins"), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn rejects_large_estimate() {
+        let plan = parse_plan(CROSS_JOIN).unwrap();
+        let guard = QueryGuard {
+            max_estimated_cardinality: 1_000_000,
+            forbid_cross_join_without_limit: false,
+        };
+        let err = check_plan(&guard, &plan).unwrap_err();
+        assert_eq!(err.code, ErrorCode::LimitExceeded);
+        assert!(err.message.contains("80000000 rows"), "{}", err.message);
+    }
+}
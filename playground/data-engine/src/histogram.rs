@@ -0,0 +1,106 @@
+//! Equal-width binning for histogram charts
+
+use playground_types::chart::{ChartData, DataValues, Dataset};
+
+/// Evenly spaced bins over `[lo, hi]`
+pub struct Bins {
+    pub lo: f64,
+    pub hi: f64,
+    pub count: u32,
+}
+
+impl Bins {
+    /// Bins over a column's range, or a single bin when the range is empty
+    pub fn new(lo: f64, hi: f64, count: u32) -> Self {
+        let count = if hi > lo { count.max(1) } else { 1 };
+        Self { lo, hi, count }
+    }
+
+    pub fn width(&self) -> f64 {
+        (self.hi - self.lo) / self.count as f64
+    }
+
+    /// SQL expression mapping `value` to its bin index, clamping values
+    /// outside the range into the first or last bin
+    pub fn index_sql(&self, value: &str) -> String {
+        if self.count == 1 {
+            return "0".to_string();
+        }
+        format!(
+            "GREATEST(0, LEAST({}, FLOOR(({}::DOUBLE - {}) / {})))::INTEGER",
+            self.count - 1,
+            value,
+            self.lo,
+            self.width()
+        )
+    }
+
+    /// Labels such as `10–20` for each bin
+    pub fn labels(&self) -> Vec<String> {
+        if self.count == 1 && self.hi <= self.lo {
+            return vec![format_edge(self.lo)];
+        }
+        (0..self.count)
+            .map(|i| {
+                let from = self.lo + self.width() * i as f64;
+                let to = if i + 1 == self.count {
+                    self.hi
+                } else {
+                    self.lo + self.width() * (i + 1) as f64
+                };
+                format!("{}–{}", format_edge(from), format_edge(to))
+            })
+            .collect()
+    }
+
+    /// Chart data from `(bin index, count)` pairs; missing bins count 0
+    pub fn chart_data(&self, label: &str, counts: &[(usize, u64)]) -> ChartData {
+        let mut values = vec![0.0; self.count as usize];
+        for &(bin, count) in counts {
+            if let Some(value) = values.get_mut(bin) {
+                *value += count as f64;
+            }
+        }
+        ChartData {
+            labels: self.labels(),
+            datasets: vec![Dataset {
+                label: label.to_string(),
+                data: DataValues::Numbers(values.into_iter().map(Some).collect()),
+                style: None,
+                y_axis_id: None,
+            }],
+        }
+    }
+}
+
+/// Format a bin edge with at most two decimals
+fn format_edge(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_equal_width_bins() {
+        let bins = Bins::new(0.0, 30.0, 3);
+        assert_eq!(bins.labels(), vec!["0–10", "10–20", "20–30"]);
+        let data = bins.chart_data("speed", &[(0, 4), (2, 1)]);
+        match &data.datasets[0].data {
+            DataValues::Numbers(values) => {
+                assert_eq!(values, &vec![Some(4.0), Some(0.0), Some(1.0)])
+            }
+            other => panic!("unexpected data {:?}", other),
+        }
+    }
+
+    #[test]
+    fn single_value_column_has_one_bin() {
+        let bins = Bins::new(7.5, 7.5, 10);
+        assert_eq!(bins.count, 1);
+        assert_eq!(bins.labels(), vec!["7.5"]);
+        assert_eq!(bins.index_sql("x"), "0");
+    }
+}
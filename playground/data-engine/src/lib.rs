@@ -5,9 +5,59 @@
 
 use wasm_bindgen::prelude::*;
 
+mod arrow;
+mod autocomplete;
+mod batch;
+mod cache;
+mod connection;
+mod convert;
+mod cursor;
+mod date_range;
+mod duckdb;
+mod engine;
+mod error;
+mod explain;
+mod export;
+mod fetch;
+mod guard;
+mod histogram;
+mod normalize;
+mod params;
+mod policy;
+mod progress;
+mod relationships;
+mod retry;
+mod sample;
+mod schema;
+mod settings;
+mod sql;
+mod staged;
+mod timeout;
+mod timezone;
+mod view;
+
+pub use duckdb::{AsyncDuckDB, AsyncDuckDBConnection};
+pub use engine::DataEngine;
+
 // Re-export types
 pub use playground_types::data::*;
 
+/// Fetch and parse the `DataManifest` at `url`
+#[wasm_bindgen(js_name = fetchManifest)]
+pub async fn fetch_manifest(url: String) -> Result<JsValue, JsValue> {
+    let manifest = fetch::fetch_manifest(&url).await.map_err(error::into_js_error)?;
+    convert::to_js(&manifest)
+}
+
+/// Filter `sql` to a `DateRangeParam`, returning the rewritten query
+///
+/// Fails with `ErrorCode::InvalidQuery` if a bound isn't an ISO-8601 date.
+#[wasm_bindgen(js_name = applyDateRange)]
+pub fn apply_date_range(sql: String, range: JsValue) -> Result<String, JsValue> {
+    let range: DateRangeParam = convert::from_js(&range).map_err(error::into_js_error)?;
+    date_range::apply_date_range(&sql, &range).map_err(error::into_js_error)
+}
+
 /// Initialize the data engine
 #[wasm_bindgen(start)]
 pub fn init() {
@@ -19,8 +69,3 @@ pub fn init() {
 
     log::info!("DataEngine initialized");
 }
-
-// TODO: Implement DuckDB-WASM integration
-// - Load Parquet files
-// - Execute SQL queries
-// - Return typed results
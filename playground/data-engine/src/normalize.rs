@@ -0,0 +1,267 @@
+//! Canonical JSON forms for result values
+
+use playground_types::data::{ColumnType, QueryResult, QueryWarning, WarningCode};
+use serde_json::Value;
+
+/// Largest integer a JS number holds exactly (2^53 - 1)
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// Coerce every value in `result` to its column type's canonical JSON form
+///
+/// Integers become JSON numbers, or strings when outside the safe integer
+/// range (with an `ImplicitCast` warning per column); decimals become
+/// strings; dates and timestamps, which arrive as epoch milliseconds,
+/// become ISO-8601 strings. Timestamps selected in a time zone, as
+/// `<epoch ms> <offset seconds>`, keep the zone's offset. Nulls and values
+/// already in canonical form are left alone.
+pub fn normalize_result(result: &mut QueryResult) {
+    let mut unsafe_counts = vec![0u32; result.columns.len()];
+    for row in &mut result.rows {
+        for ((value, column), unsafe_count) in
+            row.iter_mut().zip(&result.columns).zip(&mut unsafe_counts)
+        {
+            if let Some(normalized) = normalize_value(value, &column.data_type) {
+                *value = normalized;
+            }
+            if column.data_type == ColumnType::Int64 && value.is_string() {
+                *unsafe_count += 1;
+            }
+        }
+    }
+    for (column, count) in result.columns.iter().zip(unsafe_counts) {
+        if count > 0 {
+            result.warnings.push(QueryWarning {
+                code: WarningCode::ImplicitCast,
+                message: format!(
+                    "{} values in {} are beyond ±2^53 and are returned as strings",
+                    count, column.name
+                ),
+            });
+        }
+    }
+}
+
+/// The canonical form of `value`, or `None` if it is already canonical
+fn normalize_value(value: &Value, data_type: &ColumnType) -> Option<Value> {
+    match (data_type, value) {
+        (ColumnType::Int8 | ColumnType::Int16 | ColumnType::Int32 | ColumnType::Int64, _) => {
+            normalize_integer(value)
+        }
+        (ColumnType::Float32 | ColumnType::Float64, Value::String(s)) => s
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        (ColumnType::Decimal { .. }, Value::Number(n)) => Some(Value::String(n.to_string())),
+        (ColumnType::Date, Value::Number(n)) => {
+            let ms = n.as_f64()? as i64;
+            Some(Value::String(iso_date(ms.div_euclid(MS_PER_DAY))))
+        }
+        (ColumnType::Timestamp, Value::Number(n)) => {
+            Some(Value::String(iso_timestamp(n.as_f64()? as i64)))
+        }
+        (ColumnType::Timestamp, Value::String(s)) => {
+            let (ms, offset) = s.split_once(' ')?;
+            Some(Value::String(iso_timestamp_with_offset(
+                ms.parse().ok()?,
+                offset.parse().ok()?,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// An integer as a JSON number if it is safe, else as an exact string
+fn normalize_integer(value: &Value) -> Option<Value> {
+    let exact = match value {
+        Value::Number(n) if n.is_i64() || n.is_u64() => {
+            return (magnitude(n) > MAX_SAFE_INTEGER).then(|| Value::String(n.to_string()));
+        }
+        // Integral floats, e.g. `3.0`
+        Value::Number(n) => {
+            let f = n.as_f64()?;
+            if f.fract() != 0.0 || f.abs() > MAX_SAFE_INTEGER as f64 {
+                return None;
+            }
+            f as i64
+        }
+        Value::String(s) => match s.parse::<i64>() {
+            Ok(i) if i.unsigned_abs() <= MAX_SAFE_INTEGER => i,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(Value::from(exact))
+}
+
+fn magnitude(n: &serde_json::Number) -> u64 {
+    n.as_u64()
+        .or_else(|| n.as_i64().map(i64::unsigned_abs))
+        .unwrap_or(u64::MAX)
+}
+
+/// `YYYY-MM-DD` for a count of days since 1970-01-01
+fn iso_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// `YYYY-MM-DDTHH:MM:SS.sssZ` for milliseconds since the epoch
+fn iso_timestamp(ms: i64) -> String {
+    let days = ms.div_euclid(MS_PER_DAY);
+    let ms = ms.rem_euclid(MS_PER_DAY);
+    format!(
+        "{}T{:02}:{:02}:{:02}.{:03}Z",
+        iso_date(days),
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// `YYYY-MM-DDTHH:MM:SS.sss±HH:MM` for milliseconds since the epoch, as
+/// local time `offset_seconds` from UTC
+fn iso_timestamp_with_offset(ms: i64, offset_seconds: i64) -> String {
+    let local = iso_timestamp(ms + offset_seconds * 1000);
+    let sign = if offset_seconds < 0 { '-' } else { '+' };
+    let offset = offset_seconds.unsigned_abs();
+    format!(
+        "{}{}{:02}:{:02}",
+        local.trim_end_matches('Z'),
+        sign,
+        offset / 3600,
+        offset / 60 % 60
+    )
+}
+
+/// Proleptic Gregorian date for days since the epoch (Howard Hinnant's
+/// `civil_from_days`)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use playground_types::data::ColumnSchema;
+    use serde_json::json;
+
+    fn result(columns: &[(&str, ColumnType)], rows: Vec<Vec<Value>>) -> QueryResult {
+        let columns = columns
+            .iter()
+            .map(|(name, data_type)| ColumnSchema {
+                name: name.to_string(),
+                data_type: data_type.clone(),
+                nullable: true,
+                display_hint: None,
+            })
+            .collect();
+        QueryResult::page(columns, rows, 2, 0)
+    }
+
+    #[test]
+    fn big_integers_become_strings() {
+        let above = (1u64 << 53) + 1;
+        let mut result = result(
+            &[
+                ("parcel_id", ColumnType::Int64),
+                ("acres", ColumnType::Float64),
+            ],
+            vec![
+                vec![json!(above), json!("2.5")],
+                vec![json!(42.0), json!(null)],
+            ],
+        );
+        normalize_result(&mut result);
+        assert_eq!(result.rows[0], vec![json!("9007199254740993"), json!(2.5)]);
+        assert_eq!(result.rows[1], vec![json!(42), json!(null)]);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].code, WarningCode::ImplicitCast);
+        assert!(result.warnings[0].message.contains("parcel_id"));
+
+        // Normalizing again changes nothing
+        let mut again = result.clone();
+        again.warnings.clear();
+        normalize_result(&mut again);
+        assert_eq!(again.rows, result.rows);
+        assert_eq!(again.warnings.len(), 1);
+    }
+
+    #[test]
+    fn dates_and_decimals_become_strings() {
+        let mut result = result(
+            &[
+                (
+                    "assessed",
+                    ColumnType::Decimal {
+                        precision: 12,
+                        scale: 2,
+                    },
+                ),
+                ("sale_date", ColumnType::Date),
+                ("recorded_at", ColumnType::Timestamp),
+            ],
+            vec![
+                vec![
+                    json!(1250.5),
+                    json!(1_704_067_200_000i64),
+                    json!(1_704_112_496_789i64),
+                ],
+                vec![json!("99.95"), json!("2024-02-29"), json!(-1)],
+            ],
+        );
+        normalize_result(&mut result);
+        assert_eq!(
+            result.rows[0],
+            vec![
+                json!("1250.5"),
+                json!("2024-01-01"),
+                json!("2024-01-01T12:34:56.789Z")
+            ]
+        );
+        assert_eq!(
+            result.rows[1],
+            vec![
+                json!("99.95"),
+                json!("2024-02-29"),
+                json!("1969-12-31T23:59:59.999Z")
+            ]
+        );
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn timestamps_keep_requested_zone() {
+        // 2024-01-01T12:34:56.789Z and 2024-07-04T16:00:00Z as selected
+        // with `SET TimeZone = 'America/New_York'`
+        let mut result = result(
+            &[("recorded_at", ColumnType::Timestamp)],
+            vec![
+                vec![json!("1704112496789 -18000")],
+                vec![json!("1720108800000 -14400")],
+                vec![json!("1720108800000 19800")],
+            ],
+        );
+        normalize_result(&mut result);
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![json!("2024-01-01T07:34:56.789-05:00")],
+                vec![json!("2024-07-04T12:00:00.000-04:00")],
+                vec![json!("2024-07-04T21:30:00.000+05:30")],
+            ]
+        );
+    }
+}
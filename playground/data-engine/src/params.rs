@@ -0,0 +1,134 @@
+//! Binding JSON query parameters to `?` placeholders
+
+use crate::sql::scan_code;
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+/// A value bound to a prepared statement placeholder
+#[derive(Clone, Debug, PartialEq)]
+pub enum Param {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl Param {
+    /// Convert into the JS value DuckDB-WASM binds
+    ///
+    /// Integers are passed as `BigInt` so they bind as `BIGINT` rather than
+    /// `DOUBLE`.
+    pub fn to_js(&self) -> JsValue {
+        match self {
+            Param::Null => JsValue::NULL,
+            Param::Bool(b) => JsValue::from_bool(*b),
+            Param::Int(n) => js_sys::BigInt::from(*n).into(),
+            Param::Float(n) => JsValue::from_f64(*n),
+            Param::Text(s) => JsValue::from_str(s),
+        }
+    }
+}
+
+/// Check `params` against the placeholders in `sql` and convert them
+pub fn bind_params(sql: &str, params: &[Value]) -> Result<Vec<Param>, ErrorInfo> {
+    let expected = count_placeholders(sql);
+    if expected != params.len() {
+        return Err(ErrorInfo {
+            code: ErrorCode::InvalidQuery,
+            message: format!(
+                "Query has {} parameter placeholder(s) but {} value(s) were given",
+                expected,
+                params.len()
+            ),
+            details: None,
+        });
+    }
+    params
+        .iter()
+        .enumerate()
+        .map(|(i, value)| param(value).ok_or_else(|| unsupported_param(i, value)))
+        .collect()
+}
+
+/// Map a JSON value to a bindable parameter
+fn param(value: &Value) -> Option<Param> {
+    match value {
+        Value::Null => Some(Param::Null),
+        Value::Bool(b) => Some(Param::Bool(*b)),
+        Value::Number(n) => n
+            .as_i64()
+            .map(Param::Int)
+            .or_else(|| n.as_f64().map(Param::Float)),
+        Value::String(s) => Some(Param::Text(s.clone())),
+        Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+fn unsupported_param(index: usize, value: &Value) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::InvalidQuery,
+        message: format!("Parameter {} has an unsupported type", index + 1),
+        details: Some(value.to_string()),
+    }
+}
+
+/// Count `?` placeholders outside string literals, quoted identifiers and comments
+pub fn count_placeholders(sql: &str) -> usize {
+    let mut count = 0;
+    scan_code(sql, |_, c| {
+        if c == '?' {
+            count += 1;
+        }
+    });
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn binds_string_with_single_quote() {
+        let params = bind_params(
+            "SELECT * FROM parcels WHERE owner = ? AND note <> 'what?'",
+            &[json!("O'Brien")],
+        )
+        .unwrap();
+        assert_eq!(params, vec![Param::Text("O'Brien".to_string())]);
+    }
+
+    #[test]
+    fn maps_json_scalars() {
+        let params = bind_params(
+            "SELECT ?, ?, ?, ?",
+            &[json!(null), json!(true), json!(42), json!(1.5)],
+        )
+        .unwrap();
+        assert_eq!(
+            params,
+            vec![
+                Param::Null,
+                Param::Bool(true),
+                Param::Int(42),
+                Param::Float(1.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_arity_mismatch() {
+        let err = bind_params("SELECT ? -- or ?\n", &[json!(1), json!(2)]).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidQuery);
+    }
+
+    #[test]
+    fn ignores_quoted_and_commented_placeholders() {
+        assert_eq!(
+            count_placeholders(r#"SELECT "a?", 'it''s ?', ? /* ? */ FROM t WHERE x = ?"#),
+            2
+        );
+    }
+}
@@ -0,0 +1,572 @@
+//! Enforcing a `SqlPolicy` on user SQL before it runs
+
+use crate::sql::{leading_keyword, special_literal_len, split_statements};
+use playground_types::data::SqlPolicy;
+use playground_types::messages::{ErrorCode, ErrorInfo};
+
+/// Functions that read files or reach the network
+const FILE_FUNCTIONS: &[&str] = &[
+    "read_csv",
+    "read_csv_auto",
+    "sniff_csv",
+    "read_parquet",
+    "parquet_scan",
+    "parquet_metadata",
+    "parquet_schema",
+    "parquet_file_metadata",
+    "parquet_kv_metadata",
+    "read_json",
+    "read_json_auto",
+    "read_json_objects",
+    "read_json_objects_auto",
+    "read_ndjson",
+    "read_ndjson_auto",
+    "read_ndjson_objects",
+    "read_text",
+    "read_blob",
+    "read_xlsx",
+    "glob",
+    "st_read",
+    "delta_scan",
+    "iceberg_scan",
+    "sqlite_scan",
+    "postgres_scan",
+    "mysql_scan",
+    "getenv",
+];
+
+/// Statements that write files
+const COPY_STATEMENTS: &[&str] = &["COPY", "EXPORT"];
+
+/// Statements that attach files or fetch extensions
+const FILE_STATEMENTS: &[&str] = &["ATTACH", "DETACH", "INSTALL", "LOAD", "IMPORT", "FORCE"];
+
+/// Statements that change settings, such as `s3_endpoint`, which decide
+/// where file reads go
+const SETTING_STATEMENTS: &[&str] = &["SET", "RESET"];
+
+/// Keywords after which a string literal or path-like quoted name is read
+/// as a file, as after `FROM`
+const TABLE_KEYWORDS: &[&str] = &[
+    "from",
+    "join",
+    "summarize",
+    "describe",
+    "show",
+    "pivot",
+    "unpivot",
+    "lateral",
+];
+
+/// Keywords that end a `FROM` clause's list of tables
+const FROM_CLAUSE_ENDS: &[&str] = &[
+    "where",
+    "group",
+    "having",
+    "qualify",
+    "window",
+    "order",
+    "limit",
+    "offset",
+    "union",
+    "except",
+    "intersect",
+    "select",
+    "returning",
+    "set",
+];
+
+/// Directory the engine registers its own load and export files under,
+/// which stays usable once external access is locked
+pub const ENGINE_FILE_DIR: &str = "/engine/";
+
+/// Statements that stop DuckDB from touching files or the network at all,
+/// apart from the engine's own files in [`ENGINE_FILE_DIR`]
+pub const LOCK_EXTERNAL_ACCESS: [&str; 2] = [
+    "SET allowed_directories = ['/engine/']",
+    "SET enable_external_access = false",
+];
+
+/// Keywords that can sit before `(` without being a function call
+const KEYWORDS: &[&str] = &[
+    "all",
+    "and",
+    "any",
+    "array",
+    "as",
+    "between",
+    "by",
+    "case",
+    "cast",
+    "cube",
+    "decimal",
+    "else",
+    "except",
+    "exists",
+    "extract",
+    "filter",
+    "from",
+    "grouping",
+    "having",
+    "in",
+    "intersect",
+    "interval",
+    "join",
+    "lateral",
+    "limit",
+    "list",
+    "map",
+    "not",
+    "numeric",
+    "offset",
+    "on",
+    "or",
+    "over",
+    "partition",
+    "pivot",
+    "qualify",
+    "recursive",
+    "repeatable",
+    "returning",
+    "rollup",
+    "row",
+    "select",
+    "sets",
+    "some",
+    "struct",
+    "table",
+    "tablesample",
+    "then",
+    "try_cast",
+    "union",
+    "unpivot",
+    "using",
+    "values",
+    "varchar",
+    "when",
+    "where",
+    "window",
+    "with",
+    "within",
+];
+
+/// Check every statement of `sql` against `policy`
+///
+/// Fails with `ErrorCode::AuthError` naming the first statement or
+/// function the policy forbids. A string literal or path-like quoted name
+/// where a table belongs (after `FROM`, `JOIN`, `SUMMARIZE`, `PIVOT` and
+/// the like, or a comma in the `FROM` list) counts as a file function,
+/// since DuckDB reads it as a file. Without file functions, settings and
+/// secrets can't be changed either.
+pub fn check_sql(policy: &SqlPolicy, sql: &str) -> Result<(), ErrorInfo> {
+    for statement in split_statements(sql) {
+        let keyword = leading_keyword(statement);
+        if !policy.allow_copy && COPY_STATEMENTS.contains(&keyword.as_str()) {
+            return Err(forbidden(format!("{} statements are not allowed", keyword)));
+        }
+        if !policy.allow_file_functions
+            && (FILE_STATEMENTS.contains(&keyword.as_str())
+                || SETTING_STATEMENTS.contains(&keyword.as_str()))
+        {
+            return Err(forbidden(format!("{} statements are not allowed", keyword)));
+        }
+        let tokens = tokenize(statement);
+        if !policy.allow_file_functions && is_secret_statement(&tokens) {
+            return Err(forbidden("SECRET statements are not allowed".to_string()));
+        }
+        // Per parenthesis depth, whether we are inside a FROM clause
+        let mut in_from = vec![false];
+        let mut table_position = false;
+        for (i, token) in tokens.iter().enumerate() {
+            let next = tokens.get(i + 1);
+            if table_position && !policy.allow_file_functions && reads_file(token) {
+                return Err(path_forbidden());
+            }
+            table_position = false;
+            match token {
+                Token::Punct('(') => in_from.push(false),
+                Token::Punct(')') if in_from.len() > 1 => {
+                    in_from.pop();
+                }
+                Token::Punct(',') => table_position = *in_from.last().unwrap_or(&false),
+                Token::Word(word) => {
+                    let word = word.to_lowercase();
+                    if word == "from" || word == "join" {
+                        *in_from.last_mut().unwrap() = true;
+                        table_position = true;
+                    } else if TABLE_KEYWORDS.contains(&word.as_str()) {
+                        table_position = true;
+                    } else if FROM_CLAUSE_ENDS.contains(&word.as_str()) {
+                        *in_from.last_mut().unwrap() = false;
+                    }
+                }
+                _ => {}
+            }
+            match token {
+                Token::Word(word) if next == Some(&Token::Punct('(')) && !is_keyword(word) => {
+                    check_function(policy, &word.to_lowercase())?;
+                }
+                Token::Quoted(name) if next == Some(&Token::Punct('(')) => {
+                    check_function(policy, &name.to_lowercase())?;
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check a table name passed to `sample`, `profileColumn` or `histogram`,
+/// which is quoted into `FROM` and so could name a file
+pub fn check_table_name(policy: &SqlPolicy, table: &str) -> Result<(), ErrorInfo> {
+    if !policy.allow_file_functions && is_path_like(table) {
+        return Err(path_forbidden());
+    }
+    Ok(())
+}
+
+/// Whether the statement creates or drops a secret, as in
+/// `CREATE OR REPLACE PERSISTENT SECRET`
+fn is_secret_statement(tokens: &[Token]) -> bool {
+    let mut words = tokens.iter().map_while(|token| match token {
+        Token::Word(word) => Some(word.to_lowercase()),
+        _ => None,
+    });
+    matches!(words.next().as_deref(), Some("create" | "drop"))
+        && words
+            .find(|word| {
+                !matches!(
+                    word.as_str(),
+                    "or" | "replace" | "persistent" | "temporary" | "temp"
+                )
+            })
+            .as_deref()
+            == Some("secret")
+}
+
+/// Whether DuckDB would read `token` in table position as a file
+fn reads_file(token: &Token) -> bool {
+    match token {
+        Token::Str => true,
+        Token::Quoted(name) => is_path_like(name),
+        _ => false,
+    }
+}
+
+/// A name DuckDB's replacement scans may treat as a file path or URL
+fn is_path_like(name: &str) -> bool {
+    name.contains(['/', '\\', ':', '.'])
+}
+
+fn path_forbidden() -> ErrorInfo {
+    forbidden("Reading files by path is not allowed".to_string())
+}
+
+fn check_function(policy: &SqlPolicy, name: &str) -> Result<(), ErrorInfo> {
+    if !policy.allow_file_functions && FILE_FUNCTIONS.contains(&name) {
+        return Err(forbidden(format!("Function {} is not allowed", name)));
+    }
+    match &policy.allowed_functions {
+        Some(allowed) if !allowed.contains(name) => {
+            Err(forbidden(format!("Function {} is not allowed", name)))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS.contains(&word.to_lowercase().as_str())
+}
+
+fn forbidden(message: String) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::AuthError,
+        message,
+        details: Some("Blocked by the playground's SQL policy".to_string()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// Bare identifier or keyword; schema prefixes are dropped
+    Word(String),
+    /// Double-quoted identifier, which DuckDB also accepts as a function name
+    Quoted(String),
+    /// String literal
+    Str,
+    Punct(char),
+}
+
+/// Split a statement into words, quoted names, literals and punctuation,
+/// skipping whitespace and comments
+fn tokenize(sql: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        // Names run on over `e` and `$`, so this only sees a token's start
+        if let Some(len) = special_literal_len(&sql[i..]) {
+            while chars.next_if(|&(j, _)| j < i + len).is_some() {}
+            tokens.push(Token::Str);
+            continue;
+        }
+        match c {
+            '\'' => {
+                // A doubled quote re-enters the literal as a second token
+                for (_, d) in chars.by_ref() {
+                    if d == '\'' {
+                        break;
+                    }
+                }
+                if tokens.last() != Some(&Token::Str) {
+                    tokens.push(Token::Str);
+                }
+            }
+            '"' => {
+                let mut name = String::new();
+                while let Some((_, d)) = chars.next() {
+                    if d == '"' {
+                        if matches!(chars.peek(), Some((_, '"'))) {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    name.push(d);
+                }
+                push_name(&mut tokens, Token::Quoted(name));
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                for (_, d) in chars.by_ref() {
+                    if d == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                let mut prev = ' ';
+                for (_, d) in chars.by_ref() {
+                    if prev == '*' && d == '/' {
+                        break;
+                    }
+                    prev = d;
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&(_, d)) = chars.peek() {
+                    if !(d.is_alphanumeric() || d == '_' || d == '$') {
+                        break;
+                    }
+                    word.push(d);
+                    chars.next();
+                }
+                push_name(&mut tokens, Token::Word(word));
+            }
+            c if c.is_whitespace() => {}
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    tokens
+}
+
+/// Push a name, replacing `schema.` before it so only the last part remains
+fn push_name(tokens: &mut Vec<Token>, name: Token) {
+    if tokens.last() == Some(&Token::Punct('.')) {
+        tokens.pop();
+        if matches!(tokens.last(), Some(Token::Word(_) | Token::Quoted(_))) {
+            tokens.pop();
+        }
+    }
+    tokens.push(name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn blocked(policy: &SqlPolicy, sql: &str) -> bool {
+        match check_sql(policy, sql) {
+            Ok(()) => false,
+            Err(err) => {
+                assert_eq!(err.code, ErrorCode::AuthError);
+                true
+            }
+        }
+    }
+
+    #[test]
+    fn default_policy_blocks_file_access() {
+        let policy = SqlPolicy::default();
+        assert!(blocked(&policy, "SELECT * FROM read_csv('/etc/passwd')"));
+        assert!(blocked(
+            &policy,
+            "SELECT * FROM READ_CSV_AUTO ('s3://bucket/x.csv')"
+        ));
+        assert!(blocked(
+            &policy,
+            "SELECT * FROM main.\"read_text\"('notes.txt')"
+        ));
+        assert!(blocked(
+            &policy,
+            "SELECT * FROM 'https://example.com/x.parquet'"
+        ));
+        assert!(blocked(&policy, "SELECT 1; COPY parcels TO 'out.csv'"));
+        assert!(blocked(&policy, "INSTALL httpfs"));
+
+        let err = check_sql(&policy, "SELECT * FROM read_csv('/etc/passwd')").unwrap_err();
+        assert_eq!(err.message, "Function read_csv is not allowed");
+
+        let open = SqlPolicy {
+            allow_copy: true,
+            allow_file_functions: true,
+            allowed_functions: None,
+        };
+        assert!(!blocked(&open, "SELECT * FROM read_csv('/etc/passwd')"));
+        assert!(!blocked(&open, "COPY parcels TO 'out.csv'"));
+    }
+
+    #[test]
+    fn blocks_paths_anywhere_in_from_list() {
+        let policy = SqlPolicy::default();
+        for sql in [
+            "SELECT * FROM budget, '/etc/passwd'",
+            "SELECT * FROM budget b, parcels p, 'https://example.com/x.csv'",
+            "SELECT * FROM \"x.csv\"",
+            "SELECT * FROM budget JOIN main.\"../data/parcels.parquet\" USING (id)",
+            "SELECT * FROM budget b JOIN parcels p ON b.id = p.id, 'x.parquet'",
+            "SELECT * FROM (SELECT 1 FROM budget, \"s3://bucket/x.json\")",
+        ] {
+            let err = check_sql(&policy, sql).unwrap_err();
+            assert_eq!(err.message, "Reading files by path is not allowed", "{sql}");
+        }
+
+        // Literals and quoted names elsewhere are fine
+        for sql in [
+            "SELECT 'a', 'b' FROM budget WHERE name IN ('x.csv', 'y')",
+            "SELECT \"total.amount\" FROM \"Budget Lines\", parcels",
+            "SELECT * FROM budget ORDER BY 1, 2 LIMIT 5",
+            "SELECT * FROM budget GROUP BY ALL, 'x.csv'",
+        ] {
+            assert!(!blocked(&policy, sql), "{sql}");
+        }
+    }
+
+    #[test]
+    fn sees_through_escape_and_dollar_quoted_strings() {
+        let policy = SqlPolicy::default();
+        for sql in [
+            "SELECT $$'$$, * FROM read_text('/etc/passwd')",
+            "SELECT $q$'$q$, * FROM read_text('/etc/passwd')",
+            "SELECT E'\\'', * FROM read_csv('https://evil.example/x.csv')",
+            "SELECT $$;$$, * FROM read_text('/etc/passwd')",
+        ] {
+            let err = check_sql(&policy, sql).unwrap_err();
+            assert!(err.message.starts_with("Function read_"), "{sql}");
+        }
+        assert!(!blocked(
+            &policy,
+            "SELECT $$read_csv('x')$$, E'it\\'s read_text(', $1 FROM budget"
+        ));
+    }
+
+    #[test]
+    fn blocks_paths_after_table_keywords() {
+        let policy = SqlPolicy::default();
+        for sql in [
+            "SUMMARIZE 'https://evil.example/x.csv'",
+            "DESCRIBE 'https://evil.example/x.csv'",
+            "SHOW \"x.parquet\"",
+            "PIVOT 'x.csv' ON a USING sum(b)",
+            "UNPIVOT 'x.csv' ON COLUMNS(*) INTO NAME k VALUE v",
+            "SELECT * FROM budget, LATERAL 'x.csv'",
+        ] {
+            let err = check_sql(&policy, sql).unwrap_err();
+            assert_eq!(err.message, "Reading files by path is not allowed", "{sql}");
+        }
+        for sql in [
+            "SUMMARIZE budget",
+            "DESCRIBE SELECT 'x.csv'",
+            "PIVOT budget ON year USING sum(amount)",
+        ] {
+            assert!(!blocked(&policy, sql), "{sql}");
+        }
+    }
+
+    #[test]
+    fn blocks_settings_and_secrets() {
+        let policy = SqlPolicy::default();
+        for sql in [
+            "SET s3_endpoint = 'evil.example'",
+            "SET GLOBAL enable_external_access = true",
+            "RESET allowed_directories",
+            "CREATE SECRET (TYPE S3, KEY_ID 'k', SECRET 's')",
+            "CREATE OR REPLACE PERSISTENT SECRET s (TYPE S3)",
+            "DROP TEMPORARY SECRET s",
+        ] {
+            assert!(blocked(&policy, sql), "{sql}");
+        }
+        assert!(!blocked(
+            &policy,
+            "CREATE TABLE secrets AS SELECT 1 AS secret"
+        ));
+        let open = SqlPolicy {
+            allow_file_functions: true,
+            ..SqlPolicy::default()
+        };
+        assert!(!blocked(&open, "SET s3_region = 'us-east-1'"));
+        assert!(!blocked(&open, "CREATE SECRET (TYPE S3)"));
+    }
+
+    #[test]
+    fn lock_keeps_engine_files_readable() {
+        assert_eq!(
+            LOCK_EXTERNAL_ACCESS[0],
+            format!("SET allowed_directories = ['{}']", ENGINE_FILE_DIR)
+        );
+    }
+
+    #[test]
+    fn checks_table_names() {
+        let policy = SqlPolicy::default();
+        assert!(check_table_name(&policy, "parcels").is_ok());
+        assert!(check_table_name(&policy, "Budget Lines").is_ok());
+        for table in ["/etc/passwd", "x.csv", "https://example.com/x.parquet"] {
+            assert_eq!(
+                check_table_name(&policy, table).unwrap_err().code,
+                ErrorCode::AuthError
+            );
+        }
+        let open = SqlPolicy {
+            allow_file_functions: true,
+            ..SqlPolicy::default()
+        };
+        assert!(check_table_name(&open, "x.csv").is_ok());
+    }
+
+    #[test]
+    fn default_policy_allows_analytics() {
+        let policy = SqlPolicy::default();
+        let sql =
+            "WITH t AS (SELECT district, SUM(amount) FILTER (WHERE year IN (2023, 2024)) AS total
+                   FROM budget -- read_csv('x') in a comment
+                   WHERE note <> 'read_text(''y'')'
+                   GROUP BY ROLLUP (district))
+                   SELECT *, CAST(total AS DECIMAL(12, 2)), rank() OVER (ORDER BY total) FROM t";
+        assert!(!blocked(&policy, sql));
+    }
+
+    #[test]
+    fn allowlist_limits_functions() {
+        let policy = SqlPolicy {
+            allowed_functions: Some(HashSet::from(["sum".to_string(), "count".to_string()])),
+            ..SqlPolicy::default()
+        };
+        assert!(!blocked(
+            &policy,
+            "SELECT district, SUM(amount), COUNT(*) FROM budget WHERE year IN (2024) GROUP BY 1"
+        ));
+        assert!(blocked(&policy, "SELECT avg(amount) FROM budget"));
+    }
+}
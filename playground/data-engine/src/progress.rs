@@ -0,0 +1,71 @@
+//! Throttled query progress reporting
+
+use crate::convert::to_js;
+use playground_types::messages::{MessageId, QueryProgress};
+use wasm_bindgen::prelude::*;
+
+/// Minimum time between progress events
+const PROGRESS_INTERVAL_MS: f64 = 100.0;
+
+/// Reports progress of one running query to a JS callback
+pub struct ProgressReporter {
+    id: MessageId,
+    callback: Option<js_sys::Function>,
+    started: f64,
+    last_emit: f64,
+    total_rows: Option<u64>,
+}
+
+impl ProgressReporter {
+    /// Start timing a query; events are dropped if `callback` is `None`
+    pub fn new(id: MessageId, callback: Option<js_sys::Function>) -> Self {
+        let started = js_sys::Date::now();
+        Self {
+            id,
+            callback,
+            started,
+            last_emit: started,
+            total_rows: None,
+        }
+    }
+
+    /// Set the expected row count used to compute `percent`
+    pub fn set_total_rows(&mut self, total_rows: u64) {
+        self.total_rows = Some(total_rows);
+    }
+
+    /// Report rows read so far, at most once per `PROGRESS_INTERVAL_MS`
+    pub fn update(&mut self, rows_processed: u64) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let now = js_sys::Date::now();
+        if now - self.last_emit < PROGRESS_INTERVAL_MS {
+            return;
+        }
+        self.last_emit = now;
+
+        let percent = self.total_rows.map(|total| {
+            if total == 0 {
+                100.0
+            } else {
+                (rows_processed as f32 / total as f32 * 100.0).min(100.0)
+            }
+        });
+        let event = QueryProgress {
+            id: self.id.clone(),
+            rows_processed,
+            elapsed_ms: (now - self.started) as u32,
+            percent,
+        };
+        match to_js(&event) {
+            Ok(event) => {
+                // A throwing callback must not fail the query
+                if let Err(err) = callback.call1(&JsValue::NULL, &event) {
+                    log::warn!("progress callback failed: {:?}", err);
+                }
+            }
+            Err(err) => log::warn!("failed to serialize progress: {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Suggested joins between loaded tables
+
+use crate::schema::quote_ident;
+use playground_types::data::{ColumnSchema, ColumnType, RelationshipHint, TableSchema};
+
+/// Column name endings that mark a key, e.g. `county_fips`
+const KEY_SUFFIXES: &[&str] = &["_id", "_fips", "_code", "_key", "_no", "_number"];
+
+/// Distinct values sampled from each side when measuring overlap
+pub const OVERLAP_SAMPLE: u32 = 1000;
+
+/// Share of the final confidence that comes from value overlap rather
+/// than names
+const OVERLAP_WEIGHT: f32 = 0.6;
+
+/// Likely joins between columns of different tables, judged by names and
+/// types alone, most likely first
+///
+/// Columns must hold join-friendly types (integers, strings, whole
+/// decimals) of the same family. Shared key-like names such as
+/// `county_fips` rank highest, then `parcel_id` against `parcels.id`, then
+/// other shared names. A column named `id` in both tables is each table's
+/// own key and is not suggested. Refine the result with
+/// [`apply_overlap`].
+pub fn infer_relationships(schemas: &[TableSchema]) -> Vec<RelationshipHint> {
+    let mut hints = Vec::new();
+    for (i, left) in schemas.iter().enumerate() {
+        for right in &schemas[i + 1..] {
+            for l in &left.columns {
+                for r in &right.columns {
+                    if let Some(confidence) = confidence(left, l, right, r) {
+                        hints.push(RelationshipHint {
+                            left_table: left.name.clone(),
+                            left_column: l.name.clone(),
+                            right_table: right.name.clone(),
+                            right_column: r.name.clone(),
+                            confidence,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    hints.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    hints
+}
+
+/// Query measuring how many of a hint's values appear on the other side
+///
+/// Up to [`OVERLAP_SAMPLE`] distinct non-null values are sampled from each
+/// column and looked up among all distinct values of the other. Values
+/// compare as text, so `INTEGER` and `BIGINT` keys match. Returns one row
+/// of `left_sampled`, `left_matched`, `right_sampled` and `right_matched`.
+pub fn overlap_sql(hint: &RelationshipHint) -> String {
+    let distinct = |table: &str, column: &str| {
+        let column = quote_ident(column);
+        format!(
+            "SELECT DISTINCT CAST({column} AS VARCHAR) AS v FROM {} WHERE {column} IS NOT NULL",
+            quote_ident(table)
+        )
+    };
+    format!(
+        "WITH l AS ({}), r AS ({}), \
+         ls AS (SELECT v FROM l USING SAMPLE {n} ROWS), \
+         rs AS (SELECT v FROM r USING SAMPLE {n} ROWS) \
+         SELECT (SELECT COUNT(*) FROM ls) AS left_sampled, \
+         (SELECT COUNT(*) FROM ls WHERE v IN (SELECT v FROM r)) AS left_matched, \
+         (SELECT COUNT(*) FROM rs) AS right_sampled, \
+         (SELECT COUNT(*) FROM rs WHERE v IN (SELECT v FROM l)) AS right_matched",
+        distinct(&hint.left_table, &hint.left_column),
+        distinct(&hint.right_table, &hint.right_column),
+        n = OVERLAP_SAMPLE
+    )
+}
+
+/// Overlap ratio from the counts [`overlap_sql`] returns
+///
+/// The better of the two directions counts, so a foreign key whose values
+/// all appear among a larger table's keys scores 1. `None` if either
+/// column has no values to compare.
+pub fn overlap_ratio(
+    left_sampled: u64,
+    left_matched: u64,
+    right_sampled: u64,
+    right_matched: u64,
+) -> Option<f32> {
+    if left_sampled == 0 || right_sampled == 0 {
+        return None;
+    }
+    let left = left_matched as f32 / left_sampled as f32;
+    let right = right_matched as f32 / right_sampled as f32;
+    Some(left.max(right))
+}
+
+/// Blend a hint's name-based confidence with its value overlap and
+/// re-sort, most likely first
+///
+/// `overlaps` holds one entry per hint; hints whose overlap couldn't be
+/// measured keep their name-based confidence.
+pub fn apply_overlap(hints: &mut [RelationshipHint], overlaps: &[Option<f32>]) {
+    for (hint, overlap) in hints.iter_mut().zip(overlaps) {
+        if let Some(overlap) = overlap {
+            hint.confidence =
+                (1.0 - OVERLAP_WEIGHT) * hint.confidence + OVERLAP_WEIGHT * overlap.clamp(0.0, 1.0);
+        }
+    }
+    hints.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+}
+
+/// Confidence that `l` and `r` join, with a bonus for identical types
+fn confidence(
+    left: &TableSchema,
+    l: &ColumnSchema,
+    right: &TableSchema,
+    r: &ColumnSchema,
+) -> Option<f32> {
+    if !joinable(&l.data_type, &r.data_type) {
+        return None;
+    }
+    let same_type = l.data_type == r.data_type;
+    let (l_name, r_name) = (l.name.to_lowercase(), r.name.to_lowercase());
+    if l_name == r_name {
+        match l_name.as_str() {
+            "id" => None,
+            name if KEY_SUFFIXES.iter().any(|s| name.ends_with(s)) => {
+                Some(if same_type { 0.9 } else { 0.8 })
+            }
+            _ => Some(if same_type { 0.5 } else { 0.4 }),
+        }
+    } else if references(&l_name, &right.name, &r_name) || references(&r_name, &left.name, &l_name)
+    {
+        Some(if same_type { 0.8 } else { 0.7 })
+    } else {
+        None
+    }
+}
+
+/// Whether `column` names `table`'s `id`, as `parcel_id` does `parcels.id`
+fn references(column: &str, table: &str, key: &str) -> bool {
+    if key != "id" {
+        return false;
+    }
+    let table = table.to_lowercase();
+    let singular = match table.strip_suffix("ies") {
+        Some(stem) => format!("{}y", stem),
+        None => table.strip_suffix('s').unwrap_or(&table).to_string(),
+    };
+    column == format!("{}_id", singular)
+}
+
+/// Whether values of these types can be compared as keys
+fn joinable(a: &ColumnType, b: &ColumnType) -> bool {
+    fn family(ty: &ColumnType) -> Option<u8> {
+        match ty {
+            ColumnType::Int8 | ColumnType::Int16 | ColumnType::Int32 | ColumnType::Int64 => Some(0),
+            ColumnType::Decimal { scale: 0, .. } => Some(0),
+            ColumnType::String => Some(1),
+            _ => None,
+        }
+    }
+    matches!((family(a), family(b)), (Some(a), Some(b)) if a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use playground_types::data::TableKind;
+
+    fn table(name: &str, columns: &[(&str, ColumnType)]) -> TableSchema {
+        TableSchema {
+            name: name.to_string(),
+            columns: columns
+                .iter()
+                .map(|(name, data_type)| ColumnSchema {
+                    name: name.to_string(),
+                    data_type: data_type.clone(),
+                    nullable: true,
+                    display_hint: None,
+                })
+                .collect(),
+            row_count: 0,
+            kind: TableKind::Loaded,
+        }
+    }
+
+    #[test]
+    fn suggests_shared_key_column() {
+        let schemas = [
+            table(
+                "counties",
+                &[
+                    ("id", ColumnType::Int32),
+                    ("county_fips", ColumnType::String),
+                    ("name", ColumnType::String),
+                    ("area", ColumnType::Float64),
+                ],
+            ),
+            table(
+                "schools",
+                &[
+                    ("id", ColumnType::Int32),
+                    ("COUNTY_FIPS", ColumnType::String),
+                    ("county_id", ColumnType::Int64),
+                    ("area", ColumnType::Float64),
+                ],
+            ),
+        ];
+        let hints = infer_relationships(&schemas);
+        let pairs: Vec<(&str, &str, f32)> = hints
+            .iter()
+            .map(|h| {
+                (
+                    h.left_column.as_str(),
+                    h.right_column.as_str(),
+                    h.confidence,
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("county_fips", "COUNTY_FIPS", 0.9),
+                ("id", "county_id", 0.7)
+            ]
+        );
+        assert_eq!(hints[0].left_table, "counties");
+        assert_eq!(hints[0].right_table, "schools");
+    }
+
+    fn hint(left_column: &str, right_column: &str, confidence: f32) -> RelationshipHint {
+        RelationshipHint {
+            left_table: "schools".to_string(),
+            left_column: left_column.to_string(),
+            right_table: "districts".to_string(),
+            right_column: right_column.to_string(),
+            confidence,
+        }
+    }
+
+    #[test]
+    fn overlap_reranks_hints() {
+        let mut hints = vec![
+            hint("district_code", "district_code", 0.9),
+            hint("district_id", "id", 0.8),
+            hint("name", "name", 0.5),
+        ];
+        // Codes share no values; every school's district_id is a district;
+        // names weren't measured
+        let overlaps = [
+            overlap_ratio(40, 0, 5, 0),
+            overlap_ratio(40, 40, 5, 5),
+            None,
+        ];
+        apply_overlap(&mut hints, &overlaps);
+        let ranked: Vec<(&str, f32)> = hints
+            .iter()
+            .map(|h| {
+                (
+                    h.left_column.as_str(),
+                    (h.confidence * 100.0).round() / 100.0,
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                ("district_id", 0.92),
+                ("name", 0.5),
+                ("district_code", 0.36)
+            ]
+        );
+    }
+
+    #[test]
+    fn overlap_takes_the_better_direction() {
+        // 30 of 40 sampled school codes are districts; all 5 districts appear
+        assert_eq!(overlap_ratio(40, 30, 5, 5), Some(1.0));
+        assert_eq!(overlap_ratio(40, 30, 5, 0), Some(0.75));
+        assert_eq!(overlap_ratio(0, 0, 5, 5), None);
+
+        let sql = overlap_sql(&hint("district_code", "code", 0.9));
+        assert!(sql.starts_with(
+            "WITH l AS (SELECT DISTINCT CAST(\"district_code\" AS VARCHAR) AS v \
+             FROM \"schools\" WHERE \"district_code\" IS NOT NULL), \
+             r AS (SELECT DISTINCT CAST(\"code\" AS VARCHAR) AS v FROM \"districts\""
+        ));
+        assert!(sql.contains("ls AS (SELECT v FROM l USING SAMPLE 1000 ROWS)"));
+    }
+
+    #[test]
+    fn references_singular_table() {
+        assert!(references("parcel_id", "parcels", "id"));
+        assert!(references("county_id", "Counties", "id"));
+        assert!(!references("parcel_id", "parcels", "parcel_no"));
+    }
+}
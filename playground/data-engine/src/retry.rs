@@ -0,0 +1,175 @@
+//! Retrying transient fetch failures with exponential backoff
+
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use playground_types::storage::RetryPolicy;
+use std::future::Future;
+
+/// Delay in ms before retrying after `failures` failed attempts
+pub fn backoff_ms(policy: &RetryPolicy, failures: u32) -> u32 {
+    let factor = 1u32
+        .checked_shl(failures.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    policy
+        .base_delay_ms
+        .saturating_mul(factor)
+        .min(policy.max_delay_ms)
+}
+
+/// Whether a fetch error may succeed if tried again
+///
+/// Requests that never got a response and 5xx responses are transient;
+/// any other HTTP status is not.
+pub fn is_transient(err: &ErrorInfo) -> bool {
+    if err.code != ErrorCode::NetworkError {
+        return false;
+    }
+    let status = err
+        .details
+        .as_deref()
+        .and_then(|d| d.strip_prefix("HTTP "))
+        .and_then(|d| d.split_whitespace().next())
+        .and_then(|s| s.parse::<u16>().ok());
+    status.is_none_or(|status| status >= 500)
+}
+
+/// Run `attempt` until it succeeds, fails for good, or `policy` runs out
+///
+/// Waits with `sleep` between attempts. Once the attempts are exhausted
+/// the last error is reported as `ErrorCode::NetworkError`, with the
+/// attempt count in its `details`.
+pub async fn with_retry<T, F, Fut, S, SFut>(
+    policy: &RetryPolicy,
+    mut attempt: F,
+    mut sleep: S,
+) -> Result<T, ErrorInfo>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ErrorInfo>>,
+    S: FnMut(u32) -> SFut,
+    SFut: Future<Output = ()>,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut failures = 0;
+    loop {
+        let err = match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(err) if !is_transient(&err) => return Err(err),
+            Err(err) => err,
+        };
+        failures += 1;
+        if failures >= max_attempts {
+            return Err(ErrorInfo {
+                code: ErrorCode::NetworkError,
+                message: err.message,
+                details: Some(match err.details {
+                    Some(details) => format!("Gave up after {} attempts: {}", failures, details),
+                    None => format!("Gave up after {} attempts", failures),
+                }),
+            });
+        }
+        let delay = backoff_ms(policy, failures);
+        log::debug!("retrying in {} ms after attempt {} failed", delay, failures);
+        sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::cell::RefCell;
+
+    fn http_error(status: &str) -> ErrorInfo {
+        ErrorInfo {
+            code: ErrorCode::NetworkError,
+            message: "Failed to fetch https://data.virginia.gov/aadt.parquet".to_string(),
+            details: Some(format!("HTTP {}", status)),
+        }
+    }
+
+    /// Replays `results` one attempt at a time, recording each sleep
+    fn run(
+        policy: &RetryPolicy,
+        results: Vec<Result<&'static str, ErrorInfo>>,
+    ) -> (Result<&'static str, ErrorInfo>, usize, Vec<u32>) {
+        let results = RefCell::new(results.into_iter());
+        let attempts = RefCell::new(0);
+        let sleeps = RefCell::new(Vec::new());
+        let result = block_on(with_retry(
+            policy,
+            || {
+                *attempts.borrow_mut() += 1;
+                let next = results.borrow_mut().next().unwrap();
+                async move { next }
+            },
+            |ms| {
+                sleeps.borrow_mut().push(ms);
+                async {}
+            },
+        ));
+        (result, attempts.into_inner(), sleeps.into_inner())
+    }
+
+    #[test]
+    fn retries_until_success() {
+        let (result, attempts, sleeps) = run(
+            &RetryPolicy::default(),
+            vec![
+                Err(http_error("503 Service Unavailable")),
+                Err(ErrorInfo {
+                    code: ErrorCode::NetworkError,
+                    message: "Failed to fetch".to_string(),
+                    details: Some("TypeError: NetworkError".to_string()),
+                }),
+                Ok("parquet bytes"),
+            ],
+        );
+        assert_eq!(result.unwrap(), "parquet bytes");
+        assert_eq!(attempts, 3);
+        assert_eq!(sleeps, vec![500, 1000]);
+    }
+
+    #[test]
+    fn reports_attempts_when_exhausted() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            ..RetryPolicy::default()
+        };
+        let (result, attempts, _) = run(
+            &policy,
+            vec![
+                Err(http_error("502 Bad Gateway")),
+                Err(http_error("502 Bad Gateway")),
+            ],
+        );
+        let err = result.unwrap_err();
+        assert_eq!(attempts, 2);
+        assert_eq!(err.code, ErrorCode::NetworkError);
+        assert_eq!(
+            err.details.as_deref(),
+            Some("Gave up after 2 attempts: HTTP 502 Bad Gateway")
+        );
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let (result, attempts, sleeps) = run(
+            &RetryPolicy::default(),
+            vec![Err(http_error("403 Forbidden"))],
+        );
+        assert_eq!(
+            result.unwrap_err().details.as_deref(),
+            Some("HTTP 403 Forbidden")
+        );
+        assert_eq!(attempts, 1);
+        assert!(sleeps.is_empty());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<u32> = (1..=6).map(|n| backoff_ms(&policy, n)).collect();
+        assert_eq!(delays, vec![500, 1000, 2000, 4000, 8000, 8000]);
+        assert_eq!(backoff_ms(&policy, 40), 8000);
+    }
+}
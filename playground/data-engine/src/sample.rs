@@ -0,0 +1,74 @@
+//! SQL for previewing a sample of a table
+
+use crate::schema::quote_ident;
+use playground_types::data::SampleMethod;
+
+/// Statements to run, in order, before reading the sample with `sql`
+pub struct SamplePlan {
+    pub setup: Vec<String>,
+    pub sql: String,
+}
+
+/// Temp table random samples are drawn into, so paging reads one draw
+const SAMPLE_TABLE: &str = "_sample";
+
+/// Plan a sample of up to `n` rows of `table`
+pub fn sample_plan(table: &str, n: u32, method: &SampleMethod) -> SamplePlan {
+    let table = quote_ident(table);
+    let draw = |select: String| SamplePlan {
+        setup: vec![format!("CREATE TEMP TABLE {} AS {}", SAMPLE_TABLE, select)],
+        sql: format!("SELECT * FROM {}", SAMPLE_TABLE),
+    };
+    match method {
+        SampleMethod::First => SamplePlan {
+            setup: Vec::new(),
+            sql: format!("SELECT * FROM {} LIMIT {}", table, n),
+        },
+        SampleMethod::Random { seed } => {
+            let mut plan = draw(format!(
+                "SELECT * FROM {} ORDER BY random() LIMIT {}",
+                table, n
+            ));
+            if let Some(seed) = seed {
+                // setseed takes a value in [0, 1]
+                let seed = *seed as f64 / u32::MAX as f64;
+                plan.setup.insert(0, format!("SELECT setseed({})", seed));
+            }
+            plan
+        }
+        SampleMethod::Reservoir => draw(format!(
+            "SELECT * FROM {} USING SAMPLE reservoir({} ROWS)",
+            table, n
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_reads_leading_rows_without_randomness() {
+        let plan = sample_plan("aadt", 20, &SampleMethod::First);
+        assert!(plan.setup.is_empty());
+        assert_eq!(plan.sql, r#"SELECT * FROM "aadt" LIMIT 20"#);
+        // Same request, same SQL: the preview doesn't change between calls
+        assert_eq!(sample_plan("aadt", 20, &SampleMethod::First).sql, plan.sql);
+    }
+
+    #[test]
+    fn seeded_random_sets_seed_first() {
+        let plan = sample_plan(
+            "aadt",
+            5,
+            &SampleMethod::Random {
+                seed: Some(u32::MAX),
+            },
+        );
+        assert_eq!(plan.setup[0], "SELECT setseed(1)");
+        assert!(plan.setup[1].contains("ORDER BY random() LIMIT 5"));
+
+        let plan = sample_plan("aadt", 5, &SampleMethod::Random { seed: None });
+        assert_eq!(plan.setup.len(), 1);
+    }
+}
@@ -0,0 +1,398 @@
+//! Schema inference from DuckDB type names
+
+use crate::timezone::localized_timestamp;
+use playground_types::data::{ColumnSchema, ColumnType, DisplayHint};
+
+/// Map a DuckDB type name (as reported by `DESCRIBE`) to a `ColumnType`
+pub fn column_type(duckdb_type: &str) -> ColumnType {
+    let ty = duckdb_type.trim();
+
+    // Lists (`INTEGER[]`) and fixed-size arrays (`INTEGER[3]`)
+    if ty.ends_with(']') {
+        if let Some(open) = ty.rfind('[') {
+            return ColumnType::List(Box::new(column_type(&ty[..open])));
+        }
+    }
+    if let Some(args) = type_args(ty, "DECIMAL").or_else(|| type_args(ty, "NUMERIC")) {
+        let mut parts = args.split(',').map(|p| p.trim().parse::<u8>());
+        return match (parts.next(), parts.next()) {
+            (Some(Ok(precision)), Some(Ok(scale))) => ColumnType::Decimal { precision, scale },
+            (Some(Ok(precision)), None) => ColumnType::Decimal {
+                precision,
+                scale: 0,
+            },
+            _ => ColumnType::Unknown,
+        };
+    }
+    if let Some(args) = type_args(ty, "STRUCT") {
+        return ColumnType::Struct(split_top_level(args).map(struct_field).collect());
+    }
+
+    match ty.to_ascii_uppercase().as_str() {
+        "BOOLEAN" | "BOOL" => ColumnType::Boolean,
+        "TINYINT" => ColumnType::Int8,
+        "SMALLINT" | "UTINYINT" => ColumnType::Int16,
+        "INTEGER" | "USMALLINT" => ColumnType::Int32,
+        "BIGINT" | "UINTEGER" => ColumnType::Int64,
+        // Wider than a JS number either way; `normalize_result` returns
+        // values beyond 2^53 as exact strings
+        "UBIGINT" | "HUGEINT" | "UHUGEINT" => ColumnType::Int64,
+        "FLOAT" | "REAL" => ColumnType::Float32,
+        "DOUBLE" => ColumnType::Float64,
+        // DuckDB's default precision when none is given
+        "DECIMAL" | "NUMERIC" => ColumnType::Decimal {
+            precision: 18,
+            scale: 3,
+        },
+        "VARCHAR" | "UUID" => ColumnType::String,
+        "BLOB" => ColumnType::Binary,
+        "DATE" => ColumnType::Date,
+        "TIME" | "TIME WITH TIME ZONE" | "TIMETZ" => ColumnType::Time,
+        "TIMESTAMP"
+        | "TIMESTAMP WITH TIME ZONE"
+        | "TIMESTAMPTZ"
+        | "TIMESTAMP_S"
+        | "TIMESTAMP_MS"
+        | "TIMESTAMP_NS" => ColumnType::Timestamp,
+        "INTERVAL" => ColumnType::Interval,
+        "JSON" => ColumnType::Json,
+        _ => ColumnType::Unknown,
+    }
+}
+
+/// Arguments of a parameterized type, e.g. `10,2` for `DECIMAL(10,2)`
+fn type_args<'a>(ty: &'a str, name: &str) -> Option<&'a str> {
+    let prefix = ty.get(..name.len())?;
+    if !prefix.eq_ignore_ascii_case(name) {
+        return None;
+    }
+    ty[name.len()..]
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+/// Split on commas that are not nested inside parentheses or quotes
+fn split_top_level(args: &str) -> impl Iterator<Item = &str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quoted, mut start) = (0i32, false, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    parts.into_iter().map(str::trim).filter(|p| !p.is_empty())
+}
+
+/// Parse a `name TYPE` struct field, where the name may be quoted
+fn struct_field(field: &str) -> (String, ColumnType) {
+    let (name, ty) = match field.strip_prefix('"') {
+        Some(rest) => {
+            // Find the closing quote, skipping doubled `""` escapes
+            let mut end = 0;
+            let bytes = rest.as_bytes();
+            while end < bytes.len() {
+                if bytes[end] == b'"' {
+                    if bytes.get(end + 1) == Some(&b'"') {
+                        end += 2;
+                        continue;
+                    }
+                    break;
+                }
+                end += 1;
+            }
+            (
+                rest[..end].replace("\"\"", "\""),
+                rest.get(end + 1..).unwrap_or(""),
+            )
+        }
+        None => match field.split_once(char::is_whitespace) {
+            Some((name, ty)) => (name.to_string(), ty),
+            None => (field.to_string(), ""),
+        },
+    };
+    (name, column_type(ty))
+}
+
+/// Build a `ColumnSchema` from one row of `DESCRIBE` output, inferring
+/// its display hint from the name
+pub fn column_schema(name: String, duckdb_type: &str, null: &str) -> ColumnSchema {
+    let data_type = column_type(duckdb_type);
+    ColumnSchema {
+        display_hint: DisplayHint::infer(&name, &data_type),
+        name,
+        data_type,
+        nullable: !null.eq_ignore_ascii_case("NO"),
+    }
+}
+
+/// Select list for result rows
+///
+/// Decimals are cast to strings so they survive JSON without f64 rounding.
+/// With `localize_timestamps`, timestamps are selected with their offset
+/// in the session time zone, see [`localized_timestamp`].
+pub fn select_list(columns: &[ColumnSchema], localize_timestamps: bool) -> String {
+    let rewritten = |ty: &ColumnType| match ty {
+        ColumnType::Decimal { .. } => true,
+        ColumnType::Timestamp => localize_timestamps,
+        _ => false,
+    };
+    if !columns.iter().any(|c| rewritten(&c.data_type)) {
+        return "*".to_string();
+    }
+    columns
+        .iter()
+        .map(|c| {
+            let col = quote_ident(&c.name);
+            match c.data_type {
+                ColumnType::Decimal { .. } => format!("CAST({} AS VARCHAR) AS {}", col, col),
+                ColumnType::Timestamp if localize_timestamps => localized_timestamp(&c.name),
+                _ => col,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Why rows with `incoming` columns can't be appended to a table with
+/// `existing` ones, or `None` if they can
+///
+/// Columns must match by position and name (case-insensitively, as in
+/// DuckDB). Types must match, except that narrower integers and floats
+/// widen into wider columns.
+pub fn schema_mismatch(existing: &[ColumnSchema], incoming: &[ColumnSchema]) -> Option<String> {
+    if existing.len() != incoming.len() {
+        return Some(format!(
+            "expected {} columns, found {}",
+            existing.len(),
+            incoming.len()
+        ));
+    }
+    existing
+        .iter()
+        .zip(incoming)
+        .enumerate()
+        .find_map(|(i, (want, got))| {
+            if !want.name.eq_ignore_ascii_case(&got.name) {
+                Some(format!(
+                    "column {} is `{}`, expected `{}`",
+                    i + 1,
+                    got.name,
+                    want.name
+                ))
+            } else if !widens(&got.data_type, &want.data_type) {
+                Some(format!(
+                    "column `{}` is {:?}, expected {:?}",
+                    want.name, got.data_type, want.data_type
+                ))
+            } else {
+                None
+            }
+        })
+}
+
+/// Whether values of type `from` fit a column of type `to` without loss
+fn widens(from: &ColumnType, to: &ColumnType) -> bool {
+    use ColumnType::*;
+    let rank = |t: &ColumnType| match t {
+        Int8 => Some((0, 1)),
+        Int16 => Some((0, 2)),
+        Int32 => Some((0, 3)),
+        Int64 => Some((0, 4)),
+        Float32 => Some((1, 1)),
+        Float64 => Some((1, 2)),
+        _ => None,
+    };
+    match (rank(from), rank(to)) {
+        (Some((family, from)), Some((to_family, to))) => family == to_family && from <= to,
+        _ => from == to,
+    }
+}
+
+/// Quote an identifier for interpolation into SQL
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quote a string literal for interpolation into SQL
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(types: &[(&str, ColumnType)]) -> Vec<ColumnSchema> {
+        types
+            .iter()
+            .map(|(name, data_type)| ColumnSchema {
+                name: name.to_string(),
+                data_type: data_type.clone(),
+                nullable: true,
+                display_hint: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn infers_decimal_precision() {
+        assert_eq!(
+            column_type("DECIMAL(10,2)"),
+            ColumnType::Decimal {
+                precision: 10,
+                scale: 2
+            }
+        );
+        assert_eq!(
+            column_type("numeric (12, 0)"),
+            ColumnType::Decimal {
+                precision: 12,
+                scale: 0
+            }
+        );
+        assert_eq!(
+            column_type("DECIMAL"),
+            ColumnType::Decimal {
+                precision: 18,
+                scale: 3
+            }
+        );
+        assert_eq!(column_type("DECIMAL(x,2)"), ColumnType::Unknown);
+    }
+
+    #[test]
+    fn infers_nested_types() {
+        assert_eq!(
+            column_type("INTEGER[]"),
+            ColumnType::List(Box::new(ColumnType::Int32))
+        );
+        assert_eq!(
+            column_type("DOUBLE[3][]"),
+            ColumnType::List(Box::new(ColumnType::List(Box::new(ColumnType::Float64))))
+        );
+        assert_eq!(
+            column_type("STRUCT(\"a b\" INTEGER, c VARCHAR[])"),
+            ColumnType::Struct(vec![
+                ("a b".to_string(), ColumnType::Int32),
+                (
+                    "c".to_string(),
+                    ColumnType::List(Box::new(ColumnType::String))
+                ),
+            ])
+        );
+        assert_eq!(
+            column_type("STRUCT(\"say \"\"hi\"\"\" DECIMAL(5,1))"),
+            ColumnType::Struct(vec![(
+                "say \"hi\"".to_string(),
+                ColumnType::Decimal {
+                    precision: 5,
+                    scale: 1
+                }
+            )])
+        );
+    }
+
+    #[test]
+    fn infers_scalar_types() {
+        for (name, expected) in [
+            ("TIME", ColumnType::Time),
+            ("TIME WITH TIME ZONE", ColumnType::Time),
+            ("INTERVAL", ColumnType::Interval),
+            ("TIMESTAMP_NS", ColumnType::Timestamp),
+            ("utinyint", ColumnType::Int16),
+            ("UBIGINT", ColumnType::Int64),
+            ("HUGEINT", ColumnType::Int64),
+            ("GEOMETRY", ColumnType::Unknown),
+        ] {
+            assert_eq!(column_type(name), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn wide_integers_become_strings() {
+        use crate::normalize::normalize_result;
+        use playground_types::data::QueryResult;
+        use serde_json::json;
+
+        let mut result = QueryResult {
+            query_id: String::new(),
+            columns: columns(&[
+                ("rows_scanned", column_type("UBIGINT")),
+                ("checksum", column_type("HUGEINT")),
+            ]),
+            rows: vec![
+                vec![json!(42u64), json!("12")],
+                vec![
+                    json!(u64::MAX),
+                    json!("170141183460469231731687303715884105727"),
+                ],
+            ],
+            total_rows: 2,
+            truncated: false,
+            cached: false,
+            warnings: Vec::new(),
+        };
+        normalize_result(&mut result);
+        assert_eq!(result.rows[0], vec![json!(42), json!(12)]);
+        assert_eq!(
+            result.rows[1],
+            vec![
+                json!("18446744073709551615"),
+                json!("170141183460469231731687303715884105727")
+            ]
+        );
+        assert_eq!(result.warnings.len(), 2);
+    }
+
+    #[test]
+    fn matching_schema_can_append() {
+        let existing = columns(&[
+            ("parcel_id", ColumnType::String),
+            ("tax_year", ColumnType::Int64),
+            ("assessed", ColumnType::Float64),
+        ]);
+        let next_year = columns(&[
+            ("PARCEL_ID", ColumnType::String),
+            ("tax_year", ColumnType::Int32),
+            ("assessed", ColumnType::Float64),
+        ]);
+        assert_eq!(schema_mismatch(&existing, &existing), None);
+        assert_eq!(schema_mismatch(&existing, &next_year), None);
+    }
+
+    #[test]
+    fn reports_schema_mismatch() {
+        let existing = columns(&[
+            ("parcel_id", ColumnType::String),
+            ("tax_year", ColumnType::Int32),
+        ]);
+        let renamed = columns(&[
+            ("parcel_id", ColumnType::String),
+            ("year", ColumnType::Int32),
+        ]);
+        let retyped = columns(&[
+            ("parcel_id", ColumnType::Int64),
+            ("tax_year", ColumnType::Int32),
+        ]);
+        let wider = columns(&[
+            ("parcel_id", ColumnType::String),
+            ("tax_year", ColumnType::Int64),
+        ]);
+        assert_eq!(
+            schema_mismatch(&existing, &renamed).unwrap(),
+            "column 2 is `year`, expected `tax_year`"
+        );
+        assert!(schema_mismatch(&existing, &retyped).is_some());
+        assert!(schema_mismatch(&existing, &wider).is_some());
+        assert!(schema_mismatch(&existing, &existing[..1]).is_some());
+    }
+}
@@ -0,0 +1,145 @@
+//! Translating `EngineSettings` into DuckDB `SET` statements
+
+use playground_types::data::{EngineSettings, QueryWarning, WarningCode};
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use std::ops::RangeInclusive;
+
+/// Query reading back the memory limit as DuckDB reports it, e.g. `512.0 MiB`
+pub const MEMORY_LIMIT_QUERY: &str = "SELECT current_setting('memory_limit')";
+
+/// Memory a browser tab can reasonably give DuckDB; wasm32 caps at 4 GB
+const MEMORY_LIMIT_MB: RangeInclusive<u32> = 64..=4096;
+const THREADS: RangeInclusive<u32> = 1..=16;
+const MAX_TEMP_DIRECTORY_SIZE_MB: RangeInclusive<u32> = 0..=4096;
+
+/// Clamp `settings` to safe ranges, warning about each adjusted value
+pub fn clamp_settings(settings: &EngineSettings) -> (EngineSettings, Vec<QueryWarning>) {
+    let mut warnings = Vec::new();
+    let mut clamp = |name: &str, value: Option<u32>, range: RangeInclusive<u32>| {
+        let value = value?;
+        let clamped = value.clamp(*range.start(), *range.end());
+        if clamped != value {
+            warnings.push(QueryWarning {
+                code: WarningCode::Clamped,
+                message: format!(
+                    "{} {} is outside {}..={}, using {}",
+                    name,
+                    value,
+                    range.start(),
+                    range.end(),
+                    clamped
+                ),
+            });
+        }
+        Some(clamped)
+    };
+    let applied = EngineSettings {
+        memory_limit_mb: clamp("memory_limit_mb", settings.memory_limit_mb, MEMORY_LIMIT_MB),
+        threads: clamp("threads", settings.threads, THREADS),
+        max_temp_directory_size_mb: clamp(
+            "max_temp_directory_size_mb",
+            settings.max_temp_directory_size_mb,
+            MAX_TEMP_DIRECTORY_SIZE_MB,
+        ),
+    };
+    (applied, warnings)
+}
+
+/// `SET GLOBAL` statements applying the given (already clamped) settings
+///
+/// Sizes are given in MiB, the unit DuckDB reports them in; its `MB` is
+/// 1000 * 1000 bytes, so `'512MB'` would read back as `488.2 MiB`.
+pub fn settings_statements(settings: &EngineSettings) -> Vec<String> {
+    let mut statements = Vec::new();
+    if let Some(mb) = settings.memory_limit_mb {
+        statements.push(format!("SET GLOBAL memory_limit = '{}MiB'", mb));
+    }
+    if let Some(threads) = settings.threads {
+        statements.push(format!("SET GLOBAL threads = {}", threads));
+    }
+    if let Some(mb) = settings.max_temp_directory_size_mb {
+        statements.push(format!("SET GLOBAL max_temp_directory_size = '{}MiB'", mb));
+    }
+    statements
+}
+
+/// Check the memory limit `current_setting` reports against the MiB that
+/// were set
+///
+/// DuckDB shows one decimal of its display unit, so `1500` MiB reads back
+/// as `1.4 GiB` or `1.5 GiB`; anything within that counts as applied.
+pub fn check_memory_limit(mb: u32, reported: &str) -> Result<(), ErrorInfo> {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let parsed = reported.trim().split_once(' ').and_then(|(number, unit)| {
+        let unit = match unit {
+            "bytes" => 1.0,
+            "KiB" => 1024.0,
+            "MiB" => MIB,
+            "GiB" => 1024.0 * MIB,
+            "TiB" => 1024.0 * 1024.0 * MIB,
+            _ => return None,
+        };
+        Some((number.parse::<f64>().ok()? * unit, unit))
+    });
+    match parsed {
+        Some((bytes, unit)) if (bytes - f64::from(mb) * MIB).abs() < 0.1 * unit => Ok(()),
+        _ => Err(ErrorInfo {
+            code: ErrorCode::Unknown,
+            message: format!("memory_limit {} MiB was not applied", mb),
+            details: Some(format!("DuckDB reports {}", reported)),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_out_of_range_values() {
+        let (applied, warnings) = clamp_settings(&EngineSettings {
+            memory_limit_mb: Some(16),
+            threads: Some(0),
+            max_temp_directory_size_mb: Some(512),
+        });
+        assert_eq!(
+            applied,
+            EngineSettings {
+                memory_limit_mb: Some(64),
+                threads: Some(1),
+                max_temp_directory_size_mb: Some(512),
+            }
+        );
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings.iter().all(|w| w.code == WarningCode::Clamped));
+    }
+
+    #[test]
+    fn builds_set_statements_for_given_settings() {
+        let settings = EngineSettings {
+            memory_limit_mb: Some(512),
+            ..EngineSettings::default()
+        };
+        assert_eq!(
+            settings_statements(&settings),
+            ["SET GLOBAL memory_limit = '512MiB'"]
+        );
+        assert!(settings_statements(&EngineSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn memory_limit_reads_back_as_set() {
+        // What `current_setting('memory_limit')` reports after each SET
+        assert!(check_memory_limit(512, "512.0 MiB").is_ok());
+        assert!(check_memory_limit(64, "64.0 MiB").is_ok());
+        assert!(check_memory_limit(4096, "4.0 GiB").is_ok());
+        assert!(check_memory_limit(1500, "1.4 GiB").is_ok());
+        assert!(check_memory_limit(1500, "1.5 GiB").is_ok());
+
+        // `'512MB'` is decimal megabytes, which is not what was asked for
+        let err = check_memory_limit(512, "488.2 MiB").unwrap_err();
+        assert_eq!(err.message, "memory_limit 512 MiB was not applied");
+        assert_eq!(err.details.as_deref(), Some("DuckDB reports 488.2 MiB"));
+        assert!(check_memory_limit(512, "unlimited").is_err());
+    }
+}
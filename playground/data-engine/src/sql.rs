@@ -0,0 +1,188 @@
+//! Lightweight SQL text scanning
+
+/// Call `f` with the byte offset of every character outside string
+/// literals, quoted identifiers and comments
+pub fn scan_code(sql: &str, mut f: impl FnMut(usize, char)) {
+    let mut chars = sql.char_indices().peekable();
+    let mut prev = None;
+    while let Some((i, c)) = chars.next() {
+        if !prev.is_some_and(is_ident_char) {
+            if let Some(len) = special_literal_len(&sql[i..]) {
+                while chars.next_if(|&(j, _)| j < i + len).is_some() {}
+                prev = None;
+                continue;
+            }
+        }
+        prev = Some(c);
+        match c {
+            // Doubled quotes inside a literal are handled by re-entering it
+            '\'' | '"' => {
+                for (_, d) in chars.by_ref() {
+                    if d == c {
+                        break;
+                    }
+                }
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                for (_, d) in chars.by_ref() {
+                    if d == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                chars.next();
+                let mut prev = ' ';
+                for (_, d) in chars.by_ref() {
+                    if prev == '*' && d == '/' {
+                        break;
+                    }
+                    prev = d;
+                }
+            }
+            _ => f(i, c),
+        }
+    }
+}
+
+/// Length of the `E'...'` escape string or `$tag$...$tag$` dollar-quoted
+/// string at the start of `rest`, or `None` if it doesn't start one
+///
+/// Both can hide a `'` that would otherwise end a plain literal. An
+/// unterminated literal runs to the end of `rest`.
+pub fn special_literal_len(rest: &str) -> Option<usize> {
+    if let Some(body) = rest
+        .strip_prefix(['e', 'E'])
+        .and_then(|r| r.strip_prefix('\''))
+    {
+        let mut chars = body.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '\'' if chars.next_if(|&(_, d)| d == '\'').is_none() => return Some(i + 3),
+                _ => {}
+            }
+        }
+        return Some(rest.len());
+    }
+    let tag_len = rest.strip_prefix('$')?.find('$')?;
+    let tag = &rest[1..1 + tag_len];
+    let mut tag_chars = tag.chars();
+    let valid = match tag_chars.next() {
+        None => true,
+        Some(c) => (c.is_alphabetic() || c == '_') && tag_chars.all(is_ident_char),
+    };
+    if !valid {
+        return None;
+    }
+    let open = &rest[..tag_len + 2];
+    Some(
+        rest[open.len()..]
+            .find(open)
+            .map_or(rest.len(), |end| 2 * open.len() + end),
+    )
+}
+
+/// Whether `c` can continue an unquoted identifier
+pub fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// `sql` in parentheses for use as a subquery
+///
+/// The parentheses go on their own lines so a trailing `--` comment in
+/// `sql` can't comment out the closing one.
+pub fn subquery(sql: &str) -> String {
+    format!("(\n{}\n)", sql)
+}
+
+/// Split a script into statements on `;`, dropping empty ones
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    scan_code(sql, |i, c| {
+        if c == ';' {
+            statements.push(&sql[start..i]);
+            start = i + 1;
+        }
+    });
+    statements.push(&sql[start..]);
+    statements
+        .into_iter()
+        .map(str::trim)
+        .filter(|s| !is_blank(s))
+        .collect()
+}
+
+/// Whether a statement holds nothing but whitespace and comments
+fn is_blank(sql: &str) -> bool {
+    let mut blank = true;
+    scan_code(sql, |_, c| blank &= c.is_whitespace());
+    blank
+}
+
+/// First keyword of a statement, uppercased
+pub fn leading_keyword(sql: &str) -> String {
+    let mut keyword = String::new();
+    let mut done = false;
+    scan_code(sql, |_, c| {
+        if done {
+            return;
+        }
+        if c.is_ascii_alphabetic() {
+            keyword.push(c.to_ascii_uppercase());
+        } else if !keyword.is_empty() || !(c.is_whitespace() || c == '(') {
+            done = true;
+        }
+    });
+    keyword
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_unquoted_semicolons() {
+        let script = "CREATE VIEW v AS SELECT 'a;b' AS x;\n-- done; really\nSELECT \"c;d\" FROM v; /* ; */ ;";
+        assert_eq!(
+            split_statements(script),
+            vec![
+                "CREATE VIEW v AS SELECT 'a;b' AS x",
+                "-- done; really\nSELECT \"c;d\" FROM v",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_escape_and_dollar_quoted_strings() {
+        assert_eq!(
+            split_statements("SELECT E'it\\'s;', $$a;'b$$, $t$x;$$y$t$; SELECT 2"),
+            vec!["SELECT E'it\\'s;', $$a;'b$$, $t$x;$$y$t$", "SELECT 2"]
+        );
+        assert_eq!(special_literal_len("E'a''b' x"), Some(7));
+        assert_eq!(special_literal_len("$1 + $2"), None);
+        assert_eq!(special_literal_len("$$open"), Some(6));
+        // `e` ending a name doesn't start an escape string
+        assert_eq!(split_statements("SELECT name'x;'"), vec!["SELECT name'x;'"]);
+    }
+
+    #[test]
+    fn subquery_survives_trailing_comment() {
+        let wrapped = format!("SELECT * FROM {} AS _q", subquery("SELECT 1 -- one"));
+        let mut code = String::new();
+        scan_code(&wrapped, |_, c| code.push(c));
+        assert!(code.ends_with(") AS _q"), "{code}");
+    }
+
+    #[test]
+    fn finds_leading_keyword() {
+        assert_eq!(leading_keyword("  -- note\n(select 1)"), "SELECT");
+        assert_eq!(
+            leading_keyword("WITH t AS (SELECT 1) SELECT * FROM t"),
+            "WITH"
+        );
+    }
+}
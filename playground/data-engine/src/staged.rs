@@ -0,0 +1,86 @@
+//! Loads that report a table's schema before its rows are materialized
+
+use playground_types::data::TableSchema;
+use playground_types::messages::ErrorInfo;
+use std::future::Future;
+
+/// Hand the schema from `infer` to `on_schema`, then run `materialize`
+///
+/// `on_schema` is always called before `materialize` starts, so anything
+/// sent from it precedes the load's result. If the schema can't be
+/// inferred up front the load goes ahead without it.
+pub async fn schema_first<T>(
+    infer: impl Future<Output = Result<TableSchema, ErrorInfo>>,
+    on_schema: impl FnOnce(TableSchema),
+    materialize: impl Future<Output = Result<T, ErrorInfo>>,
+) -> Result<T, ErrorInfo> {
+    match infer.await {
+        Ok(schema) => on_schema(schema),
+        Err(err) => log::debug!("no early schema: {}", err.message),
+    }
+    materialize.await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use playground_types::data::{ColumnSchema, ColumnType, TableKind};
+    use playground_types::messages::ErrorCode;
+    use std::cell::RefCell;
+
+    fn footer_schema() -> TableSchema {
+        TableSchema {
+            name: "parcels".to_string(),
+            columns: vec![ColumnSchema {
+                name: "parcel_id".to_string(),
+                data_type: ColumnType::String,
+                nullable: true,
+                display_hint: None,
+            }],
+            row_count: 0,
+            kind: TableKind::Loaded,
+        }
+    }
+
+    #[test]
+    fn schema_arrives_before_row_count() {
+        let events = RefCell::new(Vec::new());
+        let row_count = block_on(schema_first(
+            async { Ok(footer_schema()) },
+            |schema| {
+                events
+                    .borrow_mut()
+                    .push(format!("schema: {} columns", schema.columns.len()))
+            },
+            async {
+                events.borrow_mut().push("rows: 43210".to_string());
+                Ok(43210)
+            },
+        ))
+        .unwrap();
+        assert_eq!(row_count, 43210);
+        assert_eq!(
+            events.into_inner(),
+            vec!["schema: 1 columns", "rows: 43210"]
+        );
+    }
+
+    #[test]
+    fn load_continues_without_early_schema() {
+        let sent = RefCell::new(false);
+        let result = block_on(schema_first(
+            async {
+                Err(ErrorInfo {
+                    code: ErrorCode::ParseError,
+                    message: "No magic bytes found".to_string(),
+                    details: None,
+                })
+            },
+            |_| *sent.borrow_mut() = true,
+            async { Ok(7) },
+        ));
+        assert_eq!(result.unwrap(), 7);
+        assert!(!sent.into_inner());
+    }
+}
@@ -0,0 +1,167 @@
+//! Query timeouts enforced through a connection's interrupt handle
+
+use crate::connection::InterruptHandle;
+use futures::future::{select, Either};
+use playground_types::messages::{ErrorCode, ErrorInfo};
+use std::future::Future;
+use std::pin::pin;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    /// Global `setTimeout`, available in both windows and workers
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(callback: &js_sys::Function, ms: u32) -> JsValue;
+}
+
+/// How long a query may run in ms, or `None` for no limit
+///
+/// The request's `timeout_seconds` overrides the engine default; 0 disables
+/// the timeout.
+pub fn timeout_ms(requested: Option<u32>, default_seconds: u32) -> Option<u32> {
+    match requested.unwrap_or(default_seconds) {
+        0 => None,
+        seconds => Some(seconds.saturating_mul(1000)),
+    }
+}
+
+/// Error for a query interrupted by its timeout
+pub fn timeout_error(elapsed_ms: u32, limit_ms: u32) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::Timeout,
+        message: format!("Query timed out after {} ms", elapsed_ms),
+        details: Some(format!("Limit is {} s", limit_ms / 1000)),
+    }
+}
+
+/// Resolve after `ms` milliseconds
+pub async fn sleep(ms: u32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        set_timeout(&resolve, ms);
+    });
+    JsFuture::from(promise).await.ok();
+}
+
+/// Run `query`, interrupting it through `handle` once `limit_ms` elapses
+///
+/// A query that finishes while being interrupted keeps its result.
+pub async fn with_timeout<T>(
+    query: impl Future<Output = Result<T, ErrorInfo>>,
+    handle: &InterruptHandle,
+    limit_ms: u32,
+) -> Result<T, ErrorInfo> {
+    let started = js_sys::Date::now();
+    race_timeout(
+        query,
+        sleep(limit_ms),
+        || handle.interrupt(),
+        || (js_sys::Date::now() - started) as u32,
+        limit_ms,
+    )
+    .await
+}
+
+/// [`with_timeout`] with the timer, interrupt and clock passed in
+///
+/// If `timer` resolves first, `interrupt` runs and the query is awaited;
+/// its failure becomes a [`timeout_error`] after `elapsed_ms()`.
+pub async fn race_timeout<T, I: Future<Output = ()>>(
+    query: impl Future<Output = Result<T, ErrorInfo>>,
+    timer: impl Future<Output = ()>,
+    interrupt: impl FnOnce() -> I,
+    elapsed_ms: impl FnOnce() -> u32,
+    limit_ms: u32,
+) -> Result<T, ErrorInfo> {
+    match select(pin!(query), pin!(timer)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), query)) => {
+            log::debug!("query timed out after {} ms", limit_ms);
+            interrupt().await;
+            let result = query.await;
+            result.map_err(|_| timeout_error(elapsed_ms(), limit_ms))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::oneshot;
+    use futures::executor::block_on;
+    use futures::future::{pending, ready};
+    use std::cell::Cell;
+
+    #[test]
+    fn request_timeout_overrides_default() {
+        assert_eq!(timeout_ms(None, 30), Some(30_000));
+        assert_eq!(timeout_ms(Some(5), 30), Some(5_000));
+        assert_eq!(timeout_ms(Some(0), 30), None);
+        assert_eq!(timeout_ms(None, 0), None);
+    }
+
+    /// A query that only ends when interrupted, like a runaway recursive
+    /// CTE, returning `on_interrupt` then
+    fn runaway_query<T>(
+        on_interrupt: Result<T, ErrorInfo>,
+    ) -> (
+        impl Future<Output = Result<T, ErrorInfo>>,
+        impl FnOnce() -> futures::future::Ready<()>,
+    ) {
+        let (interrupted, wait) = oneshot::channel::<()>();
+        let query = async move {
+            wait.await.ok();
+            on_interrupt
+        };
+        let interrupt = move || {
+            interrupted.send(()).ok();
+            ready(())
+        };
+        (query, interrupt)
+    }
+
+    fn interrupt_error() -> ErrorInfo {
+        ErrorInfo {
+            code: ErrorCode::Cancelled,
+            message: "INTERRUPT Error: Interrupted!".to_string(),
+            details: None,
+        }
+    }
+
+    #[test]
+    fn interrupts_query_when_timer_fires() {
+        let (query, interrupt) = runaway_query::<u32>(Err(interrupt_error()));
+        let err = block_on(race_timeout(query, ready(()), interrupt, || 5_003, 5_000)).unwrap_err();
+        assert_eq!(err.code, ErrorCode::Timeout);
+        assert_eq!(err.message, "Query timed out after 5003 ms");
+    }
+
+    #[test]
+    fn query_finishing_first_is_not_interrupted() {
+        let interrupted = Cell::new(false);
+        let result = block_on(race_timeout(
+            ready(Ok(42)),
+            pending(),
+            || {
+                interrupted.set(true);
+                ready(())
+            },
+            || 0,
+            5_000,
+        ));
+        assert_eq!(result.unwrap(), 42);
+        assert!(!interrupted.get());
+
+        // Finishing while being interrupted keeps the result
+        let (query, interrupt) = runaway_query(Ok(7));
+        let result = block_on(race_timeout(query, ready(()), interrupt, || 5_000, 5_000));
+        assert_eq!(result.unwrap(), 7);
+    }
+
+    #[test]
+    fn timeout_error_reports_elapsed_ms() {
+        let error = timeout_error(5_012, 5_000);
+        assert_eq!(error.code, ErrorCode::Timeout);
+        assert!(error.message.contains("5012 ms"), "{}", error.message);
+    }
+}
@@ -0,0 +1,70 @@
+//! Returning timestamps in a requested time zone
+
+use crate::schema::{quote_ident, quote_literal};
+use playground_types::messages::{ErrorCode, ErrorInfo};
+
+/// `SET TimeZone` for `zone`, which must look like an IANA name
+///
+/// DuckDB decides whether the zone exists; this only keeps arbitrary SQL
+/// out of the statement.
+pub fn set_timezone_sql(zone: &str) -> Result<String, ErrorInfo> {
+    let valid = !zone.is_empty()
+        && zone.len() <= 64
+        && zone
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'));
+    if !valid {
+        return Err(invalid_timezone(zone, None));
+    }
+    Ok(format!("SET TimeZone = {}", quote_literal(zone)))
+}
+
+/// Error for a time zone DuckDB doesn't know
+pub fn invalid_timezone(zone: &str, details: Option<String>) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::InvalidQuery,
+        message: format!("Unknown time zone: {}", zone),
+        details,
+    }
+}
+
+/// Select expression for timestamp column `name` under the session time
+/// zone, as `<epoch ms> <UTC offset in seconds>`
+///
+/// `normalize_result` turns the pair into ISO-8601 with the offset. Plain
+/// `TIMESTAMP` values are taken to be UTC.
+pub fn localized_timestamp(name: &str) -> String {
+    let col = quote_ident(name);
+    format!(
+        "CAST(epoch_ms({col}) AS VARCHAR) || ' ' || \
+         CAST(date_part('timezone', to_timestamp(epoch_ms({col}) / 1000)) AS VARCHAR) AS {col}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_zone_names() {
+        assert_eq!(
+            set_timezone_sql("America/New_York").unwrap(),
+            "SET TimeZone = 'America/New_York'"
+        );
+        assert!(set_timezone_sql("Etc/GMT+5").is_ok());
+        for zone in ["", "UTC'; DROP TABLE parcels; --", "America/New York"] {
+            let err = set_timezone_sql(zone).unwrap_err();
+            assert_eq!(err.code, ErrorCode::InvalidQuery);
+        }
+    }
+
+    #[test]
+    fn localizes_timestamp_column() {
+        assert_eq!(
+            localized_timestamp("sale date"),
+            "CAST(epoch_ms(\"sale date\") AS VARCHAR) || ' ' || \
+             CAST(date_part('timezone', to_timestamp(epoch_ms(\"sale date\") / 1000)) AS VARCHAR) \
+             AS \"sale date\""
+        );
+    }
+}
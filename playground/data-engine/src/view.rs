@@ -0,0 +1,97 @@
+//! Views defined over the session's tables
+
+use crate::schema::quote_ident;
+use crate::sql::{leading_keyword, split_statements};
+use playground_types::data::TableKind;
+use playground_types::messages::{ErrorCode, ErrorInfo};
+
+/// Statements that can define a view
+const QUERY_KEYWORDS: &[&str] = &["SELECT", "WITH", "FROM", "VALUES", "TABLE"];
+
+/// `CREATE OR REPLACE VIEW` statement defining `name` as `sql`
+///
+/// Fails with `ErrorCode::InvalidQuery` if `name` is a loaded table (which
+/// the view would otherwise shadow) or `sql` is not a single query.
+pub fn create_view_sql(name: &str, sql: &str, loaded: bool) -> Result<String, ErrorInfo> {
+    if loaded {
+        return Err(ErrorInfo {
+            code: ErrorCode::InvalidQuery,
+            message: format!(
+                "Cannot create view {}: a table with that name is loaded",
+                name
+            ),
+            details: None,
+        });
+    }
+    let query = match split_statements(sql).as_slice() {
+        [query] if QUERY_KEYWORDS.contains(&leading_keyword(query).as_str()) => *query,
+        _ => {
+            return Err(ErrorInfo {
+                code: ErrorCode::InvalidQuery,
+                message: format!("View {} must be defined by a single query", name),
+                details: None,
+            })
+        }
+    };
+    Ok(format!(
+        "CREATE OR REPLACE VIEW {} AS {}",
+        quote_ident(name),
+        query
+    ))
+}
+
+/// Kind of a relation from its `information_schema.tables` type
+pub fn table_kind(table_type: &str, loaded: bool) -> TableKind {
+    match table_type {
+        "VIEW" => TableKind::View,
+        _ if loaded => TableKind::Loaded,
+        _ => TableKind::BaseTable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use playground_types::data::{ListTablesResult, TableSchema};
+
+    #[test]
+    fn creates_then_lists_view() {
+        let sql = create_view_sql(
+            "big_parcels",
+            "SELECT * FROM parcels WHERE acres > 10;",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "CREATE OR REPLACE VIEW \"big_parcels\" AS SELECT * FROM parcels WHERE acres > 10"
+        );
+
+        let listing = ListTablesResult {
+            tables: [
+                ("big_parcels", "VIEW", false),
+                ("notes", "BASE TABLE", false),
+                ("parcels", "BASE TABLE", true),
+            ]
+            .into_iter()
+            .map(|(name, table_type, loaded)| TableSchema {
+                name: name.to_string(),
+                columns: Vec::new(),
+                row_count: 0,
+                kind: table_kind(table_type, loaded),
+            })
+            .collect(),
+        };
+        assert_eq!(listing.views(), vec!["big_parcels"]);
+        assert_eq!(listing.tables[1].kind, TableKind::BaseTable);
+        assert_eq!(listing.tables[2].kind, TableKind::Loaded);
+    }
+
+    #[test]
+    fn rejects_view_over_loaded_table() {
+        let err = create_view_sql("parcels", "SELECT 1", true).unwrap_err();
+        assert_eq!(err.code, ErrorCode::InvalidQuery);
+        assert!(create_view_sql("v", "DELETE FROM parcels", false).is_err());
+        assert!(create_view_sql("v", "SELECT 1; SELECT 2", false).is_err());
+    }
+}
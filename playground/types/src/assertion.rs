@@ -0,0 +1,318 @@
+//! Expected results for checking answers, e.g. in a course notebook
+
+use crate::editor::{CellOutput, Notebook, QueryOutput};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tsify::Tsify;
+
+/// What a cell's query result should be
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Assertion {
+    /// Cell whose output is checked
+    pub cell_id: String,
+    pub expected: ExpectedResult,
+}
+
+/// An expected query result
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExpectedResult {
+    /// The query returns this many rows in total
+    RowCount { count: u64 },
+    /// The query returns exactly these rows
+    ExactRows {
+        rows: Vec<Vec<Value>>,
+        /// Accept the rows in any order
+        #[serde(default)]
+        ignore_order: bool,
+    },
+    /// The query returns these columns, in any order
+    ColumnSet { columns: Vec<String> },
+}
+
+/// Whether an assertion held, and why not if it didn't
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct AssertionOutcome {
+    pub passed: bool,
+    /// What differed, for the student
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl AssertionOutcome {
+    fn pass() -> Self {
+        Self {
+            passed: true,
+            reason: None,
+        }
+    }
+
+    fn fail(reason: impl Into<String>) -> Self {
+        Self {
+            passed: false,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+/// Check a query result against an expected result
+///
+/// Numbers compare by value, so `2` matches `2.0`. Column names compare
+/// case-insensitively, as in DuckDB.
+pub fn check_assertion(output: &QueryOutput, expected: &ExpectedResult) -> AssertionOutcome {
+    match expected {
+        ExpectedResult::RowCount { count } if output.total_rows == *count => {
+            AssertionOutcome::pass()
+        }
+        ExpectedResult::RowCount { count } => AssertionOutcome::fail(format!(
+            "expected {} rows, got {}",
+            count, output.total_rows
+        )),
+        ExpectedResult::ExactRows { rows, ignore_order } => {
+            if output.total_rows != rows.len() as u64 {
+                return AssertionOutcome::fail(format!(
+                    "expected {} rows, got {}",
+                    rows.len(),
+                    output.total_rows
+                ));
+            }
+            if output.rows.len() < rows.len() {
+                return AssertionOutcome::fail(
+                    "the result was truncated; rerun with a higher limit",
+                );
+            }
+            if *ignore_order {
+                return match unmatched_row(&output.rows, rows) {
+                    None => AssertionOutcome::pass(),
+                    Some(row) => AssertionOutcome::fail(format!(
+                        "expected a row {}",
+                        Value::from(row.clone())
+                    )),
+                };
+            }
+            match output
+                .rows
+                .iter()
+                .zip(rows)
+                .position(|(actual, expected)| !rows_equal(actual, expected))
+            {
+                None => AssertionOutcome::pass(),
+                Some(i) => AssertionOutcome::fail(format!(
+                    "row {} is {}, expected {}",
+                    i + 1,
+                    Value::from(output.rows[i].clone()),
+                    Value::from(rows[i].clone())
+                )),
+            }
+        }
+        ExpectedResult::ColumnSet { columns } => {
+            let has =
+                |list: &[String], name: &str| list.iter().any(|c| c.eq_ignore_ascii_case(name));
+            let missing: Vec<&str> = columns
+                .iter()
+                .filter(|c| !has(&output.columns, c))
+                .map(String::as_str)
+                .collect();
+            let extra: Vec<&str> = output
+                .columns
+                .iter()
+                .filter(|c| !has(columns, c))
+                .map(String::as_str)
+                .collect();
+            match (missing.is_empty(), extra.is_empty()) {
+                (true, true) => AssertionOutcome::pass(),
+                (false, _) => {
+                    AssertionOutcome::fail(format!("missing columns: {}", missing.join(", ")))
+                }
+                (true, false) => {
+                    AssertionOutcome::fail(format!("unexpected columns: {}", extra.join(", ")))
+                }
+            }
+        }
+    }
+}
+
+impl Notebook {
+    /// Check every assertion against its cell's current output, in order,
+    /// with parameter `values` on engine session `session` as for
+    /// [`Notebook::stale_cells`]
+    ///
+    /// Cells that haven't produced a query result fail, as do cells whose
+    /// output is stale because the cell, one above it or a parameter it
+    /// uses changed since it ran.
+    pub fn check_assertions(
+        &self,
+        values: &[(String, serde_json::Value)],
+        session: &str,
+    ) -> Vec<(String, AssertionOutcome)> {
+        let hashes = self.input_hashes(values, session);
+        self.assertions
+            .iter()
+            .map(|assertion| {
+                let found = self
+                    .cells
+                    .iter()
+                    .zip(&hashes)
+                    .find(|(cell, _)| cell.id == assertion.cell_id);
+                let outcome = match found {
+                    Some((cell, hash)) => match &cell.output {
+                        Some(CellOutput::Query(_)) if cell.content_hash != *hash => {
+                            AssertionOutcome::fail("the cell changed since it ran; run it again")
+                        }
+                        Some(CellOutput::Query(output)) => {
+                            check_assertion(output, &assertion.expected)
+                        }
+                        _ => AssertionOutcome::fail("run the cell to check its answer"),
+                    },
+                    None => AssertionOutcome::fail("run the cell to check its answer"),
+                };
+                (assertion.cell_id.clone(), outcome)
+            })
+            .collect()
+    }
+}
+
+/// The first expected row with no equal row left in `actual`, matching
+/// each actual row at most once
+fn unmatched_row<'a>(actual: &[Vec<Value>], expected: &'a [Vec<Value>]) -> Option<&'a Vec<Value>> {
+    let mut used = vec![false; actual.len()];
+    expected.iter().find(|row| {
+        let found = actual
+            .iter()
+            .enumerate()
+            .position(|(i, candidate)| !used[i] && rows_equal(candidate, row));
+        match found {
+            Some(i) => {
+                used[i] = true;
+                false
+            }
+            None => true,
+        }
+    })
+}
+
+fn rows_equal(a: &[Value], b: &[Value]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| values_equal(a, b))
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn output(columns: &[&str], rows: Vec<Vec<Value>>) -> QueryOutput {
+        QueryOutput {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            total_rows: rows.len() as u64,
+            rows,
+            execution_time_ms: 3,
+            truncated: false,
+            output_bytes: 0,
+            column_types: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn row_count_assertion_passes() {
+        let result = output(
+            &["district", "schools"],
+            vec![
+                vec![json!("Back Creek"), json!(7)],
+                vec![json!("Gainesboro"), json!(5)],
+                vec![json!("Opequon"), json!(6)],
+            ],
+        );
+        assert_eq!(
+            check_assertion(&result, &ExpectedResult::RowCount { count: 3 }),
+            AssertionOutcome::pass()
+        );
+        assert!(!check_assertion(&result, &ExpectedResult::RowCount { count: 4 }).passed);
+        assert!(
+            check_assertion(
+                &result,
+                &ExpectedResult::ColumnSet {
+                    columns: vec!["Schools".to_string(), "district".to_string()],
+                }
+            )
+            .passed
+        );
+    }
+
+    #[test]
+    fn exact_rows_assertion_fails_on_wrong_row() {
+        let result = output(
+            &["district", "schools"],
+            vec![
+                vec![json!("Back Creek"), json!(7)],
+                vec![json!("Gainesboro"), json!(4)],
+            ],
+        );
+        let expected = |ignore_order| ExpectedResult::ExactRows {
+            rows: vec![
+                vec![json!("Gainesboro"), json!(5.0)],
+                vec![json!("Back Creek"), json!(7.0)],
+            ],
+            ignore_order,
+        };
+
+        let outcome = check_assertion(&result, &expected(false));
+        assert!(!outcome.passed);
+        assert_eq!(
+            outcome.reason.as_deref(),
+            Some(r#"row 1 is ["Back Creek",7], expected ["Gainesboro",5.0]"#)
+        );
+
+        let outcome = check_assertion(&result, &expected(true));
+        assert_eq!(
+            outcome.reason.as_deref(),
+            Some(r#"expected a row ["Gainesboro",5.0]"#)
+        );
+
+        let fixed = output(
+            &["district", "schools"],
+            vec![
+                vec![json!("Back Creek"), json!(7)],
+                vec![json!("Gainesboro"), json!(5)],
+            ],
+        );
+        assert!(check_assertion(&fixed, &expected(true)).passed);
+        assert!(!check_assertion(&fixed, &expected(false)).passed);
+    }
+
+    #[test]
+    fn stale_output_fails_its_assertion() {
+        let mut nb = crate::templates::builtin_templates()
+            .remove(0)
+            .instantiate("Schools", 0);
+        let id = nb.cells[3].id.clone();
+        nb.assertions = vec![Assertion {
+            cell_id: id.clone(),
+            expected: ExpectedResult::RowCount { count: 1 },
+        }];
+        assert_eq!(
+            nb.check_assertions(&[], "s1")[0].1.reason.as_deref(),
+            Some("run the cell to check its answer")
+        );
+
+        nb.cells[3].output = Some(CellOutput::Query(output(&["n"], vec![vec![json!(1)]])));
+        nb.record_run(&id, &[], "s1");
+        assert!(nb.check_assertions(&[], "s1")[0].1.passed);
+
+        // Editing a cell above invalidates the output below it
+        nb.cells[1].content = "DESCRIBE parcels".to_string();
+        assert_eq!(
+            nb.check_assertions(&[], "s1")[0].1.reason.as_deref(),
+            Some("the cell changed since it ran; run it again")
+        );
+    }
+}
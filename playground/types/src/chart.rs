@@ -1,6 +1,9 @@
 //! ChartEngine types for visualization
 
+use crate::data::{ColumnType, QueryResult};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use tsify::Tsify;
 
 // Re-use Theme from storage module for consistency
@@ -30,6 +33,10 @@ pub enum ChartType {
     Sunburst,
     // Geographic
     Choropleth,
+    // Financial
+    Candlestick,
+    // Matrix
+    Heatmap,
 }
 
 /// Chart configuration
@@ -51,6 +58,62 @@ pub struct ChartConfig {
     /// Theme (light/dark)
     #[serde(default)]
     pub theme: Theme,
+    /// Notebook cell whose result the chart plots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cell_id: Option<String>,
+}
+
+impl ChartConfig {
+    /// Check that the data fits the chart type
+    pub fn validate(&self) -> Result<(), ChartError> {
+        let axis_ids: Vec<&str> = match &self.options {
+            Some(options) => {
+                let y_axes = options.y_axis.iter().chain(&options.y_axes);
+                for axis in options.x_axis.iter().chain(y_axes.clone()) {
+                    axis.validate()?;
+                }
+                y_axes.filter_map(|axis| axis.id.as_deref()).collect()
+            }
+            None => Vec::new(),
+        };
+        for dataset in &self.data.datasets {
+            if let Some(id) = &dataset.y_axis_id {
+                if !axis_ids.contains(&id.as_str()) {
+                    return Err(ChartError::UnknownAxis {
+                        dataset: dataset.label.clone(),
+                        axis: id.clone(),
+                    });
+                }
+            }
+        }
+        for dataset in &self.data.datasets {
+            match (&self.chart_type, &dataset.data) {
+                (ChartType::Candlestick, DataValues::Ohlc(points)) => {
+                    if let Some(index) = points.iter().position(|p| p.high < p.low) {
+                        return Err(ChartError::InvalidOhlc {
+                            dataset: dataset.label.clone(),
+                            index,
+                        });
+                    }
+                }
+                (ChartType::Candlestick, _) => {
+                    return Err(ChartError::DataMismatch {
+                        chart_type: self.chart_type.clone(),
+                        expected: "OHLC",
+                    })
+                }
+                (ChartType::Heatmap, DataValues::Matrix(matrix)) => matrix.validate()?,
+                (ChartType::Heatmap, _) => {
+                    return Err(ChartError::DataMismatch {
+                        chart_type: self.chart_type.clone(),
+                        expected: "matrix",
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Chart data specification
@@ -63,6 +126,156 @@ pub struct ChartData {
     pub datasets: Vec<Dataset>,
 }
 
+impl ChartData {
+    /// Build chart data from query rows: `label_col` supplies the labels
+    /// and each of `value_cols` becomes a numeric dataset
+    ///
+    /// Integer and decimal values are converted to `f64`; NULLs become
+    /// `None`, which serializes as `null` and is drawn as a gap.
+    pub fn from_query_result(
+        result: &QueryResult,
+        label_col: &str,
+        value_cols: &[String],
+    ) -> Result<ChartData, ChartError> {
+        let column_index = |name: &str| {
+            result
+                .columns
+                .iter()
+                .position(|c| c.name == name)
+                .ok_or_else(|| ChartError::MissingColumn(name.to_string()))
+        };
+
+        let label_index = column_index(label_col)?;
+        let labels = result
+            .rows
+            .iter()
+            .map(|row| match row.get(label_index) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => String::new(),
+                Some(other) => other.to_string(),
+            })
+            .collect();
+
+        let datasets = value_cols
+            .iter()
+            .map(|name| {
+                let index = column_index(name)?;
+                if !result.columns[index].data_type.is_numeric() {
+                    return Err(ChartError::NonNumericColumn(name.clone()));
+                }
+                let values = result
+                    .rows
+                    .iter()
+                    .enumerate()
+                    .map(|(row, values)| {
+                        match values.get(index) {
+                            Some(Value::Number(n)) => n.as_f64().map(Some).ok_or(()),
+                            Some(Value::String(s)) => s.parse::<f64>().map(Some).map_err(|_| ()),
+                            Some(Value::Null) | None => Ok(None),
+                            Some(_) => Err(()),
+                        }
+                        .map_err(|_| ChartError::NonNumericValue {
+                            column: name.clone(),
+                            row,
+                        })
+                    })
+                    .collect::<Result<Vec<Option<f64>>, ChartError>>()?;
+                Ok(Dataset {
+                    label: name.clone(),
+                    data: DataValues::Numbers(values),
+                    style: None,
+                    y_axis_id: None,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ChartData { labels, datasets })
+    }
+}
+
+/// Most distinct categories `suggest_chart_types` will offer a pie for
+pub const PIE_MAX_SLICES: usize = 8;
+
+/// Chart types that fit the shape of a query result, best first
+///
+/// Looks at the first date/timestamp and categorical columns and the
+/// number of numeric ones: a date with a measure is a trend, a category
+/// with a measure a comparison, and several measures a scatter. Pie and
+/// doughnut are left out when the category has too many distinct values
+/// to read as slices.
+pub fn suggest_chart_types(result: &QueryResult) -> Vec<ChartType> {
+    let columns_where = |pred: fn(&ColumnType) -> bool| {
+        result
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| pred(&column.data_type))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>()
+    };
+    let temporal = columns_where(|t| matches!(t, ColumnType::Date | ColumnType::Timestamp));
+    let categorical = columns_where(|t| matches!(t, ColumnType::String | ColumnType::Boolean));
+    let numeric = columns_where(ColumnType::is_numeric);
+
+    let mut suggestions = Vec::new();
+    if !numeric.is_empty() {
+        if !temporal.is_empty() {
+            suggestions.extend([ChartType::Line, ChartType::Area]);
+        }
+        if let Some(&category) = categorical.first() {
+            suggestions.push(ChartType::Bar);
+            let distinct: HashSet<&Value> = result
+                .rows
+                .iter()
+                .filter_map(|row| row.get(category))
+                .collect();
+            if distinct.len() <= PIE_MAX_SLICES {
+                suggestions.extend([ChartType::Pie, ChartType::Doughnut]);
+            }
+        }
+    }
+    match numeric.len() {
+        0 => {}
+        1 if suggestions.is_empty() => suggestions.push(ChartType::Histogram),
+        1 => {}
+        2 => suggestions.push(ChartType::Scatter),
+        _ => suggestions.extend([ChartType::Scatter, ChartType::Bubble]),
+    }
+    suggestions
+}
+
+/// Errors building or validating chart data
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ChartError {
+    /// A named column is not in the result
+    #[error("column `{0}` not found in query result")]
+    MissingColumn(String),
+    /// A value column does not have a numeric type
+    #[error("column `{0}` is not numeric")]
+    NonNumericColumn(String),
+    /// A value in a numeric column could not be read as a number
+    #[error("column `{column}` has a non-numeric value in row {row}")]
+    NonNumericValue { column: String, row: usize },
+    /// A dataset's values don't suit the chart type
+    #[error("{chart_type:?} charts require {expected} data")]
+    DataMismatch {
+        chart_type: ChartType,
+        expected: &'static str,
+    },
+    /// A candlestick point has its high below its low
+    #[error("dataset `{dataset}` point {index} has high below low")]
+    InvalidOhlc { dataset: String, index: usize },
+    /// Heatmap values don't match the labels
+    #[error("invalid matrix: {0}")]
+    InvalidMatrix(String),
+    /// Axis bounds don't suit its scale
+    #[error("invalid axis: {0}")]
+    InvalidAxis(String),
+    /// A dataset refers to a Y axis that is not configured
+    #[error("dataset `{dataset}` uses unknown y axis `{axis}`")]
+    UnknownAxis { dataset: String, axis: String },
+}
+
 /// A single data series
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -74,15 +287,21 @@ pub struct Dataset {
     /// Optional styling
     #[serde(skip_serializing_if = "Option::is_none")]
     pub style: Option<DatasetStyle>,
+    /// ID of the Y axis this series is plotted against (default axis if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y_axis_id: Option<String>,
 }
 
 /// Data values (varies by chart type)
+///
+/// Serialized as `{"type": "numbers", "values": [...]}`; the tag keeps an
+/// empty series' variant, which its values alone can't show.
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
-#[serde(untagged)]
+#[serde(tag = "type", content = "values", rename_all = "snake_case")]
 pub enum DataValues {
-    /// Simple numeric values (bar, line, pie, etc.)
-    Numbers(Vec<f64>),
+    /// Simple numeric values (bar, line, pie, etc.); `None` is a gap
+    Numbers(Vec<Option<f64>>),
     /// X,Y points (scatter)
     Points(Vec<Point>),
     /// X,Y,R points (bubble)
@@ -91,6 +310,10 @@ pub enum DataValues {
     Hierarchical(Vec<HierarchicalNode>),
     /// Geographic data (choropleth)
     Geographic(Vec<GeoDataPoint>),
+    /// Open/high/low/close points (candlestick)
+    Ohlc(Vec<OhlcPoint>),
+    /// Grid of cell values (heatmap)
+    Matrix(MatrixData),
 }
 
 /// 2D point
@@ -133,8 +356,59 @@ pub struct GeoDataPoint {
     pub label: Option<String>,
 }
 
+/// Open/high/low/close point for candlestick charts
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct OhlcPoint {
+    /// X position (typically a timestamp in milliseconds)
+    pub x: f64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+/// Cell values for heatmaps, one row per Y label
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct MatrixData {
+    /// Column labels
+    pub x_labels: Vec<String>,
+    /// Row labels
+    pub y_labels: Vec<String>,
+    /// `values[y][x]`
+    pub values: Vec<Vec<f64>>,
+}
+
+impl MatrixData {
+    /// Check that `values` has one row per Y label and one cell per X label
+    pub fn validate(&self) -> Result<(), ChartError> {
+        if self.values.len() != self.y_labels.len() {
+            return Err(ChartError::InvalidMatrix(format!(
+                "{} rows of values for {} y labels",
+                self.values.len(),
+                self.y_labels.len()
+            )));
+        }
+        if let Some((row, values)) = self
+            .values
+            .iter()
+            .enumerate()
+            .find(|(_, values)| values.len() != self.x_labels.len())
+        {
+            return Err(ChartError::InvalidMatrix(format!(
+                "row {} has {} values for {} x labels",
+                row,
+                values.len(),
+                self.x_labels.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
 /// Dataset styling
-#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct DatasetStyle {
     /// Background color
@@ -155,7 +429,7 @@ pub struct DatasetStyle {
 }
 
 /// Color value (single or array for gradients)
-#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 #[serde(untagged)]
 pub enum ColorValue {
@@ -163,6 +437,152 @@ pub enum ColorValue {
     Multiple(Vec<String>),
 }
 
+/// Named color palettes for datasets
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Palette {
+    /// D3's ten categorical colors
+    Category10,
+    /// Perceptually uniform purple-to-yellow ramp
+    Viridis,
+    /// Light-to-dark blue ramp
+    Blues,
+    /// Colors from the Virginia state flag and seal
+    VirginiaFlag,
+}
+
+impl Palette {
+    /// Colors that define the palette
+    pub fn colors(&self) -> &'static [&'static str] {
+        match self {
+            Palette::Category10 => &[
+                "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2",
+                "#7f7f7f", "#bcbd22", "#17becf",
+            ],
+            Palette::Viridis => &["#440154", "#3b528b", "#21918c", "#5ec962", "#fde725"],
+            Palette::Blues => &["#deebf7", "#9ecae1", "#4292c6", "#2171b5", "#08306b"],
+            Palette::VirginiaFlag => &["#1b2f6e", "#c8102e", "#d4a017", "#2e6b30", "#7a4a2a"],
+        }
+    }
+
+    /// Whether colors form a ramp to interpolate rather than cycle through
+    pub fn is_sequential(&self) -> bool {
+        matches!(self, Palette::Viridis | Palette::Blues)
+    }
+
+    /// `count` colors: cycled for categorical palettes, evenly spaced
+    /// along the ramp for sequential ones
+    pub fn pick(&self, count: usize) -> Vec<String> {
+        let colors = self.colors();
+        if !self.is_sequential() {
+            return (0..count)
+                .map(|i| colors[i % colors.len()].to_string())
+                .collect();
+        }
+        (0..count)
+            .map(|i| {
+                let t = if count > 1 {
+                    i as f64 / (count - 1) as f64
+                } else {
+                    0.5
+                };
+                ramp(colors, t)
+            })
+            .collect()
+    }
+}
+
+/// Fill dataset background and border colors from a palette
+///
+/// A single dataset with several segments (pie, doughnut) colored with a
+/// sequential palette gets one color per segment; otherwise each dataset
+/// gets its own color.
+pub fn apply_palette(data: &mut ChartData, palette: Palette) {
+    let segments = data.labels.len();
+    if data.datasets.len() == 1 && segments > 1 && palette.is_sequential() {
+        let colors = ColorValue::Multiple(palette.pick(segments));
+        set_colors(&mut data.datasets[0], colors);
+        return;
+    }
+    let colors = palette.pick(data.datasets.len());
+    for (dataset, color) in data.datasets.iter_mut().zip(colors) {
+        set_colors(dataset, ColorValue::Single(color));
+    }
+}
+
+fn set_colors(dataset: &mut Dataset, color: ColorValue) {
+    let style = dataset.style.get_or_insert_with(DatasetStyle::default);
+    style.background_color = Some(color.clone());
+    style.border_color = Some(color);
+}
+
+/// Colors a renderer should use for a theme
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ThemeChartDefaults {
+    /// Chart area background
+    pub background: String,
+    /// Axis grid lines
+    pub grid_color: String,
+    /// Titles, labels and legend text
+    pub text_color: String,
+    /// Dataset colors where none are set
+    pub palette: Palette,
+}
+
+impl Theme {
+    /// Chart colors for this theme; `System` follows `prefers_dark`
+    pub fn chart_defaults(&self, prefers_dark: bool) -> ThemeChartDefaults {
+        let dark = match self {
+            Theme::Light => false,
+            Theme::Dark => true,
+            Theme::System => prefers_dark,
+        };
+        let (background, grid_color, text_color) = if dark {
+            ("#1e1e1e", "#3a3a3a", "#e0e0e0")
+        } else {
+            ("#ffffff", "#e5e5e5", "#333333")
+        };
+        ThemeChartDefaults {
+            background: background.to_string(),
+            grid_color: grid_color.to_string(),
+            text_color: text_color.to_string(),
+            palette: Palette::Category10,
+        }
+    }
+}
+
+impl ChartConfig {
+    /// Fill dataset colors left unset with the theme's palette
+    ///
+    /// Colors already chosen, e.g. by `apply_palette`, are kept.
+    pub fn apply_theme_defaults(&mut self, prefers_dark: bool) {
+        let palette = self.theme.chart_defaults(prefers_dark).palette;
+        let colors = palette.pick(self.data.datasets.len());
+        for (dataset, color) in self.data.datasets.iter_mut().zip(colors) {
+            let style = dataset.style.get_or_insert_with(DatasetStyle::default);
+            style
+                .background_color
+                .get_or_insert_with(|| ColorValue::Single(color.clone()));
+            style.border_color.get_or_insert(ColorValue::Single(color));
+        }
+    }
+}
+
+/// Linear interpolation along `#rrggbb` stops at `t` in `[0, 1]`
+fn ramp(stops: &[&str], t: f64) -> String {
+    let rgb = |hex: &str| {
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0) as f64;
+        [channel(1), channel(3), channel(5)]
+    };
+    let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let index = (position.floor() as usize).min(stops.len() - 2);
+    let local = position - index as f64;
+    let (from, to) = (rgb(stops[index]), rgb(stops[index + 1]));
+    let mix = |c: usize| (from[c] + (to[c] - from[c]) * local).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", mix(0), mix(1), mix(2))
+}
+
 /// Chart options
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -182,15 +602,58 @@ pub struct ChartOptions {
     /// X axis config
     #[serde(skip_serializing_if = "Option::is_none")]
     pub x_axis: Option<AxisConfig>,
-    /// Y axis config
+    /// Y axis config (the default axis)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub y_axis: Option<AxisConfig>,
+    /// Additional named Y axes, e.g. a secondary axis on the right
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub y_axes: Vec<AxisConfig>,
     /// Enable tooltips
     #[serde(default = "default_true")]
     pub tooltips: bool,
     /// Enable animations
     #[serde(default = "default_true")]
     pub animations: bool,
+    /// Color scale for value-colored cells (heatmap)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_scale: Option<ColorScale>,
+    /// Reference lines and bands drawn over the data
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+}
+
+/// A reference mark drawn over a chart
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum Annotation {
+    /// Line at a single value, e.g. a speed limit
+    #[serde(rename = "line")]
+    Line {
+        axis: AxisRef,
+        value: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        label: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color: Option<String>,
+    },
+    /// Shaded range between two values
+    #[serde(rename = "band")]
+    Band {
+        axis: AxisRef,
+        from: f64,
+        to: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color: Option<String>,
+    },
+}
+
+/// Axis an annotation is positioned on
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum AxisRef {
+    X,
+    Y,
 }
 
 fn default_true() -> bool {
@@ -212,6 +675,9 @@ pub enum LegendPosition {
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct AxisConfig {
+    /// Axis ID referenced by `Dataset.y_axis_id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     /// Axis title
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
@@ -227,6 +693,46 @@ pub struct AxisConfig {
     /// Stacked mode
     #[serde(default)]
     pub stacked: bool,
+    /// Scale type
+    #[serde(default)]
+    pub scale_type: AxisScale,
+}
+
+impl AxisConfig {
+    /// Check that the bounds suit the scale type
+    pub fn validate(&self) -> Result<(), ChartError> {
+        if self.scale_type == AxisScale::Logarithmic {
+            if let Some(min) = self.min.filter(|min| *min <= 0.0) {
+                return Err(ChartError::InvalidAxis(format!(
+                    "logarithmic axis minimum must be positive, got {}",
+                    min
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Axis scale type
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum AxisScale {
+    #[default]
+    Linear,
+    Logarithmic,
+    /// Dates and timestamps, labeled in `unit` steps
+    Time {
+        unit: TimeUnit,
+    },
+}
+
+/// Display unit for time axes
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum TimeUnit {
+    Day,
+    Month,
+    Year,
 }
 
 // Theme is defined in storage.rs and re-exported above
@@ -243,6 +749,106 @@ pub struct ChoroplethConfig {
     pub color_scale: ColorScale,
 }
 
+/// Join a query result to map regions
+///
+/// Each row with a region ID becomes a point; numeric region IDs such as
+/// FIPS codes are stringified. Rows with a NULL region or value are
+/// skipped, since there is nothing to color.
+pub fn build_choropleth_data(
+    result: &QueryResult,
+    region_col: &str,
+    value_col: &str,
+) -> Result<Vec<GeoDataPoint>, ChartError> {
+    let column_index = |name: &str| {
+        result
+            .columns
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| ChartError::MissingColumn(name.to_string()))
+    };
+    let region_index = column_index(region_col)?;
+    let value_index = column_index(value_col)?;
+    if !result.columns[value_index].data_type.is_numeric() {
+        return Err(ChartError::NonNumericColumn(value_col.to_string()));
+    }
+
+    let mut points = Vec::new();
+    for (row, values) in result.rows.iter().enumerate() {
+        let region_id = match values.get(region_index) {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Null) | None => continue,
+            Some(other) => other.to_string(),
+        };
+        let value = match values.get(value_index) {
+            Some(Value::Number(n)) => n.as_f64(),
+            Some(Value::String(s)) => s.parse::<f64>().ok(),
+            Some(Value::Null) | None => continue,
+            Some(_) => None,
+        }
+        .ok_or_else(|| ChartError::NonNumericValue {
+            column: value_col.to_string(),
+            row,
+        })?;
+        points.push(GeoDataPoint {
+            region_id,
+            value,
+            label: None,
+        });
+    }
+    Ok(points)
+}
+
+/// How well choropleth data covers the map's regions
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CoverageReport {
+    /// Regions that matched a data point
+    pub matched: usize,
+    /// Map regions with no data, drawn uncolored
+    pub regions_without_data: Vec<String>,
+    /// Data points whose region isn't on the map, e.g. a mistyped ID
+    pub data_without_region: Vec<String>,
+    /// Regions with more than one data point; only one can be drawn
+    pub duplicate_regions: Vec<String>,
+}
+
+impl CoverageReport {
+    /// Whether every region has exactly one point and every point a region
+    pub fn is_complete(&self) -> bool {
+        self.regions_without_data.is_empty()
+            && self.data_without_region.is_empty()
+            && self.duplicate_regions.is_empty()
+    }
+}
+
+/// Compare data points against the region IDs in a map's GeoJSON
+pub fn validate_choropleth_coverage(
+    points: &[GeoDataPoint],
+    region_ids: &[String],
+) -> CoverageReport {
+    let regions: HashSet<&str> = region_ids.iter().map(String::as_str).collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut report = CoverageReport::default();
+    for point in points {
+        let count = counts.entry(point.region_id.as_str()).or_default();
+        *count += 1;
+        if *count == 2 {
+            report.duplicate_regions.push(point.region_id.clone());
+        }
+        if *count == 1 && !regions.contains(point.region_id.as_str()) {
+            report.data_without_region.push(point.region_id.clone());
+        }
+    }
+    for id in region_ids {
+        if counts.contains_key(id.as_str()) {
+            report.matched += 1;
+        } else {
+            report.regions_without_data.push(id.clone());
+        }
+    }
+    report
+}
+
 /// GeoJSON source
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -254,7 +860,7 @@ pub enum GeoSource {
     Inline { geojson: String },
 }
 
-/// Color scale for choropleth
+/// Color scale for choropleths and heatmaps
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct ColorScale {
@@ -277,10 +883,10 @@ fn default_steps() -> u32 {
 pub struct ExportConfig {
     /// Export format
     pub format: ExportFormat,
-    /// Width in pixels
+    /// Width in pixels (points for PDF)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub width: Option<u32>,
-    /// Height in pixels
+    /// Height in pixels (points for PDF)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub height: Option<u32>,
     /// Background color (default: transparent for SVG)
@@ -294,6 +900,20 @@ pub struct ExportConfig {
 pub enum ExportFormat {
     Png,
     Svg,
+    WebP,
+    Pdf,
+}
+
+impl ExportConfig {
+    /// MIME type the renderer should report in `ExportResult` for `format`
+    pub fn default_mime(format: &ExportFormat) -> &'static str {
+        match format {
+            ExportFormat::Png => "image/png",
+            ExportFormat::Svg => "image/svg+xml",
+            ExportFormat::WebP => "image/webp",
+            ExportFormat::Pdf => "application/pdf",
+        }
+    }
 }
 
 /// Render request
@@ -320,8 +940,436 @@ pub struct ChartExportRequest {
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct ExportResult {
-    /// Data URL (base64 for PNG, SVG string for SVG)
+    /// Data URL (base64 for PNG, WebP and PDF, SVG string for SVG)
     pub data_url: String,
     /// MIME type
     pub mime_type: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::ColumnSchema;
+    use serde_json::json;
+
+    fn result() -> QueryResult {
+        let column = |name: &str, data_type| ColumnSchema {
+            name: name.to_string(),
+            data_type,
+            nullable: true,
+            display_hint: None,
+        };
+        QueryResult {
+            query_id: String::new(),
+            columns: vec![
+                column("district", ColumnType::String),
+                column("enrollment", ColumnType::Int64),
+                column(
+                    "spend",
+                    ColumnType::Decimal {
+                        precision: 10,
+                        scale: 2,
+                    },
+                ),
+            ],
+            rows: vec![
+                vec![json!("Back Creek"), json!(4210), json!("12.50")],
+                vec![json!("Opequon"), json!(3875), json!(null)],
+            ],
+            total_rows: 2,
+            truncated: false,
+            cached: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn builds_numeric_datasets() {
+        let data = ChartData::from_query_result(
+            &result(),
+            "district",
+            &["enrollment".to_string(), "spend".to_string()],
+        )
+        .unwrap();
+        assert_eq!(data.labels, vec!["Back Creek", "Opequon"]);
+        match &data.datasets[0].data {
+            DataValues::Numbers(values) => assert_eq!(values, &vec![Some(4210.0), Some(3875.0)]),
+            other => panic!("unexpected data {:?}", other),
+        }
+        match &data.datasets[1].data {
+            DataValues::Numbers(values) => assert_eq!(values, &vec![Some(12.5), None]),
+            other => panic!("unexpected data {:?}", other),
+        }
+    }
+
+    #[test]
+    fn null_values_round_trip_as_gaps() {
+        let data = DataValues::Numbers(vec![Some(12.5), None]);
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(json, r#"{"type":"numbers","values":[12.5,null]}"#);
+        match serde_json::from_str::<DataValues>(&json).unwrap() {
+            DataValues::Numbers(values) => assert_eq!(values, vec![Some(12.5), None]),
+            other => panic!("unexpected data {:?}", other),
+        }
+    }
+
+    fn candlestick(points: Vec<OhlcPoint>) -> ChartConfig {
+        ChartConfig {
+            id: "prices".to_string(),
+            chart_type: ChartType::Candlestick,
+            title: None,
+            data: ChartData {
+                labels: Vec::new(),
+                datasets: vec![Dataset {
+                    label: "close".to_string(),
+                    data: DataValues::Ohlc(points),
+                    style: None,
+                    y_axis_id: None,
+                }],
+            },
+            options: None,
+            theme: Theme::default(),
+            cell_id: None,
+        }
+    }
+
+    #[test]
+    fn ohlc_round_trips() {
+        let point = OhlcPoint {
+            x: 1_700_000_000_000.0,
+            open: 10.0,
+            high: 12.5,
+            low: 9.5,
+            close: 11.0,
+        };
+        let json = serde_json::to_string(&DataValues::Ohlc(vec![point.clone()])).unwrap();
+        match serde_json::from_str::<DataValues>(&json).unwrap() {
+            DataValues::Ohlc(points) => assert_eq!(points, vec![point]),
+            other => panic!("decoded as {:?}", other),
+        }
+
+        // An empty series keeps its variant
+        let json = serde_json::to_string(&DataValues::Ohlc(Vec::new())).unwrap();
+        match serde_json::from_str::<DataValues>(&json).unwrap() {
+            DataValues::Ohlc(points) => assert!(points.is_empty()),
+            other => panic!("decoded as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validates_candlesticks() {
+        let point = |high, low| OhlcPoint {
+            x: 0.0,
+            open: 1.0,
+            high,
+            low,
+            close: 1.0,
+        };
+        assert!(candlestick(vec![point(2.0, 0.5)]).validate().is_ok());
+        assert_eq!(
+            candlestick(vec![point(2.0, 0.5), point(0.5, 2.0)]).validate(),
+            Err(ChartError::InvalidOhlc {
+                dataset: "close".to_string(),
+                index: 1
+            })
+        );
+
+        let mut config = candlestick(Vec::new());
+        config.data.datasets[0].data = DataValues::Numbers(vec![Some(1.0)]);
+        assert!(matches!(
+            config.validate(),
+            Err(ChartError::DataMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn validates_matrix_shape() {
+        let matrix = MatrixData {
+            x_labels: vec!["a".to_string(), "b".to_string()],
+            y_labels: vec!["a".to_string(), "b".to_string()],
+            values: vec![vec![1.0, 0.4], vec![0.4, 1.0]],
+        };
+        assert!(matrix.validate().is_ok());
+
+        let mut short_row = matrix.clone();
+        short_row.values[1].pop();
+        assert_eq!(
+            short_row.validate(),
+            Err(ChartError::InvalidMatrix(
+                "row 1 has 1 values for 2 x labels".to_string()
+            ))
+        );
+
+        let mut missing_row = matrix;
+        missing_row.values.pop();
+        assert!(missing_row.validate().is_err());
+    }
+
+    #[test]
+    fn log_axis_requires_positive_min() {
+        let axis: AxisConfig =
+            serde_json::from_value(json!({ "min": 0.0, "scale_type": "Logarithmic" })).unwrap();
+        assert!(matches!(axis.validate(), Err(ChartError::InvalidAxis(_))));
+
+        // Older configs without a scale type stay linear
+        let axis: AxisConfig = serde_json::from_value(json!({ "min": 0.0 })).unwrap();
+        assert_eq!(axis.scale_type, AxisScale::Linear);
+        assert!(axis.validate().is_ok());
+    }
+
+    #[test]
+    fn line_annotation_serde() {
+        let line = Annotation::Line {
+            axis: AxisRef::Y,
+            value: 55.0,
+            label: Some("Speed limit".to_string()),
+            color: None,
+        };
+        let value = serde_json::to_value(&line).unwrap();
+        assert_eq!(
+            value,
+            json!({ "type": "line", "axis": "Y", "value": 55.0, "label": "Speed limit" })
+        );
+        assert_eq!(serde_json::from_value::<Annotation>(value).unwrap(), line);
+    }
+
+    #[test]
+    fn band_annotation_serde() {
+        let band = Annotation::Band {
+            axis: AxisRef::X,
+            from: 2019.0,
+            to: 2021.0,
+            color: Some("#ffd70033".to_string()),
+        };
+        let value = serde_json::to_value(&band).unwrap();
+        assert_eq!(
+            value,
+            json!({ "type": "band", "axis": "X", "from": 2019.0, "to": 2021.0, "color": "#ffd70033" })
+        );
+        assert_eq!(serde_json::from_value::<Annotation>(value).unwrap(), band);
+    }
+
+    #[test]
+    fn validates_secondary_axis_ids() {
+        let mut config = candlestick(vec![]);
+        config.chart_type = ChartType::Line;
+        config.data.datasets[0].data = DataValues::Numbers(vec![Some(1.0)]);
+        config.data.datasets[0].y_axis_id = Some("rate".to_string());
+        assert_eq!(
+            config.validate(),
+            Err(ChartError::UnknownAxis {
+                dataset: "close".to_string(),
+                axis: "rate".to_string()
+            })
+        );
+
+        config.options = Some(
+            serde_json::from_value(json!({
+                "y_axis": { "title": "Vehicles" },
+                "y_axes": [{ "id": "rate", "title": "Crash rate" }]
+            }))
+            .unwrap(),
+        );
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn export_mime_types() {
+        assert_eq!(
+            ExportConfig::default_mime(&ExportFormat::WebP),
+            "image/webp"
+        );
+        assert_eq!(
+            ExportConfig::default_mime(&ExportFormat::Pdf),
+            "application/pdf"
+        );
+        assert_eq!(
+            ExportConfig::default_mime(&ExportFormat::Svg),
+            "image/svg+xml"
+        );
+        assert_eq!(
+            serde_json::from_value::<ExportFormat>(json!("WebP")).unwrap(),
+            ExportFormat::WebP
+        );
+    }
+
+    fn bar_chart(series: usize, labels: usize) -> ChartData {
+        ChartData {
+            labels: (0..labels).map(|i| i.to_string()).collect(),
+            datasets: (0..series)
+                .map(|i| Dataset {
+                    label: format!("series {}", i),
+                    data: DataValues::Numbers(vec![Some(1.0); labels]),
+                    style: None,
+                    y_axis_id: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn background(dataset: &Dataset) -> Option<ColorValue> {
+        dataset.style.as_ref()?.background_color.clone()
+    }
+
+    #[test]
+    fn category10_colors_datasets_in_order() {
+        let mut data = bar_chart(3, 4);
+        apply_palette(&mut data, Palette::Category10);
+        let colors: Vec<_> = data.datasets.iter().map(background).collect();
+        assert_eq!(
+            colors,
+            vec![
+                Some(ColorValue::Single("#1f77b4".to_string())),
+                Some(ColorValue::Single("#ff7f0e".to_string())),
+                Some(ColorValue::Single("#2ca02c".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn sequential_palette_colors_pie_segments() {
+        let mut data = bar_chart(1, 3);
+        apply_palette(&mut data, Palette::Viridis);
+        assert_eq!(
+            background(&data.datasets[0]),
+            Some(ColorValue::Multiple(vec![
+                "#440154".to_string(),
+                "#21918c".to_string(),
+                "#fde725".to_string(),
+            ]))
+        );
+    }
+
+    #[test]
+    fn rejects_missing_and_non_numeric_columns() {
+        let missing = ChartData::from_query_result(&result(), "region", &[]);
+        assert_eq!(
+            missing.unwrap_err(),
+            ChartError::MissingColumn("region".to_string())
+        );
+        let text = ChartData::from_query_result(&result(), "district", &["district".to_string()]);
+        assert_eq!(
+            text.unwrap_err(),
+            ChartError::NonNumericColumn("district".to_string())
+        );
+    }
+
+    #[test]
+    fn choropleth_reports_mismatched_regions() {
+        let points = build_choropleth_data(&result(), "district", "spend").unwrap();
+        // The NULL value row is skipped
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].region_id, "Back Creek");
+
+        let mut points = points;
+        points.push(GeoDataPoint {
+            region_id: "Opequan".to_string(),
+            value: 9.75,
+            label: None,
+        });
+        let regions: Vec<String> = ["Back Creek", "Opequon", "Shawnee"]
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        let report = validate_choropleth_coverage(&points, &regions);
+        assert_eq!(
+            report,
+            CoverageReport {
+                matched: 1,
+                regions_without_data: vec!["Opequon".to_string(), "Shawnee".to_string()],
+                data_without_region: vec!["Opequan".to_string()],
+                duplicate_regions: Vec::new(),
+            }
+        );
+        assert!(!report.is_complete());
+    }
+
+    #[test]
+    fn suggests_bar_and_pie_for_category_and_measure() {
+        assert_eq!(
+            suggest_chart_types(&result()),
+            vec![
+                ChartType::Bar,
+                ChartType::Pie,
+                ChartType::Doughnut,
+                ChartType::Scatter
+            ]
+        );
+
+        let mut many = result();
+        many.columns.truncate(2);
+        many.rows = (0..20)
+            .map(|i| vec![json!(format!("Precinct {}", i)), json!(i)])
+            .collect();
+        assert_eq!(suggest_chart_types(&many), vec![ChartType::Bar]);
+    }
+
+    #[test]
+    fn suggests_line_for_date_and_measure() {
+        let mut trend = result();
+        trend.columns = vec![
+            ColumnSchema {
+                name: "crash_date".to_string(),
+                data_type: ColumnType::Date,
+                nullable: false,
+                display_hint: None,
+            },
+            ColumnSchema {
+                name: "crashes".to_string(),
+                data_type: ColumnType::Int64,
+                nullable: false,
+                display_hint: None,
+            },
+        ];
+        trend.rows = vec![
+            vec![json!("2024-01-01"), json!(14)],
+            vec![json!("2024-01-02"), json!(9)],
+        ];
+        assert_eq!(
+            suggest_chart_types(&trend),
+            vec![ChartType::Line, ChartType::Area]
+        );
+    }
+
+    #[test]
+    fn theme_chart_defaults() {
+        let light = Theme::Light.chart_defaults(true);
+        let dark = Theme::Dark.chart_defaults(false);
+        assert_eq!(light.background, "#ffffff");
+        assert_eq!(dark.background, "#1e1e1e");
+        assert_ne!(light.text_color, dark.text_color);
+        assert_eq!(Theme::System.chart_defaults(true), dark);
+        assert_eq!(Theme::System.chart_defaults(false), light);
+    }
+
+    #[test]
+    fn theme_defaults_fill_only_unset_colors() {
+        let mut config = candlestick(Vec::new());
+        config.data = ChartData::from_query_result(
+            &result(),
+            "district",
+            &["enrollment".to_string(), "spend".to_string()],
+        )
+        .unwrap();
+        config.data.datasets[0].style = Some(DatasetStyle {
+            border_color: Some(ColorValue::Single("#000000".to_string())),
+            ..Default::default()
+        });
+        config.apply_theme_defaults(false);
+
+        let first = config.data.datasets[0].style.as_ref().unwrap();
+        assert_eq!(
+            first.background_color,
+            Some(ColorValue::Single("#1f77b4".to_string()))
+        );
+        assert_eq!(
+            first.border_color,
+            Some(ColorValue::Single("#000000".to_string()))
+        );
+        let second = config.data.datasets[1].style.as_ref().unwrap();
+        assert_eq!(
+            second.border_color,
+            Some(ColorValue::Single("#ff7f0e".to_string()))
+        );
+    }
+}
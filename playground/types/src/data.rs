@@ -1,16 +1,80 @@
 //! DataEngine types for SQL queries and data loading
 
+use crate::messages::ErrorInfo;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use tsify::Tsify;
 
 /// Request to load a data file
-#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct LoadRequest {
     /// Unique name for the table
     pub name: String,
-    /// URL to the Parquet file
+    /// URL to the data file
     pub url: String,
+    /// File format (default: Parquet)
+    #[serde(default)]
+    pub format: DataFormat,
+}
+
+/// Format of a data file to load
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum DataFormat {
+    /// Apache Parquet
+    #[default]
+    #[serde(rename = "parquet")]
+    Parquet,
+    /// Delimited text
+    #[serde(rename = "csv")]
+    Csv {
+        /// Field delimiter (auto-detected if omitted)
+        #[serde(default)]
+        delimiter: Option<char>,
+        /// Whether the first line is a header row
+        #[serde(default = "default_true")]
+        header: bool,
+    },
+    /// Newline-delimited JSON
+    #[serde(rename = "ndjson")]
+    NdJson,
+}
+
+impl DataFormat {
+    /// File extension for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DataFormat::Parquet => "parquet",
+            DataFormat::Csv { .. } => "csv",
+            DataFormat::NdJson => "ndjson",
+        }
+    }
+
+    /// Guess the format from a file path's extension, defaulting to Parquet
+    pub fn from_path(path: &str) -> DataFormat {
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        let extension = path
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("csv") => DataFormat::Csv {
+                delimiter: None,
+                header: true,
+            },
+            Some("tsv") => DataFormat::Csv {
+                delimiter: Some('\t'),
+                header: true,
+            },
+            Some("ndjson" | "jsonl") => DataFormat::NdJson,
+            _ => DataFormat::Parquet,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
 }
 
 /// Result of loading a file
@@ -25,6 +89,24 @@ pub struct LoadResult {
     pub schema: TableSchema,
 }
 
+/// Request to drop a loaded table
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DropRequest {
+    /// Table name
+    pub name: String,
+}
+
+/// Result of dropping a table
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DropResult {
+    /// Table name
+    pub name: String,
+    /// Whether a table or view existed and was dropped
+    pub freed: bool,
+}
+
 /// Request to execute a SQL query
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -34,6 +116,38 @@ pub struct QueryRequest {
     /// Maximum rows to return (default: 10000)
     #[serde(default = "default_limit")]
     pub limit: u32,
+    /// Rows to skip before the first returned row
+    #[serde(default)]
+    pub offset: u64,
+    /// Values bound to `?` placeholders in `sql`, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<serde_json::Value>,
+    /// Overrides the engine's timeout for this query; 0 disables it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u32>,
+    /// On failure, add the query plan or the error's position in `sql`
+    /// to `ErrorInfo.details`
+    #[serde(default)]
+    pub explain_on_error: bool,
+    /// IANA time zone, e.g. `America/New_York`, in which to return
+    /// `Timestamp` values; UTC if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+}
+
+impl QueryRequest {
+    /// A request for `sql` with the default limit and no params
+    pub fn new(sql: impl Into<String>) -> Self {
+        Self {
+            sql: sql.into(),
+            limit: default_limit(),
+            offset: 0,
+            params: Vec::new(),
+            timeout_seconds: None,
+            explain_on_error: false,
+            timezone: None,
+        }
+    }
 }
 
 fn default_limit() -> u32 {
@@ -44,14 +158,340 @@ fn default_limit() -> u32 {
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct QueryResult {
+    /// Identifies the query (a hash of its SQL, paging and params)
+    #[serde(default)]
+    pub query_id: String,
     /// Column metadata
     pub columns: Vec<ColumnSchema>,
     /// Row data as JSON values
     pub rows: Vec<Vec<serde_json::Value>>,
-    /// Total rows in result (before limit)
+    /// Total rows in result (before offset and limit)
     pub total_rows: u64,
     /// Whether result was truncated
     pub truncated: bool,
+    /// Whether the result was served from the engine's query cache
+    #[serde(default)]
+    pub cached: bool,
+    /// Caveats about a successful result, e.g. truncation
+    #[serde(default)]
+    pub warnings: Vec<QueryWarning>,
+}
+
+impl QueryResult {
+    /// A page of rows starting at `offset`, of `total_rows` in the result
+    ///
+    /// Sets `truncated` and its warning when rows lie beyond the page.
+    pub fn page(
+        columns: Vec<ColumnSchema>,
+        rows: Vec<Vec<serde_json::Value>>,
+        total_rows: u64,
+        offset: u64,
+    ) -> Self {
+        let shown = rows.len() as u64;
+        let truncated = total_rows > offset + shown;
+        let mut warnings = Vec::new();
+        if truncated {
+            warnings.push(QueryWarning {
+                code: WarningCode::Truncated,
+                message: format!("Showing {} of {} rows", shown, total_rows),
+            });
+        }
+        QueryResult {
+            query_id: String::new(),
+            columns,
+            rows,
+            total_rows,
+            truncated,
+            cached: false,
+            warnings,
+        }
+    }
+
+    /// Rows as objects keyed by column name
+    pub fn to_records(&self) -> Vec<serde_json::Map<String, serde_json::Value>> {
+        self.rows
+            .iter()
+            .map(|row| {
+                self.columns
+                    .iter()
+                    .zip(row)
+                    .map(|(column, value)| (column.name.clone(), value.clone()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Values of the column `name`, in row order
+    pub fn column(&self, name: &str) -> Option<Vec<&serde_json::Value>> {
+        let index = self.columns.iter().position(|c| c.name == name)?;
+        Some(self.rows.iter().filter_map(|row| row.get(index)).collect())
+    }
+}
+
+/// Most differing rows a [`ResultDiff`] lists
+pub const MAX_DIFF_ROWS: usize = 20;
+
+/// Differences between two results of the same query
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ResultDiff {
+    /// Columns differ in names, order or types; rows were not compared
+    pub schema_changed: bool,
+    /// Columns only in the new result
+    pub added_columns: Vec<String>,
+    /// Columns only in the old result
+    pub removed_columns: Vec<String>,
+    /// Columns in both whose type changed
+    pub retyped_columns: Vec<String>,
+    /// `new.total_rows - old.total_rows`
+    pub row_count_delta: i64,
+    /// Returned rows that differ, by position, up to [`MAX_DIFF_ROWS`]
+    pub differing_rows: Vec<RowDiff>,
+    /// How many returned rows differ in total
+    pub differing_row_count: u64,
+}
+
+impl ResultDiff {
+    /// Whether the results are identical
+    pub fn is_empty(&self) -> bool {
+        !self.schema_changed && self.row_count_delta == 0 && self.differing_row_count == 0
+    }
+}
+
+/// A row that differs between two results
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RowDiff {
+    /// Position in the returned rows
+    pub index: u64,
+    /// The old row, if the old result had one here
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Vec<serde_json::Value>>,
+    /// The new row, if the new result had one here
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Vec<serde_json::Value>>,
+}
+
+/// Compare two results of a query, e.g. across dataset versions
+///
+/// Rows are compared by position, so the query should have an `ORDER BY`.
+/// A schema change skips the row comparison.
+pub fn diff_query_results(old: &QueryResult, new: &QueryResult) -> ResultDiff {
+    let mut diff = ResultDiff {
+        row_count_delta: new.total_rows as i64 - old.total_rows as i64,
+        ..ResultDiff::default()
+    };
+    let find = |columns: &[ColumnSchema], name: &str| {
+        columns
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.data_type.clone())
+    };
+    for column in &new.columns {
+        match find(&old.columns, &column.name) {
+            None => diff.added_columns.push(column.name.clone()),
+            Some(data_type) if data_type != column.data_type => {
+                diff.retyped_columns.push(column.name.clone())
+            }
+            Some(_) => {}
+        }
+    }
+    for column in &old.columns {
+        if find(&new.columns, &column.name).is_none() {
+            diff.removed_columns.push(column.name.clone());
+        }
+    }
+    let names =
+        |columns: &[ColumnSchema]| columns.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+    diff.schema_changed =
+        !diff.retyped_columns.is_empty() || names(&old.columns) != names(&new.columns);
+    if diff.schema_changed {
+        return diff;
+    }
+
+    for index in 0..old.rows.len().max(new.rows.len()) {
+        let (old_row, new_row) = (old.rows.get(index), new.rows.get(index));
+        if old_row == new_row {
+            continue;
+        }
+        diff.differing_row_count += 1;
+        if diff.differing_rows.len() < MAX_DIFF_ROWS {
+            diff.differing_rows.push(RowDiff {
+                index: index as u64,
+                old: old_row.cloned(),
+                new: new_row.cloned(),
+            });
+        }
+    }
+    diff
+}
+
+/// A caveat attached to a successful query
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct QueryWarning {
+    /// Warning code for programmatic handling
+    pub code: WarningCode,
+    /// Human-readable warning message
+    pub message: String,
+}
+
+/// Standard warning codes
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum WarningCode {
+    /// Only part of the result was returned
+    Truncated,
+    /// A value was implicitly cast to another type
+    ImplicitCast,
+    /// The query uses a deprecated function
+    DeprecatedFunction,
+    /// A setting was outside its allowed range and was clamped
+    Clamped,
+}
+
+/// Result of running a multi-statement script
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ScriptResult {
+    /// Outcomes of the statements that ran, in order
+    pub outcomes: Vec<StatementOutcome>,
+    /// The statement that failed, if any; later statements were not run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<StatementError>,
+}
+
+/// Outcome of one statement in a script
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum StatementOutcome {
+    /// A statement that returned rows
+    #[serde(rename = "rows")]
+    Rows(QueryResult),
+    /// A statement that changed data or schema
+    #[serde(rename = "ack")]
+    Ack {
+        /// Rows inserted, updated or deleted (0 for DDL)
+        affected_rows: u64,
+    },
+}
+
+/// A failed statement in a script
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct StatementError {
+    /// Zero-based index of the statement
+    pub index: u32,
+    /// The statement's SQL
+    pub sql: String,
+    /// What went wrong
+    pub error: ErrorInfo,
+}
+
+/// Handle to an open query cursor
+pub type QueryCursorHandle = u32;
+
+/// A chunk of rows pulled from a query cursor
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct QueryResultBatch {
+    /// Column metadata
+    pub columns: Vec<ColumnSchema>,
+    /// Row data as JSON values
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// Zero-based index of this batch
+    pub batch_index: u32,
+    /// Whether the cursor is exhausted
+    pub is_last: bool,
+}
+
+/// Query plan from `EXPLAIN` or `EXPLAIN ANALYZE`
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ExplainResult {
+    /// Plan exactly as DuckDB rendered it (JSON)
+    pub raw: String,
+    /// Root operator of the physical plan
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<PlanNode>,
+    /// Whether the query was executed and timed
+    pub analyzed: bool,
+}
+
+/// An operator in a query plan
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PlanNode {
+    /// Operator name, e.g. `HASH_GROUP_BY`
+    pub name: String,
+    /// Planner's row estimate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_cardinality: Option<u64>,
+    /// Rows actually produced (analyze only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual_cardinality: Option<u64>,
+    /// Time spent in this operator in milliseconds (analyze only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing_ms: Option<f64>,
+    /// Input operators
+    pub children: Vec<PlanNode>,
+}
+
+/// File format for exported query results
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ExportDataFormat {
+    /// Comma-separated values with a header row
+    Csv,
+    /// Apache Parquet
+    Parquet,
+    /// JSON array of row objects
+    Json,
+}
+
+impl ExportDataFormat {
+    /// File extension for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportDataFormat::Csv => "csv",
+            ExportDataFormat::Parquet => "parquet",
+            ExportDataFormat::Json => "json",
+        }
+    }
+
+    /// MIME type for downloads
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ExportDataFormat::Csv => "text/csv",
+            ExportDataFormat::Parquet => "application/vnd.apache.parquet",
+            ExportDataFormat::Json => "application/json",
+        }
+    }
+}
+
+/// An exported query result ready for download
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ExportedBytes {
+    /// Base64-encoded file contents
+    pub data_base64: String,
+    /// MIME type
+    pub mime_type: String,
+    /// Suggested file name
+    pub filename: String,
+}
+
+/// A query result as an Arrow IPC stream, for Arrow JS
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ArrowResult {
+    /// Base64-encoded IPC stream (schema message, then record batches)
+    pub ipc_bytes_base64: String,
+    /// Column metadata
+    pub schema: Vec<ColumnSchema>,
+    /// Rows across all record batches
+    pub row_count: u64,
 }
 
 /// Request to get table schema
@@ -72,6 +512,34 @@ pub struct TableSchema {
     pub columns: Vec<ColumnSchema>,
     /// Estimated row count
     pub row_count: u64,
+    /// Whether this is a loaded table, another table, or a view
+    #[serde(default)]
+    pub kind: TableKind,
+}
+
+/// Kind of relation described by a `TableSchema`
+#[derive(Tsify, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum TableKind {
+    /// A table created with SQL rather than loaded from a file
+    #[default]
+    BaseTable,
+    /// A view, recomputed each time it is queried
+    View,
+    /// A table loaded from a data file
+    Loaded,
+}
+
+/// Lightweight description of a loaded table
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct TableSummary {
+    /// Table name
+    pub name: String,
+    /// Number of columns
+    pub column_count: u32,
+    /// Row count recorded when the table was loaded
+    pub row_count: u64,
 }
 
 /// Column metadata
@@ -84,6 +552,127 @@ pub struct ColumnSchema {
     pub data_type: ColumnType,
     /// Whether column can be null
     pub nullable: bool,
+    /// How renderers should format the column's values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_hint: Option<DisplayHint>,
+}
+
+/// Display intent for a numeric column
+///
+/// Inferred from column names by [`DisplayHint::infer`]; users can
+/// override it, with `None` to turn formatting off.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum DisplayHint {
+    /// Money in an ISO 4217 currency, e.g. `USD`
+    #[serde(rename = "currency")]
+    Currency { code: String },
+    /// Values already in percent, so `12.5` is 12.5%
+    #[serde(rename = "percentage")]
+    Percentage,
+    /// Whole numbers such as counts, grouped by thousands
+    #[serde(rename = "integer")]
+    Integer,
+    /// A length of time in seconds
+    #[serde(rename = "duration")]
+    Duration,
+    /// Plain numbers
+    #[serde(rename = "none")]
+    None,
+}
+
+/// Currency codes recognized as a column name word, e.g. `amount_usd`
+const CURRENCY_CODES: &[&str] = &["usd", "eur", "gbp", "cad", "jpy"];
+
+/// Column name words for money, assumed to be in dollars
+const MONEY_WORDS: &[&str] = &[
+    "amount", "price", "cost", "revenue", "budget", "salary", "fee", "tax", "spend", "dollars",
+];
+
+/// Final column name words for numbers that identify rather than measure
+const NON_AMOUNT_SUFFIXES: &[&str] = &["year", "yr", "fy", "id", "code"];
+
+impl DisplayHint {
+    /// Guess a hint from a numeric column's name, split into words on `_`
+    ///
+    /// Percentage, duration and count words win over money words, and a
+    /// name ending in a year or ID word is never money. Returns `None` for
+    /// non-numeric columns and names with no telling word.
+    pub fn infer(name: &str, data_type: &ColumnType) -> Option<DisplayHint> {
+        if !data_type.is_numeric() {
+            return None;
+        }
+        let name = name.to_lowercase();
+        let words: Vec<&str> = name.split(|c: char| !c.is_ascii_alphanumeric()).collect();
+        let has = |candidates: &[&str]| words.iter().any(|w| candidates.contains(w));
+        if has(&["pct", "percent", "percentage"]) {
+            return Some(DisplayHint::Percentage);
+        }
+        if has(&["duration", "seconds", "secs", "elapsed"]) {
+            return Some(DisplayHint::Duration);
+        }
+        if has(&[
+            "count",
+            "cnt",
+            "qty",
+            "quantity",
+            "population",
+            "enrollment",
+        ]) {
+            return Some(DisplayHint::Integer);
+        }
+        // `tax_year` and `budget_id` label money, they aren't amounts
+        if words
+            .last()
+            .is_some_and(|w| NON_AMOUNT_SUFFIXES.contains(w))
+        {
+            return None;
+        }
+        if let Some(code) = words.iter().find(|w| CURRENCY_CODES.contains(w)) {
+            return Some(DisplayHint::Currency {
+                code: code.to_uppercase(),
+            });
+        }
+        if has(MONEY_WORDS) {
+            return Some(DisplayHint::Currency {
+                code: "USD".to_string(),
+            });
+        }
+        None
+    }
+
+    /// Render `value` as this hint describes
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            DisplayHint::Currency { code } => {
+                let amount = crate::editor::group_thousands(&format!("{:.2}", value.abs()));
+                let sign = if value < 0.0 { "-" } else { "" };
+                match code.as_str() {
+                    "USD" => format!("{}${}", sign, amount),
+                    _ => format!("{}{} {}", sign, amount, code),
+                }
+            }
+            DisplayHint::Percentage => {
+                let text = format!("{:.1}", value);
+                format!("{}%", text.strip_suffix(".0").unwrap_or(&text))
+            }
+            DisplayHint::Integer => crate::editor::group_thousands(&format!("{:.0}", value)),
+            DisplayHint::Duration => {
+                let secs = value.max(0.0);
+                let whole = secs as u64;
+                match (whole / 3600, whole % 3600 / 60) {
+                    (0, 0) => {
+                        let text = format!("{:.1}", secs);
+                        format!("{}s", text.strip_suffix(".0").unwrap_or(&text))
+                    }
+                    (0, m) => format!("{}m {:02}s", m, whole % 60),
+                    (h, m) => format!("{}h {:02}m {:02}s", h, m, whole % 60),
+                }
+            }
+            DisplayHint::None => value.to_string(),
+        }
+    }
 }
 
 /// SQL data types
@@ -112,12 +701,96 @@ pub enum ColumnType {
     Date,
     /// Timestamp with timezone
     Timestamp,
+    /// Time of day
+    Time,
+    /// Calendar interval
+    Interval,
+    /// Fixed-precision decimal (rendered as strings in rows)
+    Decimal { precision: u8, scale: u8 },
+    /// List of values
+    List(Box<ColumnType>),
+    /// Struct with named fields
+    Struct(Vec<(String, ColumnType)>),
     /// JSON object
     Json,
     /// Unknown type
     Unknown,
 }
 
+impl ColumnType {
+    /// Whether values are numbers that support mean and standard deviation
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            ColumnType::Int8
+                | ColumnType::Int16
+                | ColumnType::Int32
+                | ColumnType::Int64
+                | ColumnType::Float32
+                | ColumnType::Float64
+                | ColumnType::Decimal { .. }
+        )
+    }
+}
+
+/// Request to bin a numeric column for a histogram
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct HistogramRequest {
+    /// Table name
+    pub table: String,
+    /// Numeric column to bin
+    pub column: String,
+    /// Number of equal-width bins
+    pub bins: u32,
+    /// Explicit `[min, max]`; values outside it fall into the edge bins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<(f64, f64)>,
+}
+
+/// Summary statistics for one column of a table
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ColumnStats {
+    /// Table name
+    pub table: String,
+    /// Column name
+    pub column: String,
+    /// Data type
+    pub data_type: ColumnType,
+    /// Total rows in the table
+    pub row_count: u64,
+    /// Smallest value, rendered as text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<String>,
+    /// Largest value, rendered as text
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<String>,
+    /// Approximate number of distinct values
+    pub distinct_count: u64,
+    /// Number of NULL values
+    pub null_count: u64,
+    /// Mean (numeric columns only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mean: Option<f64>,
+    /// Sample standard deviation (numeric columns only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stddev: Option<f64>,
+    /// Most frequent values (string columns only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_values: Vec<ValueCount>,
+}
+
+/// A value and how many rows hold it
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ValueCount {
+    /// The value
+    pub value: String,
+    /// Number of rows with this value
+    pub count: u64,
+}
+
 /// Request to list all loaded tables
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -131,6 +804,29 @@ pub struct ListTablesResult {
     pub tables: Vec<TableSchema>,
 }
 
+impl ListTablesResult {
+    /// Names of the views in the listing
+    pub fn views(&self) -> Vec<&str> {
+        self.tables
+            .iter()
+            .filter(|t| t.kind == TableKind::View)
+            .map(|t| t.name.as_str())
+            .collect()
+    }
+}
+
+/// A likely join between two tables' columns
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RelationshipHint {
+    pub left_table: String,
+    pub left_column: String,
+    pub right_table: String,
+    pub right_column: String,
+    /// How likely the columns refer to the same thing, from 0 to 1
+    pub confidence: f32,
+}
+
 /// All DataEngine request types
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -138,12 +834,17 @@ pub struct ListTablesResult {
 pub enum DataRequest {
     #[serde(rename = "load")]
     Load(LoadRequest),
+    #[serde(rename = "drop")]
+    Drop(DropRequest),
     #[serde(rename = "query")]
     Query(QueryRequest),
     #[serde(rename = "schema")]
     Schema(SchemaRequest),
     #[serde(rename = "list_tables")]
     ListTables(ListTablesRequest),
+    /// Several requests run in order, answered by `DataResponse::Batch`
+    #[serde(rename = "batch")]
+    Batch { requests: Vec<DataRequest> },
 }
 
 /// All DataEngine response types
@@ -153,12 +854,27 @@ pub enum DataRequest {
 pub enum DataResponse {
     #[serde(rename = "load")]
     Load(LoadResult),
+    #[serde(rename = "drop")]
+    Drop(DropResult),
     #[serde(rename = "query")]
     Query(QueryResult),
     #[serde(rename = "schema")]
     Schema(TableSchema),
+    /// A loading table's columns, read from the file before its rows
+    ///
+    /// Sent to the engine's response callback with the `Load` request's ID,
+    /// always before the `Load` response. `row_count` is 0; the `Load`
+    /// response has the real count.
+    #[serde(rename = "schema_ready")]
+    SchemaReady(TableSchema),
     #[serde(rename = "list_tables")]
     ListTables(ListTablesResult),
+    /// One response per batched request, in request order
+    #[serde(rename = "batch")]
+    Batch { responses: Vec<DataResponse> },
+    /// A batched request that failed; the rest of the batch still ran
+    #[serde(rename = "error")]
+    Error(ErrorInfo),
 }
 
 /// Available data files
@@ -177,6 +893,195 @@ pub struct DataFile {
     pub large: bool,
 }
 
+impl DataFile {
+    /// A request loading this file as a table named after its display name
+    ///
+    /// `None` when the manifest can't give the file a URL; see
+    /// [`DataManifest::url_for`].
+    pub fn to_load_request(&self, manifest: &DataManifest) -> Option<LoadRequest> {
+        Some(LoadRequest {
+            name: sanitize_table_name(&self.name),
+            url: manifest.url_for(self)?,
+            format: DataFormat::from_path(&self.path),
+        })
+    }
+}
+
+/// Keywords DuckDB reserves, which can't be bare table names
+const RESERVED_KEYWORDS: &[&str] = &[
+    "all",
+    "analyse",
+    "analyze",
+    "and",
+    "any",
+    "array",
+    "as",
+    "asc",
+    "asymmetric",
+    "both",
+    "case",
+    "cast",
+    "check",
+    "collate",
+    "column",
+    "constraint",
+    "create",
+    "default",
+    "deferrable",
+    "desc",
+    "distinct",
+    "do",
+    "else",
+    "end",
+    "except",
+    "false",
+    "fetch",
+    "for",
+    "foreign",
+    "from",
+    "grant",
+    "group",
+    "having",
+    "in",
+    "initially",
+    "intersect",
+    "into",
+    "lateral",
+    "leading",
+    "limit",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "only",
+    "or",
+    "order",
+    "placing",
+    "primary",
+    "references",
+    "returning",
+    "select",
+    "some",
+    "symmetric",
+    "table",
+    "then",
+    "to",
+    "trailing",
+    "true",
+    "union",
+    "unique",
+    "using",
+    "variadic",
+    "when",
+    "where",
+    "window",
+    "with",
+];
+
+/// A bare DuckDB identifier for a display name, e.g. `VDOT AADT 2024` -> `vdot_aadt_2024`
+///
+/// Lowercases and replaces each run of other characters with one
+/// underscore, trimmed at the ends. Non-ASCII letters are kept, as DuckDB
+/// accepts them unquoted. Names starting with a digit, or that are reserved
+/// words, get a leading underscore.
+pub fn sanitize_table_name(name: &str) -> String {
+    let mut sanitized = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            sanitized.extend(c.to_lowercase());
+        } else if !sanitized.is_empty() && !sanitized.ends_with('_') {
+            sanitized.push('_');
+        }
+    }
+    let sanitized = sanitized.trim_end_matches('_');
+    match sanitized.chars().next() {
+        None => "_".to_string(),
+        Some(c) if c.is_ascii_digit() || RESERVED_KEYWORDS.contains(&sanitized) => {
+            format!("_{}", sanitized)
+        }
+        Some(_) => sanitized.to_string(),
+    }
+}
+
+/// Date filter on one column; either bound may be left open
+///
+/// Bounds are ISO-8601 dates (`2024-11-05`) or date-times
+/// (`2024-11-05T07:00:00`) and are inclusive.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DateRangeParam {
+    pub column: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+}
+
+/// How `DataEngine::sample` picks rows from a table
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum SampleMethod {
+    /// The first rows in storage order; cheapest and deterministic
+    #[default]
+    #[serde(rename = "first")]
+    First,
+    /// A uniform random choice of rows, reproducible with a `seed`
+    #[serde(rename = "random")]
+    Random {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u32>,
+    },
+    /// Reservoir sampling in a single pass over the table
+    #[serde(rename = "reservoir")]
+    Reservoir,
+}
+
+/// DuckDB resource settings; unset fields are left unchanged
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EngineSettings {
+    /// Memory DuckDB may use, in MiB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u32>,
+    /// Worker threads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+    /// Space for spilling to temporary files, in MiB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_temp_directory_size_mb: Option<u32>,
+}
+
+/// What user SQL may do, for playgrounds embedded on public sites
+///
+/// The default allows ordinary analytics over loaded tables but blocks
+/// `COPY`/`EXPORT` and functions that read files or the network.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SqlPolicy {
+    /// Allow `COPY` and `EXPORT DATABASE`
+    #[serde(default)]
+    pub allow_copy: bool,
+    /// Allow file and network access: `read_csv`, `read_parquet`, `glob`,
+    /// `ATTACH`, `INSTALL` and the like
+    #[serde(default)]
+    pub allow_file_functions: bool,
+    /// If set, the only functions that may be called (lowercase names)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_functions: Option<HashSet<String>>,
+}
+
+/// Result of `DataEngine::configure`
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ConfigureResult {
+    /// Settings as applied, after clamping
+    pub applied: EngineSettings,
+    /// One `WarningCode::Clamped` per adjusted value
+    #[serde(default)]
+    pub warnings: Vec<QueryWarning>,
+}
+
 /// Data file manifest
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -186,3 +1091,377 @@ pub struct DataManifest {
     /// Base URL for files
     pub base_url: String,
 }
+
+/// Size above which a file counts as large, see `DataFile::large`
+pub const LARGE_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+impl DataManifest {
+    /// Parse a JSON manifest
+    ///
+    /// Files without a `large` flag get one computed from their size.
+    pub fn from_json(json: &str) -> Result<DataManifest, ErrorInfo> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        if let Some(files) = value.get_mut("files").and_then(|f| f.as_array_mut()) {
+            for file in files.iter_mut().filter_map(|f| f.as_object_mut()) {
+                if !file.contains_key("large") {
+                    let size = file.get("size").and_then(|s| s.as_u64()).unwrap_or(0);
+                    file.insert("large".to_string(), (size > LARGE_FILE_BYTES).into());
+                }
+            }
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Files grouped by category, in manifest order within each
+    pub fn files_by_category(&self) -> BTreeMap<String, Vec<&DataFile>> {
+        let mut categories: BTreeMap<String, Vec<&DataFile>> = BTreeMap::new();
+        for file in &self.files {
+            categories
+                .entry(file.category.clone())
+                .or_default()
+                .push(file);
+        }
+        categories
+    }
+
+    /// Absolute URL of `file`, joining its path onto `base_url`
+    ///
+    /// Paths that are already URLs are returned as-is. Duplicate slashes
+    /// at the join, or within the path, are collapsed. `None` when
+    /// `base_url` is blank, as the path alone isn't a URL.
+    pub fn url_for(&self, file: &DataFile) -> Option<String> {
+        if file.path.starts_with("http://") || file.path.starts_with("https://") {
+            return Some(file.path.clone());
+        }
+        if self.base_url.trim().is_empty() {
+            return None;
+        }
+        let joined = format!("{}/{}", self.base_url, file.path);
+        let (scheme, rest) = match joined.split_once("://") {
+            Some((scheme, rest)) => (format!("{}://", scheme), rest),
+            None => (String::new(), joined.as_str()),
+        };
+        let mut url = scheme;
+        for c in rest.chars() {
+            if !(c == '/' && url.ends_with('/')) {
+                url.push(c);
+            }
+        }
+        Some(url)
+    }
+
+    /// The file with display name `name`
+    pub fn find(&self, name: &str) -> Option<&DataFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const MANIFEST: &str = r#"{
+        "base_url": "https://example.com/data/",
+        "files": [
+            {"name": "AADT 2023", "path": "traffic/aadt_2023.parquet", "size": 52428800, "category": "Traffic"},
+            {"name": "School Budget", "path": "budget/schools.parquet", "size": 204800, "category": "Budget"},
+            {"name": "County Budget", "path": "budget/county.parquet", "size": 409600, "category": "Budget", "large": true}
+        ]
+    }"#;
+
+    #[test]
+    fn manifest_groups_files_by_category() {
+        let manifest = DataManifest::from_json(MANIFEST).unwrap();
+
+        let categories = manifest.files_by_category();
+        let names: Vec<(&str, Vec<&str>)> = categories
+            .iter()
+            .map(|(category, files)| {
+                (
+                    category.as_str(),
+                    files.iter().map(|f| f.name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("Budget", vec!["School Budget", "County Budget"]),
+                ("Traffic", vec!["AADT 2023"]),
+            ]
+        );
+
+        // `large` is derived from size unless given
+        assert!(manifest.find("AADT 2023").unwrap().large);
+        assert!(!manifest.find("School Budget").unwrap().large);
+        assert!(manifest.find("County Budget").unwrap().large);
+        assert!(manifest.find("Parcels").is_none());
+    }
+
+    fn file(path: &str) -> DataFile {
+        DataFile {
+            name: "AADT 2023".to_string(),
+            path: path.to_string(),
+            size: 0,
+            category: "Traffic".to_string(),
+            large: false,
+        }
+    }
+
+    fn manifest(base_url: &str) -> DataManifest {
+        DataManifest {
+            files: Vec::new(),
+            base_url: base_url.to_string(),
+        }
+    }
+
+    #[test]
+    fn url_for_joins_with_one_slash() {
+        for base_url in ["https://example.com/data", "https://example.com/data/"] {
+            for path in ["aadt.parquet", "/aadt.parquet"] {
+                assert_eq!(
+                    manifest(base_url).url_for(&file(path)).unwrap(),
+                    "https://example.com/data/aadt.parquet",
+                    "{base_url} + {path}"
+                );
+            }
+        }
+        assert_eq!(
+            manifest("/data/").url_for(&file("traffic//aadt.parquet")),
+            Some("/data/traffic/aadt.parquet".to_string())
+        );
+    }
+
+    #[test]
+    fn url_for_needs_a_base_url() {
+        for base_url in ["", "  "] {
+            assert_eq!(manifest(base_url).url_for(&file("aadt.parquet")), None);
+            assert!(file("aadt.parquet")
+                .to_load_request(&manifest(base_url))
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn url_for_keeps_absolute_paths() {
+        let url = "https://cdn.example.com/aadt.parquet";
+        assert_eq!(
+            manifest("https://example.com/data/").url_for(&file(url)),
+            Some(url.to_string())
+        );
+        assert_eq!(manifest("").url_for(&file(url)), Some(url.to_string()));
+    }
+
+    #[test]
+    fn load_request_uses_sanitized_name() {
+        let request = file("aadt_2023.csv")
+            .to_load_request(&manifest("/data"))
+            .unwrap();
+        assert_eq!(request.name, "aadt_2023");
+        assert_eq!(request.url, "/data/aadt_2023.csv");
+        assert!(matches!(request.format, DataFormat::Csv { .. }));
+    }
+
+    #[test]
+    fn sanitizes_table_names() {
+        assert_eq!(sanitize_table_name("VDOT AADT 2024"), "vdot_aadt_2024");
+        assert_eq!(
+            sanitize_table_name("  Real Estate -- Sales  "),
+            "real_estate_sales"
+        );
+        assert_eq!(sanitize_table_name("2024 Budget (FY)"), "_2024_budget_fy");
+        assert_eq!(sanitize_table_name("Écoles Publiques"), "écoles_publiques");
+        assert_eq!(sanitize_table_name("Traffic 🚗 Counts"), "traffic_counts");
+        assert_eq!(sanitize_table_name("Select"), "_select");
+        assert_eq!(sanitize_table_name("??"), "_");
+    }
+
+    fn district_result(
+        columns: &[(&str, ColumnType)],
+        rows: Vec<Vec<serde_json::Value>>,
+    ) -> QueryResult {
+        let columns = columns
+            .iter()
+            .map(|(name, data_type)| ColumnSchema {
+                name: name.to_string(),
+                data_type: data_type.clone(),
+                nullable: true,
+                display_hint: None,
+            })
+            .collect();
+        let total_rows = rows.len() as u64;
+        QueryResult::page(columns, rows, total_rows, 0)
+    }
+
+    #[test]
+    fn converts_rows_to_records_and_columns() {
+        let result = district_result(
+            &[
+                ("district", ColumnType::String),
+                ("aadt", ColumnType::Int64),
+            ],
+            vec![
+                vec![json!("Back Creek"), json!(4210)],
+                vec![json!("Opequon"), json!(3875)],
+                vec![json!("Shawnee"), json!(null)],
+            ],
+        );
+
+        let records = result.to_records();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            serde_json::Value::Object(records[1].clone()),
+            json!({"district": "Opequon", "aadt": 3875})
+        );
+
+        assert_eq!(
+            result.column("aadt"),
+            Some(vec![&json!(4210), &json!(3875), &json!(null)])
+        );
+        assert_eq!(result.column("year"), None);
+    }
+
+    #[test]
+    fn diffs_rows_with_same_schema() {
+        let columns = [
+            ("district", ColumnType::String),
+            ("aadt", ColumnType::Int64),
+        ];
+        let old = district_result(
+            &columns,
+            vec![
+                vec![json!("Back Creek"), json!(4210)],
+                vec![json!("Opequon"), json!(3875)],
+            ],
+        );
+        assert!(diff_query_results(&old, &old).is_empty());
+
+        let new = district_result(
+            &columns,
+            vec![
+                vec![json!("Back Creek"), json!(4210)],
+                vec![json!("Opequon"), json!(3990)],
+                vec![json!("Shawnee"), json!(5120)],
+            ],
+        );
+        let diff = diff_query_results(&old, &new);
+        assert!(!diff.schema_changed);
+        assert_eq!(diff.row_count_delta, 1);
+        assert_eq!(diff.differing_row_count, 2);
+        let indices: Vec<u64> = diff.differing_rows.iter().map(|r| r.index).collect();
+        assert_eq!(indices, [1, 2]);
+        assert_eq!(diff.differing_rows[1].old, None);
+        assert_eq!(
+            diff.differing_rows[1].new,
+            Some(vec![json!("Shawnee"), json!(5120)])
+        );
+    }
+
+    #[test]
+    fn schema_change_skips_row_diff() {
+        let old = district_result(
+            &[
+                ("district", ColumnType::String),
+                ("aadt", ColumnType::Int64),
+            ],
+            vec![vec![json!("Opequon"), json!(3875)]],
+        );
+        let new = district_result(
+            &[
+                ("district", ColumnType::String),
+                ("aadt", ColumnType::Float64),
+                ("year", ColumnType::Int32),
+            ],
+            vec![vec![json!("Opequon"), json!(3875.0), json!(2024)]],
+        );
+        let diff = diff_query_results(&old, &new);
+        assert!(diff.schema_changed);
+        assert_eq!(diff.added_columns, ["year"]);
+        assert!(diff.removed_columns.is_empty());
+        assert_eq!(diff.retyped_columns, ["aadt"]);
+        assert!(diff.differing_rows.is_empty());
+        assert_eq!(diff.differing_row_count, 0);
+    }
+
+    #[test]
+    fn truncated_page_carries_warning() {
+        let columns = vec![ColumnSchema {
+            name: "n".to_string(),
+            data_type: ColumnType::Int32,
+            nullable: false,
+            display_hint: None,
+        }];
+        let rows = vec![vec![json!(1)], vec![json!(2)]];
+
+        let result = QueryResult::page(columns.clone(), rows.clone(), 10, 0);
+        assert!(result.truncated);
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].code, WarningCode::Truncated);
+
+        // The last page is complete
+        let result = QueryResult::page(columns, rows, 10, 8);
+        assert!(!result.truncated);
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn infers_display_hints_from_names() {
+        assert_eq!(
+            DisplayHint::infer("amount_usd", &ColumnType::Float64),
+            Some(DisplayHint::Currency {
+                code: "USD".to_string()
+            })
+        );
+        assert_eq!(
+            DisplayHint::infer(
+                "Budget",
+                &ColumnType::Decimal {
+                    precision: 12,
+                    scale: 2
+                }
+            ),
+            Some(DisplayHint::Currency {
+                code: "USD".to_string()
+            })
+        );
+        assert_eq!(
+            DisplayHint::infer("graduation_pct", &ColumnType::Float32),
+            Some(DisplayHint::Percentage)
+        );
+        assert_eq!(
+            DisplayHint::infer("student_count", &ColumnType::Int64),
+            Some(DisplayHint::Integer)
+        );
+        assert_eq!(DisplayHint::infer("amount_usd", &ColumnType::String), None);
+        assert_eq!(DisplayHint::infer("district", &ColumnType::Int32), None);
+        // "discount" is not "count"
+        assert_eq!(DisplayHint::infer("discount", &ColumnType::Int32), None);
+        // Money words don't override more specific ones
+        assert_eq!(
+            DisplayHint::infer("budget_pct", &ColumnType::Float64),
+            Some(DisplayHint::Percentage)
+        );
+        assert_eq!(
+            DisplayHint::infer("fee_count", &ColumnType::Int64),
+            Some(DisplayHint::Integer)
+        );
+        assert_eq!(DisplayHint::infer("tax_year", &ColumnType::Int32), None);
+        assert_eq!(DisplayHint::infer("budget_year", &ColumnType::Int32), None);
+        assert_eq!(DisplayHint::infer("fee_id", &ColumnType::Int64), None);
+
+        let usd = DisplayHint::Currency {
+            code: "USD".to_string(),
+        };
+        assert_eq!(usd.format(-1234567.5), "-$1,234,567.50");
+        let eur = DisplayHint::Currency {
+            code: "EUR".to_string(),
+        };
+        assert_eq!(eur.format(99.0), "99.00 EUR");
+        assert_eq!(DisplayHint::Percentage.format(12.5), "12.5%");
+        assert_eq!(DisplayHint::Percentage.format(40.0), "40%");
+        assert_eq!(DisplayHint::Integer.format(12500.0), "12,500");
+        assert_eq!(DisplayHint::Duration.format(3725.0), "1h 02m 05s");
+        assert_eq!(DisplayHint::Duration.format(4.25), "4.2s");
+    }
+}
@@ -0,0 +1,245 @@
+//! Setup directives for `CellType::Directive` cells
+
+use crate::data::{sanitize_table_name, DataFormat, LoadRequest};
+use crate::messages::{ErrorCode, ErrorInfo, IntoErrorInfo};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Forms `parse_directive` accepts, for error messages and help text
+pub const DIRECTIVE_USAGE: &[&str] = &["LOAD <url> AS <name>", "SET <setting> = <value>"];
+
+/// A parsed directive cell, ready for the editor to run
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum Directive {
+    /// `LOAD <url> AS <name>`: load a data file as a table, its format
+    /// guessed from the URL
+    #[serde(rename = "load")]
+    Load(LoadRequest),
+    /// `SET <setting> = <value>`: change an engine setting
+    #[serde(rename = "set")]
+    Set { name: String, value: String },
+}
+
+/// Errors parsing a directive cell
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum DirectiveError {
+    /// The cell has no directive, only blank lines or comments
+    #[error("empty directive; expected one of: {}", DIRECTIVE_USAGE.join(", "))]
+    Empty,
+    /// The cell holds more than one directive
+    #[error("a directive cell holds one directive, found {0}")]
+    Multiple(usize),
+    /// The first word isn't a known verb
+    #[error("unknown directive `{0}`; expected one of: {}", DIRECTIVE_USAGE.join(", "))]
+    UnknownVerb(String),
+    /// A known verb with the wrong arguments
+    #[error("expected `{0}`")]
+    Usage(&'static str),
+    /// A table or setting name that isn't a plain identifier
+    #[error("invalid name `{0}`; use letters, digits and underscores")]
+    InvalidName(String),
+}
+
+impl IntoErrorInfo for DirectiveError {
+    fn into_error_info(self) -> ErrorInfo {
+        ErrorInfo {
+            code: ErrorCode::ParseError,
+            message: self.to_string(),
+            details: None,
+        }
+    }
+}
+
+crate::error_info_from!(DirectiveError);
+
+/// Parse the content of a directive cell
+///
+/// Verbs are case-insensitive, `--` comment lines and a trailing `;` are
+/// ignored, and the URL or value may be single- or double-quoted.
+pub fn parse_directive(content: &str) -> Result<Directive, DirectiveError> {
+    let lines: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("--"))
+        .collect();
+    let line = match lines.as_slice() {
+        [] => return Err(DirectiveError::Empty),
+        [line] => line.trim_end_matches(';').trim_end(),
+        lines => return Err(DirectiveError::Multiple(lines.len())),
+    };
+    let (verb, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    match verb.to_ascii_uppercase().as_str() {
+        "LOAD" => parse_load(rest),
+        "SET" => parse_set(rest),
+        _ => Err(DirectiveError::UnknownVerb(verb.to_string())),
+    }
+}
+
+fn parse_load(rest: &str) -> Result<Directive, DirectiveError> {
+    let usage = DirectiveError::Usage(DIRECTIVE_USAGE[0]);
+    let (url, name) = rsplit_keyword(rest, "AS").ok_or(usage.clone())?;
+    let url = unquote(url.trim());
+    let name = name.trim();
+    if url.is_empty() || name.is_empty() {
+        return Err(usage);
+    }
+    if sanitize_table_name(name) != name {
+        return Err(DirectiveError::InvalidName(name.to_string()));
+    }
+    Ok(Directive::Load(LoadRequest {
+        name: name.to_string(),
+        url: url.to_string(),
+        format: DataFormat::from_path(url),
+    }))
+}
+
+fn parse_set(rest: &str) -> Result<Directive, DirectiveError> {
+    let usage = DirectiveError::Usage(DIRECTIVE_USAGE[1]);
+    let (name, value) = rest.split_once('=').ok_or(usage.clone())?;
+    let (name, value) = (name.trim(), unquote(value.trim()));
+    if name.is_empty() || value.is_empty() {
+        return Err(usage);
+    }
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(DirectiveError::InvalidName(name.to_string()));
+    }
+    Ok(Directive::Set {
+        name: name.to_ascii_lowercase(),
+        value: value.to_string(),
+    })
+}
+
+/// Split `s` around the last `keyword` surrounded by whitespace, matched
+/// case-insensitively
+fn rsplit_keyword<'a>(s: &'a str, keyword: &str) -> Option<(&'a str, &'a str)> {
+    s.char_indices()
+        .rev()
+        .filter(|(_, c)| c.is_whitespace())
+        .find_map(|(i, c)| {
+            let after = &s[i + c.len_utf8()..];
+            if !after.get(..keyword.len())?.eq_ignore_ascii_case(keyword) {
+                return None;
+            }
+            let rest = &after[keyword.len()..];
+            rest.starts_with(char::is_whitespace)
+                .then(|| (&s[..i], rest))
+        })
+}
+
+/// Strip one pair of matching single or double quotes
+fn unquote(s: &str) -> &str {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = s
+            .strip_prefix(quote)
+            .and_then(|rest| rest.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_load() {
+        let directive = parse_directive("LOAD data/parcels.parquet AS parcels").unwrap();
+        assert_eq!(
+            directive,
+            Directive::Load(LoadRequest {
+                name: "parcels".to_string(),
+                url: "data/parcels.parquet".to_string(),
+                format: DataFormat::Parquet,
+            })
+        );
+
+        let directive = parse_directive(
+            "-- County school enrollment\nload 'https://example.com/enrollment 2024.csv' as enrollment;",
+        )
+        .unwrap();
+        match directive {
+            Directive::Load(req) => {
+                assert_eq!(req.url, "https://example.com/enrollment 2024.csv");
+                assert_eq!(req.name, "enrollment");
+                assert!(matches!(req.format, DataFormat::Csv { .. }));
+            }
+            other => panic!("expected load, got {other:?}"),
+        }
+
+        for content in [
+            "LOAD data/parcels.parquet As parcels",
+            "LOAD data/parcels.parquet\taS\tparcels",
+            "LOAD 'data/gas as.parquet'  AS  parcels",
+        ] {
+            match parse_directive(content).unwrap() {
+                Directive::Load(req) => assert_eq!(req.name, "parcels", "{content}"),
+                other => panic!("expected load, got {other:?}"),
+            }
+        }
+
+        assert_eq!(
+            parse_directive("LOAD data/parcels.parquet"),
+            Err(DirectiveError::Usage("LOAD <url> AS <name>"))
+        );
+        assert_eq!(
+            parse_directive("LOAD data/parcels.parquet AS Parcel Data"),
+            Err(DirectiveError::InvalidName("Parcel Data".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_set() {
+        for content in [
+            "SET memory_limit=512MB",
+            "set memory_limit = '512MB';",
+            "SET Memory_Limit = \"512MB\"",
+        ] {
+            assert_eq!(
+                parse_directive(content).unwrap(),
+                Directive::Set {
+                    name: "memory_limit".to_string(),
+                    value: "512MB".to_string(),
+                },
+                "{content}"
+            );
+        }
+        assert_eq!(
+            parse_directive("SET memory_limit"),
+            Err(DirectiveError::Usage("SET <setting> = <value>"))
+        );
+        assert_eq!(
+            parse_directive("SET memory limit = 1GB"),
+            Err(DirectiveError::InvalidName("memory limit".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_empty_directives() {
+        let err = parse_directive("INSTALL httpfs").unwrap_err();
+        assert_eq!(err, DirectiveError::UnknownVerb("INSTALL".to_string()));
+        assert_eq!(
+            err.to_string(),
+            "unknown directive `INSTALL`; expected one of: \
+             LOAD <url> AS <name>, SET <setting> = <value>"
+        );
+        assert_eq!(
+            parse_directive("  \n-- nothing yet\n"),
+            Err(DirectiveError::Empty)
+        );
+        assert_eq!(
+            parse_directive("SET threads = 2\nSET memory_limit = 1GB"),
+            Err(DirectiveError::Multiple(2))
+        );
+        assert_eq!(
+            ErrorInfo::from(DirectiveError::Empty).code,
+            ErrorCode::ParseError
+        );
+    }
+}
@@ -1,6 +1,9 @@
 //! EditorEngine types for notebook cells
 
-use crate::messages::Timestamp;
+use crate::data::ColumnType;
+use crate::messages::{ErrorCode, ErrorInfo, IntoErrorInfo, Timestamp};
+use crate::sanitize::sanitize_html;
+use crate::storage::{CachedParquet, EditorPreferences};
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
 
@@ -12,20 +15,28 @@ pub enum CellType {
     Sql,
     /// Markdown documentation cell
     Markdown,
+    /// Setup step such as `LOAD <url> AS <name>`, see
+    /// [`crate::directive::parse_directive`]
+    Directive,
 }
 
 /// Execution state
-#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub enum ExecutionState {
     /// Not executed
+    #[default]
     Idle,
+    /// Waiting for earlier cells in a run to finish
+    Queued,
     /// Currently running
     Running,
     /// Completed successfully
     Success,
     /// Completed with error
     Error,
+    /// Stopped by the user before it completed
+    Cancelled,
 }
 
 /// A notebook cell
@@ -54,12 +65,13 @@ pub struct Cell {
     /// Whether cell is collapsed
     #[serde(default)]
     pub collapsed: bool,
-}
-
-impl Default for ExecutionState {
-    fn default() -> Self {
-        ExecutionState::Idle
-    }
+    /// Cell tags, e.g. `setup`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// `input_hash` as of the run that produced `output`; empty if the
+    /// cell hasn't run since it or a cell above it changed
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content_hash: String,
 }
 
 /// Cell output
@@ -76,6 +88,9 @@ pub enum CellOutput {
     /// Error output
     #[serde(rename = "error")]
     Error(ErrorOutput),
+    /// Chart of the cell's query result
+    #[serde(rename = "chart")]
+    Chart(Box<crate::chart::ChartConfig>),
 }
 
 /// Query output
@@ -92,6 +107,40 @@ pub struct QueryOutput {
     pub execution_time_ms: u32,
     /// Whether result was truncated
     pub truncated: bool,
+    /// Size of `rows` serialized as JSON
+    #[serde(default)]
+    pub output_bytes: u64,
+    /// Column types, parallel to `columns`, when known
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub column_types: Vec<ColumnType>,
+}
+
+/// Serialized size of a row set as a JSON array
+fn rows_bytes(rows: &[Vec<serde_json::Value>]) -> Vec<u64> {
+    rows.iter()
+        .map(|row| serde_json::to_vec(row).map_or(0, |v| v.len() as u64))
+        .collect()
+}
+
+/// Drop trailing rows until `output` serializes to at most `max_bytes`
+///
+/// Columns and `total_rows` are kept so the UI can still say how much was
+/// cut. Updates `output_bytes` and sets `truncated` when rows were dropped;
+/// returns whether any were.
+pub fn truncate_output(output: &mut QueryOutput, max_bytes: u64) -> bool {
+    let sizes = rows_bytes(&output.rows);
+    // `[` + rows joined by `,` + `]`
+    let mut size = 2 + sizes.iter().sum::<u64>() + sizes.len().saturating_sub(1) as u64;
+    let mut keep = sizes.len();
+    while keep > 0 && size > max_bytes {
+        keep -= 1;
+        size -= sizes[keep] + u64::from(keep > 0);
+    }
+    let truncated = keep < output.rows.len();
+    output.rows.truncate(keep);
+    output.output_bytes = size;
+    output.truncated |= truncated;
+    truncated
 }
 
 /// Rendered markdown output
@@ -100,6 +149,73 @@ pub struct QueryOutput {
 pub struct MarkdownOutput {
     /// Rendered HTML
     pub html: String,
+    /// Math spans, referenced from `html` by index
+    #[serde(default)]
+    pub math_blocks: Vec<MathBlock>,
+}
+
+/// A LaTeX span extracted from markdown for the frontend to typeset
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct MathBlock {
+    /// `$...$` (true) or `$$...$$` (false)
+    pub inline: bool,
+    /// LaTeX source without the delimiters
+    pub latex: String,
+}
+
+/// Render markdown to HTML, pulling math out for separate typesetting
+///
+/// Each `$...$` or `$$...$$` span becomes an empty
+/// `<span class="math math-inline|math-display" data-math="N">` placeholder,
+/// where `N` indexes `math_blocks`. Escaped `\$` and dollars inside code are
+/// left as text. Raw HTML in the markdown goes through [`sanitize_html`].
+pub fn render_markdown(content: &str) -> MarkdownOutput {
+    render_markdown_with(content, true)
+}
+
+/// Render markdown to sanitized HTML with `$` left as text, for notes,
+/// where a dollar sign is usually money
+pub fn render_markdown_html(content: &str) -> String {
+    render_markdown_with(content, false).html
+}
+
+fn render_markdown_with(content: &str, math: bool) -> MarkdownOutput {
+    use pulldown_cmark::{html, Event, Options, Parser};
+
+    let mut options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    if math {
+        options |= Options::ENABLE_MATH;
+    }
+    let mut math_blocks = Vec::new();
+    let events = Parser::new_ext(content, options).map(|event| {
+        let (inline, latex) = match event {
+            Event::InlineMath(latex) => (true, latex),
+            Event::DisplayMath(latex) => (false, latex),
+            other => return other,
+        };
+        let class = if inline {
+            "math-inline"
+        } else {
+            "math-display"
+        };
+        let placeholder = format!(
+            r#"<span class="math {class}" data-math="{}"></span>"#,
+            math_blocks.len()
+        );
+        math_blocks.push(MathBlock {
+            inline,
+            latex: latex.into_string(),
+        });
+        Event::InlineHtml(placeholder.into())
+    });
+    let mut html_out = String::new();
+    html::push_html(&mut html_out, events);
+    MarkdownOutput {
+        html: sanitize_html(&html_out),
+        math_blocks,
+    }
 }
 
 /// Error output
@@ -113,6 +229,118 @@ pub struct ErrorOutput {
     pub details: Option<String>,
 }
 
+/// Timing and size of one cell's last execution
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CellExecutionMetrics {
+    pub cell_id: String,
+    /// Query time; 0 for markdown and failed cells, which aren't timed
+    pub execution_time_ms: u32,
+    /// Rows in the query result
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub row_count: Option<u64>,
+    pub state: ExecutionState,
+}
+
+impl Cell {
+    /// Metrics for the last execution, or `None` if the cell hasn't run
+    pub fn execution_metrics(&self) -> Option<CellExecutionMetrics> {
+        if matches!(self.state, ExecutionState::Idle | ExecutionState::Queued) {
+            return None;
+        }
+        let (execution_time_ms, row_count) = match &self.output {
+            Some(CellOutput::Query(output)) => (output.execution_time_ms, Some(output.total_rows)),
+            _ => (0, None),
+        };
+        Some(CellExecutionMetrics {
+            cell_id: self.id.clone(),
+            execution_time_ms,
+            row_count,
+            state: self.state.clone(),
+        })
+    }
+}
+
+/// Hash of what a cell's output depends on: its content and `upstream`,
+/// the input hash of the SQL cell above it
+///
+/// 64-bit FNV-1a as 16 hex digits; for cache keys, not security.
+pub fn input_hash(content: &str, upstream: &str) -> String {
+    let hash = upstream
+        .bytes()
+        .chain([0])
+        .chain(content.bytes())
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// Statement keywords that only read the database
+const READ_ONLY_KEYWORDS: &[&str] = &[
+    "SELECT",
+    "WITH",
+    "FROM",
+    "VALUES",
+    "TABLE",
+    "DESCRIBE",
+    "SHOW",
+    "SUMMARIZE",
+    "EXPLAIN",
+    "PIVOT",
+    "UNPIVOT",
+];
+
+/// Whether any statement of `sql` may change the database, i.e. starts
+/// with something other than a query keyword
+fn changes_database(sql: &str) -> bool {
+    let mut statement_start = true;
+    tokenize_sql(sql).into_iter().any(|token| match token {
+        SqlToken::Semi => {
+            statement_start = true;
+            false
+        }
+        SqlToken::LineComment(_) | SqlToken::BlockComment(_) | SqlToken::Open => false,
+        _ if statement_start => {
+            statement_start = false;
+            !matches!(token, SqlToken::Word(word)
+                if READ_ONLY_KEYWORDS.contains(&word.to_ascii_uppercase().as_str()))
+        }
+        _ => false,
+    })
+}
+
+/// Whether `cell` must run again to be current, given its `input_hash`
+///
+/// A cell whose last run succeeded with the same inputs can keep its
+/// output.
+pub fn needs_execution(cell: &Cell, input_hash: &str) -> bool {
+    cell.state != ExecutionState::Success
+        || cell.output.is_none()
+        || cell.content_hash != input_hash
+}
+
+/// Summary of a notebook run, for the post-run banner
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct NotebookRunReport {
+    /// Executed cells, in notebook order
+    pub cells: Vec<CellExecutionMetrics>,
+    /// Sum of cell execution times
+    pub total_time_ms: u64,
+    pub success_count: u32,
+    pub error_count: u32,
+    /// The cell that took longest, if any was timed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slowest: Option<CellExecutionMetrics>,
+}
+
+/// Current `Notebook::version`
+///
+/// Version 2 added cell tags and notebook parameters; see
+/// [`crate::storage::migrate_notebook`].
+pub const NOTEBOOK_VERSION: u32 = 2;
+
 /// Notebook document
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -128,6 +356,337 @@ pub struct Notebook {
     /// Chart configurations
     #[serde(default)]
     pub charts: Vec<crate::chart::ChartConfig>,
+    /// Expected results for cells, see [`Notebook::check_assertions`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assertions: Vec<crate::assertion::Assertion>,
+}
+
+impl Notebook {
+    /// Cap every query output before persisting, see [`truncate_output`]
+    ///
+    /// Called by `StorageCommand::save_notebook`. Returns how many cells
+    /// were truncated.
+    pub fn truncate_outputs(&mut self, max_bytes: u64) -> usize {
+        self.cells
+            .iter_mut()
+            .filter_map(|cell| match &mut cell.output {
+                Some(CellOutput::Query(output)) => Some(truncate_output(output, max_bytes)),
+                _ => None,
+            })
+            .filter(|truncated| *truncated)
+            .count()
+    }
+
+    /// Parameter values to run with: each of `metadata.parameters`, taking
+    /// its value from `values` (e.g. the parameters panel) or its default
+    pub fn resolve_parameters(
+        &self,
+        values: &[(String, serde_json::Value)],
+    ) -> Vec<(String, serde_json::Value)> {
+        self.metadata
+            .parameters
+            .iter()
+            .map(|param| {
+                let value = values
+                    .iter()
+                    .find(|(name, _)| *name == param.name)
+                    .map_or(&param.default, |(_, value)| value);
+                (param.name.clone(), value.clone())
+            })
+            .collect()
+    }
+
+    /// The current `input_hash` of each cell, in notebook order, with
+    /// parameters resolved from `values` as by [`Notebook::resolve_parameters`]
+    /// on the engine session `session` (`DataEngine.sessionId`)
+    ///
+    /// SQL cells are hashed with their parameters substituted, so changing
+    /// a value changes the hash of the cells that use it. SQL and directive
+    /// cells chain through the one above them, since it may create or
+    /// change the tables they read; editing one therefore changes the hash
+    /// of every such cell below it. Markdown cells depend only on their own
+    /// content.
+    ///
+    /// Directive cells and SQL cells that change the database, e.g. with
+    /// `CREATE TABLE`, also hash `session`: a new engine starts empty, so
+    /// they must run again to recreate their tables. Cells below them only
+    /// chain through their content.
+    pub fn input_hashes(
+        &self,
+        values: &[(String, serde_json::Value)],
+        session: &str,
+    ) -> Vec<String> {
+        let params = self.resolve_parameters(values);
+        let mut upstream = String::new();
+        self.cells
+            .iter()
+            .map(|cell| {
+                let (content, side_effects) = match cell.cell_type {
+                    CellType::Sql => {
+                        let sql = substitute_parameters(&cell.content, &params);
+                        let side_effects = changes_database(&sql);
+                        (sql, side_effects)
+                    }
+                    CellType::Directive => (cell.content.clone(), true),
+                    CellType::Markdown => return input_hash(&cell.content, ""),
+                };
+                upstream = input_hash(&content, &upstream);
+                if side_effects {
+                    input_hash(session, &upstream)
+                } else {
+                    upstream.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// IDs of the cells "Run all" must execute with parameter `values` on
+    /// engine session `session`, in notebook order; the rest can keep their
+    /// outputs
+    pub fn stale_cells(&self, values: &[(String, serde_json::Value)], session: &str) -> Vec<&str> {
+        self.cells
+            .iter()
+            .zip(self.input_hashes(values, session))
+            .filter(|(cell, hash)| needs_execution(cell, hash))
+            .map(|(cell, _)| cell.id.as_str())
+            .collect()
+    }
+
+    /// Stamp the cell `cell_id` with its current input hash after a run
+    /// with parameter `values` on engine session `session`, so its output
+    /// is reused until it, a cell above it or a parameter it uses changes
+    ///
+    /// Returns whether the cell exists.
+    pub fn record_run(
+        &mut self,
+        cell_id: &str,
+        values: &[(String, serde_json::Value)],
+        session: &str,
+    ) -> bool {
+        let hashes = self.input_hashes(values, session);
+        match self.cells.iter().position(|cell| cell.id == cell_id) {
+            Some(index) => {
+                self.cells[index].content_hash = hashes[index].clone();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cells carrying `tag`, in notebook order
+    pub fn cells_with_tag(&self, tag: &str) -> Vec<&Cell> {
+        self.cells
+            .iter()
+            .filter(|cell| cell.tags.iter().any(|t| t == tag))
+            .collect()
+    }
+
+    /// Every tag used by any cell, sorted and deduplicated
+    pub fn all_cell_tags(&self) -> Vec<String> {
+        let tags: std::collections::BTreeSet<&String> =
+            self.cells.iter().flat_map(|cell| &cell.tags).collect();
+        tags.into_iter().cloned().collect()
+    }
+
+    /// Summary of the last run, from each executed cell's state and output
+    pub fn run_report(&self) -> NotebookRunReport {
+        let cells: Vec<CellExecutionMetrics> = self
+            .cells
+            .iter()
+            .filter_map(Cell::execution_metrics)
+            .collect();
+        let count =
+            |state: ExecutionState| cells.iter().filter(|m| m.state == state).count() as u32;
+        NotebookRunReport {
+            total_time_ms: cells.iter().map(|m| m.execution_time_ms as u64).sum(),
+            success_count: count(ExecutionState::Success),
+            error_count: count(ExecutionState::Error),
+            slowest: cells
+                .iter()
+                .filter(|m| m.execution_time_ms > 0)
+                .max_by_key(|m| m.execution_time_ms)
+                .cloned(),
+            cells,
+        }
+    }
+
+    /// Record that a cell loaded `source`
+    ///
+    /// Loading a name again replaces the table, so it replaces the
+    /// earlier reference too.
+    pub fn record_data_source(&mut self, source: DataSourceRef) {
+        let sources = &mut self.metadata.data_sources;
+        match sources.iter_mut().find(|s| s.name == source.name) {
+            Some(existing) => *existing = source,
+            None => sources.push(source),
+        }
+    }
+
+    /// The data files behind the notebook's results, in load order
+    pub fn provenance(&self) -> Vec<&DataSourceRef> {
+        let mut sources: Vec<&DataSourceRef> = self.metadata.data_sources.iter().collect();
+        sources.sort_by_key(|s| s.loaded_at);
+        sources
+    }
+
+    /// Consistency problems, e.g. after an import or merge
+    pub fn validate(&self) -> Vec<NotebookValidationIssue> {
+        let mut issues = Vec::new();
+
+        let mut counts: Vec<(&str, u32)> = Vec::new();
+        for cell in &self.cells {
+            match counts.iter_mut().find(|(id, _)| *id == cell.id) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((&cell.id, 1)),
+            }
+        }
+        issues.extend(
+            counts
+                .into_iter()
+                .filter(|(_, count)| *count > 1)
+                .map(|(id, count)| NotebookValidationIssue::DuplicateCellId {
+                    cell_id: id.to_string(),
+                    count,
+                }),
+        );
+
+        // Inline charts plot their own cell unless they name another
+        let inline_charts = self.cells.iter().filter_map(|cell| match &cell.output {
+            Some(CellOutput::Chart(chart)) => Some(chart.as_ref()),
+            _ => None,
+        });
+        for chart in self.charts.iter().chain(inline_charts) {
+            if let Some(cell_id) = &chart.cell_id {
+                if !self.cells.iter().any(|cell| &cell.id == cell_id) {
+                    issues.push(NotebookValidationIssue::MissingChartCell {
+                        chart_id: chart.id.clone(),
+                        cell_id: cell_id.clone(),
+                    });
+                }
+            }
+        }
+
+        for assertion in &self.assertions {
+            if !self.cells.iter().any(|cell| cell.id == assertion.cell_id) {
+                issues.push(NotebookValidationIssue::MissingAssertionCell {
+                    cell_id: assertion.cell_id.clone(),
+                });
+            }
+        }
+
+        for entry in &self.loaded_data {
+            let used = self
+                .cells
+                .iter()
+                .filter(|cell| cell.cell_type == CellType::Sql)
+                .any(|cell| data_names(entry).any(|name| cell.content.contains(name)));
+            if !used {
+                issues.push(NotebookValidationIssue::UnusedData {
+                    entry: entry.clone(),
+                });
+            }
+        }
+
+        issues
+    }
+
+    /// A fresh cell ID not used by any cell in this notebook
+    pub fn new_cell_id(&self) -> String {
+        loop {
+            let id = generate_cell_id();
+            if !self.cells.iter().any(|cell| cell.id == id) {
+                return id;
+            }
+        }
+    }
+
+    /// Parse a notebook for `StorageCommand::ImportNotebook`
+    ///
+    /// Older formats are migrated first. Rejects malformed JSON, unknown
+    /// versions and notebooks with duplicate cell IDs, which would make cell
+    /// operations ambiguous.
+    pub fn import_json(json: &str) -> Result<Notebook, ErrorInfo> {
+        let parse_error = |e: &dyn std::fmt::Display| ErrorInfo {
+            code: ErrorCode::ParseError,
+            message: "Invalid notebook JSON".to_string(),
+            details: Some(e.to_string()),
+        };
+        let value: serde_json::Value = serde_json::from_str(json).map_err(|e| parse_error(&e))?;
+        let notebook =
+            crate::storage::migrate_notebook_value(value).map_err(|e| parse_error(&e))?;
+        let duplicates: Vec<String> = notebook
+            .validate()
+            .into_iter()
+            .filter_map(|issue| match issue {
+                NotebookValidationIssue::DuplicateCellId { cell_id, .. } => Some(cell_id),
+                _ => None,
+            })
+            .collect();
+        if !duplicates.is_empty() {
+            return Err(ErrorInfo {
+                code: ErrorCode::ParseError,
+                message: "Notebook has duplicate cell IDs".to_string(),
+                details: Some(duplicates.join(", ")),
+            });
+        }
+        Ok(notebook)
+    }
+}
+
+/// Names a SQL cell may use to refer to a `loaded_data` entry: the entry
+/// itself, its file name and its file name without extension
+fn data_names(entry: &str) -> impl Iterator<Item = &str> {
+    let file = entry.rsplit('/').next().unwrap_or(entry);
+    let stem = file.split('.').next().unwrap_or(file);
+    [entry, file, stem]
+        .into_iter()
+        .filter(|name| !name.is_empty())
+}
+
+/// A problem found by [`Notebook::validate`]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum NotebookValidationIssue {
+    /// More than one cell has this ID
+    #[serde(rename = "duplicate_cell_id")]
+    DuplicateCellId { cell_id: String, count: u32 },
+    /// A chart plots a cell that doesn't exist
+    #[serde(rename = "missing_chart_cell")]
+    MissingChartCell { chart_id: String, cell_id: String },
+    /// An assertion checks a cell that doesn't exist
+    #[serde(rename = "missing_assertion_cell")]
+    MissingAssertionCell { cell_id: String },
+    /// A loaded data file no SQL cell refers to
+    #[serde(rename = "unused_data")]
+    UnusedData { entry: String },
+}
+
+/// Mint a short cell ID
+///
+/// Each ID is 8 base-36 characters drawn from fresh random bytes, so IDs
+/// can't be predicted from earlier ones and cells from notebooks created
+/// apart are unlikely to collide when merged. Use
+/// [`Notebook::new_cell_id`] to also rule out IDs already in a notebook.
+pub fn generate_cell_id() -> String {
+    let mut bytes = [0u8; 8];
+    if getrandom::getrandom(&mut bytes).is_err() {
+        // No randomness: fall back to a per-session counter so IDs at least
+        // don't repeat within the session
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        bytes = NEXT.fetch_add(1, Ordering::Relaxed).to_le_bytes();
+    }
+    let mut z = u64::from_le_bytes(bytes);
+
+    const DIGITS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    (0..8)
+        .map(|_| {
+            let digit = DIGITS[(z % 36) as usize] as char;
+            z /= 36;
+            digit
+        })
+        .collect()
 }
 
 /// Notebook metadata
@@ -150,6 +709,1067 @@ pub struct NotebookMetadata {
     /// Description
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Parameters referenced as `@name` in SQL cells
+    #[serde(default)]
+    pub parameters: Vec<NotebookParameter>,
+    /// Data files loaded by the notebook's cells, for provenance
+    #[serde(default)]
+    pub data_sources: Vec<DataSourceRef>,
+}
+
+/// A data file a notebook loaded, and which version of it
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DataSourceRef {
+    /// Table name the file was loaded as
+    pub name: String,
+    /// Source URL
+    pub url: String,
+    /// SHA-256 of the file's content, if it was read from the cache
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Unix timestamp (ms) when the file was loaded
+    pub loaded_at: Timestamp,
+}
+
+impl DataSourceRef {
+    /// Reference to `url` loaded as `name`, taking the content hash from
+    /// its cache entry when there is one
+    pub fn new(
+        name: &str,
+        url: &str,
+        cached: Option<&CachedParquet>,
+        loaded_at: Timestamp,
+    ) -> Self {
+        DataSourceRef {
+            name: name.to_string(),
+            url: url.to_string(),
+            content_hash: cached
+                .filter(|c| c.url == url)
+                .map(|c| c.content_hash.clone()),
+            loaded_at,
+        }
+    }
+}
+
+/// A notebook parameter, e.g. `@year`
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct NotebookParameter {
+    /// Name without the `@`
+    pub name: String,
+    /// Expected value type
+    pub param_type: ColumnType,
+    /// Value used when none is supplied
+    pub default: serde_json::Value,
+    /// Help text for the parameters panel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Errors importing a notebook from another format
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ImportError {
+    /// The input is not valid JSON or lacks required notebook fields
+    #[error("malformed notebook JSON: {0}")]
+    MalformedJson(String),
+    /// The notebook uses an nbformat version we can't read
+    #[error("unsupported nbformat version {major}.{minor}")]
+    UnsupportedVersion { major: u32, minor: u32 },
+}
+
+impl IntoErrorInfo for ImportError {
+    fn into_error_info(self) -> ErrorInfo {
+        ErrorInfo {
+            code: ErrorCode::ParseError,
+            message: self.to_string(),
+            details: None,
+        }
+    }
+}
+
+crate::error_info_from!(ImportError);
+
+/// Jupyter notebook document (nbformat 4)
+#[derive(Deserialize)]
+struct Ipynb {
+    nbformat: u32,
+    #[serde(default)]
+    nbformat_minor: u32,
+    #[serde(default)]
+    metadata: serde_json::Value,
+    #[serde(default)]
+    cells: Vec<IpynbCell>,
+}
+
+#[derive(Deserialize)]
+struct IpynbCell {
+    cell_type: String,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    source: IpynbText,
+    #[serde(default)]
+    outputs: Vec<serde_json::Value>,
+    #[serde(default)]
+    execution_count: Option<u32>,
+    #[serde(default)]
+    metadata: IpynbCellMetadata,
+}
+
+#[derive(Deserialize, Default)]
+struct IpynbCellMetadata {
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Multiline text, stored either as one string or a list of lines
+#[derive(Deserialize, Default)]
+#[serde(untagged)]
+enum IpynbText {
+    #[default]
+    Empty,
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl IpynbText {
+    fn into_string(self) -> String {
+        match self {
+            IpynbText::Empty => String::new(),
+            IpynbText::Text(s) => s,
+            IpynbText::Lines(lines) => lines.concat(),
+        }
+    }
+}
+
+/// Statement keywords that mark an unmarked code cell as SQL
+const SQL_STATEMENT_KEYWORDS: &[&str] = &[
+    "ALTER",
+    "ATTACH",
+    "COPY",
+    "CREATE",
+    "DELETE",
+    "DESCRIBE",
+    "DROP",
+    "EXPLAIN",
+    "FROM",
+    "INSERT",
+    "PIVOT",
+    "SELECT",
+    "SHOW",
+    "SUMMARIZE",
+    "UNPIVOT",
+    "UPDATE",
+    "WITH",
+];
+
+/// Whether code with no `%%sql` magic reads as plain SQL
+fn looks_like_sql(code: &str) -> bool {
+    let Some(line) = code
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with("--"))
+    else {
+        return false;
+    };
+    let word = line
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    // `from x import y` is Python, not DuckDB's FROM-first syntax
+    SQL_STATEMENT_KEYWORDS.contains(&word.as_str()) && !line.contains(" import ")
+}
+
+/// Text from `stream`, `execute_result` and `display_data` outputs
+fn output_text(output: &serde_json::Value) -> Option<String> {
+    let text = match output.get("output_type")?.as_str()? {
+        "stream" => output.get("text")?,
+        "execute_result" | "display_data" => output.get("data")?.get("text/plain")?,
+        _ => return None,
+    };
+    let text = IpynbText::deserialize(text).ok()?.into_string();
+    (!text.trim().is_empty()).then_some(text)
+}
+
+/// A code fence longer than any run of backticks in `text`
+fn fence_for(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn code_block(language: &str, code: &str) -> String {
+    let fence = fence_for(code);
+    format!("{fence}{language}\n{}\n{fence}", code.trim_end())
+}
+
+impl Notebook {
+    /// Import a Jupyter notebook (nbformat 4)
+    ///
+    /// Markdown cells stay markdown. Code cells starting with `%%sql`, or
+    /// that read as plain SQL, become SQL cells with the magic line removed;
+    /// other code is kept as a fenced code block in a markdown cell. Text
+    /// outputs follow their cell as a markdown note, while errors, images
+    /// and other rich outputs are dropped. Timestamps are left at zero for
+    /// the caller to fill in.
+    pub fn from_ipynb(json: &str) -> Result<Notebook, ImportError> {
+        let nb: Ipynb =
+            serde_json::from_str(json).map_err(|e| ImportError::MalformedJson(e.to_string()))?;
+        if nb.nbformat != 4 {
+            return Err(ImportError::UnsupportedVersion {
+                major: nb.nbformat,
+                minor: nb.nbformat_minor,
+            });
+        }
+
+        let language = nb
+            .metadata
+            .pointer("/kernelspec/language")
+            .or_else(|| nb.metadata.pointer("/language_info/name"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("python")
+            .to_string();
+        let sql_kernel = language.eq_ignore_ascii_case("sql");
+
+        let mut cells = Vec::new();
+        for (i, cell) in nb.cells.into_iter().enumerate() {
+            let id = cell.id.unwrap_or_else(|| format!("cell-{}", i + 1));
+            let source = cell.source.into_string();
+            let (cell_type, content) = match cell.cell_type.as_str() {
+                "markdown" => (CellType::Markdown, source),
+                "code" => {
+                    let trimmed = source.trim_start();
+                    if let Some(rest) = trimmed.strip_prefix("%%sql") {
+                        // The magic line may carry a connection string
+                        let body = rest.split_once('\n').map_or("", |(_, body)| body);
+                        (CellType::Sql, body.trim().to_string())
+                    } else if sql_kernel || looks_like_sql(&source) {
+                        (CellType::Sql, source.trim().to_string())
+                    } else {
+                        (CellType::Markdown, code_block(&language, &source))
+                    }
+                }
+                // Raw cells have no rendering; keep their text visible
+                _ => (CellType::Markdown, code_block("", &source)),
+            };
+
+            let notes: Vec<String> = cell.outputs.iter().filter_map(output_text).collect();
+            cells.push(Cell {
+                id: id.clone(),
+                cell_type,
+                content,
+                output: None,
+                state: ExecutionState::Idle,
+                execution_count: cell.execution_count,
+                created_at: 0,
+                modified_at: 0,
+                collapsed: false,
+                tags: cell.metadata.tags.clone(),
+                content_hash: String::new(),
+            });
+            if !notes.is_empty() {
+                cells.push(Cell {
+                    id: format!("{id}-output"),
+                    cell_type: CellType::Markdown,
+                    content: format!("Output:\n\n{}", code_block("", &notes.concat())),
+                    output: None,
+                    state: ExecutionState::Idle,
+                    execution_count: None,
+                    created_at: 0,
+                    modified_at: 0,
+                    collapsed: false,
+                    tags: cell.metadata.tags,
+                    content_hash: String::new(),
+                });
+            }
+        }
+
+        let title = nb
+            .metadata
+            .get("title")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        Ok(Notebook {
+            version: NOTEBOOK_VERSION,
+            metadata: NotebookMetadata {
+                title,
+                author: None,
+                tags: Vec::new(),
+                created_at: 0,
+                modified_at: 0,
+                description: None,
+                parameters: Vec::new(),
+                data_sources: Vec::new(),
+            },
+            cells,
+            loaded_data: Vec::new(),
+            charts: Vec::new(),
+            assertions: Vec::new(),
+        })
+    }
+
+    /// Render the notebook as a markdown article, including cell outputs
+    pub fn to_longform_markdown(&self) -> String {
+        self.longform_markdown(true, &ResultFormatOptions::default())
+    }
+
+    /// Render the notebook as a markdown article
+    ///
+    /// SQL cells become fenced code blocks. With `include_outputs`, query
+    /// results follow as tables of at most [`LONGFORM_MAX_ROWS`] rows, their
+    /// values formatted with `format`, and errors as quotes.
+    pub fn longform_markdown(&self, include_outputs: bool, format: &ResultFormatOptions) -> String {
+        let mut sections = Vec::new();
+        if let Some(title) = &self.metadata.title {
+            sections.push(format!("# {title}"));
+        }
+        if let Some(description) = &self.metadata.description {
+            sections.push(description.clone());
+        }
+        for cell in &self.cells {
+            match cell.cell_type {
+                CellType::Markdown => sections.push(cell.content.trim_end().to_string()),
+                CellType::Sql => sections.push(code_block("sql", &cell.content)),
+                CellType::Directive => sections.push(code_block("", &cell.content)),
+            }
+            if !include_outputs {
+                continue;
+            }
+            match &cell.output {
+                Some(CellOutput::Query(output)) => sections.push(markdown_table(output, format)),
+                Some(CellOutput::Error(error)) => {
+                    sections.push(format!("> **Error:** {}", error.message))
+                }
+                Some(CellOutput::Chart(chart)) => sections.push(format!(
+                    "> **Chart:** {}",
+                    chart.title.as_deref().unwrap_or("untitled")
+                )),
+                // Rendered markdown duplicates the cell content
+                Some(CellOutput::Markdown(_)) | None => {}
+            }
+        }
+        sections.retain(|s| !s.is_empty());
+        sections.join("\n\n")
+    }
+}
+
+/// Rows of a query output shown in a long-form article
+pub const LONGFORM_MAX_ROWS: usize = 50;
+
+/// A query output as a GitHub-style markdown table
+fn markdown_table(output: &QueryOutput, format: &ResultFormatOptions) -> String {
+    let escape = |s: &str| s.replace('|', "\\|").replace(['\n', '\r'], " ");
+    let row = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+
+    let mut lines = vec![
+        row(output.columns.iter().map(|c| escape(c)).collect()),
+        row(output.columns.iter().map(|_| "---".to_string()).collect()),
+    ];
+    lines.extend(output.rows.iter().take(LONGFORM_MAX_ROWS).map(|r| {
+        row(output
+            .format_row(r, format)
+            .iter()
+            .map(|c| escape(c))
+            .collect())
+    }));
+    let shown = output.rows.len().min(LONGFORM_MAX_ROWS) as u64;
+    let total = output.total_rows.max(output.rows.len() as u64);
+    if total > shown {
+        lines.push(String::new());
+        lines.push(format!("_Showing {shown} of {total} rows._"));
+    }
+    lines.join("\n")
+}
+
+/// How result values are rendered as text
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ResultFormatOptions {
+    /// Shown for NULL
+    #[serde(default)]
+    pub null_text: String,
+    /// Decimal places for non-integer numbers; `None` keeps full precision
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub float_precision: Option<u8>,
+    /// Group integer digits with commas, e.g. `12,500`
+    #[serde(default)]
+    pub thousands_separator: bool,
+    /// Shown for `true`
+    #[serde(default = "default_true_text")]
+    pub true_text: String,
+    /// Shown for `false`
+    #[serde(default = "default_false_text")]
+    pub false_text: String,
+}
+
+fn default_true_text() -> String {
+    "true".to_string()
+}
+fn default_false_text() -> String {
+    "false".to_string()
+}
+
+impl Default for ResultFormatOptions {
+    fn default() -> Self {
+        Self {
+            null_text: String::new(),
+            float_precision: None,
+            thousands_separator: false,
+            true_text: default_true_text(),
+            false_text: default_false_text(),
+        }
+    }
+}
+
+/// Render one result value as text
+///
+/// Integers never gain decimals; `float_precision` applies only to
+/// non-integer numbers. Arrays and objects render as JSON.
+pub fn format_cell(value: &serde_json::Value, opts: &ResultFormatOptions) -> String {
+    use serde_json::Value;
+    match value {
+        Value::Null => opts.null_text.clone(),
+        Value::Bool(true) => opts.true_text.clone(),
+        Value::Bool(false) => opts.false_text.clone(),
+        Value::String(s) => s.clone(),
+        Value::Number(n) => {
+            let text = match (n.as_f64(), opts.float_precision) {
+                _ if n.is_i64() || n.is_u64() => n.to_string(),
+                (Some(f), Some(precision)) => format!("{:.*}", precision as usize, f),
+                (Some(f), None) => f.to_string(),
+                (None, _) => n.to_string(),
+            };
+            if opts.thousands_separator {
+                group_thousands(&text)
+            } else {
+                text
+            }
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Render one value of a column of type `column_type` as text
+///
+/// DECIMAL values arrive as strings to keep their exact digits; they are
+/// rounded and grouped like other numbers without going through `f64`.
+pub fn format_column_cell(
+    value: &serde_json::Value,
+    column_type: Option<&ColumnType>,
+    opts: &ResultFormatOptions,
+) -> String {
+    match (value, column_type) {
+        (serde_json::Value::String(text), Some(ColumnType::Decimal { .. }))
+            if is_decimal_text(text) =>
+        {
+            let text = match opts.float_precision {
+                Some(places) if text.contains('.') => round_decimal(text, places as usize),
+                _ => text.clone(),
+            };
+            if opts.thousands_separator {
+                group_thousands(&text)
+            } else {
+                text
+            }
+        }
+        _ => format_cell(value, opts),
+    }
+}
+
+/// Whether `text` is a plain decimal number, e.g. `-1234.50`
+fn is_decimal_text(text: &str) -> bool {
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, "0"));
+    [integer, fraction]
+        .iter()
+        .all(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Round a [`is_decimal_text`] number to `places` decimals, half away
+/// from zero, padding with zeros when it has fewer
+fn round_decimal(text: &str, places: usize) -> String {
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let mut digits: Vec<u8> = integer.bytes().collect();
+    digits.extend(fraction.bytes().chain(std::iter::repeat(b'0')).take(places));
+    if fraction.as_bytes().get(places).is_some_and(|&d| d >= b'5') {
+        // Carry through trailing nines, growing a digit if they all were
+        let carried = digits.iter_mut().rev().all(|d| {
+            let nine = *d == b'9';
+            *d = if nine { b'0' } else { *d + 1 };
+            nine
+        });
+        if carried {
+            digits.insert(0, b'1');
+        }
+    }
+    let split = digits.len() - places;
+    let (integer, fraction) = digits.split_at(split);
+    let integer = std::str::from_utf8(integer).unwrap_or_default();
+    let fraction = std::str::from_utf8(fraction).unwrap_or_default();
+    if places == 0 {
+        format!("{sign}{integer}")
+    } else {
+        format!("{sign}{integer}.{fraction}")
+    }
+}
+
+/// Insert commas into the integer part of a decimal number
+pub(crate) fn group_thousands(number: &str) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", number),
+    };
+    let (integer, fraction) = match unsigned.find('.') {
+        Some(dot) => unsigned.split_at(dot),
+        None => (unsigned, ""),
+    };
+    let mut grouped = String::with_capacity(number.len() + integer.len() / 3);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!("{sign}{grouped}{fraction}")
+}
+
+impl QueryOutput {
+    /// Rows rendered as text, for table views
+    pub fn formatted_rows(&self, opts: &ResultFormatOptions) -> Vec<Vec<String>> {
+        self.rows
+            .iter()
+            .map(|row| self.format_row(row, opts))
+            .collect()
+    }
+
+    /// One row rendered as text, formatted by its columns' types
+    fn format_row(&self, row: &[serde_json::Value], opts: &ResultFormatOptions) -> Vec<String> {
+        row.iter()
+            .enumerate()
+            .map(|(i, v)| format_column_cell(v, self.column_types.get(i), opts))
+            .collect()
+    }
+}
+
+/// Replace `@name` tokens in SQL with the matching values as SQL literals
+///
+/// Occurrences inside string literals, quoted identifiers and comments are
+/// left alone, as are tokens with no matching parameter. Strings are quoted
+/// and escaped, so values cannot break out of their literal.
+pub fn substitute_parameters(sql: &str, params: &[(String, serde_json::Value)]) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                let mut end = sql.len();
+                for (j, d) in chars.by_ref() {
+                    if d == c {
+                        end = j + 1;
+                        break;
+                    }
+                }
+                out.push_str(&sql[i..end]);
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                let end = sql[i..].find('\n').map_or(sql.len(), |n| i + n);
+                out.push_str(&sql[i..end]);
+                while chars.next_if(|(j, _)| *j < end).is_some() {}
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                let end = sql[i + 2..].find("*/").map_or(sql.len(), |n| i + 2 + n + 2);
+                out.push_str(&sql[i..end]);
+                while chars.next_if(|(j, _)| *j < end).is_some() {}
+            }
+            '@' => {
+                let start = i + 1;
+                let mut end = start;
+                while let Some((j, d)) = chars.next_if(|(_, d)| d.is_alphanumeric() || *d == '_') {
+                    end = j + d.len_utf8();
+                }
+                let name = &sql[start..end];
+                match params.iter().find(|(n, _)| n == name) {
+                    Some((_, value)) => out.push_str(&sql_literal(value)),
+                    None => out.push_str(&sql[i..end]),
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Render a JSON value as a SQL literal
+///
+/// Negative numbers are parenthesized, so `10 -@n` can't become the
+/// comment `10 --5`.
+fn sql_literal(value: &serde_json::Value) -> String {
+    use serde_json::Value;
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => if *b { "TRUE" } else { "FALSE" }.to_string(),
+        Value::Number(n) if n.as_f64().is_some_and(|f| f < 0.0) => format!("({})", n),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => quote(s),
+        other => quote(&other.to_string()),
+    }
+}
+
+/// Options for [`format_sql`]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(default)]
+pub struct FormatOptions {
+    /// Upper-case keywords (lower-case them otherwise)
+    pub uppercase_keywords: bool,
+    /// Spaces per subquery level
+    pub indent_width: u32,
+    /// SELECT lists that don't fit on one line get one column per line
+    pub max_line_length: u32,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            uppercase_keywords: true,
+            indent_width: 4,
+            max_line_length: 80,
+        }
+    }
+}
+
+impl From<&EditorPreferences> for FormatOptions {
+    fn from(prefs: &EditorPreferences) -> Self {
+        Self {
+            indent_width: prefs.tab_size,
+            ..Self::default()
+        }
+    }
+}
+
+/// Keywords whose casing is normalized
+const SQL_KEYWORDS: &[&str] = &[
+    "ALL",
+    "AND",
+    "ANTI",
+    "ANY",
+    "AS",
+    "ASC",
+    "ASOF",
+    "BETWEEN",
+    "BY",
+    "CASE",
+    "CAST",
+    "CREATE",
+    "CROSS",
+    "DELETE",
+    "DESC",
+    "DISTINCT",
+    "DROP",
+    "ELSE",
+    "END",
+    "EXCEPT",
+    "EXISTS",
+    "FALSE",
+    "FILTER",
+    "FIRST",
+    "FROM",
+    "FULL",
+    "GROUP",
+    "HAVING",
+    "ILIKE",
+    "IN",
+    "INNER",
+    "INSERT",
+    "INTERSECT",
+    "INTO",
+    "IS",
+    "JOIN",
+    "LAST",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "NATURAL",
+    "NOT",
+    "NULL",
+    "NULLS",
+    "OFFSET",
+    "ON",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "QUALIFY",
+    "RECURSIVE",
+    "REPLACE",
+    "RIGHT",
+    "SELECT",
+    "SEMI",
+    "SET",
+    "TABLE",
+    "THEN",
+    "TRUE",
+    "UNION",
+    "UPDATE",
+    "USING",
+    "VALUES",
+    "VIEW",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+];
+
+/// Keywords that start a clause on a new line
+const CLAUSE_KEYWORDS: &[&str] = &[
+    "SELECT",
+    "FROM",
+    "WHERE",
+    "GROUP",
+    "ORDER",
+    "HAVING",
+    "LIMIT",
+    "OFFSET",
+    "UNION",
+    "INTERSECT",
+    "EXCEPT",
+    "QUALIFY",
+    "WINDOW",
+    "VALUES",
+    "WITH",
+    "JOIN",
+    "LEFT",
+    "RIGHT",
+    "INNER",
+    "FULL",
+    "CROSS",
+    "NATURAL",
+    "ASOF",
+    "ANTI",
+    "SEMI",
+];
+
+/// Keywords that may precede `JOIN`
+const JOIN_MODIFIERS: &[&str] = &[
+    "LEFT", "RIGHT", "INNER", "FULL", "CROSS", "NATURAL", "OUTER", "ASOF", "ANTI", "SEMI",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SqlToken<'a> {
+    Word(&'a str),
+    /// String literal or quoted identifier, kept verbatim
+    Quoted(&'a str),
+    LineComment(&'a str),
+    BlockComment(&'a str),
+    Op(&'a str),
+    Open,
+    Close,
+    Comma,
+    Semi,
+    Dot,
+}
+
+impl SqlToken<'_> {
+    fn keyword(&self) -> Option<String> {
+        match self {
+            SqlToken::Word(w) => {
+                let upper = w.to_ascii_uppercase();
+                SQL_KEYWORDS.contains(&upper.as_str()).then_some(upper)
+            }
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            SqlToken::Word(s)
+            | SqlToken::Quoted(s)
+            | SqlToken::LineComment(s)
+            | SqlToken::BlockComment(s)
+            | SqlToken::Op(s) => s,
+            SqlToken::Open => "(",
+            SqlToken::Close => ")",
+            SqlToken::Comma => ",",
+            SqlToken::Semi => ";",
+            SqlToken::Dot => ".",
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '@' | '$')
+}
+
+fn tokenize_sql(sql: &str) -> Vec<SqlToken<'_>> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '\'' | '"' => {
+                let mut end = sql.len();
+                while let Some((j, d)) = chars.next() {
+                    // A doubled quote is an escape, not the end
+                    if d == c && chars.next_if(|(_, e)| *e == c).is_none() {
+                        end = j + 1;
+                        break;
+                    }
+                }
+                SqlToken::Quoted(&sql[i..end])
+            }
+            '-' if matches!(chars.peek(), Some((_, '-'))) => {
+                let end = sql[i..].find('\n').map_or(sql.len(), |n| i + n);
+                while chars.next_if(|(j, _)| *j < end).is_some() {}
+                SqlToken::LineComment(sql[i..end].trim_end())
+            }
+            '/' if matches!(chars.peek(), Some((_, '*'))) => {
+                let end = sql[i + 2..].find("*/").map_or(sql.len(), |n| i + 2 + n + 2);
+                while chars.next_if(|(j, _)| *j < end).is_some() {}
+                SqlToken::BlockComment(&sql[i..end])
+            }
+            '(' => SqlToken::Open,
+            ')' => SqlToken::Close,
+            ',' => SqlToken::Comma,
+            ';' => SqlToken::Semi,
+            '.' => SqlToken::Dot,
+            c if is_word_char(c) => {
+                let number = c.is_ascii_digit();
+                let mut end = i + c.len_utf8();
+                while let Some((j, d)) = chars.next_if(|(j, d)| {
+                    is_word_char(*d)
+                        || (number && *d == '.')
+                        || (number && matches!(d, '+' | '-') && sql[..*j].ends_with(['e', 'E']))
+                }) {
+                    end = j + d.len_utf8();
+                }
+                SqlToken::Word(&sql[i..end])
+            }
+            _ => {
+                let mut end = i + c.len_utf8();
+                // A comment can follow an operator with no space between
+                while let Some((j, d)) = chars.next_if(|(j, d)| {
+                    "<>=!|:+-*/%&^~".contains(*d)
+                        && !sql[*j..].starts_with("--")
+                        && !sql[*j..].starts_with("/*")
+                }) {
+                    end = j + d.len_utf8();
+                }
+                SqlToken::Op(&sql[i..end])
+            }
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Whether a keyword at `idx` starts a new clause
+fn starts_clause(tokens: &[SqlToken], idx: usize, keyword: &str) -> bool {
+    if !CLAUSE_KEYWORDS.contains(&keyword) {
+        return false;
+    }
+    let prev = idx.checked_sub(1).and_then(|i| tokens[i].keyword());
+    let prev = prev.as_deref();
+    match keyword {
+        // `left(s, 3)` is a function call
+        "LEFT" | "RIGHT" => tokens.get(idx + 1) != Some(&SqlToken::Open),
+        "JOIN" => !prev.is_some_and(|p| JOIN_MODIFIERS.contains(&p)),
+        "ANTI" | "SEMI" | "ASOF" => !prev.is_some_and(|p| JOIN_MODIFIERS.contains(&p)),
+        "FROM" => prev != Some("DELETE"),
+        _ => true,
+    }
+}
+
+/// Whether the parenthesis at `idx` opens a subquery
+fn opens_subquery(tokens: &[SqlToken], idx: usize) -> bool {
+    tokens[idx + 1..]
+        .iter()
+        .find(|t| !matches!(t, SqlToken::LineComment(_) | SqlToken::BlockComment(_)))
+        .and_then(|t| t.keyword())
+        .is_some_and(|k| matches!(k.as_str(), "SELECT" | "WITH" | "FROM"))
+}
+
+/// Whether the SELECT list starting after `idx` must be split across lines
+fn select_list_breaks(tokens: &[SqlToken], idx: usize, room: usize) -> bool {
+    let mut depth = 0usize;
+    let mut width = 0;
+    for (i, token) in tokens.iter().enumerate().skip(idx + 1) {
+        match token {
+            SqlToken::LineComment(_) => return true,
+            SqlToken::Open if opens_subquery(tokens, i) => return true,
+            SqlToken::Open => depth += 1,
+            SqlToken::Close if depth == 0 => break,
+            SqlToken::Close => depth -= 1,
+            SqlToken::Semi => break,
+            _ if depth == 0
+                && token
+                    .keyword()
+                    .is_some_and(|k| k != "SELECT" && starts_clause(tokens, i, &k)) =>
+            {
+                break
+            }
+            _ => {}
+        }
+        width += token.text().chars().count() + 1;
+    }
+    width > room
+}
+
+/// A parenthesized level of the statement being formatted
+struct SqlFrame {
+    indent: usize,
+    /// Clauses start new lines (top level and subqueries)
+    clauses: bool,
+    /// Column to align SELECT list continuation lines to
+    select_align: Option<usize>,
+}
+
+#[derive(Default)]
+struct SqlWriter {
+    out: String,
+    line_start: usize,
+    glue: bool,
+}
+
+impl SqlWriter {
+    fn line_is_blank(&self) -> bool {
+        self.out[self.line_start..].trim().is_empty()
+    }
+
+    fn newline(&mut self, indent: usize) {
+        if self.line_is_blank() {
+            self.out.truncate(self.line_start);
+        } else {
+            let trimmed = self.out.trim_end_matches(' ').len();
+            self.out.truncate(trimmed);
+            self.out.push('\n');
+            self.line_start = self.out.len();
+        }
+        self.out.extend(std::iter::repeat_n(' ', indent));
+        self.glue = false;
+    }
+
+    fn push(&mut self, text: &str, space: bool) {
+        if space && !self.glue && !self.line_is_blank() {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.glue = false;
+    }
+}
+
+/// Pretty-print SQL for display in a cell
+///
+/// Keywords are re-cased, clauses start on their own line, subqueries are
+/// indented one level and long SELECT lists get one column per line, aligned
+/// under the first. String literals, quoted identifiers and comments are
+/// copied verbatim.
+pub fn format_sql(sql: &str, opts: &FormatOptions) -> String {
+    let tokens = tokenize_sql(sql);
+    let indent_width = opts.indent_width as usize;
+    let root = || SqlFrame {
+        indent: 0,
+        clauses: true,
+        select_align: None,
+    };
+    let mut frames = vec![root()];
+    let mut w = SqlWriter::default();
+    let mut break_pending = false;
+
+    for (idx, token) in tokens.iter().enumerate() {
+        let frame = frames.last_mut().expect("root frame is never popped");
+        if break_pending && !matches!(token, SqlToken::LineComment(_)) {
+            w.newline(frame.select_align.unwrap_or(frame.indent));
+        }
+        break_pending = false;
+        let prev = idx.checked_sub(1).map(|i| tokens[i]);
+
+        match token {
+            SqlToken::Word(word) => match token.keyword() {
+                Some(upper) => {
+                    let text = if opts.uppercase_keywords {
+                        upper.clone()
+                    } else {
+                        upper.to_ascii_lowercase()
+                    };
+                    if frame.clauses && starts_clause(&tokens, idx, &upper) {
+                        frame.select_align = None;
+                        w.newline(frame.indent);
+                        if upper == "SELECT" {
+                            let align = frame.indent + text.len() + 1;
+                            let room = (opts.max_line_length as usize).saturating_sub(align);
+                            if select_list_breaks(&tokens, idx, room) {
+                                frame.select_align = Some(align);
+                            }
+                        }
+                    }
+                    w.push(&text, true);
+                }
+                None => w.push(word, true),
+            },
+            SqlToken::Quoted(text) | SqlToken::BlockComment(text) => w.push(text, true),
+            SqlToken::LineComment(text) => {
+                w.push(text, true);
+                break_pending = true;
+            }
+            SqlToken::Comma => {
+                w.push(",", false);
+                break_pending = frame.select_align.is_some();
+            }
+            SqlToken::Dot => {
+                w.push(".", false);
+                w.glue = true;
+            }
+            SqlToken::Op(op) => {
+                let unary = matches!(*op, "-" | "+")
+                    && match prev {
+                        None | Some(SqlToken::Open | SqlToken::Comma | SqlToken::Op(_)) => true,
+                        Some(t) => t.keyword().is_some(),
+                    };
+                w.push(op, *op != "::");
+                w.glue = unary || *op == "::";
+            }
+            SqlToken::Open => {
+                // No space between a function name and its arguments
+                let call = matches!(prev, Some(t @ SqlToken::Word(_)) if t.keyword().is_none());
+                let subquery = opens_subquery(&tokens, idx);
+                let indent = frame.indent + if subquery { indent_width } else { 0 };
+                w.push("(", !call);
+                w.glue = true;
+                frames.push(SqlFrame {
+                    indent,
+                    clauses: subquery,
+                    select_align: None,
+                });
+            }
+            SqlToken::Close => {
+                if frames.len() > 1 {
+                    let closed = frames.pop().expect("checked above");
+                    if closed.clauses {
+                        w.newline(closed.indent.saturating_sub(indent_width));
+                    }
+                }
+                w.push(")", false);
+            }
+            SqlToken::Semi => {
+                w.push(";", false);
+                frames.truncate(1);
+                frames[0] = root();
+                if idx + 1 < tokens.len() {
+                    w.newline(0);
+                    w.out.push('\n');
+                    w.line_start = w.out.len();
+                }
+            }
+        }
+    }
+    w.out.trim_end().to_string()
 }
 
 /// Execute cell request
@@ -202,6 +1822,123 @@ pub struct UpdateCellRequest {
     pub content: String,
 }
 
+/// A reversible notebook edit
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum EditorOperation {
+    /// `cell` was inserted at `index`
+    #[serde(rename = "add_cell")]
+    AddCell { index: u32, cell: Cell },
+    /// `cell` was removed from `index`
+    #[serde(rename = "delete_cell")]
+    DeleteCell { index: u32, cell: Cell },
+    /// The cell at `from` was moved to `to`
+    #[serde(rename = "move_cell")]
+    MoveCell { from: u32, to: u32 },
+    /// A cell's content changed from `before` to `after`
+    #[serde(rename = "update_cell")]
+    UpdateCell {
+        cell_id: String,
+        before: String,
+        after: String,
+    },
+}
+
+impl EditorOperation {
+    /// The operation that undoes this one
+    pub fn inverse(&self) -> EditorOperation {
+        match self {
+            EditorOperation::AddCell { index, cell } => EditorOperation::DeleteCell {
+                index: *index,
+                cell: cell.clone(),
+            },
+            EditorOperation::DeleteCell { index, cell } => EditorOperation::AddCell {
+                index: *index,
+                cell: cell.clone(),
+            },
+            EditorOperation::MoveCell { from, to } => EditorOperation::MoveCell {
+                from: *to,
+                to: *from,
+            },
+            EditorOperation::UpdateCell {
+                cell_id,
+                before,
+                after,
+            } => EditorOperation::UpdateCell {
+                cell_id: cell_id.clone(),
+                before: after.clone(),
+                after: before.clone(),
+            },
+        }
+    }
+}
+
+/// Bounded undo/redo history of notebook edits
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EditorHistory {
+    /// Maximum number of undoable operations kept
+    pub max_depth: u32,
+    /// Applied operations, oldest first
+    undo_stack: Vec<EditorOperation>,
+    /// Undone operations, most recently undone last
+    redo_stack: Vec<EditorOperation>,
+}
+
+impl Default for EditorHistory {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl EditorHistory {
+    /// Empty history keeping at most `max_depth` operations
+    pub fn new(max_depth: u32) -> Self {
+        Self {
+            max_depth,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    /// Record an operation that was just applied
+    ///
+    /// A new edit discards anything that could have been redone.
+    pub fn push(&mut self, op: EditorOperation) {
+        self.redo_stack.clear();
+        self.undo_stack.push(op);
+        let excess = self
+            .undo_stack
+            .len()
+            .saturating_sub(self.max_depth as usize);
+        self.undo_stack.drain(..excess);
+    }
+
+    /// Step back, returning the operation to apply to undo the last edit
+    pub fn undo(&mut self) -> Option<EditorOperation> {
+        let op = self.undo_stack.pop()?;
+        let inverse = op.inverse();
+        self.redo_stack.push(op);
+        Some(inverse)
+    }
+
+    /// Step forward, returning the operation to apply to redo the last undo
+    pub fn redo(&mut self) -> Option<EditorOperation> {
+        let op = self.redo_stack.pop()?;
+        self.undo_stack.push(op.clone());
+        Some(op)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+}
+
 /// Autocomplete request
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -244,3 +1981,855 @@ pub struct AutocompleteResult {
     /// Suggestions
     pub suggestions: Vec<AutocompleteSuggestion>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn substitutes_parameters_outside_literals() {
+        let sql = "SELECT '@year' AS label, \"@year\" FROM results -- @year\n\
+                   WHERE year = @year AND county = @county AND x = @years";
+        let params = vec![
+            ("year".to_string(), json!(2024)),
+            ("county".to_string(), json!("O'Neil")),
+        ];
+        assert_eq!(
+            substitute_parameters(sql, &params),
+            "SELECT '@year' AS label, \"@year\" FROM results -- @year\n\
+             WHERE year = 2024 AND county = 'O''Neil' AND x = @years"
+        );
+    }
+
+    fn update(before: &str, after: &str) -> EditorOperation {
+        EditorOperation::UpdateCell {
+            cell_id: "c1".to_string(),
+            before: before.to_string(),
+            after: after.to_string(),
+        }
+    }
+
+    fn content(op: Option<EditorOperation>) -> Option<String> {
+        match op? {
+            EditorOperation::UpdateCell { after, .. } => Some(after),
+            other => panic!("unexpected operation {:?}", other),
+        }
+    }
+
+    #[test]
+    fn new_edit_after_undo_clears_redo() {
+        let mut history = EditorHistory::new(10);
+        history.push(update("", "SELECT 1"));
+        history.push(update("SELECT 1", "SELECT 2"));
+
+        // Undo returns the inverse: restore the previous content
+        assert_eq!(content(history.undo()), Some("SELECT 1".to_string()));
+        assert!(history.can_redo());
+
+        // Branch off with a new edit; the undone edit is gone
+        history.push(update("SELECT 1", "SELECT 3"));
+        assert!(!history.can_redo());
+        assert!(history.redo().is_none());
+
+        assert_eq!(content(history.undo()), Some("SELECT 1".to_string()));
+        assert_eq!(content(history.redo()), Some("SELECT 3".to_string()));
+        assert_eq!(content(history.undo()), Some("SELECT 1".to_string()));
+        assert_eq!(content(history.undo()), Some("".to_string()));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = EditorHistory::new(2);
+        history.push(update("a", "b"));
+        history.push(update("b", "c"));
+        history.push(update("c", "d"));
+        assert_eq!(content(history.undo()), Some("c".to_string()));
+        assert_eq!(content(history.undo()), Some("b".to_string()));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn move_inverse_swaps_positions() {
+        match (EditorOperation::MoveCell { from: 1, to: 3 }).inverse() {
+            EditorOperation::MoveCell { from, to } => assert_eq!((from, to), (3, 1)),
+            other => panic!("unexpected operation {:?}", other),
+        }
+    }
+
+    #[test]
+    fn formats_nested_subquery() {
+        let sql = "select a, b from (select x as a, count(*) as b from t \
+                   where x > -1 group by x) s order by a";
+        let formatted = format_sql(sql, &FormatOptions::default());
+        assert_eq!(
+            formatted,
+            "SELECT a, b\n\
+             FROM (\n\
+             \x20   SELECT x AS a, count(*) AS b\n\
+             \x20   FROM t\n\
+             \x20   WHERE x > -1\n\
+             \x20   GROUP BY x\n\
+             ) s\n\
+             ORDER BY a"
+        );
+        assert_eq!(format_sql(&formatted, &FormatOptions::default()), formatted);
+    }
+
+    #[test]
+    fn format_leaves_comments_and_literals_alone() {
+        let sql = "select a, -- select from where\n b from t /* from here */ \
+                   where a = 'select  from'";
+        let opts = FormatOptions {
+            uppercase_keywords: false,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_sql(sql, &opts),
+            "select a, -- select from where\n\
+             \x20      b\n\
+             from t /* from here */\n\
+             where a = 'select  from'"
+        );
+    }
+
+    #[test]
+    fn operators_stop_at_comments() {
+        assert_eq!(
+            tokenize_sql("1+--c\n2"),
+            vec![
+                SqlToken::Word("1"),
+                SqlToken::Op("+"),
+                SqlToken::LineComment("--c"),
+                SqlToken::Word("2"),
+            ]
+        );
+        assert_eq!(
+            tokenize_sql("a</*x*/b"),
+            vec![
+                SqlToken::Word("a"),
+                SqlToken::Op("<"),
+                SqlToken::BlockComment("/*x*/"),
+                SqlToken::Word("b"),
+            ]
+        );
+        assert_eq!(
+            format_sql("select a>--x\nb from t", &FormatOptions::default()),
+            "SELECT a > --x\n       b\nFROM t"
+        );
+    }
+
+    #[test]
+    fn format_aligns_long_select_list() {
+        let opts = FormatOptions {
+            max_line_length: 20,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_sql("SELECT alpha, beta, gamma FROM t", &opts),
+            "SELECT alpha,\n       beta,\n       gamma\nFROM t"
+        );
+    }
+
+    #[test]
+    fn imports_ipynb_cells() {
+        let json = r##"{
+            "nbformat": 4,
+            "nbformat_minor": 5,
+            "metadata": {"kernelspec": {"language": "python"}},
+            "cells": [
+                {"cell_type": "markdown", "id": "intro", "source": ["# Budget\n", "Notes"]},
+                {"cell_type": "code", "id": "q", "execution_count": 3,
+                 "source": "%%sql duckdb://\nSELECT 1",
+                 "outputs": [
+                     {"output_type": "execute_result", "data": {"text/plain": ["1\n"]}},
+                     {"output_type": "error", "ename": "E", "evalue": "", "traceback": []}
+                 ]},
+                {"cell_type": "code", "source": "from t select *", "outputs": []},
+                {"cell_type": "code", "source": "from pandas import DataFrame", "outputs": [
+                     {"output_type": "display_data", "data": {"image/png": "AAAA"}}
+                 ]}
+            ]
+        }"##;
+        let nb = Notebook::from_ipynb(json).unwrap();
+        let cells: Vec<_> = nb
+            .cells
+            .iter()
+            .map(|c| (c.id.as_str(), c.cell_type.clone(), c.content.as_str()))
+            .collect();
+        assert_eq!(
+            cells,
+            vec![
+                ("intro", CellType::Markdown, "# Budget\nNotes"),
+                ("q", CellType::Sql, "SELECT 1"),
+                ("q-output", CellType::Markdown, "Output:\n\n```\n1\n```"),
+                ("cell-3", CellType::Sql, "from t select *"),
+                (
+                    "cell-4",
+                    CellType::Markdown,
+                    "```python\nfrom pandas import DataFrame\n```"
+                ),
+            ]
+        );
+        assert_eq!(nb.cells[1].execution_count, Some(3));
+    }
+
+    #[test]
+    fn rejects_bad_ipynb() {
+        assert!(matches!(
+            Notebook::from_ipynb("{not json"),
+            Err(ImportError::MalformedJson(_))
+        ));
+        assert_eq!(
+            Notebook::from_ipynb(r#"{"nbformat": 3, "nbformat_minor": 0, "worksheets": []}"#)
+                .unwrap_err(),
+            ImportError::UnsupportedVersion { major: 3, minor: 0 }
+        );
+    }
+
+    #[test]
+    fn import_errors_map_to_parse_error() {
+        let err = Notebook::from_ipynb("[]").unwrap_err();
+        assert_eq!(ErrorInfo::from(err).code, ErrorCode::ParseError);
+    }
+
+    #[test]
+    fn truncates_large_output() {
+        let blob = "x".repeat(1000);
+        let rows: Vec<Vec<serde_json::Value>> =
+            (0..5000).map(|i| vec![json!(i), json!(blob)]).collect();
+        let mut output = QueryOutput {
+            columns: vec!["id".to_string(), "payload".to_string()],
+            rows,
+            total_rows: 5000,
+            execution_time_ms: 12,
+            truncated: false,
+            output_bytes: 0,
+            column_types: Vec::new(),
+        };
+        let cap = 1024 * 1024;
+
+        assert!(truncate_output(&mut output, cap));
+        assert!(output.truncated);
+        assert!(output.output_bytes <= cap);
+        assert_eq!(
+            output.output_bytes,
+            serde_json::to_vec(&output.rows).unwrap().len() as u64
+        );
+        assert!(!output.rows.is_empty() && output.rows.len() < 5000);
+        assert_eq!(output.columns, vec!["id", "payload"]);
+        assert_eq!(output.total_rows, 5000);
+
+        // Already under the cap: nothing more to drop
+        assert!(!truncate_output(&mut output, cap));
+    }
+
+    #[test]
+    fn saving_truncates_outputs() {
+        let blob = "x".repeat(1000);
+        let mut big = cell("big", "SELECT * FROM payloads");
+        big.output = Some(CellOutput::Query(QueryOutput {
+            columns: vec!["payload".to_string()],
+            rows: (0..5000).map(|_| vec![json!(blob)]).collect(),
+            total_rows: 5000,
+            execution_time_ms: 12,
+            truncated: false,
+            output_bytes: 0,
+            column_types: Vec::new(),
+        }));
+        let nb = notebook(vec![big, cell("empty", "SELECT 1")]);
+
+        let command = crate::storage::StorageCommand::save_notebook(nb, 64 * 1024);
+        let crate::storage::StorageCommand::SaveNotebook { notebook } = command else {
+            panic!("expected a save command");
+        };
+        let Some(CellOutput::Query(output)) = &notebook.cells[0].output else {
+            panic!("query output was dropped");
+        };
+        assert!(output.truncated);
+        assert!(output.output_bytes <= 64 * 1024);
+        assert_eq!(output.columns, vec!["payload"]);
+        assert_eq!(output.total_rows, 5000);
+        assert!(notebook.cells[1].output.is_none());
+    }
+
+    #[test]
+    fn extracts_math_from_markdown() {
+        let md = "Growth is $r = \\frac{a}{b}$, costs \\$5.\n\n\
+                  $$\\sum_i x_i$$\n\n\
+                  ```\nSELECT '$1' AS price\n```\n";
+        let out = render_markdown(md);
+        assert_eq!(
+            out.math_blocks,
+            vec![
+                MathBlock {
+                    inline: true,
+                    latex: "r = \\frac{a}{b}".to_string(),
+                },
+                MathBlock {
+                    inline: false,
+                    latex: "\\sum_i x_i".to_string(),
+                },
+            ]
+        );
+        assert!(out
+            .html
+            .contains(r#"<span class="math math-inline" data-math="0"></span>"#));
+        assert!(out
+            .html
+            .contains(r#"<span class="math math-display" data-math="1"></span>"#));
+        assert!(out.html.contains("costs $5."));
+        assert!(out.html.contains("SELECT '$1' AS price"));
+    }
+
+    #[test]
+    fn filters_cells_by_tag() {
+        let json = r#"{
+            "nbformat": 4,
+            "cells": [
+                {"cell_type": "markdown", "source": "a", "metadata": {"tags": ["setup", "docs"]}},
+                {"cell_type": "markdown", "source": "b"},
+                {"cell_type": "markdown", "source": "c", "metadata": {"tags": ["setup"]}}
+            ]
+        }"#;
+        let nb = Notebook::from_ipynb(json).unwrap();
+        let setup: Vec<_> = nb
+            .cells_with_tag("setup")
+            .iter()
+            .map(|c| c.content.as_str())
+            .collect();
+        assert_eq!(setup, vec!["a", "c"]);
+        assert!(nb.cells_with_tag("missing").is_empty());
+        assert_eq!(nb.all_cell_tags(), vec!["docs", "setup"]);
+
+        // Cells saved before tags existed still load
+        let cell: Cell = serde_json::from_value(json!({
+            "id": "c1",
+            "cell_type": "Sql",
+            "content": "SELECT 1",
+            "created_at": 0,
+            "modified_at": 0
+        }))
+        .unwrap();
+        assert!(cell.tags.is_empty());
+    }
+
+    fn cell(id: &str, content: &str) -> Cell {
+        Cell {
+            id: id.to_string(),
+            cell_type: CellType::Sql,
+            content: content.to_string(),
+            output: None,
+            state: ExecutionState::Idle,
+            execution_count: None,
+            created_at: 0,
+            modified_at: 0,
+            collapsed: false,
+            tags: Vec::new(),
+            content_hash: String::new(),
+        }
+    }
+
+    fn notebook(cells: Vec<Cell>) -> Notebook {
+        Notebook {
+            version: NOTEBOOK_VERSION,
+            metadata: NotebookMetadata {
+                title: None,
+                author: None,
+                tags: Vec::new(),
+                created_at: 0,
+                modified_at: 0,
+                description: None,
+                parameters: Vec::new(),
+                data_sources: Vec::new(),
+            },
+            cells,
+            loaded_data: Vec::new(),
+            charts: Vec::new(),
+            assertions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn validate_flags_duplicate_ids() {
+        let nb = notebook(vec![cell("a", ""), cell("b", ""), cell("a", "")]);
+        assert_eq!(
+            nb.validate(),
+            vec![NotebookValidationIssue::DuplicateCellId {
+                cell_id: "a".to_string(),
+                count: 2,
+            }]
+        );
+
+        let err = Notebook::import_json(&serde_json::to_string(&nb).unwrap()).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ParseError);
+        assert_eq!(err.details.as_deref(), Some("a"));
+        let err = Notebook::import_json("{").unwrap_err();
+        assert_eq!(err.code, ErrorCode::ParseError);
+    }
+
+    #[test]
+    fn validate_flags_chart_without_cell() {
+        let mut nb = notebook(vec![cell("q1", "SELECT 1")]);
+        let chart = |id: &str, cell_id: &str| {
+            serde_json::from_value::<crate::chart::ChartConfig>(json!({
+                "id": id,
+                "chart_type": "Bar",
+                "data": {"labels": [], "datasets": []},
+                "cell_id": cell_id
+            }))
+            .unwrap()
+        };
+        nb.charts = vec![chart("ok", "q1"), chart("orphan", "gone")];
+        nb.assertions = vec![crate::assertion::Assertion {
+            cell_id: "gone".to_string(),
+            expected: crate::assertion::ExpectedResult::RowCount { count: 1 },
+        }];
+        assert_eq!(
+            nb.validate(),
+            vec![
+                NotebookValidationIssue::MissingChartCell {
+                    chart_id: "orphan".to_string(),
+                    cell_id: "gone".to_string(),
+                },
+                NotebookValidationIssue::MissingAssertionCell {
+                    cell_id: "gone".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn chart_output_round_trips() {
+        let mut inline = cell(
+            "q1",
+            "SELECT district, COUNT(*) AS n FROM schools GROUP BY 1",
+        );
+        inline.output = Some(
+            serde_json::from_value(json!({
+                "type": "chart",
+                "id": "by-district",
+                "chart_type": "Bar",
+                "title": "Schools by district",
+                "data": {"labels": ["North", "South"], "datasets": [
+                    {"label": "n", "data": {"type": "numbers", "values": [12, 9]}}
+                ]}
+            }))
+            .unwrap(),
+        );
+        let nb = notebook(vec![inline]);
+        assert!(nb.validate().is_empty());
+
+        let json = serde_json::to_string(&nb).unwrap();
+        let restored = Notebook::import_json(&json).unwrap();
+        match &restored.cells[0].output {
+            Some(CellOutput::Chart(chart)) => {
+                assert_eq!(chart.id, "by-district");
+                assert_eq!(chart.title.as_deref(), Some("Schools by district"));
+                assert_eq!(chart.data.labels, vec!["North", "South"]);
+            }
+            other => panic!("unexpected output {:?}", other),
+        }
+        assert!(restored
+            .to_longform_markdown()
+            .contains("> **Chart:** Schools by district"));
+    }
+
+    #[test]
+    fn renders_longform_article() {
+        let mut intro = cell("intro", "Spending by district.\n");
+        intro.cell_type = CellType::Markdown;
+        let mut query = cell("q", "SELECT district, note FROM budget");
+        query.output = Some(CellOutput::Query(QueryOutput {
+            columns: vec!["district".to_string(), "note | flag".to_string()],
+            rows: vec![
+                vec![json!("Back Creek"), json!("a|b")],
+                vec![json!("Opequon"), json!("line one\nline two")],
+            ],
+            total_rows: 2,
+            execution_time_ms: 4,
+            truncated: false,
+            output_bytes: 0,
+            column_types: Vec::new(),
+        }));
+        let mut failed = cell("bad", "SELECT * FROM missing");
+        failed.output = Some(CellOutput::Error(ErrorOutput {
+            message: "Table missing does not exist".to_string(),
+            details: None,
+        }));
+        let mut nb = notebook(vec![intro, query, failed]);
+        nb.metadata.title = Some("FY24 budget".to_string());
+
+        assert_eq!(
+            nb.to_longform_markdown(),
+            "# FY24 budget\n\n\
+             Spending by district.\n\n\
+             ```sql\nSELECT district, note FROM budget\n```\n\n\
+             | district | note \\| flag |\n\
+             | --- | --- |\n\
+             | Back Creek | a\\|b |\n\
+             | Opequon | line one line two |\n\n\
+             ```sql\nSELECT * FROM missing\n```\n\n\
+             > **Error:** Table missing does not exist"
+        );
+        let without = nb.longform_markdown(false, &ResultFormatOptions::default());
+        assert!(!without.contains('|') && !without.contains("Error"));
+    }
+
+    #[test]
+    fn longform_tables_note_omitted_rows() {
+        let output = QueryOutput {
+            columns: vec!["n".to_string()],
+            rows: (0..LONGFORM_MAX_ROWS + 5).map(|i| vec![json!(i)]).collect(),
+            total_rows: 1200,
+            execution_time_ms: 4,
+            truncated: true,
+            output_bytes: 0,
+            column_types: Vec::new(),
+        };
+        let table = markdown_table(&output, &ResultFormatOptions::default());
+        assert_eq!(table.lines().count(), 2 + LONGFORM_MAX_ROWS + 2);
+        assert!(table.ends_with("\n\n_Showing 50 of 1200 rows._"));
+    }
+
+    #[test]
+    fn validate_flags_unused_data() {
+        let mut nb = notebook(vec![
+            cell("q1", "SELECT * FROM budget"),
+            cell("q2", "SELECT * FROM 'data/schools.parquet'"),
+        ]);
+        nb.loaded_data = vec![
+            "https://example.com/data/budget.parquet".to_string(),
+            "data/schools.parquet".to_string(),
+            "data/parcels.parquet".to_string(),
+        ];
+        assert_eq!(
+            nb.validate(),
+            vec![NotebookValidationIssue::UnusedData {
+                entry: "data/parcels.parquet".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn generated_cell_ids_are_short_and_unique() {
+        let mut nb = notebook(Vec::new());
+        for _ in 0..1000 {
+            let id = nb.new_cell_id();
+            assert_eq!(id.len(), 8);
+            nb.cells.push(cell(&id, ""));
+        }
+        assert!(nb.validate().is_empty());
+    }
+
+    #[test]
+    fn generated_cell_ids_are_not_sequential() {
+        let ids: Vec<String> = (0..64).map(|_| generate_cell_id()).collect();
+        // A counter-based ID changes only in its low digits between calls
+        let shared_prefix = ids
+            .windows(2)
+            .filter(|pair| pair[0][4..] == pair[1][4..])
+            .count();
+        assert!(shared_prefix < 4, "{ids:?}");
+        assert!(ids
+            .iter()
+            .all(|id| id.bytes().all(|b| b.is_ascii_alphanumeric())));
+    }
+
+    #[test]
+    fn substitutes_null_and_bool() {
+        let params = vec![
+            ("a".to_string(), json!(null)),
+            ("b".to_string(), json!(true)),
+        ];
+        assert_eq!(
+            substitute_parameters("SELECT @a, @b /* @a */", &params),
+            "SELECT NULL, TRUE /* @a */"
+        );
+    }
+
+    #[test]
+    fn negative_numbers_cannot_start_a_comment() {
+        let params = vec![("n".to_string(), json!(-5)), ("x".to_string(), json!(-0.5))];
+        assert_eq!(
+            substitute_parameters("SELECT 10 -@n, 1-@x FROM t\nWHERE a = @n", &params),
+            "SELECT 10 -(-5), 1-(-0.5) FROM t\nWHERE a = (-5)"
+        );
+    }
+
+    #[test]
+    fn formats_each_value_kind() {
+        let plain = ResultFormatOptions::default();
+        let opts = ResultFormatOptions {
+            null_text: "—".to_string(),
+            float_precision: Some(2),
+            thousands_separator: true,
+            true_text: "Yes".to_string(),
+            false_text: "No".to_string(),
+        };
+        let cases = [
+            (json!(null), "", "—"),
+            (json!(true), "true", "Yes"),
+            (json!(false), "false", "No"),
+            (json!(1234567), "1234567", "1,234,567"),
+            (json!(-4210), "-4210", "-4,210"),
+            (
+                json!(18446744073709551615u64),
+                "18446744073709551615",
+                "18,446,744,073,709,551,615",
+            ),
+            (json!(12345.6789), "12345.6789", "12,345.68"),
+            (json!(0.5), "0.5", "0.50"),
+            (json!("Back Creek"), "Back Creek", "Back Creek"),
+            (json!([1, 2]), "[1,2]", "[1,2]"),
+            (json!({"a": 1}), r#"{"a":1}"#, r#"{"a":1}"#),
+        ];
+        for (value, expected_plain, expected) in cases {
+            assert_eq!(format_cell(&value, &plain), expected_plain, "{value}");
+            assert_eq!(format_cell(&value, &opts), expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn formats_decimal_strings_as_numbers() {
+        let opts = ResultFormatOptions {
+            float_precision: Some(2),
+            thousands_separator: true,
+            ..ResultFormatOptions::default()
+        };
+        let decimal = ColumnType::Decimal {
+            precision: 38,
+            scale: 3,
+        };
+        let cases = [
+            ("1234567.125", "1,234,567.13"),
+            ("-999.995", "-1,000.00"),
+            ("0.5", "0.50"),
+            // Beyond f64's precision, the digits are kept exactly
+            ("12345678901234567890.004", "12,345,678,901,234,567,890.00"),
+            // Scale 0 decimals stay integers
+            ("4210", "4,210"),
+            ("n/a", "n/a"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                format_column_cell(&json!(text), Some(&decimal), &opts),
+                expected,
+                "{text}"
+            );
+        }
+        // Strings in other columns are left alone
+        assert_eq!(
+            format_column_cell(&json!("01234"), Some(&ColumnType::String), &opts),
+            "01234"
+        );
+
+        let output = QueryOutput {
+            columns: vec!["district".to_string(), "spend".to_string()],
+            rows: vec![vec![json!("Back Creek"), json!("4210000.50")]],
+            total_rows: 1,
+            execution_time_ms: 4,
+            truncated: false,
+            output_bytes: 0,
+            column_types: vec![ColumnType::String, decimal],
+        };
+        assert_eq!(
+            output.formatted_rows(&opts),
+            vec![vec!["Back Creek", "4,210,000.50"]]
+        );
+    }
+
+    fn ran(id: &str, execution_time_ms: u32, total_rows: u64) -> Cell {
+        let mut cell = cell(id, "SELECT 1");
+        cell.state = ExecutionState::Success;
+        cell.output = Some(CellOutput::Query(QueryOutput {
+            columns: vec!["n".to_string()],
+            rows: Vec::new(),
+            total_rows,
+            execution_time_ms,
+            truncated: false,
+            output_bytes: 0,
+            column_types: Vec::new(),
+        }));
+        cell
+    }
+
+    #[test]
+    fn editing_upstream_cell_marks_downstream_stale() {
+        let mut nb = notebook(vec![
+            ran("load", 10, 1),
+            ran("filter", 10, 1),
+            cell("notes", "# Findings"),
+            ran("summary", 10, 1),
+        ]);
+        nb.cells[0].content = "CREATE TABLE t AS SELECT 1 AS n".to_string();
+        nb.cells[1].content = "SELECT * FROM t WHERE n > 0".to_string();
+        nb.cells[2].cell_type = CellType::Markdown;
+        nb.cells[2].state = ExecutionState::Success;
+        nb.cells[2].output = Some(CellOutput::Markdown(render_markdown("# Findings")));
+        assert_eq!(
+            nb.stale_cells(&[], "s1"),
+            vec!["load", "filter", "notes", "summary"]
+        );
+
+        for id in ["load", "filter", "notes", "summary"] {
+            assert!(nb.record_run(id, &[], "s1"));
+        }
+        assert!(nb.stale_cells(&[], "s1").is_empty());
+        assert!(!nb.record_run("missing", &[], "s1"));
+
+        nb.cells[1].content = "SELECT * FROM t WHERE n > 1".to_string();
+        assert_eq!(nb.stale_cells(&[], "s1"), vec!["filter", "summary"]);
+
+        // Restoring the content makes the cached outputs current again
+        nb.cells[1].content = "SELECT * FROM t WHERE n > 0".to_string();
+        assert!(nb.stale_cells(&[], "s1").is_empty());
+
+        let json = serde_json::to_value(cell("new", "SELECT 1")).unwrap();
+        assert!(json.get("content_hash").is_none());
+    }
+
+    #[test]
+    fn new_engine_session_reruns_cells_that_change_the_database() {
+        let mut nb = notebook(vec![
+            ran("load", 10, 1),
+            ran("create", 10, 1),
+            ran("query", 10, 1),
+            ran("insert", 10, 1),
+        ]);
+        nb.cells[0].cell_type = CellType::Directive;
+        nb.cells[0].content = "LOAD parcels.parquet AS parcels".to_string();
+        nb.cells[1].content = "CREATE TABLE t AS SELECT * FROM parcels".to_string();
+        nb.cells[2].content = "-- totals\nSUMMARIZE t".to_string();
+        nb.cells[3].content = "SELECT 1; INSERT INTO t SELECT * FROM t".to_string();
+        for id in ["load", "create", "query", "insert"] {
+            assert!(nb.record_run(id, &[], "s1"));
+        }
+        assert!(nb.stale_cells(&[], "s1").is_empty());
+        // After a reload the database is empty, but read-only outputs hold
+        assert_eq!(nb.stale_cells(&[], "s2"), vec!["load", "create", "insert"]);
+    }
+
+    #[test]
+    fn changing_a_parameter_marks_cells_using_it_stale() {
+        let mut nb = notebook(vec![
+            ran("totals", 10, 1),
+            ran("by_year", 10, 1),
+            ran("summary", 10, 1),
+        ]);
+        nb.cells[0].content = "CREATE TABLE t AS SELECT * FROM budget".to_string();
+        nb.cells[1].content = "SELECT * FROM t WHERE fiscal_year = @year".to_string();
+        nb.cells[2].content = "SELECT COUNT(*) FROM t".to_string();
+        nb.metadata.parameters = vec![NotebookParameter {
+            name: "year".to_string(),
+            param_type: ColumnType::Int32,
+            default: json!(2024),
+            description: None,
+        }];
+        for id in ["totals", "by_year", "summary"] {
+            assert!(nb.record_run(id, &[], "s1"));
+        }
+        // The default is what ran, so supplying it changes nothing
+        let default = [("year".to_string(), json!(2024))];
+        assert!(nb.stale_cells(&default, "s1").is_empty());
+
+        let changed = [("year".to_string(), json!(2023))];
+        assert_eq!(nb.stale_cells(&changed, "s1"), vec!["by_year", "summary"]);
+
+        nb.metadata.parameters[0].default = json!(2023);
+        assert_eq!(nb.stale_cells(&[], "s1"), vec!["by_year", "summary"]);
+    }
+
+    #[test]
+    fn execution_state_serde_names() {
+        let states = [
+            (ExecutionState::Idle, "Idle"),
+            (ExecutionState::Queued, "Queued"),
+            (ExecutionState::Running, "Running"),
+            (ExecutionState::Success, "Success"),
+            (ExecutionState::Error, "Error"),
+            (ExecutionState::Cancelled, "Cancelled"),
+        ];
+        for (state, name) in states {
+            assert_eq!(serde_json::to_value(&state).unwrap(), json!(name));
+            assert_eq!(
+                serde_json::from_value::<ExecutionState>(json!(name)).unwrap(),
+                state
+            );
+        }
+
+        let mut queued = cell("q", "SELECT 1");
+        queued.state = ExecutionState::Queued;
+        assert!(queued.execution_metrics().is_none());
+        queued.state = ExecutionState::Cancelled;
+        assert_eq!(
+            queued.execution_metrics().unwrap().state,
+            ExecutionState::Cancelled
+        );
+    }
+
+    #[test]
+    fn run_report_summarizes_cells() {
+        let mut failed = cell("bad", "SELECT * FROM missing");
+        failed.state = ExecutionState::Error;
+        failed.output = Some(CellOutput::Error(ErrorOutput {
+            message: "Table missing does not exist".to_string(),
+            details: None,
+        }));
+        let nb = notebook(vec![
+            ran("fast", 12, 3),
+            failed,
+            cell("never-run", "SELECT 2"),
+            ran("slow", 480, 10_000),
+        ]);
+
+        let report = nb.run_report();
+        assert_eq!(report.cells.len(), 3);
+        assert_eq!(report.success_count, 2);
+        assert_eq!(report.error_count, 1);
+        assert_eq!(report.total_time_ms, 492);
+        let slowest = report.slowest.unwrap();
+        assert_eq!(slowest.cell_id, "slow");
+        assert_eq!(slowest.row_count, Some(10_000));
+        assert_eq!(report.cells[1].row_count, None);
+    }
+
+    #[test]
+    fn notebook_records_data_sources() {
+        let cached = CachedParquet {
+            url: "https://data.virginia.gov/aadt_2023.parquet".to_string(),
+            size: 2048,
+            etag: None,
+            fetched_at: 1_700_000_000_000,
+            last_accessed: 1_700_000_000_000,
+            content_hash: "9f86d081884c7d65".to_string(),
+            compression: Default::default(),
+            stored_size: 0,
+        };
+        let mut nb = notebook(vec![cell("a", "SELECT * FROM aadt")]);
+        nb.record_data_source(DataSourceRef::new(
+            "parcels",
+            "/data/parcels.parquet",
+            Some(&cached),
+            1_700_000_060_000,
+        ));
+        nb.record_data_source(DataSourceRef::new(
+            "aadt",
+            &cached.url,
+            Some(&cached),
+            1_700_000_050_000,
+        ));
+
+        let json = serde_json::to_value(&nb).unwrap();
+        let sources = &json["metadata"]["data_sources"];
+        assert_eq!(sources.as_array().unwrap().len(), 2);
+        assert!(sources[0].get("content_hash").is_none());
+        assert_eq!(sources[1]["content_hash"], "9f86d081884c7d65");
+
+        let restored = Notebook::import_json(&json.to_string()).unwrap();
+        let provenance = restored.provenance();
+        assert_eq!(provenance[0].name, "aadt");
+        assert_eq!(provenance[1].url, "/data/parcels.parquet");
+    }
+}
@@ -5,13 +5,21 @@
 pub mod messages;
 pub mod data;
 pub mod chart;
+pub mod directive;
 pub mod editor;
+pub mod assertion;
 pub mod notes;
+pub mod nip19;
+pub mod sanitize;
 pub mod storage;
+pub mod templates;
 
 pub use messages::*;
 pub use data::*;
 pub use chart::*;
+pub use directive::*;
 pub use editor::*;
+pub use assertion::*;
 pub use notes::*;
 pub use storage::*;
+pub use templates::*;
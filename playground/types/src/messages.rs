@@ -1,6 +1,8 @@
 //! Base message types for inter-component communication
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
 use tsify::Tsify;
 
 /// Unique identifier for message correlation
@@ -35,6 +37,104 @@ pub struct Response<T> {
     pub result: MessageResult<T>,
 }
 
+impl<T> Request<T> {
+    /// Wrap `payload` with a fresh ID, sent at `timestamp`
+    pub fn new(payload: T, timestamp: Timestamp) -> Self {
+        Request {
+            id: generate_id(),
+            timestamp,
+            payload,
+        }
+    }
+}
+
+/// In-flight requests awaiting a response, keyed by ID
+///
+/// Takes explicit timestamps so engines can drive it from `Date.now()`.
+#[derive(Clone, Debug, Default)]
+pub struct PendingRequests {
+    sent_at: HashMap<MessageId, Timestamp>,
+}
+
+impl PendingRequests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap and register `payload`, returning the request to post
+    pub fn send<T>(&mut self, payload: T, now: Timestamp) -> Request<T> {
+        let request = Request::new(payload, now);
+        self.register(request.id.clone(), now);
+        request
+    }
+
+    /// Track a request sent at `now`
+    pub fn register(&mut self, id: impl Into<MessageId>, now: Timestamp) {
+        self.sent_at.insert(id.into(), now);
+    }
+
+    /// Match `response` to its request, filling `execution_time_ms`
+    ///
+    /// Returns the round-trip latency, or `None` for an unknown or expired
+    /// ID, in which case the response should be dropped.
+    pub fn complete<T>(&mut self, response: &mut Response<T>, now: Timestamp) -> Option<Duration> {
+        let sent_at = self.sent_at.remove(&response.id)?;
+        let latency = now.saturating_sub(sent_at);
+        response.execution_time_ms = latency.try_into().unwrap_or(u32::MAX);
+        Some(Duration::from_millis(latency))
+    }
+
+    /// Drop requests sent before `older_than`, returning their IDs
+    pub fn expire(&mut self, older_than: Timestamp) -> Vec<MessageId> {
+        let expired: Vec<MessageId> = self
+            .sent_at
+            .iter()
+            .filter(|(_, &sent_at)| sent_at < older_than)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            self.sent_at.remove(id);
+        }
+        expired
+    }
+
+    /// Whether `id` is still awaiting a response
+    pub fn contains(&self, id: &str) -> bool {
+        self.sent_at.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sent_at.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sent_at.is_empty()
+    }
+}
+
+/// Request to cancel an in-flight request
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CancelRequest {
+    /// ID of the request to cancel
+    pub target_id: MessageId,
+}
+
+/// Progress event emitted while a request is running
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct QueryProgress {
+    /// ID of the running request
+    pub id: MessageId,
+    /// Rows read from the result so far
+    pub rows_processed: u64,
+    /// Time since the request started in milliseconds
+    pub elapsed_ms: u32,
+    /// Completion percentage (0-100), if the total is known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f32>,
+}
+
 /// Result wrapper for all responses
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -79,6 +179,8 @@ pub enum ErrorCode {
     Cancelled,
     /// Resource limit exceeded
     LimitExceeded,
+    /// Operation ran past its time limit
+    Timeout,
     /// Unknown error
     Unknown,
 }
@@ -100,6 +202,13 @@ impl<T> MessageResult<T> {
         }
     }
 
+    /// Create an error result from any error with a known [`ErrorCode`]
+    pub fn from_err<E: IntoErrorInfo>(err: E) -> Self {
+        MessageResult::Error {
+            error: err.into_error_info(),
+        }
+    }
+
     /// Create an error result with details
     pub fn error_with_details(
         code: ErrorCode,
@@ -126,14 +235,62 @@ impl<T> MessageResult<T> {
     }
 }
 
+/// Errors with a fixed mapping onto [`ErrorCode`]
+///
+/// Pair an implementation with [`crate::error_info_from!`] to get
+/// `From<E> for ErrorInfo`, so `?` works in functions returning
+/// `Result<_, ErrorInfo>`.
+pub trait IntoErrorInfo {
+    fn into_error_info(self) -> ErrorInfo;
+}
+
+/// Implement `From<E> for ErrorInfo` through [`IntoErrorInfo`] for each
+/// listed error type
+///
+/// A blanket impl over `IntoErrorInfo` would overlap with the standard
+/// `From<T> for T`, so each type is listed instead.
+#[macro_export]
+macro_rules! error_info_from {
+    ($($error:ty),+ $(,)?) => {
+        $(
+            impl From<$error> for $crate::messages::ErrorInfo {
+                fn from(err: $error) -> Self {
+                    $crate::messages::IntoErrorInfo::into_error_info(err)
+                }
+            }
+        )+
+    };
+}
+
+impl IntoErrorInfo for serde_json::Error {
+    fn into_error_info(self) -> ErrorInfo {
+        ErrorInfo {
+            code: ErrorCode::ParseError,
+            message: "Invalid JSON".to_string(),
+            details: Some(self.to_string()),
+        }
+    }
+}
+
+crate::error_info_from!(serde_json::Error);
+
 /// Generate a new message ID
+///
+/// A per-process sequence number keeps IDs unique within a session, and a
+/// random suffix keeps sessions (e.g. two tabs) from sharing them. Uses no
+/// clock, which wasm32-unknown-unknown doesn't have.
 pub fn generate_id() -> MessageId {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    format!("{:x}", now)
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+
+    let mut suffix = [0u8; 4];
+    // Without randomness the sequence number alone still tells IDs apart
+    getrandom::getrandom(&mut suffix).ok();
+    format!(
+        "{:x}-{:08x}",
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        u32::from_le_bytes(suffix)
+    )
 }
 
 /// Get current timestamp
@@ -144,3 +301,85 @@ pub fn now() -> Timestamp {
         .unwrap()
         .as_millis() as u64
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(id: &str) -> Response<u32> {
+        Response {
+            id: id.to_string(),
+            timestamp: 0,
+            execution_time_ms: 0,
+            result: MessageResult::ok(1),
+        }
+    }
+
+    #[test]
+    fn send_registers_unique_ids() {
+        let mut pending = PendingRequests::new();
+        let requests: Vec<Request<u32>> = (0..100).map(|i| pending.send(i, 1_000)).collect();
+        assert_eq!(pending.len(), 100);
+
+        let ids: std::collections::HashSet<&str> = requests.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids.len(), 100);
+        assert_eq!(requests[7].payload, 7);
+        assert_eq!(requests[7].timestamp, 1_000);
+
+        let mut reply = response(&requests[7].id);
+        assert_eq!(
+            pending.complete(&mut reply, 1_250),
+            Some(Duration::from_millis(250))
+        );
+        assert!(!pending.contains(&requests[7].id));
+        assert!(pending.contains(&requests[8].id));
+    }
+
+    #[test]
+    fn serde_errors_map_to_parse_error() {
+        let err = serde_json::from_str::<u32>("nope").unwrap_err();
+        let result: MessageResult<u32> = MessageResult::from_err(err);
+        match result {
+            MessageResult::Error { error } => {
+                assert_eq!(error.code, ErrorCode::ParseError);
+                assert!(error.details.is_some());
+            }
+            MessageResult::Ok { .. } => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn complete_measures_round_trip_latency() {
+        let mut pending = PendingRequests::new();
+        pending.register("a", 1_000);
+        pending.register("b", 1_100);
+
+        let mut a = response("a");
+        assert_eq!(
+            pending.complete(&mut a, 1_250),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(a.execution_time_ms, 250);
+
+        // A second response for the same ID is unmatched
+        assert_eq!(pending.complete(&mut response("a"), 1_300), None);
+        assert!(pending.contains("b"));
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn expire_drops_only_stale_requests() {
+        let mut pending = PendingRequests::new();
+        pending.register("old", 1_000);
+        pending.register("new", 5_000);
+
+        assert_eq!(pending.expire(3_000), vec!["old".to_string()]);
+        assert!(!pending.contains("old"));
+        assert_eq!(pending.complete(&mut response("old"), 6_000), None);
+        assert_eq!(
+            pending.complete(&mut response("new"), 6_000),
+            Some(Duration::from_secs(1))
+        );
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! NIP-19 bech32 encodings for keys, events and addresses
+
+use crate::messages::{ErrorCode, ErrorInfo, IntoErrorInfo};
+use bech32::{Bech32, Hrp};
+
+/// Errors encoding or decoding NIP-19 entities
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Nip19Error {
+    /// Input is not 64 hex characters
+    #[error("expected 32 bytes of hex, got `{0}`")]
+    InvalidHex(String),
+    /// Bech32 encoding or checksum failure
+    #[error("invalid bech32: {0}")]
+    Bech32(String),
+    /// Decoded a different entity than expected
+    #[error("expected `{expected}` prefix, got `{found}`")]
+    WrongPrefix {
+        expected: &'static str,
+        found: String,
+    },
+    /// TLV payload is truncated or lacks a required field
+    #[error("invalid TLV data: {0}")]
+    InvalidTlv(&'static str),
+}
+
+impl IntoErrorInfo for Nip19Error {
+    fn into_error_info(self) -> ErrorInfo {
+        ErrorInfo {
+            code: ErrorCode::ParseError,
+            message: self.to_string(),
+            details: None,
+        }
+    }
+}
+
+crate::error_info_from!(Nip19Error);
+
+/// TLV type holding the special value (event id, pubkey or identifier)
+const TLV_SPECIAL: u8 = 0;
+const TLV_RELAY: u8 = 1;
+const TLV_AUTHOR: u8 = 2;
+const TLV_KIND: u8 = 3;
+
+/// A decoded `naddr`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Naddr {
+    pub kind: u32,
+    /// Author pubkey as hex
+    pub pubkey: String,
+    /// The event's `d` tag
+    pub identifier: String,
+    pub relays: Vec<String>,
+}
+
+pub fn encode_npub(hex: &str) -> Result<String, Nip19Error> {
+    encode("npub", &hex_to_bytes(hex)?)
+}
+
+pub fn decode_npub(npub: &str) -> Result<String, Nip19Error> {
+    decode_32("npub", npub)
+}
+
+pub fn encode_nsec(hex: &str) -> Result<String, Nip19Error> {
+    encode("nsec", &hex_to_bytes(hex)?)
+}
+
+pub fn decode_nsec(nsec: &str) -> Result<String, Nip19Error> {
+    decode_32("nsec", nsec)
+}
+
+pub fn encode_note(event_id: &str) -> Result<String, Nip19Error> {
+    encode("note", &hex_to_bytes(event_id)?)
+}
+
+pub fn decode_note(note: &str) -> Result<String, Nip19Error> {
+    decode_32("note", note)
+}
+
+/// Encode an event reference with relay and author hints
+pub fn encode_nevent(
+    event_id: &str,
+    author: Option<&str>,
+    relays: &[String],
+) -> Result<String, Nip19Error> {
+    let mut tlv = Vec::new();
+    push_tlv(&mut tlv, TLV_SPECIAL, &hex_to_bytes(event_id)?)?;
+    for relay in relays {
+        push_tlv(&mut tlv, TLV_RELAY, relay.as_bytes())?;
+    }
+    if let Some(author) = author {
+        push_tlv(&mut tlv, TLV_AUTHOR, &hex_to_bytes(author)?)?;
+    }
+    encode("nevent", &tlv)
+}
+
+/// Encode an addressable event reference (kind + author + `d` tag)
+pub fn encode_naddr(
+    kind: u32,
+    pubkey: &str,
+    identifier: &str,
+    relays: &[String],
+) -> Result<String, Nip19Error> {
+    let mut tlv = Vec::new();
+    push_tlv(&mut tlv, TLV_SPECIAL, identifier.as_bytes())?;
+    for relay in relays {
+        push_tlv(&mut tlv, TLV_RELAY, relay.as_bytes())?;
+    }
+    push_tlv(&mut tlv, TLV_AUTHOR, &hex_to_bytes(pubkey)?)?;
+    push_tlv(&mut tlv, TLV_KIND, &kind.to_be_bytes())?;
+    encode("naddr", &tlv)
+}
+
+pub fn decode_naddr(naddr: &str) -> Result<Naddr, Nip19Error> {
+    let data = decode("naddr", naddr)?;
+    let mut identifier = None;
+    let mut pubkey = None;
+    let mut kind = None;
+    let mut relays = Vec::new();
+    let mut rest = data.as_slice();
+    while let [t, len, tail @ ..] = rest {
+        let len = *len as usize;
+        if tail.len() < len {
+            return Err(Nip19Error::InvalidTlv("value longer than payload"));
+        }
+        let (value, tail) = tail.split_at(len);
+        match *t {
+            TLV_SPECIAL => identifier = Some(String::from_utf8_lossy(value).into_owned()),
+            TLV_RELAY => relays.push(String::from_utf8_lossy(value).into_owned()),
+            TLV_AUTHOR if len == 32 => pubkey = Some(bytes_to_hex(value)),
+            TLV_KIND if len == 4 => {
+                kind = Some(u32::from_be_bytes(
+                    value.try_into().expect("length checked"),
+                ))
+            }
+            // Unknown types must be ignored
+            _ => {}
+        }
+        rest = tail;
+    }
+    Ok(Naddr {
+        kind: kind.ok_or(Nip19Error::InvalidTlv("missing kind"))?,
+        pubkey: pubkey.ok_or(Nip19Error::InvalidTlv("missing author"))?,
+        identifier: identifier.ok_or(Nip19Error::InvalidTlv("missing identifier"))?,
+        relays,
+    })
+}
+
+fn push_tlv(out: &mut Vec<u8>, t: u8, value: &[u8]) -> Result<(), Nip19Error> {
+    let len = u8::try_from(value.len())
+        .map_err(|_| Nip19Error::InvalidTlv("value is longer than 255 bytes"))?;
+    out.push(t);
+    out.push(len);
+    out.extend_from_slice(value);
+    Ok(())
+}
+
+fn encode(prefix: &str, data: &[u8]) -> Result<String, Nip19Error> {
+    let hrp = Hrp::parse(prefix).map_err(|e| Nip19Error::Bech32(e.to_string()))?;
+    bech32::encode::<Bech32>(hrp, data).map_err(|e| Nip19Error::Bech32(e.to_string()))
+}
+
+fn decode(prefix: &'static str, s: &str) -> Result<Vec<u8>, Nip19Error> {
+    let (hrp, data) = bech32::decode(s).map_err(|e| Nip19Error::Bech32(e.to_string()))?;
+    if hrp.as_str() != prefix {
+        return Err(Nip19Error::WrongPrefix {
+            expected: prefix,
+            found: hrp.to_string(),
+        });
+    }
+    Ok(data)
+}
+
+fn decode_32(prefix: &'static str, s: &str) -> Result<String, Nip19Error> {
+    let data = decode(prefix, s)?;
+    if data.len() != 32 {
+        return Err(Nip19Error::InvalidTlv("expected 32 bytes"));
+    }
+    Ok(bytes_to_hex(&data))
+}
+
+pub(crate) fn hex_to_bytes(hex: &str) -> Result<[u8; 32], Nip19Error> {
+    let invalid = || Nip19Error::InvalidHex(hex.to_string());
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut bytes = [0u8; 32];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Vectors from the NIP-19 spec
+    const PUBKEY: &str = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+    const NPUB: &str = "npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg";
+    const SECKEY: &str = "67dea2ed018072d675f5415ecfaed7d2597555e202d85b3d65ea4e58d2d92ffa";
+    const NSEC: &str = "nsec1vl029mgpspedva04g90vltkh6fvh240zqtv9k0t9af8935ke9laqsnlfe5";
+
+    #[test]
+    fn npub_and_nsec_match_spec_vectors() {
+        assert_eq!(encode_npub(PUBKEY).unwrap(), NPUB);
+        assert_eq!(decode_npub(NPUB).unwrap(), PUBKEY);
+        assert_eq!(encode_nsec(SECKEY).unwrap(), NSEC);
+        assert_eq!(decode_nsec(NSEC).unwrap(), SECKEY);
+    }
+
+    #[test]
+    fn rejects_bad_checksum_and_prefix() {
+        let mut corrupted = NPUB.to_string();
+        corrupted.replace_range(10..11, "q");
+        assert!(matches!(
+            decode_npub(&corrupted),
+            Err(Nip19Error::Bech32(_))
+        ));
+        assert_eq!(
+            decode_note(NPUB),
+            Err(Nip19Error::WrongPrefix {
+                expected: "note",
+                found: "npub".to_string(),
+            })
+        );
+        assert!(matches!(encode_npub("abc"), Err(Nip19Error::InvalidHex(_))));
+    }
+
+    #[test]
+    fn decode_errors_map_to_parse_error() {
+        let err = decode_npub("npub1").unwrap_err();
+        assert_eq!(ErrorInfo::from(err).code, ErrorCode::ParseError);
+    }
+
+    #[test]
+    fn note_and_naddr_round_trip() {
+        let note = encode_note(PUBKEY).unwrap();
+        assert!(note.starts_with("note1"));
+        assert_eq!(decode_note(&note).unwrap(), PUBKEY);
+
+        let relays = vec!["wss://relay.damus.io".to_string()];
+        let naddr = encode_naddr(30023, PUBKEY, "budget-2024", &relays).unwrap();
+        assert!(naddr.starts_with("naddr1"));
+        assert_eq!(
+            decode_naddr(&naddr).unwrap(),
+            Naddr {
+                kind: 30023,
+                pubkey: PUBKEY.to_string(),
+                identifier: "budget-2024".to_string(),
+                relays,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_values_too_long_for_tlv() {
+        let identifier = "d".repeat(256);
+        assert_eq!(
+            encode_naddr(30023, PUBKEY, &identifier, &[]).unwrap_err(),
+            Nip19Error::InvalidTlv("value is longer than 255 bytes")
+        );
+        assert!(encode_naddr(30023, PUBKEY, &identifier[..255], &[]).is_ok());
+
+        let relays = vec![format!("wss://{}.example", "r".repeat(250))];
+        assert!(encode_nevent(PUBKEY, None, &relays).is_err());
+    }
+}
@@ -1,8 +1,18 @@
 //! NotesEngine types for Nostr integration
 
-use crate::messages::Timestamp;
+use crate::editor::{Notebook, ResultFormatOptions};
+use crate::messages::{ErrorCode, ErrorInfo, Timestamp};
+use crate::nip19::{self, Nip19Error};
+use crate::sanitize::sanitize_note_content;
+use futures::future::join_all;
+use k256::sha2::{Digest, Sha256};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use tsify::Tsify;
+use zeroize::{Zeroize, Zeroizing};
 
 /// Nostr key management strategy
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -23,6 +33,61 @@ pub enum KeyStrategy {
     Ephemeral,
 }
 
+/// Request to generate a fresh `Ephemeral` key, replacing the session's
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct GenerateEphemeralKeyRequest {}
+
+/// A newly generated ephemeral key
+///
+/// The engine keeps no copy of the key: this is the only time the nsec is
+/// produced, so the user must save it now or lose it with the session. The
+/// nsec is zeroized when this value is dropped.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EphemeralKeyInfo {
+    /// Public key (bech32)
+    pub npub: String,
+    /// Secret key (bech32)
+    pub nsec: String,
+}
+
+impl EphemeralKeyInfo {
+    /// Generate a random secp256k1 key pair
+    pub fn generate() -> Result<EphemeralKeyInfo, ErrorInfo> {
+        let key = loop {
+            let mut secret = Zeroizing::new([0u8; 32]);
+            getrandom::getrandom(secret.as_mut())
+                .map_err(|e| signing_error(ErrorCode::Unknown, format!("No randomness: {e}")))?;
+            // Zero or values past the curve order are rejected; just retry
+            if let Ok(key) = k256::schnorr::SigningKey::from_bytes(secret.as_ref()) {
+                break key;
+            }
+        };
+        let encode_error = |e: Nip19Error| signing_error(ErrorCode::Unknown, e.to_string());
+        let secret_hex = Zeroizing::new(nip19::bytes_to_hex(&key.to_bytes()));
+        Ok(EphemeralKeyInfo {
+            npub: nip19::encode_npub(&nip19::bytes_to_hex(&key.verifying_key().to_bytes()))
+                .map_err(encode_error)?,
+            nsec: nip19::encode_nsec(&secret_hex).map_err(encode_error)?,
+        })
+    }
+}
+
+impl Drop for EphemeralKeyInfo {
+    fn drop(&mut self) {
+        self.nsec.zeroize();
+    }
+}
+
+fn signing_error(code: ErrorCode, message: impl Into<String>) -> ErrorInfo {
+    ErrorInfo {
+        code,
+        message: message.into(),
+        details: None,
+    }
+}
+
 /// Note attachment type
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -48,6 +113,92 @@ pub enum NoteAttachment {
     General,
 }
 
+/// NIP-32 label namespace for note attachments
+pub const ATTACHMENT_NAMESPACE: &str = "fredco.attachment";
+
+impl NoteAttachment {
+    /// Relay-indexable `l` label, e.g. `dataset:parcels`
+    pub fn label(&self) -> String {
+        match self {
+            NoteAttachment::Dataset { name } => format!("dataset:{}", name),
+            NoteAttachment::Query {
+                cell_id: Some(cell_id),
+                ..
+            } => format!("query:{}", cell_id),
+            NoteAttachment::Query { sql, .. } => format!("query:{}", sql),
+            NoteAttachment::Cell { cell_id } => format!("cell:{}", cell_id),
+            NoteAttachment::Notebook { notebook_id } => format!("notebook:{}", notebook_id),
+            NoteAttachment::General => "general".to_string(),
+        }
+    }
+
+    /// NIP-32 label tags for relay filtering, plus the full attachment
+    pub fn event_tags(&self) -> Vec<Vec<String>> {
+        let json = serde_json::to_string(self).unwrap_or_default();
+        vec![
+            vec!["L".to_string(), ATTACHMENT_NAMESPACE.to_string()],
+            vec![
+                "l".to_string(),
+                self.label(),
+                ATTACHMENT_NAMESPACE.to_string(),
+            ],
+            vec!["attachment".to_string(), json],
+        ]
+    }
+
+    /// Read the attachment back from an event's tags
+    pub fn from_tags(tags: &[Vec<String>]) -> Option<NoteAttachment> {
+        tags.iter()
+            .find(|t| t.first().map(String::as_str) == Some("attachment"))
+            .and_then(|t| t.get(1))
+            .and_then(|json| serde_json::from_str(json).ok())
+    }
+}
+
+/// Event kind for public short notes
+pub const KIND_TEXT_NOTE: u16 = 1;
+/// Event kind for encrypted direct messages, used for private notes
+pub const KIND_ENCRYPTED_NOTE: u16 = 4;
+/// Event kind for reactions (NIP-25)
+pub const KIND_REACTION: u16 = 7;
+/// Event kind for zap requests (NIP-57)
+pub const KIND_ZAP_REQUEST: u16 = 9734;
+/// Event kind for long-form articles
+pub const KIND_LONG_FORM: u16 = 30023;
+
+/// Content shown for a private note that could not be decrypted
+pub const ENCRYPTED_PLACEHOLDER: &str = "[encrypted]";
+
+/// How a note's content is protected
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+#[serde(tag = "type")]
+pub enum NoteEncryption {
+    /// Public note
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    /// Private note encrypted with NIP-44
+    #[serde(rename = "nip44")]
+    Nip44 {
+        /// Recipient pubkey; `None` encrypts to the author
+        #[serde(skip_serializing_if = "Option::is_none")]
+        recipient_pubkey: Option<String>,
+    },
+}
+
+impl NoteEncryption {
+    /// Pubkey the content is encrypted to, or `None` for public notes
+    pub fn recipient<'a>(&'a self, author_pubkey: &'a str) -> Option<&'a str> {
+        match self {
+            NoteEncryption::None => None,
+            NoteEncryption::Nip44 { recipient_pubkey } => {
+                Some(recipient_pubkey.as_deref().unwrap_or(author_pubkey))
+            }
+        }
+    }
+}
+
 /// Request to publish a note
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -65,6 +216,172 @@ pub struct PublishNoteRequest {
     /// Whether to publish as long-form article (kind 30023)
     #[serde(default)]
     pub long_form: bool,
+    /// Content encryption; private notes are never long-form
+    #[serde(default)]
+    pub encryption: NoteEncryption,
+    /// Event ID of the note being replied to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Event ID of the thread root, when it isn't `reply_to` itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_root: Option<String>,
+    /// Author of the note being replied to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_pubkey: Option<String>,
+}
+
+impl PublishNoteRequest {
+    /// Kind of the event to sign
+    pub fn event_kind(&self) -> u16 {
+        match self.encryption {
+            NoteEncryption::Nip44 { .. } => KIND_ENCRYPTED_NOTE,
+            NoteEncryption::None if self.long_form => KIND_LONG_FORM,
+            NoteEncryption::None => KIND_TEXT_NOTE,
+        }
+    }
+
+    /// Tags of the event to sign
+    ///
+    /// Private notes only carry the recipient `p` tag, a marker for the
+    /// encryption scheme and the reply tags; hashtags and the title would
+    /// leak what the note is about, so they stay out of the envelope. The
+    /// content itself must be NIP-44 encrypted to
+    /// [`NoteEncryption::recipient`] by the signer.
+    pub fn event_tags(&self, author_pubkey: &str) -> Vec<Vec<String>> {
+        if let Some(recipient) = self.encryption.recipient(author_pubkey) {
+            let mut tags = vec![
+                vec!["p".to_string(), recipient.to_string()],
+                vec!["encryption".to_string(), "nip44".to_string()],
+            ];
+            self.push_reply_tags(&mut tags);
+            return tags;
+        }
+        let mut tags: Vec<Vec<String>> = self
+            .tags
+            .iter()
+            .map(|t| vec!["t".to_string(), t.clone()])
+            .collect();
+        if let (true, Some(title)) = (self.long_form, &self.title) {
+            tags.push(vec!["title".to_string(), title.clone()]);
+        }
+        tags.extend(self.attachment.event_tags());
+        self.push_reply_tags(&mut tags);
+        tags
+    }
+
+    /// Append the NIP-10 marked `e` tags and the parent author's `p` tag
+    ///
+    /// A direct reply to the root only needs `root`. The `p` tag is skipped
+    /// when `tags` already has it, e.g. a private reply to the same author.
+    fn push_reply_tags(&self, tags: &mut Vec<Vec<String>>) {
+        if let Some(parent) = &self.reply_to {
+            let marker = |id: &str, marker: &str| {
+                vec![
+                    "e".to_string(),
+                    id.to_string(),
+                    String::new(),
+                    marker.to_string(),
+                ]
+            };
+            match self.reply_root.as_deref().filter(|root| *root != parent) {
+                Some(root) => {
+                    tags.push(marker(root, "root"));
+                    tags.push(marker(parent, "reply"));
+                }
+                None => tags.push(marker(parent, "root")),
+            }
+        }
+        if let Some(pubkey) = &self.reply_pubkey {
+            let tag = vec!["p".to_string(), pubkey.clone()];
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+
+    /// Make this note a reply to `parent`, threading it under the same root
+    pub fn set_reply_to(&mut self, parent: &CommunityNote) {
+        self.reply_to = Some(parent.id.clone());
+        self.reply_root = Some(parent.root_id.clone().unwrap_or_else(|| parent.id.clone()));
+        self.reply_pubkey = Some(parent.pubkey.clone());
+    }
+}
+
+/// Read `(root_id, reply_to)` from an event's NIP-10 `e` tags
+///
+/// Marked tags are preferred. Unmarked tags use the deprecated positional
+/// scheme: the first is the root and the last the direct parent.
+pub fn thread_ids(tags: &[Vec<String>]) -> (Option<String>, Option<String>) {
+    let e_tags: Vec<&Vec<String>> = tags
+        .iter()
+        .filter(|t| t.first().map(String::as_str) == Some("e") && t.len() >= 2)
+        .collect();
+    let marked = |marker: &str| {
+        e_tags
+            .iter()
+            .find(|t| t.get(3).map(String::as_str) == Some(marker))
+            .map(|t| t[1].clone())
+    };
+    let (root, reply) = (marked("root"), marked("reply"));
+    if root.is_some() || reply.is_some() {
+        let reply = reply.or_else(|| root.clone());
+        return (root, reply);
+    }
+    let root = e_tags.first().map(|t| t[1].clone());
+    let reply = e_tags.last().map(|t| t[1].clone());
+    (root, reply)
+}
+
+/// Request to publish a notebook as a long-form article (NIP-23)
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PublishNotebookRequest {
+    /// Notebook ID in storage
+    pub notebook_id: String,
+    /// Include query results as tables
+    #[serde(default)]
+    pub include_outputs: bool,
+    /// Article summary; defaults to the notebook description
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// How values in output tables are rendered
+    #[serde(default)]
+    pub format: ResultFormatOptions,
+}
+
+impl PublishNotebookRequest {
+    /// The article's `d` tag
+    ///
+    /// Derived from the notebook ID alone, so re-publishing the notebook
+    /// replaces the earlier article instead of adding another.
+    pub fn identifier(&self) -> String {
+        format!("fredco-notebook-{}", self.notebook_id)
+    }
+
+    /// Markdown content of the kind-30023 event
+    pub fn event_content(&self, notebook: &Notebook) -> String {
+        notebook.longform_markdown(self.include_outputs, &self.format)
+    }
+
+    /// Tags of the kind-30023 event
+    pub fn event_tags(&self, notebook: &Notebook) -> Vec<Vec<String>> {
+        let meta = &notebook.metadata;
+        let tag = |name: &str, value: &str| vec![name.to_string(), value.to_string()];
+        let mut tags = vec![
+            tag("d", &self.identifier()),
+            tag(
+                "title",
+                meta.title.as_deref().unwrap_or("Untitled notebook"),
+            ),
+        ];
+        if let Some(summary) = self.summary.as_ref().or(meta.description.as_ref()) {
+            tags.push(tag("summary", summary));
+        }
+        // NIP-23 timestamps are in seconds
+        tags.push(tag("published_at", &(meta.created_at / 1000).to_string()));
+        tags.extend(meta.tags.iter().map(|t| tag("t", t)));
+        tags
+    }
 }
 
 /// Result of publishing a note
@@ -75,12 +392,139 @@ pub struct PublishNoteResult {
     pub event_id: String,
     /// Author pubkey
     pub pubkey: String,
-    /// Relays published to
+    /// Relays that accepted the event
     pub relays: Vec<String>,
-    /// Note URL (for sharing)
+    /// Relays that failed or rejected the event, with the reason
+    #[serde(default)]
+    pub failed_relays: Vec<(String, String)>,
+    /// Problems that may make relays drop the event, e.g. exceeding a
+    /// relay's `max_message_length`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// `nostr:` URL for sharing, see [`PublishNoteResult::share_url`]
     pub url: String,
 }
 
+impl PublishNoteResult {
+    /// NIP-21 link to a published note
+    ///
+    /// Long-form notes with a `d` identifier are addressable, so they get an
+    /// `naddr` that keeps pointing at the latest edit; everything else gets
+    /// an `nevent` carrying author and relay hints.
+    pub fn share_url(
+        event_id: &str,
+        pubkey: &str,
+        kind: u16,
+        identifier: Option<&str>,
+        relays: &[String],
+    ) -> Result<String, Nip19Error> {
+        let entity = match identifier {
+            Some(identifier) if kind == KIND_LONG_FORM => {
+                nip19::encode_naddr(kind.into(), pubkey, identifier, relays)?
+            }
+            _ => nip19::encode_nevent(event_id, Some(pubkey), relays)?,
+        };
+        Ok(format!("nostr:{entity}"))
+    }
+}
+
+/// A signed Nostr event (NIP-01)
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct NostrEvent {
+    /// Hash of the event's content, see [`NostrEvent::compute_id`]
+    pub id: String,
+    /// Author pubkey (hex)
+    pub pubkey: String,
+    /// Unix time in seconds
+    pub created_at: u64,
+    pub kind: u16,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    /// Schnorr signature of `id`
+    pub sig: String,
+}
+
+impl NostrEvent {
+    /// SHA-256 of `[0, pubkey, created_at, kind, tags, content]`, which is
+    /// the id relays store the event under
+    pub fn compute_id(&self) -> String {
+        let serialized = json!([
+            0,
+            self.pubkey,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content
+        ])
+        .to_string();
+        nip19::bytes_to_hex(&Sha256::digest(serialized.as_bytes()))
+    }
+}
+
+/// A note signed locally and not yet sent to relays
+///
+/// The event id is final, so the UI can render the note right away and
+/// match it up once [`RelayPool::broadcast`] reports back.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PreparedNote {
+    pub event: NostrEvent,
+}
+
+impl PreparedNote {
+    /// The event's `d` tag, which makes long-form notes addressable
+    pub fn identifier(&self) -> Option<&str> {
+        self.event
+            .tags
+            .iter()
+            .find(|t| t.first().map(String::as_str) == Some("d"))
+            .and_then(|t| t.get(1))
+            .map(String::as_str)
+    }
+}
+
+/// Build and sign the event for `req` with a local key
+///
+/// Private notes need NIP-44 encryption, which happens in the signer, so
+/// they can't be prepared here.
+pub fn prepare_note(
+    req: &PublishNoteRequest,
+    nsec: &str,
+    now: Timestamp,
+) -> Result<PreparedNote, ErrorInfo> {
+    if req.encryption != NoteEncryption::None {
+        return Err(signing_error(
+            ErrorCode::AuthError,
+            "Private notes must be encrypted and signed by the signer",
+        ));
+    }
+    let secret = nip19::hex_to_bytes(&nip19::decode_nsec(nsec)?)?;
+    let key = k256::schnorr::SigningKey::from_bytes(&secret)
+        .map_err(|_| signing_error(ErrorCode::AuthError, "Invalid secret key"))?;
+    let pubkey = nip19::bytes_to_hex(&key.verifying_key().to_bytes());
+
+    let mut event = NostrEvent {
+        id: String::new(),
+        tags: req.event_tags(&pubkey),
+        pubkey,
+        created_at: now / 1000,
+        kind: req.event_kind(),
+        content: req.content.clone(),
+        sig: String::new(),
+    };
+    event.id = event.compute_id();
+    let digest = nip19::hex_to_bytes(&event.id)?;
+    let mut aux = [0u8; 32];
+    getrandom::getrandom(&mut aux)
+        .map_err(|e| signing_error(ErrorCode::Unknown, format!("No randomness: {e}")))?;
+    let sig = key
+        .sign_prehash_with_aux_rand(&digest, &aux)
+        .map_err(|e| signing_error(ErrorCode::Unknown, format!("Signing failed: {e}")))?;
+    event.sig = nip19::bytes_to_hex(&sig.to_bytes());
+    Ok(PreparedNote { event })
+}
+
 /// Request to fetch community notes
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -94,18 +538,66 @@ pub struct FetchNotesRequest {
     /// Filter by attachment type
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attachment_type: Option<String>,
+    /// Only notes attached to this dataset, notebook, cell or query
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment_filter: Option<NoteAttachment>,
     /// Maximum notes to fetch
     #[serde(default = "default_limit")]
     pub limit: u32,
     /// Fetch notes since timestamp
     #[serde(skip_serializing_if = "Option::is_none")]
     pub since: Option<Timestamp>,
+    /// Fetch notes up to and including this timestamp; pass the previous
+    /// page's `FetchNotesResult::next_cursor` to page backward
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<Timestamp>,
 }
 
 fn default_limit() -> u32 {
     50
 }
 
+impl FetchNotesRequest {
+    /// NIP-01 filter for the `REQ`, narrowed on relays by hashtag, author
+    /// and attachment label
+    pub fn filter(&self) -> Value {
+        let mut filter = json!({
+            "kinds": [KIND_TEXT_NOTE, KIND_LONG_FORM],
+            "limit": self.limit,
+        });
+        if let Some(hashtag) = &self.hashtag {
+            filter["#t"] = json!([hashtag]);
+        }
+        if let Some(author) = &self.author {
+            filter["authors"] = json!([author]);
+        }
+        if let Some(attachment) = &self.attachment_filter {
+            filter["#l"] = json!([attachment.label()]);
+        }
+        // Nostr timestamps are in seconds
+        if let Some(since) = self.since {
+            filter["since"] = json!(since / 1000);
+        }
+        if let Some(until) = self.until {
+            filter["until"] = json!(until / 1000);
+        }
+        filter
+    }
+
+    /// Whether a fetched note passes the attachment filter
+    ///
+    /// Relays don't all support tag queries, so results are checked again
+    /// here. With a filter set, notes without a readable attachment are
+    /// dropped.
+    pub fn matches(&self, note: &CommunityNote) -> bool {
+        match (&self.attachment_filter, &note.attachment) {
+            (None, _) => true,
+            (Some(filter), Some(attachment)) => filter.label() == attachment.label(),
+            (Some(_), None) => false,
+        }
+    }
+}
+
 /// A community note
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -122,7 +614,7 @@ pub struct CommunityNote {
     pub author_nip05: Option<String>,
     /// Whether author is verified
     pub author_verified: bool,
-    /// Note content
+    /// Note content, rendered from markdown to sanitized HTML
     pub content: String,
     /// Title (for long-form)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -140,6 +632,163 @@ pub struct CommunityNote {
     /// Reply count
     #[serde(default)]
     pub replies: u32,
+    /// Whether `content` is the decrypted text of a private note
+    #[serde(default)]
+    pub decrypted: bool,
+    /// Event ID of the note this replies to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Event ID of the thread root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_id: Option<String>,
+    /// Reactions broken down by content
+    #[serde(default)]
+    pub reaction_summary: ReactionSummary,
+}
+
+/// Tally of NIP-25 reactions to a note
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ReactionSummary {
+    /// `+` (or empty) reactions
+    pub likes: u32,
+    /// Every other reaction, e.g. emoji or `-`, by content
+    #[serde(default)]
+    pub others: HashMap<String, u32>,
+}
+
+impl ReactionSummary {
+    /// Count one reaction event's content
+    pub fn add(&mut self, content: &str) {
+        match content {
+            "" | "+" => self.likes += 1,
+            other => *self.others.entry(other.to_string()).or_default() += 1,
+        }
+    }
+
+    /// Total reactions of any kind
+    pub fn total(&self) -> u32 {
+        self.likes + self.others.values().sum::<u32>()
+    }
+}
+
+/// Request to react to a note
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ReactNoteRequest {
+    /// Event ID being reacted to
+    pub event_id: String,
+    /// Author of the note being reacted to
+    pub author_pubkey: String,
+    /// `+` for a like, `-` for a dislike, or an emoji
+    #[serde(default = "default_reaction")]
+    pub content: String,
+}
+
+fn default_reaction() -> String {
+    "+".to_string()
+}
+
+impl ReactNoteRequest {
+    /// Tags of the kind-7 event to sign
+    pub fn event_tags(&self) -> Vec<Vec<String>> {
+        vec![
+            vec!["e".to_string(), self.event_id.clone()],
+            vec!["p".to_string(), self.author_pubkey.clone()],
+        ]
+    }
+}
+
+/// Result of publishing a reaction
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ReactNoteResult {
+    /// ID of the reaction event
+    pub event_id: String,
+}
+
+impl CommunityNote {
+    /// Note for an event fetched from a relay, before profiles and
+    /// reactions are joined in
+    ///
+    /// Content is untrusted, so it is sanitized here; private notes show
+    /// [`ENCRYPTED_PLACEHOLDER`] until decrypted.
+    pub fn from_event(event: &NostrEvent) -> CommunityNote {
+        let tag_values = |name: &'static str| {
+            event
+                .tags
+                .iter()
+                .filter(move |t| t.first().map(String::as_str) == Some(name))
+                .filter_map(|t| t.get(1).cloned())
+        };
+        let content = if event.kind == KIND_ENCRYPTED_NOTE {
+            ENCRYPTED_PLACEHOLDER.to_string()
+        } else {
+            sanitize_note_content(&event.content)
+        };
+        let (root_id, reply_to) = thread_ids(&event.tags);
+        CommunityNote {
+            id: event.id.clone(),
+            pubkey: event.pubkey.clone(),
+            author_name: None,
+            author_nip05: None,
+            author_verified: false,
+            content,
+            title: tag_values("title").next(),
+            created_at: event.created_at * 1000,
+            tags: tag_values("t").collect(),
+            attachment: NoteAttachment::from_tags(&event.tags),
+            reactions: 0,
+            replies: 0,
+            decrypted: false,
+            reply_to,
+            root_id,
+            reaction_summary: ReactionSummary::default(),
+        }
+    }
+
+    /// Fill in a private note's content from a decryption attempt
+    ///
+    /// On failure the content becomes [`ENCRYPTED_PLACEHOLDER`] so ciphertext
+    /// is never shown as if it were text.
+    pub fn apply_decryption(&mut self, result: &DecryptNoteResult) {
+        match &result.plaintext {
+            Some(plaintext) => {
+                self.content = sanitize_note_content(plaintext);
+                self.decrypted = true;
+            }
+            None => {
+                self.content = ENCRYPTED_PLACEHOLDER.to_string();
+                self.decrypted = false;
+            }
+        }
+    }
+}
+
+/// Request to decrypt a private note
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DecryptNoteRequest {
+    /// Event ID of the note
+    pub event_id: String,
+    /// Pubkey of the note's author
+    pub sender_pubkey: String,
+    /// NIP-44 payload from the event content
+    pub ciphertext: String,
+}
+
+/// Result of decrypting a private note
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DecryptNoteResult {
+    /// Event ID of the note
+    pub event_id: String,
+    /// Decrypted content, if decryption succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plaintext: Option<String>,
+    /// Why decryption failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// Fetch notes result
@@ -150,6 +799,296 @@ pub struct FetchNotesResult {
     pub notes: Vec<CommunityNote>,
     /// Whether more notes are available
     pub has_more: bool,
+    /// Profiles of the notes' authors, by pubkey
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, NostrProfile>,
+    /// `FetchNotesRequest::until` for the next page: the second of the
+    /// oldest note's `created_at` (see [`NoteFeed`]), or `None` on the
+    /// last page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Timestamp>,
+}
+
+/// Pages backward through a feed, remembering which notes were shown
+///
+/// `until` is inclusive, so notes sharing the oldest timestamp of a page
+/// are fetched again with the next one rather than skipped; they are
+/// dropped here by event id. Relays filter by whole seconds, so when a
+/// whole page falls in one second the cursor steps back a second instead;
+/// notes in that second beyond the page's `limit` can't be reached.
+#[derive(Debug, Default)]
+pub struct NoteFeed {
+    seen: HashSet<String>,
+}
+
+impl NoteFeed {
+    /// Turn the notes relays returned for `req` into the next page
+    pub fn page(
+        &mut self,
+        req: &FetchNotesRequest,
+        mut notes: Vec<CommunityNote>,
+    ) -> FetchNotesResult {
+        let has_more = notes.len() >= req.limit as usize;
+        let second = |created_at: Timestamp| created_at / 1000 * 1000;
+        let next_cursor = notes
+            .iter()
+            .map(|n| second(n.created_at))
+            .min()
+            .filter(|_| has_more)
+            .map(|oldest| {
+                // Fetching up to `oldest` again would return this same page
+                if notes.iter().all(|n| second(n.created_at) == oldest) {
+                    oldest.saturating_sub(1000)
+                } else {
+                    oldest
+                }
+            });
+        notes.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        notes.retain(|note| req.matches(note) && self.seen.insert(note.id.clone()));
+        FetchNotesResult {
+            notes,
+            has_more,
+            profiles: HashMap::new(),
+            next_cursor,
+        }
+    }
+}
+
+/// Request to fetch the replies in a thread
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct FetchRepliesRequest {
+    /// Event ID of the note whose replies to fetch
+    pub event_id: String,
+    /// Maximum replies to fetch
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+/// Fetch replies result
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct FetchRepliesResult {
+    /// Event ID the replies belong to
+    pub event_id: String,
+    /// Replies, oldest first
+    pub notes: Vec<CommunityNote>,
+    /// Whether more replies are available
+    pub has_more: bool,
+}
+
+/// Request to zap a note's author (NIP-57)
+///
+/// Zapping takes three round trips: fetch [`ZapRequest::lnurl_endpoint`],
+/// check the response with [`LnurlPayInfo::parse`], then sign the kind-9734
+/// event and fetch [`LnurlPayInfo::invoice_url`] to get the invoice.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ZapRequest {
+    /// Event being zapped
+    pub event_id: String,
+    /// Author receiving the zap
+    pub author_pubkey: String,
+    /// Amount in satoshis
+    pub amount_sats: u64,
+    /// Message sent with the zap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Result of a zap request
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ZapResult {
+    /// BOLT-11 invoice to pay
+    pub invoice: String,
+}
+
+fn zap_error(code: ErrorCode, message: impl Into<String>) -> ErrorInfo {
+    ErrorInfo {
+        code,
+        message: message.into(),
+        details: None,
+    }
+}
+
+impl ZapRequest {
+    pub fn amount_msats(&self) -> u64 {
+        self.amount_sats.saturating_mul(1000)
+    }
+
+    /// LNURL-pay endpoint for the author's `lud16` lightning address
+    pub fn lnurl_endpoint(&self, profile: &NostrProfile) -> Result<String, ErrorInfo> {
+        let address = profile
+            .lud16
+            .as_deref()
+            .filter(|a| !a.is_empty())
+            .ok_or_else(|| zap_error(ErrorCode::NotFound, "Author has no lightning address"))?;
+        match address.split_once('@') {
+            Some((name, domain)) if !name.is_empty() && !domain.is_empty() => Ok(format!(
+                "https://{domain}/.well-known/lnurlp/{}",
+                name.to_lowercase()
+            )),
+            _ => Err(zap_error(
+                ErrorCode::NotFound,
+                format!("Invalid lightning address `{address}`"),
+            )),
+        }
+    }
+
+    /// Content of the kind-9734 event
+    pub fn event_content(&self) -> String {
+        self.comment.clone().unwrap_or_default()
+    }
+
+    /// Tags of the kind-9734 event; `relays` receive the zap receipt
+    pub fn event_tags(&self, relays: &[String]) -> Vec<Vec<String>> {
+        let mut relay_tag = vec!["relays".to_string()];
+        relay_tag.extend(relays.iter().cloned());
+        vec![
+            relay_tag,
+            vec!["amount".to_string(), self.amount_msats().to_string()],
+            vec!["p".to_string(), self.author_pubkey.clone()],
+            vec!["e".to_string(), self.event_id.clone()],
+        ]
+    }
+}
+
+/// The parts of an LNURL-pay response needed for zaps
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct LnurlPayInfo {
+    /// URL to request the invoice from
+    pub callback: String,
+    /// Smallest payable amount in millisatoshis
+    pub min_sendable: u64,
+    /// Largest payable amount in millisatoshis
+    pub max_sendable: u64,
+    /// Key that signs zap receipts
+    pub nostr_pubkey: String,
+}
+
+impl LnurlPayInfo {
+    /// Read an LNURL-pay response, checking it accepts this zap
+    pub fn parse(body: &str, zap: &ZapRequest) -> Result<LnurlPayInfo, ErrorInfo> {
+        let json: serde_json::Value = serde_json::from_str(body).map_err(|e| {
+            zap_error(
+                ErrorCode::ParseError,
+                format!("Invalid LNURL response: {e}"),
+            )
+        })?;
+        if json.get("status").and_then(|s| s.as_str()) == Some("ERROR") {
+            let reason = json
+                .get("reason")
+                .and_then(|r| r.as_str())
+                .unwrap_or("unknown");
+            return Err(zap_error(
+                ErrorCode::NetworkError,
+                format!("LNURL error: {reason}"),
+            ));
+        }
+        let nostr_pubkey = json
+            .get("nostrPubkey")
+            .and_then(|k| k.as_str())
+            .filter(|_| json.get("allowsNostr").and_then(|a| a.as_bool()) == Some(true))
+            .ok_or_else(|| {
+                zap_error(
+                    ErrorCode::NotFound,
+                    "Lightning address does not support zaps",
+                )
+            })?;
+        let field = |name: &str| json.get(name).and_then(|v| v.as_u64());
+        let (Some(callback), Some(min_sendable), Some(max_sendable)) = (
+            json.get("callback").and_then(|c| c.as_str()),
+            field("minSendable"),
+            field("maxSendable"),
+        ) else {
+            return Err(zap_error(
+                ErrorCode::ParseError,
+                "LNURL response is missing callback or limits",
+            ));
+        };
+        let amount = zap.amount_msats();
+        if amount < min_sendable || amount > max_sendable {
+            return Err(zap_error(
+                ErrorCode::LimitExceeded,
+                format!(
+                    "Amount must be between {} and {} sats",
+                    min_sendable.div_ceil(1000),
+                    max_sendable / 1000
+                ),
+            ));
+        }
+        Ok(LnurlPayInfo {
+            callback: callback.to_string(),
+            min_sendable,
+            max_sendable,
+            nostr_pubkey: nostr_pubkey.to_string(),
+        })
+    }
+
+    /// Invoice URL carrying the signed zap request event as JSON
+    pub fn invoice_url(&self, zap: &ZapRequest, signed_event_json: &str) -> String {
+        let separator = if self.callback.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        format!(
+            "{}{separator}amount={}&nostr={}",
+            self.callback,
+            zap.amount_msats(),
+            percent_encode(signed_event_json)
+        )
+    }
+}
+
+impl ZapResult {
+    /// Read the invoice from the callback's response
+    pub fn parse(body: &str) -> Result<ZapResult, ErrorInfo> {
+        let json: serde_json::Value = serde_json::from_str(body).map_err(|e| {
+            zap_error(
+                ErrorCode::ParseError,
+                format!("Invalid invoice response: {e}"),
+            )
+        })?;
+        json.get("pr")
+            .and_then(|pr| pr.as_str())
+            .map(|pr| ZapResult {
+                invoice: pr.to_string(),
+            })
+            .ok_or_else(|| zap_error(ErrorCode::ParseError, "Invoice response has no `pr`"))
+    }
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// Whether `domain` is a DNS hostname: dot-separated labels of ASCII
+/// letters, digits and inner hyphens
+fn is_hostname(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
 }
 
 /// NIP-05 verification request
@@ -173,6 +1112,249 @@ pub struct VerifyNip05Result {
     pub relays: Vec<String>,
 }
 
+impl VerifyNip05Request {
+    /// Split `name@domain` into a lowercase name and the domain
+    ///
+    /// The domain follows the last `@` and must be a hostname, so nothing
+    /// in the identifier can redirect the request to another host. A bare
+    /// domain stands for `_@domain`.
+    fn parts(&self) -> Option<(String, &str)> {
+        let (name, domain) = self
+            .identifier
+            .rsplit_once('@')
+            .unwrap_or(("_", &self.identifier));
+        if name.is_empty() || !is_hostname(domain) {
+            return None;
+        }
+        Some((name.to_lowercase(), domain))
+    }
+
+    /// The `.well-known/nostr.json` URL to check, if the identifier is valid
+    pub fn url(&self) -> Option<String> {
+        let (name, domain) = self.parts()?;
+        Some(format!(
+            "https://{domain}/.well-known/nostr.json?name={}",
+            percent_encode(&name)
+        ))
+    }
+
+    /// Check a `nostr.json` response against the expected pubkey
+    ///
+    /// A failed request or malformed body means "not verified", not an error:
+    /// a misconfigured domain shouldn't break the feed.
+    pub fn check_response(&self, response: &Nip05Response) -> VerifyNip05Result {
+        let unverified = VerifyNip05Result {
+            verified: false,
+            relays: Vec::new(),
+        };
+        if !(200..300).contains(&response.status) {
+            return unverified;
+        }
+        let (Some((name, _)), Ok(json)) = (
+            self.parts(),
+            serde_json::from_str::<serde_json::Value>(&response.body),
+        ) else {
+            return unverified;
+        };
+        let verified = json
+            .get("names")
+            .and_then(|names| names.get(&name))
+            .and_then(|pubkey| pubkey.as_str())
+            .is_some_and(|pubkey| pubkey.eq_ignore_ascii_case(&self.pubkey));
+        if !verified {
+            return unverified;
+        }
+        let relays = json
+            .get("relays")
+            .and_then(|relays| relays.get(self.pubkey.to_lowercase()))
+            .and_then(|relays| relays.as_array())
+            .map(|relays| {
+                relays
+                    .iter()
+                    .filter_map(|r| r.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        VerifyNip05Result {
+            verified: true,
+            relays,
+        }
+    }
+}
+
+/// HTTP response for a `nostr.json` fetch
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Nip05Response {
+    /// HTTP status; 0 for network failures
+    pub status: u16,
+    /// Response body
+    #[serde(default)]
+    pub body: String,
+}
+
+/// Request to verify many NIP-05 identifiers at once
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct BatchVerifyNip05Request {
+    /// Identifiers with their expected pubkeys
+    pub identifiers: Vec<VerifyNip05Request>,
+}
+
+impl From<VerifyNip05Request> for BatchVerifyNip05Request {
+    fn from(request: VerifyNip05Request) -> Self {
+        Self {
+            identifiers: vec![request],
+        }
+    }
+}
+
+/// Outcome for one identifier in a batch
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Nip05Verification {
+    /// NIP-05 identifier
+    pub identifier: String,
+    /// Expected pubkey
+    pub pubkey: String,
+    /// Verification outcome
+    pub result: VerifyNip05Result,
+    /// When the outcome was determined
+    pub checked_at: Timestamp,
+}
+
+/// Batch NIP-05 verification result, in request order
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct BatchVerifyNip05Result {
+    /// One entry per requested identifier
+    pub results: Vec<Nip05Verification>,
+}
+
+impl BatchVerifyNip05Result {
+    /// The single outcome of a one-identifier batch
+    pub fn into_single(self) -> Option<VerifyNip05Result> {
+        self.results.into_iter().next().map(|v| v.result)
+    }
+}
+
+/// Recent NIP-05 outcomes, so feeds don't refetch `nostr.json` per note
+///
+/// A batch is verified in two steps: fetch every URL from
+/// [`Nip05Cache::pending_urls`] concurrently, then hand the responses to
+/// [`Nip05Cache::resolve`]. Single verifications go through the same path
+/// as a batch of one.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Nip05Cache {
+    /// How long an outcome stays fresh, in milliseconds
+    pub ttl_ms: u64,
+    /// Outcomes by identifier
+    entries: HashMap<String, Nip05Verification>,
+}
+
+impl Default for Nip05Cache {
+    fn default() -> Self {
+        // One hour
+        Self::new(60 * 60 * 1000)
+    }
+}
+
+impl Nip05Cache {
+    pub fn new(ttl_ms: u64) -> Self {
+        Self {
+            ttl_ms,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// A fresh cached outcome for this identifier and pubkey
+    pub fn get(&self, request: &VerifyNip05Request, now: Timestamp) -> Option<VerifyNip05Result> {
+        self.entries
+            .get(&request.identifier)
+            .filter(|entry| entry.pubkey.eq_ignore_ascii_case(&request.pubkey))
+            .filter(|entry| now.saturating_sub(entry.checked_at) < self.ttl_ms)
+            .map(|entry| entry.result.clone())
+    }
+
+    /// URLs a batch still needs fetched, deduplicated
+    pub fn pending_urls(&self, batch: &BatchVerifyNip05Request, now: Timestamp) -> Vec<String> {
+        let mut urls: Vec<String> = Vec::new();
+        for request in &batch.identifiers {
+            if self.get(request, now).is_some() {
+                continue;
+            }
+            if let Some(url) = request.url() {
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        }
+        urls
+    }
+
+    /// Outcomes for a batch from the cache and freshly fetched responses
+    ///
+    /// `responses` maps URLs from [`Nip05Cache::pending_urls`] to what the
+    /// fetch returned. Identifiers with no response or an invalid format are
+    /// reported unverified; new outcomes are cached.
+    pub fn resolve(
+        &mut self,
+        batch: &BatchVerifyNip05Request,
+        responses: &HashMap<String, Nip05Response>,
+        now: Timestamp,
+    ) -> BatchVerifyNip05Result {
+        let results = batch
+            .identifiers
+            .iter()
+            .map(|request| {
+                if let Some(result) = self.get(request, now) {
+                    let checked_at = self.entries[&request.identifier].checked_at;
+                    return Nip05Verification {
+                        identifier: request.identifier.clone(),
+                        pubkey: request.pubkey.clone(),
+                        result,
+                        checked_at,
+                    };
+                }
+                let response = request.url().and_then(|url| responses.get(&url));
+                let result = match response {
+                    Some(response) => request.check_response(response),
+                    None => VerifyNip05Result {
+                        verified: false,
+                        relays: Vec::new(),
+                    },
+                };
+                let verification = Nip05Verification {
+                    identifier: request.identifier.clone(),
+                    pubkey: request.pubkey.clone(),
+                    result,
+                    checked_at: now,
+                };
+                // Don't cache a miss that only means we never fetched it
+                if response.is_some() {
+                    self.entries
+                        .insert(request.identifier.clone(), verification.clone());
+                }
+                verification
+            })
+            .collect();
+        BatchVerifyNip05Result { results }
+    }
+
+    /// Verify one identifier through the batch path
+    pub fn resolve_single(
+        &mut self,
+        request: VerifyNip05Request,
+        responses: &HashMap<String, Nip05Response>,
+        now: Timestamp,
+    ) -> VerifyNip05Result {
+        self.resolve(&request.into(), responses, now)
+            .into_single()
+            .expect("batch of one yields one result")
+    }
+}
+
 /// Nostr relay configuration
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -224,6 +1406,318 @@ impl Default for NostrConfig {
     }
 }
 
+/// `Accept` header value for fetching a relay information document
+pub const NIP11_ACCEPT: &str = "application/nostr+json";
+
+/// A relay's information document (NIP-11)
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RelayInfo {
+    /// Relay name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// NIPs the relay implements
+    #[serde(default)]
+    pub supported_nips: Vec<u32>,
+    /// Limits the relay enforces
+    #[serde(default)]
+    pub limitation: RelayLimitation,
+    /// Whether writing requires payment
+    #[serde(default)]
+    pub payment_required: bool,
+}
+
+/// Server limits from a NIP-11 document; `None` where not advertised
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RelayLimitation {
+    /// Largest websocket message the relay accepts, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_message_length: Option<u64>,
+    /// Most subscriptions one connection may hold open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_subscriptions: Option<u32>,
+}
+
+impl RelayInfo {
+    /// Parse a NIP-11 document
+    ///
+    /// Unknown fields are ignored. `payment_required` is read from
+    /// `limitation`, where NIP-11 puts it.
+    pub fn parse(body: &str) -> Result<RelayInfo, ErrorInfo> {
+        let json: Value = serde_json::from_str(body).map_err(|e| ErrorInfo {
+            code: ErrorCode::ParseError,
+            message: "Invalid relay information document".to_string(),
+            details: Some(e.to_string()),
+        })?;
+        let limitation = &json["limitation"];
+        Ok(RelayInfo {
+            name: json["name"].as_str().map(str::to_string),
+            supported_nips: json["supported_nips"]
+                .as_array()
+                .map(|nips| {
+                    nips.iter()
+                        .filter_map(|n| n.as_u64().and_then(|n| u32::try_from(n).ok()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            limitation: RelayLimitation {
+                max_message_length: limitation["max_message_length"].as_u64(),
+                max_subscriptions: limitation["max_subscriptions"]
+                    .as_u64()
+                    .and_then(|n| u32::try_from(n).ok()),
+            },
+            payment_required: limitation["payment_required"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// Request to fetch a relay's NIP-11 information document
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct FetchRelayInfoRequest {
+    /// Relay websocket URL
+    pub url: String,
+}
+
+impl FetchRelayInfoRequest {
+    /// The HTTP URL serving the document, fetched with `Accept:`
+    /// [`NIP11_ACCEPT`]; `None` unless `url` is a websocket URL
+    pub fn http_url(&self) -> Option<String> {
+        if let Some(rest) = self.url.strip_prefix("wss://") {
+            Some(format!("https://{rest}"))
+        } else {
+            self.url
+                .strip_prefix("ws://")
+                .map(|rest| format!("http://{rest}"))
+        }
+    }
+}
+
+/// Fetch relay info result
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct FetchRelayInfoResult {
+    /// Relay websocket URL
+    pub url: String,
+    /// The relay's information document
+    pub info: RelayInfo,
+}
+
+/// Connection to Nostr relays, e.g. browser WebSockets opened from JS
+pub trait RelayTransport {
+    /// Send a text frame to `relay` and wait for the reply to it
+    fn request(&self, relay: &str, message: String)
+        -> impl Future<Output = Result<String, String>>;
+    /// Send a text frame to `relay` without waiting for a reply
+    fn send(&self, relay: &str, message: String) -> Result<(), String>;
+}
+
+/// Per-relay results of publishing one event
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PublishOutcome {
+    pub event_id: String,
+    /// Relays that answered `OK` with `true`
+    pub accepted: Vec<String>,
+    /// Relays that rejected the event or couldn't be reached, with the reason
+    pub failed: Vec<(String, String)>,
+    /// Problems noticed before sending, see [`PublishNoteResult::warnings`]
+    pub warnings: Vec<String>,
+}
+
+impl PublishOutcome {
+    /// Result to report to the UI, sharing only relays that hold the event
+    pub fn into_result(
+        self,
+        pubkey: &str,
+        kind: u16,
+        identifier: Option<&str>,
+    ) -> Result<PublishNoteResult, Nip19Error> {
+        let url =
+            PublishNoteResult::share_url(&self.event_id, pubkey, kind, identifier, &self.accepted)?;
+        Ok(PublishNoteResult {
+            event_id: self.event_id,
+            pubkey: pubkey.to_string(),
+            relays: self.accepted,
+            failed_relays: self.failed,
+            warnings: self.warnings,
+            url,
+        })
+    }
+}
+
+/// A relay URL with its scheme and host lowercased and trailing slashes
+/// dropped, so `wss://Nos.lol/` and `wss://nos.lol` name the same relay
+fn relay_key(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let authority_end = url
+        .find("://")
+        .map(|scheme_end| scheme_end + 3)
+        .and_then(|start| url[start..].find('/').map(|end| start + end))
+        .unwrap_or(url.len());
+    format!(
+        "{}{}",
+        url[..authority_end].to_ascii_lowercase(),
+        &url[authority_end..]
+    )
+}
+
+/// An open `REQ` subscription; close it with [`RelayPool::unsubscribe`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriptionHandle {
+    pub id: String,
+    /// Relays the subscription was sent to
+    pub relays: Vec<String>,
+}
+
+/// The configured relays, reached through a [`RelayTransport`]
+pub struct RelayPool<T> {
+    relays: Vec<RelayConfig>,
+    transport: T,
+    next_subscription: Cell<u32>,
+    /// NIP-11 documents fetched for the relays, keyed by [`relay_key`]
+    relay_info: RefCell<HashMap<String, RelayInfo>>,
+}
+
+impl<T: RelayTransport> RelayPool<T> {
+    pub fn new(relays: Vec<RelayConfig>, transport: T) -> Self {
+        Self {
+            relays,
+            transport,
+            next_subscription: Cell::new(0),
+            relay_info: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Remember a relay's NIP-11 document, so publishing can check its
+    /// limits
+    pub fn set_relay_info(&self, url: &str, info: RelayInfo) {
+        self.relay_info.borrow_mut().insert(relay_key(url), info);
+    }
+
+    /// Send a signed event to every write relay and collect their `OK`s
+    ///
+    /// The event still goes to relays whose known `max_message_length` it
+    /// exceeds, but the outcome carries a warning for each.
+    pub async fn publish(&self, event: &Value) -> PublishOutcome {
+        let event_id = event["id"].as_str().unwrap_or_default().to_string();
+        let message = json!(["EVENT", event]).to_string();
+        let relays: Vec<&str> = self
+            .relays
+            .iter()
+            .filter(|r| r.write)
+            .map(|r| r.url.as_str())
+            .collect();
+        let replies = join_all(
+            relays
+                .iter()
+                .map(|relay| self.transport.request(relay, message.clone())),
+        )
+        .await;
+
+        let mut outcome = PublishOutcome {
+            event_id,
+            ..Default::default()
+        };
+        outcome.warnings = self.size_warnings(&relays, message.len());
+        for (relay, reply) in relays.into_iter().zip(replies) {
+            match reply.and_then(|reply| publish_reply(&reply, &outcome.event_id)) {
+                Ok(()) => outcome.accepted.push(relay.to_string()),
+                Err(reason) => outcome.failed.push((relay.to_string(), reason)),
+            }
+        }
+        outcome
+    }
+
+    /// Warnings for `relays` whose `max_message_length` is below `len`
+    fn size_warnings(&self, relays: &[&str], len: usize) -> Vec<String> {
+        let info = self.relay_info.borrow();
+        relays
+            .iter()
+            .filter_map(|relay| {
+                let max = info.get(&relay_key(relay))?.limitation.max_message_length?;
+                (len as u64 > max).then(|| {
+                    let warning = format!(
+                        "{} accepts messages up to {} bytes; this one is {}",
+                        relay, max, len
+                    );
+                    log::warn!("{}", warning);
+                    warning
+                })
+            })
+            .collect()
+    }
+
+    /// Publish a [`prepare_note`]d event, reporting it under its
+    /// precomputed id
+    pub async fn broadcast(&self, prepared: PreparedNote) -> Result<PublishNoteResult, ErrorInfo> {
+        let outcome = self.publish(&serde_json::to_value(&prepared.event)?).await;
+        let result = outcome.into_result(
+            &prepared.event.pubkey,
+            prepared.event.kind,
+            prepared.identifier(),
+        )?;
+        Ok(result)
+    }
+
+    /// Send a `REQ` for `filter` to every read relay
+    ///
+    /// Matching events arrive through the transport; relays that couldn't
+    /// be sent to are left out of the handle.
+    pub fn subscribe(&self, filter: &Value) -> SubscriptionHandle {
+        let n = self.next_subscription.get();
+        self.next_subscription.set(n.wrapping_add(1));
+        let id = format!("sub-{}", n);
+        let message = json!(["REQ", id, filter]).to_string();
+        let relays = self
+            .relays
+            .iter()
+            .filter(|r| r.read)
+            .filter(|r| match self.transport.send(&r.url, message.clone()) {
+                Ok(()) => true,
+                Err(reason) => {
+                    log::warn!("subscribe to {} failed: {}", r.url, reason);
+                    false
+                }
+            })
+            .map(|r| r.url.clone())
+            .collect();
+        SubscriptionHandle { id, relays }
+    }
+
+    /// Send `CLOSE` for a subscription to the relays it was sent to
+    pub fn unsubscribe(&self, handle: &SubscriptionHandle) {
+        let message = json!(["CLOSE", handle.id]).to_string();
+        for relay in &handle.relays {
+            self.transport.send(relay, message.clone()).ok();
+        }
+    }
+}
+
+/// Check a relay's reply to `["EVENT", ...]`, returning its reason on rejection
+fn publish_reply(reply: &str, event_id: &str) -> Result<(), String> {
+    let value: Value =
+        serde_json::from_str(reply).map_err(|_| format!("invalid reply: {}", reply))?;
+    match value.as_array().map(Vec::as_slice) {
+        Some([kind, id, accepted, rest @ ..]) if kind == "OK" && id == event_id => {
+            if accepted.as_bool() == Some(true) {
+                Ok(())
+            } else {
+                let reason = rest.first().and_then(Value::as_str).unwrap_or_default();
+                Err(if reason.is_empty() {
+                    "rejected".to_string()
+                } else {
+                    reason.to_string()
+                })
+            }
+        }
+        Some([kind, message]) if kind == "NOTICE" => {
+            Err(message.as_str().unwrap_or_default().to_string())
+        }
+        _ => Err(format!("unexpected reply: {}", reply)),
+    }
+}
+
 /// User profile from Nostr
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -244,6 +1738,73 @@ pub struct NostrProfile {
     pub nip05: Option<String>,
     /// Whether NIP-05 is verified
     pub nip05_verified: bool,
+    /// Lightning address for zaps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lud16: Option<String>,
+}
+
+/// A profile with the time it was fetched
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CachedProfile {
+    /// The kind-0 profile
+    pub profile: NostrProfile,
+    /// When the profile was fetched
+    pub fetched_at: Timestamp,
+}
+
+/// Profiles by pubkey, refetched once older than the TTL
+///
+/// Callers pass the current time so the cache works the same in tests and
+/// in the browser.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ProfileCache {
+    /// How long an entry stays fresh, in milliseconds
+    pub ttl_ms: u64,
+    entries: HashMap<String, CachedProfile>,
+}
+
+impl Default for ProfileCache {
+    fn default() -> Self {
+        // One hour
+        Self::new(60 * 60 * 1000)
+    }
+}
+
+impl ProfileCache {
+    pub fn new(ttl_ms: u64) -> Self {
+        Self {
+            ttl_ms,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The cached profile for `pubkey`, unless missing or expired
+    pub fn get(&self, pubkey: &str, now: Timestamp) -> Option<NostrProfile> {
+        self.entries
+            .get(pubkey)
+            .filter(|entry| now.saturating_sub(entry.fetched_at) < self.ttl_ms)
+            .map(|entry| entry.profile.clone())
+    }
+
+    /// Store a freshly fetched profile
+    pub fn insert(&mut self, profile: NostrProfile, now: Timestamp) {
+        self.entries.insert(
+            profile.pubkey.clone(),
+            CachedProfile {
+                profile,
+                fetched_at: now,
+            },
+        );
+    }
+
+    /// Drop expired entries
+    pub fn prune(&mut self, now: Timestamp) {
+        let ttl = self.ttl_ms;
+        self.entries
+            .retain(|_, entry| now.saturating_sub(entry.fetched_at) < ttl);
+    }
 }
 
 /// Current authentication state
@@ -267,3 +1828,677 @@ pub enum AuthState {
     #[serde(rename = "error")]
     Error { message: String },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(pubkey: &str) -> NostrProfile {
+        NostrProfile {
+            pubkey: pubkey.to_string(),
+            name: Some("alice".to_string()),
+            about: None,
+            picture: None,
+            nip05: None,
+            nip05_verified: false,
+            lud16: None,
+        }
+    }
+
+    #[test]
+    fn ephemeral_keys_are_distinct() {
+        let a = EphemeralKeyInfo::generate().unwrap();
+        let b = EphemeralKeyInfo::generate().unwrap();
+        assert_ne!(a.nsec, b.nsec);
+        assert_ne!(a.npub, b.npub);
+        assert!(nip19::decode_npub(&a.npub).is_ok());
+        assert!(nip19::decode_nsec(&a.nsec).is_ok());
+    }
+
+    #[test]
+    fn expired_profile_is_not_returned() {
+        let mut cache = ProfileCache::new(1000);
+        cache.insert(profile("abc"), 5000);
+
+        assert_eq!(
+            cache.get("abc", 5999).and_then(|p| p.name).as_deref(),
+            Some("alice")
+        );
+        assert!(cache.get("abc", 6000).is_none());
+        assert!(cache.get("other", 5000).is_none());
+
+        cache.prune(6000);
+        assert!(cache.get("abc", 5000).is_none());
+    }
+
+    /// Relays that answer `EVENT`s from a fixed table of replies
+    struct MockRelays {
+        replies: HashMap<&'static str, String>,
+        sent: RefCell<Vec<(String, String)>>,
+    }
+
+    impl RelayTransport for MockRelays {
+        async fn request(&self, relay: &str, message: String) -> Result<String, String> {
+            self.sent.borrow_mut().push((relay.to_string(), message));
+            match self.replies.get(relay) {
+                Some(reply) => Ok(reply.clone()),
+                None => Err("connection refused".to_string()),
+            }
+        }
+
+        fn send(&self, relay: &str, message: String) -> Result<(), String> {
+            self.sent.borrow_mut().push((relay.to_string(), message));
+            Ok(())
+        }
+    }
+
+    fn relay(url: &str, read: bool, write: bool) -> RelayConfig {
+        RelayConfig {
+            url: url.to_string(),
+            read,
+            write,
+        }
+    }
+
+    #[test]
+    fn publish_reports_accepting_and_rejecting_relays() {
+        let id = "e".repeat(64);
+        let transport = MockRelays {
+            replies: HashMap::from([
+                (
+                    "wss://accept.example",
+                    json!(["OK", id, true, ""]).to_string(),
+                ),
+                (
+                    "wss://reject.example",
+                    json!(["OK", id, false, "blocked: not on allowlist"]).to_string(),
+                ),
+            ]),
+            sent: RefCell::new(Vec::new()),
+        };
+        let pool = RelayPool::new(
+            vec![
+                relay("wss://accept.example", true, true),
+                relay("wss://reject.example", true, true),
+                relay("wss://read-only.example", true, false),
+            ],
+            transport,
+        );
+        let event = json!({"id": id, "kind": 1, "content": "Budget question"});
+
+        let outcome = futures::executor::block_on(pool.publish(&event));
+        assert_eq!(outcome.accepted, vec!["wss://accept.example"]);
+        assert_eq!(
+            outcome.failed,
+            vec![(
+                "wss://reject.example".to_string(),
+                "blocked: not on allowlist".to_string()
+            )]
+        );
+        assert_eq!(pool.transport.sent.borrow().len(), 2);
+
+        let pubkey = "a".repeat(64);
+        let result = outcome.into_result(&pubkey, KIND_TEXT_NOTE, None).unwrap();
+        assert_eq!(result.relays, vec!["wss://accept.example"]);
+        assert_eq!(result.failed_relays.len(), 1);
+    }
+
+    #[test]
+    fn parses_nip11_document() {
+        let body = r#"{
+            "name": "nos.lol",
+            "description": "Generally accepted notes",
+            "pubkey": "4c800257a588a82849d049817c2bdaad984b25a45ad9f6dad66e47d3b47e3b2f",
+            "supported_nips": [1, 2, 4, 9, 11, 22, 28, 40, 70, 77],
+            "software": "git+https://github.com/hoytech/strfry.git",
+            "version": "1.0.4",
+            "limitation": {
+                "max_message_length": 131072,
+                "max_subscriptions": 20,
+                "max_filters": 100,
+                "auth_required": false,
+                "payment_required": true
+            }
+        }"#;
+        let info = RelayInfo::parse(body).unwrap();
+        assert_eq!(info.name.as_deref(), Some("nos.lol"));
+        assert_eq!(
+            info.supported_nips,
+            vec![1, 2, 4, 9, 11, 22, 28, 40, 70, 77]
+        );
+        assert_eq!(info.limitation.max_message_length, Some(131072));
+        assert_eq!(info.limitation.max_subscriptions, Some(20));
+        assert!(info.payment_required);
+
+        assert_eq!(RelayInfo::parse("{}").unwrap(), RelayInfo::default());
+        assert_eq!(
+            RelayInfo::parse("<html>").unwrap_err().code,
+            ErrorCode::ParseError
+        );
+
+        let request = FetchRelayInfoRequest {
+            url: "wss://nos.lol/".to_string(),
+        };
+        assert_eq!(request.http_url().as_deref(), Some("https://nos.lol/"));
+        let request = FetchRelayInfoRequest {
+            url: "https://nos.lol".to_string(),
+        };
+        assert!(request.http_url().is_none());
+    }
+
+    #[test]
+    fn publish_warns_about_oversized_notes() {
+        let id = "e".repeat(64);
+        let pool = RelayPool::new(
+            vec![
+                relay("wss://small.example", true, true),
+                relay("wss://large.example", true, true),
+            ],
+            MockRelays {
+                replies: HashMap::from([
+                    (
+                        "wss://small.example",
+                        json!(["OK", id, true, ""]).to_string(),
+                    ),
+                    (
+                        "wss://large.example",
+                        json!(["OK", id, true, ""]).to_string(),
+                    ),
+                ]),
+                sent: RefCell::new(Vec::new()),
+            },
+        );
+        let limited = |max| RelayInfo {
+            limitation: RelayLimitation {
+                max_message_length: Some(max),
+                max_subscriptions: None,
+            },
+            ..RelayInfo::default()
+        };
+        // Info may be fetched under a differently written URL
+        pool.set_relay_info("wss://Small.example/", limited(64));
+        pool.set_relay_info("wss://large.example", limited(65536));
+        let event = json!({"id": id, "kind": 1, "content": "x".repeat(200)});
+
+        let outcome = futures::executor::block_on(pool.publish(&event));
+        assert_eq!(outcome.accepted.len(), 2);
+        assert_eq!(outcome.warnings.len(), 1);
+        assert!(
+            outcome.warnings[0].starts_with("wss://small.example accepts messages up to 64 bytes")
+        );
+    }
+
+    #[test]
+    fn subscribe_sends_req_to_read_relays() {
+        let pool = RelayPool::new(
+            vec![
+                relay("wss://a.example", true, true),
+                relay("wss://write-only.example", false, true),
+            ],
+            MockRelays {
+                replies: HashMap::new(),
+                sent: RefCell::new(Vec::new()),
+            },
+        );
+        let handle = pool.subscribe(&json!({"kinds": [1], "#t": ["fredco-data"]}));
+        assert_eq!(handle.relays, vec!["wss://a.example"]);
+        pool.unsubscribe(&handle);
+
+        let sent = pool.transport.sent.borrow();
+        assert_eq!(
+            sent[0].1,
+            r##"["REQ","sub-0",{"#t":["fredco-data"],"kinds":[1]}]"##
+        );
+        assert_eq!(sent[1].1, r#"["CLOSE","sub-0"]"#);
+    }
+
+    #[test]
+    fn prepared_id_matches_broadcast_id() {
+        let key = EphemeralKeyInfo::generate().unwrap();
+        let req = PublishNoteRequest {
+            content: "Why did \"transportation\" spending jump in FY24?\n".to_string(),
+            title: None,
+            attachment: NoteAttachment::General,
+            tags: vec!["fredco-data".to_string()],
+            long_form: false,
+            encryption: NoteEncryption::None,
+            reply_to: None,
+            reply_root: None,
+            reply_pubkey: None,
+        };
+        let prepared = prepare_note(&req, &key.nsec, 1_700_000_000_123).unwrap();
+        assert_eq!(prepared.event.created_at, 1_700_000_000);
+        assert_eq!(prepared.event.id, prepared.event.compute_id());
+
+        let id = prepared.event.id.clone();
+        let pool = RelayPool::new(
+            vec![relay("wss://accept.example", true, true)],
+            MockRelays {
+                replies: HashMap::from([(
+                    "wss://accept.example",
+                    json!(["OK", id, true, ""]).to_string(),
+                )]),
+                sent: RefCell::new(Vec::new()),
+            },
+        );
+        let result = futures::executor::block_on(pool.broadcast(prepared.clone())).unwrap();
+        assert_eq!(result.event_id, id);
+        assert_eq!(result.relays, vec!["wss://accept.example"]);
+
+        // What the relay received hashes to the same id and verifies
+        let sent: Value = serde_json::from_str(&pool.transport.sent.borrow()[0].1).unwrap();
+        let stored: NostrEvent = serde_json::from_value(sent[1].clone()).unwrap();
+        assert_eq!(stored.compute_id(), id);
+        let pubkey = nip19::hex_to_bytes(&stored.pubkey).unwrap();
+        let sig = (0..64)
+            .map(|i| u8::from_str_radix(&stored.sig[i * 2..i * 2 + 2], 16).unwrap())
+            .collect::<Vec<_>>();
+        let verifying = k256::schnorr::VerifyingKey::from_bytes(&pubkey).unwrap();
+        let sig = k256::schnorr::Signature::try_from(sig.as_slice()).unwrap();
+        assert!(verifying
+            .verify_raw(&nip19::hex_to_bytes(&id).unwrap(), &sig)
+            .is_ok());
+    }
+
+    fn community_note(id: &str, attachment: Option<NoteAttachment>) -> CommunityNote {
+        CommunityNote {
+            id: id.to_string(),
+            pubkey: "a".repeat(64),
+            author_name: None,
+            author_nip05: None,
+            author_verified: false,
+            content: String::new(),
+            title: None,
+            created_at: 1_700_000_000_000,
+            tags: Vec::new(),
+            attachment,
+            reactions: 0,
+            replies: 0,
+            decrypted: false,
+            reply_to: None,
+            root_id: None,
+            reaction_summary: ReactionSummary::default(),
+        }
+    }
+
+    #[test]
+    fn dataset_filter_excludes_general_notes() {
+        let parcels = NoteAttachment::Dataset {
+            name: "parcels".to_string(),
+        };
+        let req: FetchNotesRequest = serde_json::from_value(json!({
+            "attachment_filter": {"type": "dataset", "name": "parcels"}
+        }))
+        .unwrap();
+        assert_eq!(req.filter()["#l"], json!(["dataset:parcels"]));
+
+        let tags = parcels.event_tags();
+        let about_parcels = community_note("1", NoteAttachment::from_tags(&tags));
+        let general = community_note("2", Some(NoteAttachment::General));
+        let other = community_note(
+            "3",
+            Some(NoteAttachment::Dataset {
+                name: "schools".to_string(),
+            }),
+        );
+        let unreadable = community_note(
+            "4",
+            NoteAttachment::from_tags(&[vec!["attachment".to_string(), "{".to_string()]]),
+        );
+        let kept: Vec<String> = [about_parcels, general, other, unreadable]
+            .into_iter()
+            .filter(|note| req.matches(note))
+            .map(|note| note.id)
+            .collect();
+        assert_eq!(kept, vec!["1"]);
+    }
+
+    #[test]
+    fn pages_through_notes_sharing_timestamps() {
+        // Three notes per second, so page boundaries fall inside a second
+        let fixture: Vec<CommunityNote> = (0..120)
+            .map(|i| {
+                let mut note = community_note(&format!("{:03}", i), None);
+                note.created_at = (1_700_000_000 - i / 3) * 1000;
+                note
+            })
+            .collect();
+        // A relay returning the newest `limit` notes at or before `until`
+        let relay = |req: &FetchNotesRequest| -> Vec<CommunityNote> {
+            let until = req.until.map(|until| until / 1000 * 1000);
+            fixture
+                .iter()
+                .filter(|n| until.is_none_or(|until| n.created_at <= until))
+                .take(req.limit as usize)
+                .cloned()
+                .collect()
+        };
+
+        let mut feed = NoteFeed::default();
+        let mut req: FetchNotesRequest = serde_json::from_value(json!({"limit": 50})).unwrap();
+        let mut ids = Vec::new();
+        let mut pages = 0;
+        loop {
+            let page = feed.page(&req, relay(&req));
+            pages += 1;
+            ids.extend(page.notes.into_iter().map(|n| n.id));
+            match page.next_cursor {
+                Some(cursor) => req.until = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3);
+        let expected: Vec<String> = (0..120).map(|i| format!("{:03}", i)).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn pages_past_a_second_holding_more_than_limit_notes() {
+        // 60 notes in the newest second, then one per second
+        let fixture: Vec<CommunityNote> = (0..90)
+            .map(|i| {
+                let mut note = community_note(&format!("{:03}", i), None);
+                note.created_at = (1_700_000_000 - (i.max(59) - 59)) * 1000 + 500;
+                note
+            })
+            .collect();
+        let relay = |req: &FetchNotesRequest| -> Vec<CommunityNote> {
+            let until = req.until.map(|until| until / 1000 * 1000 + 999);
+            fixture
+                .iter()
+                .filter(|n| until.is_none_or(|until| n.created_at <= until))
+                .take(req.limit as usize)
+                .cloned()
+                .collect()
+        };
+
+        let mut feed = NoteFeed::default();
+        let mut req: FetchNotesRequest = serde_json::from_value(json!({"limit": 50})).unwrap();
+        let mut ids = Vec::new();
+        for _ in 0..10 {
+            let page = feed.page(&req, relay(&req));
+            assert!(!page.notes.is_empty() || !page.has_more);
+            ids.extend(page.notes.into_iter().map(|n| n.id));
+            match page.next_cursor {
+                Some(cursor) => req.until = Some(cursor),
+                None => break,
+            }
+        }
+        // The first page fills the crowded second; every older note follows
+        let expected: Vec<String> = (0..50).chain(60..90).map(|i| format!("{:03}", i)).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn fetched_note_content_is_sanitized() {
+        let event = NostrEvent {
+            id: "e".repeat(64),
+            pubkey: "a".repeat(64),
+            created_at: 1_700_000_000,
+            kind: KIND_TEXT_NOTE,
+            tags: vec![vec!["t".to_string(), "fredco-data".to_string()]],
+            content: "Nice chart<script>alert(document.cookie)</script>\n\
+                      <img src=x onerror=\"alert(1)\">"
+                .to_string(),
+            sig: String::new(),
+        };
+        let note = CommunityNote::from_event(&event);
+        assert_eq!(note.content, "<p>Nice chart\n<img src=\"x\"></p>\n");
+        assert_eq!(note.tags, vec!["fredco-data"]);
+        assert_eq!(note.created_at, 1_700_000_000_000);
+    }
+
+    #[test]
+    fn nip05_url_stays_on_the_identifier_domain() {
+        let url = |identifier: &str| {
+            VerifyNip05Request {
+                identifier: identifier.to_string(),
+                pubkey: String::new(),
+            }
+            .url()
+        };
+        assert_eq!(
+            url("Bob@virginiafreedom.tech").as_deref(),
+            Some("https://virginiafreedom.tech/.well-known/nostr.json?name=bob")
+        );
+        assert_eq!(
+            url("virginiafreedom.tech").as_deref(),
+            Some("https://virginiafreedom.tech/.well-known/nostr.json?name=_")
+        );
+        // The name can't smuggle userinfo or query parameters
+        assert_eq!(
+            url("a@b@evil.example").as_deref(),
+            Some("https://evil.example/.well-known/nostr.json?name=a%40b")
+        );
+        assert_eq!(
+            url("bob&name=alice@example.com").as_deref(),
+            Some("https://example.com/.well-known/nostr.json?name=bob%26name%3Dalice")
+        );
+        for bad in [
+            "bob@",
+            "@example.com",
+            "bob@example.com/path",
+            "bob@example.com:443",
+            "bob@-example.com",
+            "bob@example..com",
+        ] {
+            assert_eq!(url(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn builds_zap_invoice_request() {
+        let zap = ZapRequest {
+            event_id: "e1".to_string(),
+            author_pubkey: "p1".to_string(),
+            amount_sats: 21,
+            comment: Some("Great chart".to_string()),
+        };
+        assert_eq!(zap.amount_msats(), 21_000);
+
+        let mut author = profile("p1");
+        assert_eq!(
+            zap.lnurl_endpoint(&author).unwrap_err().code,
+            ErrorCode::NotFound
+        );
+        author.lud16 = Some("Alice@getalby.com".to_string());
+        assert_eq!(
+            zap.lnurl_endpoint(&author).unwrap(),
+            "https://getalby.com/.well-known/lnurlp/alice"
+        );
+
+        let body = r#"{
+            "callback": "https://getalby.com/lnurlp/alice/callback?tag=pay",
+            "minSendable": 1000,
+            "maxSendable": 100000000,
+            "allowsNostr": true,
+            "nostrPubkey": "79f00d3f"
+        }"#;
+        let info = LnurlPayInfo::parse(body, &zap).unwrap();
+        assert_eq!(info.nostr_pubkey, "79f00d3f");
+        assert_eq!(
+            info.invoice_url(&zap, r#"{"kind":9734}"#),
+            "https://getalby.com/lnurlp/alice/callback?tag=pay\
+             &amount=21000&nostr=%7B%22kind%22%3A9734%7D"
+        );
+        assert_eq!(
+            ZapResult::parse(r#"{"pr":"lnbc210n1"}"#).unwrap().invoice,
+            "lnbc210n1"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_lnurl_responses() {
+        let zap = ZapRequest {
+            event_id: "e1".to_string(),
+            author_pubkey: "p1".to_string(),
+            amount_sats: 21,
+            comment: None,
+        };
+        let limits = r#""callback": "https://example.com/cb", "minSendable": 1000"#;
+        for (body, code) in [
+            ("<html>", ErrorCode::ParseError),
+            (
+                r#"{"status": "ERROR", "reason": "user not found"}"#,
+                ErrorCode::NetworkError,
+            ),
+            (
+                &format!(r#"{{{limits}, "maxSendable": 100000000}}"#),
+                ErrorCode::NotFound,
+            ),
+            (
+                &format!(
+                    r#"{{{limits}, "maxSendable": 10000, "allowsNostr": true, "nostrPubkey": "k"}}"#
+                ),
+                ErrorCode::LimitExceeded,
+            ),
+            (
+                r#"{"allowsNostr": true, "nostrPubkey": "k"}"#,
+                ErrorCode::ParseError,
+            ),
+        ] {
+            let err = LnurlPayInfo::parse(body, &zap).unwrap_err();
+            assert_eq!(err.code, code, "{body}");
+        }
+        assert!(ZapResult::parse(r#"{"status": "OK"}"#).is_err());
+    }
+
+    fn reply_request() -> PublishNoteRequest {
+        PublishNoteRequest {
+            content: "Agreed".to_string(),
+            title: None,
+            attachment: NoteAttachment::General,
+            tags: Vec::new(),
+            long_form: false,
+            encryption: NoteEncryption::None,
+            reply_to: None,
+            reply_root: None,
+            reply_pubkey: None,
+        }
+    }
+
+    fn e_tag(id: &str, marker: &str) -> Vec<String> {
+        vec!["e".into(), id.into(), String::new(), marker.into()]
+    }
+
+    #[test]
+    fn reads_marked_and_positional_thread_tags() {
+        let p = vec!["p".to_string(), "a".repeat(64)];
+        // Marked tags win regardless of order
+        let marked = vec![e_tag("parent", "reply"), p.clone(), e_tag("root", "root")];
+        assert_eq!(
+            thread_ids(&marked),
+            (Some("root".to_string()), Some("parent".to_string()))
+        );
+        // A direct reply to the root only carries `root`
+        assert_eq!(
+            thread_ids(&[e_tag("root", "root")]),
+            (Some("root".to_string()), Some("root".to_string()))
+        );
+        // Deprecated positional scheme: first is root, last is parent
+        let positional = vec![
+            vec!["e".to_string(), "root".to_string()],
+            vec!["e".to_string(), "middle".to_string()],
+            vec!["e".to_string(), "parent".to_string()],
+        ];
+        assert_eq!(
+            thread_ids(&positional),
+            (Some("root".to_string()), Some("parent".to_string()))
+        );
+        assert_eq!(thread_ids(&[p]), (None, None));
+    }
+
+    #[test]
+    fn reply_tags_thread_under_root() {
+        let author = "b".repeat(64);
+
+        // Replying to a top-level note marks it as the root only
+        let top = community_note("top", None);
+        let mut req = reply_request();
+        req.set_reply_to(&top);
+        assert_eq!(req.reply_root.as_deref(), Some("top"));
+        // Thread tags follow the attachment's label tags
+        let attachment_tags = NoteAttachment::General.event_tags().len();
+        assert_eq!(
+            req.event_tags(&author)[attachment_tags..],
+            [e_tag("top", "root"), vec!["p".to_string(), "a".repeat(64)]]
+        );
+
+        // Replying to a reply keeps the original root
+        let mut nested = community_note("mid", None);
+        nested.root_id = Some("top".to_string());
+        let mut req = reply_request();
+        req.set_reply_to(&nested);
+        let tags = req.event_tags(&author);
+        assert_eq!(
+            tags[attachment_tags..],
+            [
+                e_tag("top", "root"),
+                e_tag("mid", "reply"),
+                vec!["p".to_string(), "a".repeat(64)],
+            ]
+        );
+        assert_eq!(
+            thread_ids(&tags),
+            (Some("top".to_string()), Some("mid".to_string()))
+        );
+    }
+
+    #[test]
+    fn private_reply_keeps_thread_tags() {
+        let parent_author = "a".repeat(64);
+        let mut nested = community_note("mid", None);
+        nested.root_id = Some("top".to_string());
+        let mut req = reply_request();
+        req.set_reply_to(&nested);
+        req.encryption = NoteEncryption::Nip44 {
+            recipient_pubkey: Some(parent_author.clone()),
+        };
+        assert_eq!(
+            req.event_tags(&"b".repeat(64)),
+            vec![
+                vec!["p".to_string(), parent_author.clone()],
+                vec!["encryption".to_string(), "nip44".to_string()],
+                e_tag("top", "root"),
+                e_tag("mid", "reply"),
+            ]
+        );
+
+        // A private note to someone else still tags the parent's author
+        req.encryption = NoteEncryption::Nip44 {
+            recipient_pubkey: Some("c".repeat(64)),
+        };
+        let tags = req.event_tags(&"b".repeat(64));
+        assert_eq!(tags.last(), Some(&vec!["p".to_string(), parent_author]));
+        assert_eq!(
+            thread_ids(&tags),
+            (Some("top".to_string()), Some("mid".to_string()))
+        );
+    }
+
+    #[test]
+    fn counts_reactions_by_content() {
+        let mut summary = ReactionSummary::default();
+        for content in ["+", "", "-", "🔥", "🔥"] {
+            summary.add(content);
+        }
+        assert_eq!(summary.likes, 2);
+        assert_eq!(summary.others["-"], 1);
+        assert_eq!(summary.others["🔥"], 2);
+        assert_eq!(summary.total(), 5);
+
+        let req: ReactNoteRequest = serde_json::from_value(json!({
+            "event_id": "e1",
+            "author_pubkey": "a".repeat(64),
+        }))
+        .unwrap();
+        assert_eq!(req.content, "+");
+        assert_eq!(
+            req.event_tags(),
+            vec![
+                vec!["e".to_string(), "e1".to_string()],
+                vec!["p".to_string(), "a".repeat(64)],
+            ]
+        );
+    }
+}
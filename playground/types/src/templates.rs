@@ -0,0 +1,244 @@
+//! Built-in notebook templates for `StorageCommand::CreateFromTemplate`
+
+use crate::data::ColumnType;
+use crate::editor::{
+    generate_cell_id, Cell, CellType, ExecutionState, Notebook, NotebookMetadata,
+    NotebookParameter, NOTEBOOK_VERSION,
+};
+use crate::messages::Timestamp;
+use crate::storage::StorageError;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// A starting point for a new notebook
+#[derive(Tsify, Serialize, Deserialize, Clone, Debug)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct NotebookTemplate {
+    /// Stable template ID, e.g. `explore-dataset`
+    pub id: String,
+    /// Display name
+    pub name: String,
+    /// What the template is for
+    pub description: String,
+    /// Cells and parameters copied into new notebooks
+    pub notebook: Notebook,
+}
+
+impl NotebookTemplate {
+    /// A fresh notebook titled `title`, created at `now`
+    ///
+    /// Every cell and chart gets a new ID, so notebooks made from the same
+    /// template never collide; charts and assertions follow their cells.
+    /// Outputs and run state are not copied.
+    pub fn instantiate(&self, title: &str, now: Timestamp) -> Notebook {
+        let mut notebook = self.notebook.clone();
+        notebook.metadata.title = Some(title.to_string());
+        notebook.metadata.created_at = now;
+        notebook.metadata.modified_at = now;
+        notebook.metadata.data_sources.clear();
+
+        let mut renamed = Vec::with_capacity(notebook.cells.len());
+        for i in 0..notebook.cells.len() {
+            let id = notebook.new_cell_id();
+            let cell = &mut notebook.cells[i];
+            renamed.push((std::mem::replace(&mut cell.id, id.clone()), id));
+            cell.output = None;
+            cell.state = ExecutionState::Idle;
+            cell.execution_count = None;
+            cell.content_hash.clear();
+            cell.created_at = now;
+            cell.modified_at = now;
+        }
+        let rename = |cell_id: &mut String| {
+            if let Some((_, new)) = renamed.iter().find(|(old, _)| old == cell_id) {
+                *cell_id = new.clone();
+            }
+        };
+        for chart in &mut notebook.charts {
+            chart.id = generate_cell_id();
+            if let Some(cell_id) = &mut chart.cell_id {
+                rename(cell_id);
+            }
+        }
+        for assertion in &mut notebook.assertions {
+            rename(&mut assertion.cell_id);
+        }
+        notebook
+    }
+}
+
+/// Templates shipped with the playground
+pub fn builtin_templates() -> Vec<NotebookTemplate> {
+    vec![
+        template(
+            "explore-dataset",
+            "Explore a dataset",
+            "Look over a newly loaded table: its columns, summary statistics and first rows.",
+            vec![
+                markdown(
+                    "# Explore a dataset\n\n\
+                     Load a data file, then replace `my_table` below with its table name.",
+                ),
+                sql("DESCRIBE my_table"),
+                sql("SUMMARIZE my_table"),
+                sql("SELECT *\nFROM my_table\nLIMIT 100"),
+            ],
+            Vec::new(),
+        ),
+        template(
+            "compare-years",
+            "Compare two years",
+            "Total a measure by category for two years side by side, with the change.",
+            vec![
+                markdown(
+                    "# Compare two years\n\n\
+                     Set `@year_a` and `@year_b` in the parameters panel, and replace \
+                     `my_table`, `category` and `amount` with your own names.",
+                ),
+                sql("SELECT category,\n       \
+                     SUM(amount) FILTER (WHERE year = @year_a) AS total_a,\n       \
+                     SUM(amount) FILTER (WHERE year = @year_b) AS total_b,\n       \
+                     total_b - total_a AS change\n\
+                     FROM my_table\n\
+                     GROUP BY category\n\
+                     ORDER BY change DESC"),
+            ],
+            vec![
+                year_parameter("year_a", 2023),
+                year_parameter("year_b", 2024),
+            ],
+        ),
+    ]
+}
+
+/// Instantiate the built-in template `template_id` as `title`
+pub fn create_from_template(
+    template_id: &str,
+    title: &str,
+    now: Timestamp,
+) -> Result<Notebook, StorageError> {
+    builtin_templates()
+        .into_iter()
+        .find(|t| t.id == template_id)
+        .map(|t| t.instantiate(title, now))
+        .ok_or_else(|| StorageError::NotFound {
+            key: format!("template/{}", template_id),
+        })
+}
+
+fn template(
+    id: &str,
+    name: &str,
+    description: &str,
+    cells: Vec<Cell>,
+    parameters: Vec<NotebookParameter>,
+) -> NotebookTemplate {
+    let cells = cells
+        .into_iter()
+        .enumerate()
+        .map(|(i, cell)| Cell {
+            id: format!("{}-{}", id, i + 1),
+            ..cell
+        })
+        .collect();
+    NotebookTemplate {
+        id: id.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        notebook: Notebook {
+            version: NOTEBOOK_VERSION,
+            metadata: NotebookMetadata {
+                title: Some(name.to_string()),
+                author: None,
+                tags: Vec::new(),
+                created_at: 0,
+                modified_at: 0,
+                description: Some(description.to_string()),
+                parameters,
+                data_sources: Vec::new(),
+            },
+            cells,
+            loaded_data: Vec::new(),
+            charts: Vec::new(),
+            assertions: Vec::new(),
+        },
+    }
+}
+
+fn cell(cell_type: CellType, content: &str) -> Cell {
+    Cell {
+        id: String::new(),
+        cell_type,
+        content: content.to_string(),
+        output: None,
+        state: ExecutionState::Idle,
+        execution_count: None,
+        created_at: 0,
+        modified_at: 0,
+        collapsed: false,
+        tags: Vec::new(),
+        content_hash: String::new(),
+    }
+}
+
+fn sql(content: &str) -> Cell {
+    cell(CellType::Sql, content)
+}
+
+fn markdown(content: &str) -> Cell {
+    cell(CellType::Markdown, content)
+}
+
+fn year_parameter(name: &str, default: i64) -> NotebookParameter {
+    NotebookParameter {
+        name: name.to_string(),
+        param_type: ColumnType::Int32,
+        default: default.into(),
+        description: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assertion::{Assertion, ExpectedResult};
+
+    #[test]
+    fn created_notebook_gets_new_ids() {
+        let templates = builtin_templates();
+        assert!(templates.iter().all(|t| t.notebook.validate().is_empty()));
+
+        let template = &templates[0];
+        let notebook = create_from_template(&template.id, "Parcels", 1_700_000_000_000).unwrap();
+        assert_eq!(notebook.metadata.title.as_deref(), Some("Parcels"));
+        assert_eq!(notebook.metadata.created_at, 1_700_000_000_000);
+        assert_eq!(notebook.cells.len(), template.notebook.cells.len());
+        for (cell, original) in notebook.cells.iter().zip(&template.notebook.cells) {
+            assert_ne!(cell.id, original.id);
+            assert_eq!(cell.content, original.content);
+            assert_eq!(cell.created_at, 1_700_000_000_000);
+        }
+
+        let again = create_from_template(&template.id, "Schools", 0).unwrap();
+        assert!(again
+            .cells
+            .iter()
+            .all(|cell| notebook.cells.iter().all(|other| other.id != cell.id)));
+        assert!(matches!(
+            create_from_template("missing", "x", 0),
+            Err(StorageError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn assertions_follow_their_cells() {
+        let mut template = builtin_templates().remove(0);
+        template.notebook.assertions = vec![Assertion {
+            cell_id: template.notebook.cells[3].id.clone(),
+            expected: ExpectedResult::RowCount { count: 100 },
+        }];
+        let notebook = template.instantiate("Parcels", 0);
+        assert_eq!(notebook.assertions[0].cell_id, notebook.cells[3].id);
+        assert!(notebook.validate().is_empty());
+    }
+}